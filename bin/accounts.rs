@@ -4,7 +4,7 @@ pub use thunderclap::json;
 use thunderclap::{CommandArgs, CommandBuilder};
 
 use crate::CmdArgs;
-use rust_accounts::{Account, AccountDesc, Error};
+use rust_accounts::{banks, Account, AccountDesc, Error};
 
 //a Accounts
 //fi list_fn
@@ -39,12 +39,21 @@ fn add_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
     let bank = &cmd_args.string_args[0];
     let name = &cmd_args.string_args[1];
     let sort_code = &cmd_args.string_args[2];
+    let account_desc = &cmd_args.string_args[3];
     let account_number = cmd_args.usize_args[0];
 
-    let desc = AccountDesc::parse_uk(sort_code, account_number)?;
-    let account = Account::new(bank.to_owned(), name.to_owned(), desc);
+    let desc = {
+        if !account_desc.is_empty() {
+            account_desc.parse::<AccountDesc>()?
+        } else {
+            AccountDesc::parse_uk(sort_code, account_number)?
+        }
+    };
+    let account =
+        Account::new(bank.to_owned(), name.to_owned(), desc).with_currency(cmd_args.get_currency());
 
     let db_id = cmd_args.db.add_account(account);
+    cmd_args.record_journal()?;
     Ok(json::to_value(db_id).unwrap())
 }
 
@@ -82,6 +91,18 @@ fn transactions_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
     Ok(json::to_value(bank_transactions).unwrap())
 }
 
+//mi export_qif_fn
+fn export_qif_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
+    let name = cmd_args.next_string_arg()?;
+    let db_acc = cmd_args.get_account_by_name(&name)?;
+    let filename = cmd_args.next_string_arg()?;
+    let date_range = cmd_args.get_date_range();
+
+    let mut file = std::fs::File::create(&filename)?;
+    banks::qif::write_qif(&mut file, &cmd_args.db, &db_acc.inner(), date_range)?;
+    CmdArgs::cmd_ok()
+}
+
 //mi validate_cmd
 fn validate_cmd() -> CommandBuilder<CmdArgs> {
     let mut cmd = CommandBuilder::with_handler(
@@ -105,6 +126,26 @@ fn transactions_cmd() -> CommandBuilder<CmdArgs> {
     cmd
 }
 
+//mi export_qif_cmd
+fn export_qif_cmd() -> CommandBuilder<CmdArgs> {
+    let mut cmd = CommandBuilder::with_handler(
+        Command::new("export_qif").about("Export account transactions as QIF"),
+        export_qif_fn,
+    );
+
+    CmdArgs::arg_add_account_positional(&mut cmd);
+    CmdArgs::arg_add_positional_string(
+        &mut cmd,
+        "qif_filename",
+        "QIF filename to write",
+        Some(1),
+        None,
+    );
+    CmdArgs::arg_add_option_start_date(&mut cmd);
+    CmdArgs::arg_add_option_end_date(&mut cmd);
+    cmd
+}
+
 //mp accounts_cmd
 pub fn accounts_cmd() -> CommandBuilder<CmdArgs> {
     let command = Command::new("accounts").about("Operate on the accounts section of the database");
@@ -115,19 +156,34 @@ pub fn accounts_cmd() -> CommandBuilder<CmdArgs> {
     let mut add = CommandBuilder::with_handler(Command::new("add").about("Add an account"), add_fn);
     CmdArgs::arg_add_option_string(&mut add, "bank", None, "Bank name", None);
     CmdArgs::arg_add_option_string(&mut add, "name", None, "Account name", None);
-    CmdArgs::arg_add_option_string(&mut add, "sort_code", None, "Sort code (xx-xx-xx)", None);
+    CmdArgs::arg_add_option_string(
+        &mut add,
+        "sort_code",
+        None,
+        "Sort code (xx-xx-xx) of a UK account",
+        Some(""),
+    );
+    CmdArgs::arg_add_option_string(
+        &mut add,
+        "account_desc",
+        None,
+        "Account description (iban:..., bic:<bic>:<account>, card:<masked number>, wallet:<provider>:<account>, cash:<name>, or xx-xx-xx:<account number>) instead of a sort code and account number",
+        Some(""),
+    );
     CmdArgs::arg_add_option_usize(
         &mut add,
         "account_number",
         None,
-        "Account number - a positive integer",
-        None,
+        "Account number of a UK account - a positive integer",
+        Some("0"),
     );
+    CmdArgs::arg_add_option_currency(&mut add);
 
     build.add_subcommand(list);
     build.add_subcommand(add);
     build.add_subcommand(validate_cmd());
     build.add_subcommand(transactions_cmd());
+    build.add_subcommand(export_qif_cmd());
 
     build
 }
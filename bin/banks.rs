@@ -4,16 +4,25 @@ use thunderclap::json;
 use thunderclap::{CommandArgs, CommandBuilder};
 
 use crate::CmdArgs;
-use rust_accounts::{banks, Error};
+use rust_accounts::{
+    banks, AccountDesc, Amount, BankTransaction, Error, ImportOutcome, ImportPreview, ImportReport,
+    RelatedPartyMatch,
+};
 
-//a Members
-fn lloyds_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
-    let filename = &cmd_args.string_args[0];
-
-    println!("Attempt to import Lloyds CSV from file '{filename}'");
-    let csv_data = std::fs::read_to_string(filename)?;
-    let acc_transactions = banks::lloyds::read_transactions_csv(csv_data.as_bytes())?;
+//a Types
+//tp ReadFn
+/// The signature of the bank CSV readers (other than Lloyds)
+type ReadFn =
+    fn(std::fs::File, &AccountDesc, Option<Amount>) -> Result<Vec<BankTransaction>, Error>;
 
+//a Members
+//fi import_transactions
+/// Add transactions read from a bank export to the account they
+/// belong to
+fn import_transactions(
+    cmd_args: &mut CmdArgs,
+    acc_transactions: Vec<BankTransaction>,
+) -> Result<json::Value, Error> {
     if acc_transactions.is_empty() {
         return Err("Transactions were empty".to_string().into());
     }
@@ -29,21 +38,276 @@ fn lloyds_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
         .into());
     };
 
-    if let Err(unresolved_transactions) =
-        account
-            .inner_mut()
-            .add_transactions(&cmd_args.db, account.id(), acc_transactions)
-    {
-        eprintln!("Failed to add transactions {unresolved_transactions:?}");
-        return Err(format!(
-            "Failed to add {} transactions",
-            unresolved_transactions.len()
-        )
-        .into());
+    if cmd_args.dry_run {
+        let preview = account
+            .inner()
+            .preview_transactions(&cmd_args.db, &acc_transactions)?;
+        show_preview(&preview);
+        return Ok(json::to_value(preview).unwrap());
+    }
+    let result = account
+        .inner_mut()
+        .add_transactions(&cmd_args.db, account.id(), acc_transactions);
+    match result {
+        Err(unresolved_transactions) => {
+            eprintln!("Failed to add transactions {unresolved_transactions:?}");
+            Err(format!(
+                "Failed to add {} transactions",
+                unresolved_transactions.len()
+            )
+            .into())
+        }
+        Ok(report) => {
+            cmd_args.record_journal()?;
+            show_report(&report);
+            Ok(json::to_value(report).unwrap())
+        }
+    }
+}
+
+//fi show_report
+/// Print the summary of an import, and any conflicts
+fn show_report(report: &ImportReport) {
+    for (n, reason) in report.conflicts() {
+        eprintln!("Row {n} conflicts: {reason}");
+    }
+    println!("Imported: {report}");
+}
+
+//fi show_preview
+/// Print the preview of an import
+fn show_preview(preview: &ImportPreview) {
+    for r in preview.rows() {
+        let outcome = match &r.outcome {
+            ImportOutcome::New => "new".to_string(),
+            ImportOutcome::Added(db_id) => format!("added as {db_id}"),
+            ImportOutcome::Skipped(_) => "skipped".to_string(),
+            ImportOutcome::Conflict(reason) => format!("conflict: {reason}"),
+        };
+        let rp = match (&r.related_party, &r.related_party_name) {
+            (RelatedPartyMatch::Ambiguous, _) => "<ambiguous>",
+            (_, Some(name)) => name.as_str(),
+            _ => "<none>",
+        };
+        println!(
+            "{} {:40} {:>10} {:>10} {:20} {}",
+            r.date,
+            r.description,
+            r.balance_delta.to_string().trim(),
+            r.balance.to_string().trim(),
+            rp,
+            outcome
+        );
+    }
+    println!("Dry run: {preview}");
+}
+
+//fi import_statements
+/// Add the transactions of statements to the accounts they belong
+/// to, using the counterparties of the statements to find the
+/// related parties
+fn import_statements(
+    cmd_args: &mut CmdArgs,
+    statements: Vec<banks::Statement>,
+) -> Result<json::Value, Error> {
+    for mut statement in statements {
+        statement.resolve_related_parties(&cmd_args.db);
+        import_transactions(cmd_args, statement.into_transactions())?;
+    }
+    CmdArgs::cmd_ok()
+}
+
+//fi account_desc
+/// The account description of the account given with --account, if any
+fn account_desc(cmd_args: &CmdArgs) -> Result<AccountDesc, Error> {
+    if cmd_args.account.is_some() {
+        Ok(cmd_args.get_account()?.inner().desc().clone())
+    } else {
+        Ok(AccountDesc::None)
+    }
+}
+
+//fi import_csv
+/// Import a bank CSV export using the reader for that bank
+///
+/// The account (if given with --account) provides the account
+/// description, for those exports that do not include it
+fn import_csv(cmd_args: &mut CmdArgs, bank: &str, read_fn: ReadFn) -> Result<json::Value, Error> {
+    let filename = cmd_args.next_string_arg()?;
+    let account_desc = account_desc(cmd_args)?;
+
+    println!("Attempt to import {bank} CSV from file '{filename}'");
+    let file = std::fs::File::open(&filename)?;
+    let acc_transactions = read_fn(file, &account_desc, cmd_args.opening_balance)?;
+    import_transactions(cmd_args, acc_transactions)
+}
+
+//fi lloyds_fn
+fn lloyds_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
+    let filename = &cmd_args.string_args[0];
+
+    println!("Attempt to import Lloyds CSV from file '{filename}'");
+    let csv_data = std::fs::read_to_string(filename)?;
+    let acc_transactions = banks::lloyds::read_transactions_csv(csv_data.as_bytes())?;
+    import_transactions(cmd_args, acc_transactions)
+}
+
+//fi mapped_csv_fn
+fn mapped_csv_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
+    let mapping_filename = cmd_args.mapping.clone().unwrap_or_default();
+    let mapping = banks::mapping::CsvMapping::read_file(&mapping_filename)?;
+    let filename = cmd_args.next_string_arg()?;
+    let account_desc = account_desc(cmd_args)?;
+
+    println!("Attempt to import CSV from file '{filename}' using mapping '{mapping_filename}'");
+    let file = std::fs::File::open(&filename)?;
+    let acc_transactions =
+        mapping.read_transactions_csv(file, &account_desc, cmd_args.opening_balance)?;
+    import_transactions(cmd_args, acc_transactions)
+}
+
+//fi ofx_fn
+fn ofx_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
+    let filename = cmd_args.next_string_arg()?;
+    let account_desc = account_desc(cmd_args)?;
+
+    println!("Attempt to import OFX from file '{filename}'");
+    let file = std::fs::File::open(&filename)?;
+    let statements =
+        banks::ofx::read_transactions_ofx(file, &account_desc, cmd_args.opening_balance)?;
+    for acc_transactions in statements {
+        import_transactions(cmd_args, acc_transactions)?;
     }
     CmdArgs::cmd_ok()
 }
 
+//fi qif_fn
+fn qif_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
+    let filename = cmd_args.next_string_arg()?;
+    let db_account = cmd_args.get_account()?;
+    let account_desc = db_account.inner().desc().clone();
+
+    println!("Attempt to import QIF from file '{filename}'");
+    let file = std::fs::File::open(&filename)?;
+    let statement = banks::qif::read_statement_qif(file, &account_desc, cmd_args.opening_balance)?;
+    if cmd_args.dry_run {
+        let preview = statement.preview(&cmd_args.db, &db_account)?;
+        show_preview(&preview);
+        return Ok(json::to_value(preview).unwrap());
+    }
+    let (report, _t_ids) = statement.import(&cmd_args.db, &db_account)?;
+    show_report(&report);
+    cmd_args.record_journal()?;
+    Ok(json::to_value(report).unwrap())
+}
+
+//fi camt053_fn
+fn camt053_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
+    let filename = cmd_args.next_string_arg()?;
+    let account_desc = account_desc(cmd_args)?;
+
+    println!("Attempt to import camt.053 from file '{filename}'");
+    let file = std::fs::File::open(&filename)?;
+    let statements = banks::camt053::read_statements_camt053(file, &account_desc)?;
+    import_statements(cmd_args, statements)
+}
+
+//fi mt940_fn
+fn mt940_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
+    let filename = cmd_args.next_string_arg()?;
+    let account_desc = account_desc(cmd_args)?;
+
+    println!("Attempt to import MT940 from file '{filename}'");
+    let file = std::fs::File::open(&filename)?;
+    let statements = banks::mt940::read_statements_mt940(file, &account_desc)?;
+    import_statements(cmd_args, statements)
+}
+
+//fi statement_cmd
+/// Create a subcommand to import a statement file that includes the balances
+fn statement_cmd(
+    name: &'static str,
+    about: &'static str,
+    handler: fn(&mut CmdArgs) -> Result<json::Value, Error>,
+) -> CommandBuilder<CmdArgs> {
+    let mut cmd = CommandBuilder::with_handler(Command::new(name).about(about), handler);
+    CmdArgs::arg_add_positional_string(
+        &mut cmd,
+        "filename",
+        "Filename of statements to import",
+        Some(1),
+        None,
+    );
+    CmdArgs::arg_add_option_account(&mut cmd, false);
+    CmdArgs::arg_add_dry_run(&mut cmd);
+    cmd
+}
+
+//fi barclays_fn
+fn barclays_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
+    import_csv(cmd_args, "Barclays", banks::barclays::read_transactions_csv)
+}
+
+//fi hsbc_fn
+fn hsbc_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
+    import_csv(cmd_args, "HSBC", banks::hsbc::read_transactions_csv)
+}
+
+//fi natwest_fn
+fn natwest_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
+    import_csv(cmd_args, "NatWest", banks::natwest::read_transactions_csv)
+}
+
+//fi nationwide_fn
+fn nationwide_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
+    import_csv(
+        cmd_args,
+        "Nationwide",
+        banks::nationwide::read_transactions_csv,
+    )
+}
+
+//fi santander_fn
+fn santander_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
+    import_csv(
+        cmd_args,
+        "Santander",
+        banks::santander::read_transactions_csv,
+    )
+}
+
+//fi starling_fn
+fn starling_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
+    import_csv(cmd_args, "Starling", banks::starling::read_transactions_csv)
+}
+
+//fi monzo_fn
+fn monzo_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
+    import_csv(cmd_args, "Monzo", banks::monzo::read_transactions_csv)
+}
+
+//fi bank_csv_cmd
+/// Create a subcommand to import a bank CSV export
+fn bank_csv_cmd(
+    name: &'static str,
+    about: &'static str,
+    handler: fn(&mut CmdArgs) -> Result<json::Value, Error>,
+) -> CommandBuilder<CmdArgs> {
+    let mut cmd = CommandBuilder::with_handler(Command::new(name).about(about), handler);
+    CmdArgs::arg_add_positional_string(
+        &mut cmd,
+        "csv_filename",
+        "CSV filename of transactions to import",
+        Some(1),
+        None,
+    );
+    CmdArgs::arg_add_option_account(&mut cmd, false);
+    CmdArgs::arg_add_option_opening_balance(&mut cmd);
+    CmdArgs::arg_add_dry_run(&mut cmd);
+    cmd
+}
+
+//fp banks_cmd
 pub fn banks_cmd() -> CommandBuilder<CmdArgs> {
     let command = Command::new("banks").about("Import data from banks");
 
@@ -58,7 +322,92 @@ pub fn banks_cmd() -> CommandBuilder<CmdArgs> {
         None,
     );
 
+    CmdArgs::arg_add_dry_run(&mut lloyds);
     build.add_subcommand(lloyds);
+    let mut csv = bank_csv_cmd(
+        "csv",
+        "Import a CSV export from any bank using a column mapping file",
+        mapped_csv_fn,
+    );
+    CmdArgs::arg_add_option_mapping(&mut csv);
+    build.add_subcommand(csv);
+    let mut ofx = CommandBuilder::with_handler(
+        Command::new("ofx").about("Import an OFX or QFX statement"),
+        ofx_fn,
+    );
+    CmdArgs::arg_add_positional_string(
+        &mut ofx,
+        "ofx_filename",
+        "OFX or QFX filename of statements to import",
+        Some(1),
+        None,
+    );
+    CmdArgs::arg_add_option_account(&mut ofx, false);
+    CmdArgs::arg_add_option_opening_balance(&mut ofx);
+    CmdArgs::arg_add_dry_run(&mut ofx);
+    build.add_subcommand(ofx);
+    let mut qif = CommandBuilder::with_handler(
+        Command::new("qif").about(
+            "Import a QIF file, adding transactions against the funds named by its categories",
+        ),
+        qif_fn,
+    );
+    CmdArgs::arg_add_positional_string(
+        &mut qif,
+        "qif_filename",
+        "QIF filename of transactions to import",
+        Some(1),
+        None,
+    );
+    CmdArgs::arg_add_option_account(&mut qif, true);
+    CmdArgs::arg_add_option_opening_balance(&mut qif);
+    CmdArgs::arg_add_dry_run(&mut qif);
+    build.add_subcommand(qif);
+    build.add_subcommand(statement_cmd(
+        "camt053",
+        "Import an ISO 20022 camt.053 XML statement",
+        camt053_fn,
+    ));
+    build.add_subcommand(statement_cmd(
+        "mt940",
+        "Import a SWIFT MT940 statement",
+        mt940_fn,
+    ));
+    build.add_subcommand(bank_csv_cmd(
+        "barclays_csv",
+        "Import a Barclays CSV export",
+        barclays_fn,
+    ));
+    build.add_subcommand(bank_csv_cmd(
+        "hsbc_csv",
+        "Import an HSBC CSV export (requires --account)",
+        hsbc_fn,
+    ));
+    build.add_subcommand(bank_csv_cmd(
+        "natwest_csv",
+        "Import a NatWest CSV export",
+        natwest_fn,
+    ));
+    build.add_subcommand(bank_csv_cmd(
+        "nationwide_csv",
+        "Import a Nationwide CSV export (requires --account)",
+        nationwide_fn,
+    ));
+    build.add_subcommand(bank_csv_cmd(
+        "santander_csv",
+        "Import a Santander CSV export (requires --account)",
+        santander_fn,
+    ));
+    build.add_subcommand(bank_csv_cmd(
+        "starling_csv",
+        "Import a Starling CSV export (requires --account)",
+        starling_fn,
+    ));
+    build.add_subcommand(bank_csv_cmd(
+        "monzo_csv",
+        "Import a Monzo CSV export (requires --account and --opening_balance)",
+        monzo_fn,
+    ));
 
     build
 }
@@ -8,7 +8,10 @@ use thunderclap::{CommandArgs, CommandBuilder};
 // use rust_accounts::Idx;
 use rust_accounts::RelatedPartyQuery;
 use rust_accounts::RelatedPartyType;
-use rust_accounts::{Amount, Database, Date, DateRange, Error, FileFormat, FileType};
+use rust_accounts::{Aggregation, Currency, ExchangeRate, GroupBy, QueryView, SortKey};
+use rust_accounts::{
+    Amount, Database, Date, DateRange, Error, FileFormat, FileType, Journal, SqliteStore,
+};
 use rust_accounts::{DbAccount, DbBankTransaction, DbFund, DbId, DbItemType, DbRelatedParty};
 
 //a CmdArgs
@@ -16,8 +19,14 @@ use rust_accounts::{DbAccount, DbBankTransaction, DbFund, DbId, DbItemType, DbRe
 #[derive(Default)]
 pub struct CmdArgs {
     pub db: Database,
+    /// The snapshot file the database was loaded from, if any
+    pub db_filename: String,
+    /// The journal of changes to the database since it was loaded
+    pub journal: Option<Journal>,
     pub verbose: bool,
     pub clear: bool,
+    pub dry_run: bool,
+    pub cascade: bool,
     pub file_format: FileFormat,
 
     pub write_filename: String,
@@ -25,6 +34,10 @@ pub struct CmdArgs {
     pub db_id: Vec<DbId>,
     pub rp_id: Option<usize>,
     pub amount: Amount,
+    pub opening_balance: Option<Amount>,
+    pub mapping: Option<String>,
+    pub currency: Option<Currency>,
+    pub exchange_rate: Option<String>,
     pub rp_type: Option<RelatedPartyType>,
     pub start_date: Date,
     pub end_date: Date,
@@ -38,6 +51,13 @@ pub struct CmdArgs {
     pub desc: Option<String>,
     pub id: Option<usize>,
     pub account: Option<String>,
+    pub sort_key: SortKey,
+    pub descending: bool,
+    pub offset: usize,
+    pub limit: Option<usize>,
+    pub fields: Vec<String>,
+    pub group_by: Vec<GroupBy>,
+    pub year_start: Option<usize>,
     pub string_args: Vec<String>,
     pub usize_args: Vec<usize>,
     pub value_args: Vec<Rc<json::Value>>,
@@ -50,12 +70,19 @@ pub struct CmdArgs {
 impl std::fmt::Debug for CmdArgs {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
         write!(fmt, "CmdArgs {{")?;
+        write!(fmt, "db_filename: {:?}", self.db_filename)?;
         write!(fmt, "verbose: {:?}", self.verbose)?;
         write!(fmt, "clear: {:?}", self.clear)?;
+        write!(fmt, "dry_run: {:?}", self.dry_run)?;
+        write!(fmt, "cascade: {:?}", self.cascade)?;
         write!(fmt, "file_format: {:?}", self.file_format)?;
         write!(fmt, "write_filename: {:?}", self.write_filename)?;
         write!(fmt, "item_type: {:?}", self.item_type)?;
         write!(fmt, "amount: {:?}", self.amount)?;
+        write!(fmt, "opening_balance: {:?}", self.opening_balance)?;
+        write!(fmt, "mapping: {:?}", self.mapping)?;
+        write!(fmt, "currency: {:?}", self.currency)?;
+        write!(fmt, "exchange_rate: {:?}", self.exchange_rate)?;
         write!(fmt, "db_id: {:?}", self.db_id)?;
         write!(fmt, "rp_id: {:?}", self.rp_id)?;
         write!(fmt, "rp_type: {:?}", self.rp_type)?;
@@ -71,6 +98,13 @@ impl std::fmt::Debug for CmdArgs {
         write!(fmt, "desc: {:?}", self.desc)?;
         write!(fmt, "account: {:?}", self.account)?;
         write!(fmt, "id: {:?}", self.id)?;
+        write!(fmt, "sort_key: {:?}", self.sort_key)?;
+        write!(fmt, "descending: {:?}", self.descending)?;
+        write!(fmt, "offset: {:?}", self.offset)?;
+        write!(fmt, "limit: {:?}", self.limit)?;
+        write!(fmt, "fields: {:?}", self.fields)?;
+        write!(fmt, "group_by: {:?}", self.group_by)?;
+        write!(fmt, "year_start: {:?}", self.year_start)?;
         write!(fmt, "}}")
     }
 }
@@ -85,18 +119,31 @@ impl CommandArgs for CmdArgs {
     }
 
     fn reset_args(&mut self) {
+        // Changes left by a previous command that failed part way
+        // through are recorded before the next command starts
+        if let Err(e) = self.record_journal() {
+            eprintln!("Failed to record journal: {e}");
+        }
+
         self.string_args.clear();
         self.usize_args.clear();
         self.value_args.clear();
 
         self.clear = false;
+        self.dry_run = false;
+        self.cascade = false;
 
         self.id = None;
         self.name = None;
+        self.account = None;
         self.db_id = vec![];
         self.rp_id = None;
         self.rp_type = None;
         self.amount = Amount::default();
+        self.opening_balance = None;
+        self.mapping = None;
+        self.currency = None;
+        self.exchange_rate = None;
         self.start_date = Date::default();
         self.end_date = Date::default();
         self.postcode = None;
@@ -105,6 +152,13 @@ impl CommandArgs for CmdArgs {
         self.email = None;
         self.telephone = None;
         self.tax_name = None;
+        self.sort_key = SortKey::default();
+        self.descending = false;
+        self.offset = 0;
+        self.limit = None;
+        self.fields = vec![];
+        self.group_by = vec![];
+        self.year_start = None;
 
         self.string_args_index = 0;
         self.usize_args_index = 0;
@@ -126,6 +180,24 @@ impl CmdArgs {
         Ok(())
     }
 
+    //mi set_dry_run
+    fn set_dry_run(&mut self, dry_run: bool) -> Result<(), Error> {
+        self.dry_run = dry_run;
+        Ok(())
+    }
+
+    //mi set_cascade
+    fn set_cascade(&mut self, cascade: bool) -> Result<(), Error> {
+        self.cascade = cascade;
+        Ok(())
+    }
+
+    //mi set_descending
+    fn set_descending(&mut self, descending: bool) -> Result<(), Error> {
+        self.descending = descending;
+        Ok(())
+    }
+
     //mi set_item_type
     fn set_item_type(&mut self, item_type: &str) -> Result<(), Error> {
         self.item_type = Some(item_type.parse::<DbItemType>()?);
@@ -138,6 +210,30 @@ impl CmdArgs {
         Ok(())
     }
 
+    //mi set_opening_balance
+    fn set_opening_balance(&mut self, amount: &str) -> Result<(), Error> {
+        self.opening_balance = Some(amount.parse::<Amount>()?);
+        Ok(())
+    }
+
+    //mi set_mapping
+    fn set_mapping(&mut self, filename: &str) -> Result<(), Error> {
+        self.mapping = Some(filename.into());
+        Ok(())
+    }
+
+    //mi set_currency
+    fn set_currency(&mut self, currency: &str) -> Result<(), Error> {
+        self.currency = Some(currency.parse::<Currency>()?);
+        Ok(())
+    }
+
+    //mi set_exchange_rate
+    fn set_exchange_rate(&mut self, rate: &str) -> Result<(), Error> {
+        self.exchange_rate = Some(rate.into());
+        Ok(())
+    }
+
     //mi set_rp_type
     fn set_rp_type(&mut self, rp_type: &str) -> Result<(), Error> {
         self.rp_type = Some(rp_type.parse::<RelatedPartyType>()?);
@@ -189,6 +285,51 @@ impl CmdArgs {
         Ok(())
     }
 
+    //mi set_sort_key
+    fn set_sort_key(&mut self, s: &str) -> Result<(), Error> {
+        self.sort_key = s.parse::<SortKey>()?;
+        Ok(())
+    }
+
+    //mi set_offset
+    fn set_offset(&mut self, offset: usize) -> Result<(), Error> {
+        self.offset = offset;
+        Ok(())
+    }
+
+    //mi set_limit
+    fn set_limit(&mut self, limit: usize) -> Result<(), Error> {
+        self.limit = Some(limit);
+        Ok(())
+    }
+
+    //mi set_fields
+    fn set_fields(&mut self, s: &str) -> Result<(), Error> {
+        self.fields.extend(
+            s.split(',')
+                .map(|f| f.trim().to_string())
+                .filter(|f| !f.is_empty()),
+        );
+        Ok(())
+    }
+
+    //mi set_group_by
+    fn set_group_by(&mut self, s: &str) -> Result<(), Error> {
+        for g in s.split(',').map(|g| g.trim()).filter(|g| !g.is_empty()) {
+            self.group_by.push(g.parse::<GroupBy>()?);
+        }
+        Ok(())
+    }
+
+    //mi set_year_start
+    fn set_year_start(&mut self, month: usize) -> Result<(), Error> {
+        if !(1..=12).contains(&month) {
+            return Err(format!("Financial year start month {month} must be 1 to 12").into());
+        }
+        self.year_start = Some(month);
+        Ok(())
+    }
+
     //mi set_name
     fn set_name(&mut self, s: &str) -> Result<(), Error> {
         self.name = Some(s.into());
@@ -293,51 +434,168 @@ impl CmdArgs {
     }
 
     //mi load_database
+    /// Load the database from a snapshot file, replaying any journal
+    /// of changes made since the snapshot was written
     fn load_database(&mut self, filename: &str) -> Result<(), Error> {
+        let (db, journal) = Self::read_database(filename)?;
+        self.db = db;
+        self.db_filename = filename.into();
+        self.journal = Some(journal);
+        Ok(())
+    }
+}
+
+//ip CmdArgs - operations
+impl CmdArgs {
+    //mp read_database
+    /// Read a database from a snapshot file, replaying any journal of
+    /// changes made since the snapshot was written
+    pub fn read_database(filename: &str) -> Result<(Database, Journal), Error> {
         let ftype = FileType::from_filename(filename)?;
-        let s = std::fs::read_to_string(filename)?;
-        match ftype {
-            FileType::Json => {
-                let mut deserializer =
-                    serde_json::Deserializer::new(serde_json::de::StrRead::new(&s));
-                // Deserialize from Vec<DbItem>
-                self.db = Database::deserialize(&mut deserializer, FileFormat::Array)?;
-                Ok(())
+        let db = {
+            match ftype {
+                FileType::Sqlite => SqliteStore::open(filename)?.load()?,
+                FileType::Json => {
+                    let s = std::fs::read_to_string(filename)?;
+                    let mut deserializer =
+                        serde_json::Deserializer::new(serde_json::de::StrRead::new(&s));
+                    // Deserialize from Vec<DbItem> or a dictionary
+                    Database::deserialize_any_format(&mut deserializer)?
+                }
+                FileType::Yaml => {
+                    let s = std::fs::read_to_string(filename)?;
+                    let deserializer = serde_yaml::Deserializer::from_str(&s);
+                    // Deserialize from Vec<DbItem> or a dictionary
+                    Database::deserialize_any_format(deserializer)?
+                }
+                _ => return Err(Error::FileTypeNotSupported(ftype, "database")),
             }
-            FileType::Yaml => {
-                let deserializer = serde_yaml::Deserializer::from_str(&s);
-                // Deserialize from Vec<DbItem>
-                self.db = Database::deserialize(deserializer, FileFormat::Array)?;
-                Ok(())
+        };
+        Journal::replay_file(db, Journal::path_of(filename))
+    }
+
+    //mp write_database
+    /// Write the database to the output file; if that is the file it
+    /// was loaded from then the journal is folded into it
+    pub fn write_database(&mut self) -> Result<(), Error> {
+        self.record_journal()?;
+        let filename = self.write_filename.clone();
+        self.write_database_to(&filename)?;
+        if !self.db_filename.is_empty() && filename == self.db_filename {
+            self.compact_journal()?;
+        }
+        Ok(())
+    }
+
+    //mp compact_database
+    /// Write the database to the file it was loaded from, folding the
+    /// journal into it
+    pub fn compact_database(&mut self) -> Result<(), Error> {
+        if self.db_filename.is_empty() {
+            return Err("No database file was loaded to compact".to_string().into());
+        }
+        self.record_journal()?;
+        let filename = self.db_filename.clone();
+        self.write_database_to(&filename)?;
+        self.compact_journal()
+    }
+
+    //mp record_journal
+    /// Record the changes made to the database since they were last
+    /// recorded, if it was loaded from a file
+    ///
+    /// Each command that changes the database records its changes as
+    /// it completes, so that they are in the journal file even if the
+    /// program (such as a server) does not exit cleanly
+    pub fn record_journal(&mut self) -> Result<(), Error> {
+        if let Some(journal) = &mut self.journal {
+            let entries = journal.record(&self.db)?;
+            if self.verbose {
+                for e in entries {
+                    eprintln!("Journal: {e}");
+                }
             }
-            _ => Err(Error::FileTypeNotSupported(ftype, "database")),
         }
+        Ok(())
     }
-}
 
-//ip CmdArgs - operations
-impl CmdArgs {
-    pub fn write_database(&self) -> Result<(), Error> {
-        let ftype = FileType::from_filename(&self.write_filename)?;
+    //mp undo
+    /// Undo up to 'count' of the most recent operations on the
+    /// database, returning the number undone
+    pub fn undo(&mut self, count: usize) -> Result<usize, Error> {
+        let Some(journal) = &mut self.journal else {
+            return Err("Undo requires a database loaded from a file"
+                .to_string()
+                .into());
+        };
+        for n in 0..count {
+            let Some(db) = journal.undo(&self.db)? else {
+                return Ok(n);
+            };
+            self.db = db;
+        }
+        Ok(count)
+    }
+
+    //mp redo
+    /// Redo up to 'count' of the most recently undone operations on
+    /// the database, returning the number redone
+    pub fn redo(&mut self, count: usize) -> Result<usize, Error> {
+        let Some(journal) = &mut self.journal else {
+            return Err("Redo requires a database loaded from a file"
+                .to_string()
+                .into());
+        };
+        for n in 0..count {
+            let Some(db) = journal.redo(&self.db)? else {
+                return Ok(n);
+            };
+            self.db = db;
+        }
+        Ok(count)
+    }
 
-        // use self.file_format
+    //mi compact_journal
+    /// Empty the journal once the database has been written to its
+    /// snapshot file, and reload the database from the snapshot (as
+    /// the DbIds may be renumbered by it) for later changes to be
+    /// journalled against
+    fn compact_journal(&mut self) -> Result<(), Error> {
+        if let Some(journal) = &self.journal {
+            journal.truncate()?;
+        }
+        let filename = self.db_filename.clone();
+        self.load_database(&filename)
+    }
+
+    //mi write_database_to
+    fn write_database_to(&self, filename: &str) -> Result<(), Error> {
+        let ftype = FileType::from_filename(filename)?;
+
+        if ftype == FileType::Sqlite {
+            let report = SqliteStore::create(filename)?.write(&self.db)?;
+            if self.verbose {
+                eprintln!("Wrote {filename}: {report}");
+            }
+            return Ok(());
+        }
 
         let mut w = vec![];
         match ftype {
             FileType::Json => {
                 let mut s = serde_json::Serializer::pretty(w);
-                self.db.serialize_as_array(&mut s)?;
+                self.db.serialize(&mut s, self.file_format)?;
                 w = s.into_inner();
             }
             FileType::Yaml => {
                 let mut s = serde_yaml::Serializer::new(&mut w);
-                self.db.serialize_as_array(&mut s)?;
+                self.db.serialize(&mut s, self.file_format)?;
             }
             _ => return Err("Cannot write database out as CSV fle".to_string().into()),
         }
         use std::io::Write;
         let s = std::str::from_utf8(&w).unwrap();
-        let mut f = std::fs::File::create(&self.write_filename)?;
+        let mut f = std::fs::File::create(filename)?;
         f.write_all(s.as_bytes())?;
         Ok(())
     }
@@ -359,6 +617,47 @@ impl CmdArgs {
         (self.start_date, self.end_date).into()
     }
 
+    //ap get_query_view
+    /// Get the sorting, paging and fields to return the results of a
+    /// query with
+    pub fn get_query_view(&self) -> QueryView {
+        QueryView::default()
+            .with_sort_key(self.sort_key)
+            .with_descending(self.descending)
+            .with_offset(self.offset)
+            .with_limit(self.limit)
+            .with_fields(self.fields.iter().cloned())
+    }
+
+    //ap get_aggregation
+    /// Get the grouping to aggregate the results of a query with
+    pub fn get_aggregation(&self) -> Aggregation {
+        let aggregation = Aggregation::default().with_group_by(self.group_by.iter().copied());
+        if let Some(month) = self.year_start {
+            aggregation.with_year_start(month as u32)
+        } else {
+            aggregation
+        }
+    }
+
+    //ap get_currency
+    pub fn get_currency(&self) -> Currency {
+        self.currency.unwrap_or_default()
+    }
+
+    //ap get_exchange_rate
+    /// Get the exchange rate, if one was supplied, from one currency to another
+    pub fn get_exchange_rate(
+        &self,
+        from: Currency,
+        to: Currency,
+    ) -> Result<Option<ExchangeRate>, Error> {
+        let Some(rate) = &self.exchange_rate else {
+            return Ok(None);
+        };
+        Ok(Some(ExchangeRate::new(from, to, rate)?))
+    }
+
     //ap get_related_party
     pub fn get_related_party(&self) -> Result<DbRelatedParty, Error> {
         let Some(rp_id) = self.rp_id else {
@@ -531,12 +830,32 @@ impl CmdArgs {
         build.add_flag("clear", None, "Clear data first", CmdArgs::set_clear);
     }
 
+    //fp arg_add_dry_run
+    pub fn arg_add_dry_run(build: &mut CommandBuilder<Self>) {
+        build.add_flag(
+            "dry_run",
+            None,
+            "Show what would be imported, without changing the database",
+            CmdArgs::set_dry_run,
+        );
+    }
+
+    //fp arg_add_cascade
+    pub fn arg_add_cascade(build: &mut CommandBuilder<Self>) {
+        build.add_flag(
+            "cascade",
+            None,
+            "Also remove the items that depend on those removed",
+            CmdArgs::set_cascade,
+        );
+    }
+
     //fp arg_add_database
     pub fn arg_add_database(builder: &mut CommandBuilder<Self>) {
         builder.add_arg_string(
             "db",
             Some('d'),
-            "Database to read initially",
+            "Database to read initially (.json, .yaml or .sqlite)",
             false,
             None,
             Self::load_database,
@@ -579,11 +898,100 @@ impl CmdArgs {
         );
     }
 
+    //fp arg_add_option_new_name
+    pub fn arg_add_option_new_name(builder: &mut CommandBuilder<Self>) {
+        builder.add_arg_string(
+            "name",
+            None,
+            "New name (or invoice reason)",
+            false,
+            None,
+            Self::set_name,
+        );
+    }
+
+    //fp arg_add_option_new_desc
+    pub fn arg_add_option_new_desc(builder: &mut CommandBuilder<Self>) {
+        builder.add_arg_string(
+            "desc",
+            None,
+            "New description (account description, invoice filename, or transaction note)",
+            false,
+            None,
+            Self::set_desc,
+        );
+    }
+
     //fp arg_add_option_search_id
     pub fn arg_add_option_search_id(builder: &mut CommandBuilder<Self>) {
         builder.add_arg_usize("id", None, "Id to look for", false, None, Self::set_id);
     }
 
+    //fp arg_add_options_query_view
+    /// Add the options for sorting, paging and projecting the results
+    /// of a query
+    pub fn arg_add_options_query_view(builder: &mut CommandBuilder<Self>) {
+        builder.add_arg_string(
+            "sort",
+            None,
+            "Key to sort by: db_id/date/amount/name",
+            false,
+            None,
+            Self::set_sort_key,
+        );
+        builder.add_flag(
+            "descending",
+            None,
+            "Sort in descending order",
+            Self::set_descending,
+        );
+        builder.add_arg_usize(
+            "offset",
+            None,
+            "Number of results to skip",
+            false,
+            None,
+            Self::set_offset,
+        );
+        builder.add_arg_usize(
+            "limit",
+            None,
+            "Maximum number of results to return",
+            false,
+            None,
+            Self::set_limit,
+        );
+        builder.add_arg_string(
+            "fields",
+            None,
+            "Comma-separated fields of each result to return (default all)",
+            false,
+            None,
+            Self::set_fields,
+        );
+    }
+
+    //fp arg_add_options_aggregation
+    /// Add the options for aggregating the results of a query
+    pub fn arg_add_options_aggregation(builder: &mut CommandBuilder<Self>) {
+        builder.add_arg_string(
+            "group_by",
+            None,
+            "Comma-separated keys to group by: month/quarter/year/fund/rp/rp_type/ttype",
+            false,
+            None,
+            Self::set_group_by,
+        );
+        builder.add_arg_usize(
+            "year_start",
+            None,
+            "Month (1 to 12) that the financial year starts in (default 4)",
+            false,
+            None,
+            Self::set_year_start,
+        );
+    }
+
     //fp arg_add_option_postcode
     pub fn arg_add_option_postcode(builder: &mut CommandBuilder<Self>) {
         builder.add_arg_string(
@@ -726,6 +1134,54 @@ impl CmdArgs {
         builder.add_arg_string("amount", None, "Amount", required, None, Self::set_amount);
     }
 
+    //fp arg_add_option_opening_balance
+    pub fn arg_add_option_opening_balance(builder: &mut CommandBuilder<Self>) {
+        builder.add_arg_string(
+            "opening_balance",
+            None,
+            "Balance of the account before the first imported transaction",
+            false,
+            None,
+            Self::set_opening_balance,
+        );
+    }
+
+    //fp arg_add_option_mapping
+    pub fn arg_add_option_mapping(builder: &mut CommandBuilder<Self>) {
+        builder.add_arg_string(
+            "mapping",
+            None,
+            "YAML or JSON file mapping the CSV columns to transaction fields",
+            true,
+            None,
+            Self::set_mapping,
+        );
+    }
+
+    //fp arg_add_option_currency
+    pub fn arg_add_option_currency(builder: &mut CommandBuilder<Self>) {
+        builder.add_arg_string(
+            "currency",
+            None,
+            "Currency (ISO 4217 code such as GBP or EUR); defaults to GBP",
+            false,
+            None,
+            Self::set_currency,
+        );
+    }
+
+    //fp arg_add_option_exchange_rate
+    pub fn arg_add_option_exchange_rate(builder: &mut CommandBuilder<Self>) {
+        builder.add_arg_string(
+            "rate",
+            None,
+            "Exchange rate - units of the fund currency per unit of the transaction currency",
+            false,
+            None,
+            Self::set_exchange_rate,
+        );
+    }
+
     //fp arg_add_option_rp_type
     pub fn arg_add_option_rp_type(builder: &mut CommandBuilder<Self>, required: bool) {
         builder.add_arg_string(
@@ -906,7 +1362,7 @@ impl CmdArgs {
         builder.add_arg_string(
             "format",
             Some('f'),
-            "Format to write",
+            "Format to write (array or dict)",
             false,
             None,
             Self::set_file_format,
@@ -918,7 +1374,7 @@ impl CmdArgs {
         builder.add_arg_string(
             "output_filename",
             Some('w'),
-            "Output filename",
+            "Output filename (.json, .yaml or .sqlite, which is updated in place)",
             false,
             None,
             Self::set_write_filename,
@@ -1,14 +1,20 @@
 //a Imports
 use clap::Command;
 use thunderclap::json;
-use thunderclap::CommandBuilder;
+use thunderclap::{CommandArgs, CommandBuilder};
 
 use crate::CmdArgs;
-use rust_accounts::{DbQuery, Error};
+use rust_accounts::{AccountDesc, Database, DbItemType, DbQuery, Error};
+use rust_accounts::{FileType, Migration, SqliteStore, SCHEMA_VERSION};
 
 //a Query
-//mi query_fn
-fn query_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
+//fi build_query
+/// Build the query given by the arguments of a command
+///
+/// The text of the query (in the query language of [DbQuery::parse])
+/// may be given as a single argument, so that its quoting is as the
+/// user typed it; the options further restrict it
+fn build_query(cmd_args: &CmdArgs) -> Result<DbQuery, Error> {
     let mut query = DbQuery::default();
 
     if cmd_args.name.is_some() {
@@ -27,6 +33,36 @@ fn query_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
         query = query.with_desc(cmd_args.desc.as_ref().unwrap());
     }
     query = query.with_date_range(cmd_args.get_date_range());
+    if let Some(text) = cmd_args.string_args.first() {
+        if !text.trim().is_empty() {
+            query = query.with_all_of(DbQuery::parse(text, &cmd_args.db)?);
+        }
+    }
+    Ok(query)
+}
+
+//fi arg_add_query
+/// Add the arguments and options that build a query
+fn arg_add_query(cmd: &mut CommandBuilder<CmdArgs>) {
+    CmdArgs::arg_add_positional_string(
+        cmd,
+        "query",
+        "Query, as one (quoted) argument, such as 'type:bank_transaction date:2024-04..2025-03 desc~\"SUB WAY\" -fund:General'",
+        Some(0),
+        None,
+    );
+    CmdArgs::arg_add_option_search_name(cmd);
+    CmdArgs::arg_add_option_search_id(cmd);
+    CmdArgs::arg_add_option_search_desc(cmd);
+    CmdArgs::arg_add_option_rp_type(cmd, false);
+    CmdArgs::arg_add_option_item_type(cmd, false);
+    CmdArgs::arg_add_option_start_date(cmd);
+    CmdArgs::arg_add_option_end_date(cmd);
+}
+
+//mi query_fn
+fn query_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
+    let query = build_query(cmd_args)?;
 
     println!("{query}");
     let db_query: Vec<_> = cmd_args.db.query(query).collect();
@@ -45,13 +81,70 @@ pub fn query_cmd() -> CommandBuilder<CmdArgs> {
     let mut cmd =
         CommandBuilder::with_handler(Command::new("query").about("Query the database"), query_fn);
 
-    CmdArgs::arg_add_option_search_name(&mut cmd);
-    CmdArgs::arg_add_option_search_id(&mut cmd);
-    CmdArgs::arg_add_option_search_desc(&mut cmd);
-    CmdArgs::arg_add_option_rp_type(&mut cmd, false);
-    CmdArgs::arg_add_option_item_type(&mut cmd, false);
-    CmdArgs::arg_add_option_start_date(&mut cmd);
-    CmdArgs::arg_add_option_end_date(&mut cmd);
+    arg_add_query(&mut cmd);
+
+    cmd
+}
+
+//mi search_fn
+/// Query the database, returning a page of the sorted results with
+/// the total number that matched
+fn search_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
+    let query = build_query(cmd_args)?;
+    let result = cmd_args
+        .db
+        .query_result(query, &cmd_args.get_query_view())?;
+
+    if cmd_args.verbose {
+        for x in result.db_ids() {
+            println!("{} : {}", x, cmd_args.db.get(*x).unwrap());
+        }
+    }
+    println!(
+        "{} matching; returning {} from {}",
+        result.total(),
+        result.db_ids().len(),
+        result.offset()
+    );
+
+    Ok(json::to_value(result).unwrap())
+}
+
+//mp search_cmd
+pub fn search_cmd() -> CommandBuilder<CmdArgs> {
+    let mut cmd = CommandBuilder::with_handler(
+        Command::new("search")
+            .about("Query the database, returning a sorted page of the matching items"),
+        search_fn,
+    );
+
+    arg_add_query(&mut cmd);
+    CmdArgs::arg_add_options_query_view(&mut cmd);
+
+    cmd
+}
+
+//mi aggregate_fn
+/// Aggregate the transactions or bank transactions that match a
+/// query, showing the groups as a table
+fn aggregate_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
+    let query = build_query(cmd_args)?;
+    let result = cmd_args.db.aggregate(query, &cmd_args.get_aggregation())?;
+    print!("{result}");
+    Ok(json::to_value(result).unwrap())
+}
+
+//mp aggregate_cmd
+pub fn aggregate_cmd() -> CommandBuilder<CmdArgs> {
+    let mut cmd = CommandBuilder::with_handler(
+        Command::new("aggregate").about(
+            "Count and sum the amounts of the matching transactions or bank transactions, by group",
+        ),
+        aggregate_fn,
+    );
+
+    arg_add_query(&mut cmd);
+    CmdArgs::arg_add_options_aggregation(&mut cmd);
 
     cmd
 }
@@ -84,13 +177,307 @@ pub fn list_cmd() -> CommandBuilder<CmdArgs> {
     cmd
 }
 
+//mi remove_fn
+fn remove_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
+    let mut removed = vec![];
+    for db_id in cmd_args.db_id.iter() {
+        // It may have been removed already by the cascade
+        if removed.contains(db_id) {
+            continue;
+        }
+        removed.extend(cmd_args.db.remove(*db_id, cmd_args.cascade)?);
+    }
+    for db_id in removed.iter() {
+        println!("Removed {db_id}");
+    }
+    cmd_args.record_journal()?;
+    Ok(json::to_value(removed).unwrap())
+}
+
+//mp remove_cmd
+pub fn remove_cmd() -> CommandBuilder<CmdArgs> {
+    let mut cmd = CommandBuilder::with_handler(
+        Command::new("remove").about(
+            "Remove entries from the database; entries that others refer to are only removed with --cascade",
+        ),
+        remove_fn,
+    );
+
+    CmdArgs::arg_add_option_db_id(&mut cmd, true);
+    CmdArgs::arg_add_cascade(&mut cmd);
+
+    cmd
+}
+
+//mi update_fn
+fn update_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
+    let db = &cmd_args.db;
+    let name = cmd_args.name.clone();
+    let desc = cmd_args.desc.clone();
+    let date = (!cmd_args.start_date.is_none()).then_some(cmd_args.start_date);
+    let amount = (!cmd_args.amount.is_zero()).then_some(cmd_args.amount);
+    let rp_id = match cmd_args.rp_id {
+        Some(_) => Some(cmd_args.get_related_party()?.id()),
+        None => None,
+    };
+
+    for db_id in cmd_args.db_id.iter().copied() {
+        let Some(item) = db.get(db_id) else {
+            return Err(Error::UnknownItemId(db_id));
+        };
+        match item.itype() {
+            DbItemType::Account => {
+                let desc = desc.as_deref().map(str::parse::<AccountDesc>).transpose()?;
+                db.update_account(db_id, |a| {
+                    if let Some(name) = &name {
+                        a.set_name(name);
+                    }
+                    if let Some(desc) = desc {
+                        a.set_desc(desc);
+                    }
+                })?;
+            }
+            DbItemType::Fund => {
+                db.update_fund(db_id, |f| {
+                    if let Some(name) = &name {
+                        f.set_name(name);
+                    }
+                    if let Some(desc) = &desc {
+                        f.set_desc(desc);
+                    }
+                })?;
+            }
+            DbItemType::RelatedParty => {
+                db.update_related_party(db_id, |rp| {
+                    if let Some(name) = &name {
+                        rp.change_name(name);
+                    }
+                })?;
+            }
+            DbItemType::Invoice => {
+                db.update_invoice(db_id, |i| {
+                    if let Some(name) = &name {
+                        i.set_reason(name);
+                    }
+                    if let Some(desc) = &desc {
+                        i.set_filename(desc);
+                    }
+                    if let Some(amount) = amount {
+                        i.set_amount(amount);
+                    }
+                    if let Some(rp_id) = rp_id {
+                        i.set_supplier_id(rp_id);
+                    }
+                })?;
+            }
+            DbItemType::Transaction => {
+                db.update_transaction(db_id, |t| {
+                    if let Some(date) = date {
+                        t.set_date(date);
+                    }
+                    if let Some(amount) = amount {
+                        t.set_amount(amount);
+                    }
+                    if let Some(desc) = &desc {
+                        t.clear_notes();
+                        t.add_note(desc);
+                    }
+                })?;
+            }
+            DbItemType::BankTransaction => {
+                db.update_bank_transaction(db_id, |bt| {
+                    if let Some(date) = date {
+                        bt.set_date(date);
+                    }
+                    if let Some(desc) = &desc {
+                        bt.set_description(desc);
+                    }
+                    if let Some(rp_id) = rp_id {
+                        bt.set_related_party(rp_id);
+                    }
+                })?;
+            }
+        }
+        if cmd_args.verbose {
+            println!("{} : {}", db_id, db.get(db_id).unwrap());
+        }
+    }
+    cmd_args.record_journal()?;
+    Ok(json::to_value(&cmd_args.db_id).unwrap())
+}
+
+//mp update_cmd
+pub fn update_cmd() -> CommandBuilder<CmdArgs> {
+    let mut cmd = CommandBuilder::with_handler(
+        Command::new("update").about("Update entries in the database"),
+        update_fn,
+    );
+
+    CmdArgs::arg_add_option_db_id(&mut cmd, true);
+    CmdArgs::arg_add_option_new_name(&mut cmd);
+    CmdArgs::arg_add_option_new_desc(&mut cmd);
+    CmdArgs::arg_add_option_date(&mut cmd, false);
+    CmdArgs::arg_add_option_amount(&mut cmd, false);
+    CmdArgs::arg_add_option_related_party(&mut cmd, false);
+
+    cmd
+}
+
+//a Compact
+//mi compact_fn
+fn compact_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
+    cmd_args.compact_database()?;
+    CmdArgs::cmd_ok()
+}
+
+//mp compact_cmd
+pub fn compact_cmd() -> CommandBuilder<CmdArgs> {
+    let mut cmd = CommandBuilder::with_handler(
+        Command::new("compact")
+            .about("Write the database to the file it was loaded from, folding in its journal"),
+        compact_fn,
+    );
+
+    CmdArgs::arg_add_file_format(&mut cmd);
+
+    cmd
+}
+
+//a Check
+//mi check_fn
+fn check_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
+    let problems = cmd_args.db.check_integrity();
+    for p in problems.iter() {
+        println!("{p}");
+    }
+    if problems.is_empty() {
+        println!("No problems found");
+    }
+    Ok(json::to_value(problems).unwrap())
+}
+
+//mp check_cmd
+pub fn check_cmd() -> CommandBuilder<CmdArgs> {
+    CommandBuilder::with_handler(
+        Command::new("check")
+            .about("Check the references between entries in the database, suggesting fixes"),
+        check_fn,
+    )
+}
+
+//a Schema
+//mi schema_fn
+fn schema_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
+    let filename = {
+        if let Some(filename) = cmd_args.string_args.first() {
+            filename.clone()
+        } else if !cmd_args.db_filename.is_empty() {
+            cmd_args.db_filename.clone()
+        } else {
+            return Err("No database file given".to_string().into());
+        }
+    };
+    let (version, format) = match FileType::from_filename(&filename)? {
+        FileType::Sqlite => (SqliteStore::open(&filename)?.schema_version()?, None),
+        FileType::Json => {
+            let s = std::fs::read_to_string(&filename)?;
+            let mut deserializer = serde_json::Deserializer::from_str(&s);
+            let (version, format) = Database::file_schema(&mut deserializer)?;
+            (version, Some(format))
+        }
+        FileType::Yaml => {
+            let s = std::fs::read_to_string(&filename)?;
+            let deserializer = serde_yaml::Deserializer::from_str(&s);
+            let (version, format) = Database::file_schema(deserializer)?;
+            (version, Some(format))
+        }
+        ftype => return Err(Error::FileTypeNotSupported(ftype, "database")),
+    };
+
+    match format {
+        Some(format) => println!("{filename}: schema version {version} ({format} layout)"),
+        None => println!("{filename}: schema version {version}"),
+    }
+    if version < SCHEMA_VERSION {
+        println!("Loading upgrades it to schema version {SCHEMA_VERSION} with:");
+        for m in Migration::all()
+            .iter()
+            .filter(|m| m.from_version() >= version)
+        {
+            println!("  {m}");
+        }
+    } else if version > SCHEMA_VERSION {
+        println!("This is newer than schema version {SCHEMA_VERSION}, so it cannot be loaded");
+    }
+    Ok(serde_json::json!({
+        "filename": filename,
+        "schema_version": version,
+        "format": format.map(|f| f.to_string()),
+        "current_version": SCHEMA_VERSION,
+    }))
+}
+
+//mp schema_cmd
+pub fn schema_cmd() -> CommandBuilder<CmdArgs> {
+    let mut cmd = CommandBuilder::with_handler(
+        Command::new("schema").about(
+            "Report the schema version of a database file (by default that loaded), and how it is upgraded when loaded",
+        ),
+        schema_fn,
+    );
+
+    CmdArgs::arg_add_positional_string(&mut cmd, "file", "Database file", Some(0), None);
+
+    cmd
+}
+
+//a Merge
+//mi merge_fn
+fn merge_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
+    let (other, _) = CmdArgs::read_database(&cmd_args.string_args[0])?;
+    let report = cmd_args.db.merge(&other)?;
+    println!("Merged {}: {report}", cmd_args.string_args[0]);
+    if cmd_args.verbose {
+        for db_id in report.added() {
+            println!("Added {} : {}", db_id, cmd_args.db.get(*db_id).unwrap());
+        }
+    }
+    for c in report.conflicts() {
+        println!("Conflict {c}");
+    }
+    cmd_args.record_journal()?;
+    Ok(json::to_value(report).unwrap())
+}
+
+//mp merge_cmd
+pub fn merge_cmd() -> CommandBuilder<CmdArgs> {
+    let mut cmd = CommandBuilder::with_handler(
+        Command::new("merge").about(
+            "Merge another database file into the database; conflicting entries keep their values, for updating by hand",
+        ),
+        merge_fn,
+    );
+
+    CmdArgs::arg_add_positional_string(&mut cmd, "file", "Database file to merge", Some(1), None);
+
+    cmd
+}
+
 //mp database_cmd
 pub fn database_cmd() -> CommandBuilder<CmdArgs> {
     let mut cmd =
         CommandBuilder::new(Command::new("database").about("Operate on the whole database"));
 
     cmd.add_subcommand(query_cmd());
+    cmd.add_subcommand(search_cmd());
+    cmd.add_subcommand(aggregate_cmd());
     cmd.add_subcommand(list_cmd());
+    cmd.add_subcommand(remove_cmd());
+    cmd.add_subcommand(update_cmd());
+    cmd.add_subcommand(compact_cmd());
+    cmd.add_subcommand(check_cmd());
+    cmd.add_subcommand(schema_cmd());
+    cmd.add_subcommand(merge_cmd());
 
     cmd
 }
@@ -51,16 +51,39 @@ fn add_cmd() -> CommandBuilder<CmdArgs> {
     let mut add = CommandBuilder::with_handler(Command::new("add").about("Add a fund"), add_fn);
     CmdArgs::arg_add_positional_string(&mut add, "name", "Fund name", Some(1), None);
     CmdArgs::arg_add_positional_string(&mut add, "description", "Description", Some(1), None);
+    CmdArgs::arg_add_option_currency(&mut add);
     add
 }
 
+//mi balance_cmd
+fn balance_cmd() -> CommandBuilder<CmdArgs> {
+    let mut cmd = CommandBuilder::with_handler(
+        Command::new("balance").about("Show the balance of a fund in a base currency"),
+        balance_fn,
+    );
+    CmdArgs::arg_add_fund_positional(&mut cmd);
+    CmdArgs::arg_add_option_currency(&mut cmd);
+    cmd
+}
+
+//fi balance_fn
+fn balance_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
+    let name = cmd_args.next_string_arg()?;
+    let db_f = cmd_args.get_fund(&name)?;
+    let base = cmd_args.currency.unwrap_or(db_f.inner().currency());
+    let balance = db_f.inner().balance_in(&cmd_args.db, db_f.id(), base)?;
+    println!("Fund {} : {balance}", db_f.inner().name());
+    Ok(json::to_value(balance).unwrap())
+}
+
 //fi add_fn
 fn add_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
     let name = cmd_args.next_string_arg()?;
     let description = cmd_args.next_string_arg()?;
 
-    let fund = Fund::new(&name, &description);
+    let fund = Fund::new(&name, &description).with_currency(cmd_args.get_currency());
     let db_id = cmd_args.db.add_fund(fund);
+    cmd_args.record_journal()?;
     Ok(json::to_value(db_id).unwrap())
 }
 
@@ -92,6 +115,7 @@ fn add_alias_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
         db_m.inner_mut().add_alias(a);
     }
     cmd_args.db.funds().add_fund_aliases(&db_m);
+    cmd_args.record_journal()?;
     Ok(json::to_value(db_id).unwrap())
 }
 
@@ -103,6 +127,7 @@ pub fn funds_cmd() -> CommandBuilder<CmdArgs> {
 
     build.add_subcommand(list_cmd());
     build.add_subcommand(show_cmd());
+    build.add_subcommand(balance_cmd());
     build.add_subcommand(add_cmd());
     build.add_subcommand(add_alias_cmd());
 
@@ -50,6 +50,7 @@ fn add_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
         amount,
     );
     let db_id = cmd_args.db.add_invoice(invoice);
+    cmd_args.record_journal()?;
     Ok(json::to_value(db_id).unwrap())
 }
 
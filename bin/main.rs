@@ -3,12 +3,28 @@
 ///
 /// database
 ///
-///  query - returns an array of DbId
+///  query - returns an array of DbId matching a query such as "type:transaction date:2024-04..2025-03 amount>100 -fund:General"
+///
+///  search - query the database, returning a page of the matching items sorted by date, amount, name or DbId with only the selected fields, and the total number matching
+///
+///  aggregate - count, sum, min and max of the amounts of matching transactions, grouped by month, quarter, financial year, fund, related party, related party type or transaction type
 ///
 ///  list - shows a list DbID
 ///
 ///  get - get a list of database entries
 ///
+///  remove - remove entries, optionally with those that depend on them
+///
+///  update - update the name, description, date, amount or related party of entries
+///
+///  compact - write the database to the file it was loaded from, folding its journal into it
+///
+///  check - check the references between entries, reporting problems and suggested fixes
+///
+///  schema - report the schema version of a database file, and the migrations applied when it is loaded
+///
+///  merge - merge another database file, matching entries by their natural keys and reporting conflicts
+///
 /// accounts
 ///
 ///  add - add a new account
@@ -41,6 +57,10 @@
 ///
 /// write
 ///
+/// undo - undo the most recent operations (since the database was last written)
+///
+/// redo - redo the most recently undone operations
+///
 use clap::Command;
 use thunderclap::CommandBuilder;
 
@@ -55,6 +75,7 @@ mod invoices;
 mod members;
 mod related_parties;
 mod transactions;
+mod undo;
 mod write;
 
 pub fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -75,11 +96,15 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
     build.add_subcommand(related_parties::related_parties_cmd());
     build.add_subcommand(transactions::transactions_cmd());
     build.add_subcommand(write::write_cmd());
+    build.add_subcommand(undo::undo_cmd());
+    build.add_subcommand(undo::redo_cmd());
     build.add_subcommand(database::database_cmd());
 
     let mut cmd_args = CmdArgs::default();
     let mut command_set = thunderclap_httpd::CommandSetHttpd::build(build, true, true);
-    command_set.execute_env(&mut cmd_args)?;
+    let result = command_set.execute_env(&mut cmd_args);
+    cmd_args.record_journal()?;
+    result?;
     // thunderclap_httpd::execute_env(&mut command, &mut cmd_args)?;
     // command.execute_env(&mut cmd_args)?;
     Ok(())
@@ -28,6 +28,7 @@ fn add_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
 
     let member = RelatedParty::new(name, member_id, RelatedPartyType::Member);
     let db_id = cmd_args.db.add_related_party(member);
+    cmd_args.record_journal()?;
     Ok(json::to_value(db_id).unwrap())
 }
 
@@ -51,6 +52,7 @@ fn add_alias_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
         .db
         .related_parties()
         .add_related_party_aliases(&db_m);
+    cmd_args.record_journal()?;
     CmdArgs::cmd_ok()
 }
 
@@ -66,6 +68,7 @@ fn add_account_descr_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
     for i in 1..cmd_args.string_args.len() {
         db_m.inner_mut().add_account_descr(&cmd_args.string_args[i]);
     }
+    cmd_args.record_journal()?;
     CmdArgs::cmd_ok()
 }
 
@@ -96,6 +99,7 @@ fn change_address_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
     if let Some(s) = cmd_args.tax_name.as_ref() {
         db_m.inner_mut().change_tax_name(s);
     }
+    cmd_args.record_journal()?;
     CmdArgs::cmd_ok()
 }
 
@@ -39,6 +39,8 @@ fn add_payment_cmd() -> CommandBuilder<CmdArgs> {
         add_payment_fn,
     );
     CmdArgs::arg_add_option_amount(&mut cmd, true);
+    CmdArgs::arg_add_option_currency(&mut cmd);
+    CmdArgs::arg_add_option_exchange_rate(&mut cmd);
     CmdArgs::arg_add_option_date(&mut cmd, true);
     CmdArgs::arg_add_fund_positional(&mut cmd);
     CmdArgs::arg_add_related_party_positional(&mut cmd);
@@ -56,11 +58,18 @@ fn add_payment_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
     let to_rp = cmd_args.get_related_party_by_name(&cmd_args.string_args[1])?;
     let to_id = to_rp.id();
 
-    let mut transaction = Transaction::new_payment(date, amount, from_fund_id, to_id);
+    let currency = cmd_args.get_currency();
+    let mut transaction =
+        Transaction::new_payment(date, amount, from_fund_id, to_id).with_currency(currency);
+    if let Some(rate) = cmd_args.get_exchange_rate(currency, from_fund.inner().currency())? {
+        transaction = transaction.with_exchange_rate(rate);
+    }
+    transaction.check_currencies(&cmd_args.db)?;
     for n in cmd_args.string_args.iter().skip(2) {
         transaction.add_note(n);
     }
-    let (db_id, okay) = cmd_args.db.add_transaction(transaction);
+    let (db_id, okay) = cmd_args.db.add_transaction(transaction)?;
+    cmd_args.record_journal()?;
     if !okay {
         Err(format!("Added transaction {db_id} but database is not consistent").into())
     } else {
@@ -100,7 +109,8 @@ fn add_income_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
     for n in notes.iter() {
         transaction.add_note(n);
     }
-    let (db_id, okay) = cmd_args.db.add_transaction(transaction);
+    let (db_id, okay) = cmd_args.db.add_transaction(transaction)?;
+    cmd_args.record_journal()?;
     if !okay {
         Err(format!("Added transaction {db_id} but database is not consistent").into())
     } else {
@@ -147,11 +157,12 @@ fn add_income_bt_db_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
         for n in notes.iter() {
             transaction.add_note(n);
         }
-        let (db_id, okay) = cmd_args.db.add_transaction(transaction);
+        let (db_id, okay) = cmd_args.db.add_transaction(transaction)?;
         if !okay {
             return Err(format!("Added transaction {db_id} but database is not consistent").into());
         }
     }
+    cmd_args.record_journal()?;
     Ok(json::to_value(db_ids).unwrap())
 }
 
@@ -0,0 +1,61 @@
+//a Imports
+use clap::Command;
+use thunderclap::json;
+use thunderclap::CommandBuilder;
+
+use crate::CmdArgs;
+use rust_accounts::Error;
+
+//a Undo
+//fi undo_fn
+fn undo_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
+    let count = cmd_args.usize_args[0];
+    let undone = cmd_args.undo(count)?;
+    if cmd_args.verbose {
+        println!("Undid {undone} of {count} operations");
+    }
+    Ok(json::to_value(undone).unwrap())
+}
+
+//fi redo_fn
+fn redo_fn(cmd_args: &mut CmdArgs) -> Result<json::Value, Error> {
+    let count = cmd_args.usize_args[0];
+    let redone = cmd_args.redo(count)?;
+    if cmd_args.verbose {
+        println!("Redid {redone} of {count} operations");
+    }
+    Ok(json::to_value(redone).unwrap())
+}
+
+//a Commands
+//fp undo_cmd
+pub fn undo_cmd() -> CommandBuilder<CmdArgs> {
+    let command = Command::new("undo").about(
+        "Undo the most recent operations on the database since it was last written to its file",
+    );
+
+    let mut build = CommandBuilder::with_handler(command, undo_fn);
+    CmdArgs::arg_add_option_usize(
+        &mut build,
+        "count",
+        Some('n'),
+        "Number of operations to undo",
+        Some("1"),
+    );
+    build
+}
+
+//fp redo_cmd
+pub fn redo_cmd() -> CommandBuilder<CmdArgs> {
+    let command = Command::new("redo").about("Redo the most recently undone operations");
+
+    let mut build = CommandBuilder::with_handler(command, redo_fn);
+    CmdArgs::arg_add_option_usize(
+        &mut build,
+        "count",
+        Some('n'),
+        "Number of operations to redo",
+        Some("1"),
+    );
+    build
+}
@@ -1,15 +1,27 @@
 //a Imports
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use serde::{Deserialize, Serialize, Serializer};
 
 use crate::indexed_vec::Idx;
 use crate::{
-    AccountDesc, Amount, BankTransaction, Database, DatabaseRebuild, Date, DateRange, DbId, Error,
-    OrderedTransactions,
+    AccountDesc, Amount, BankTransaction, Currency, Database, DatabaseRebuild, Date, DateRange,
+    DbId, Error, ImportOutcome, ImportPreview, ImportReport, OrderedTransactions,
+    RelatedPartyMatch,
 };
 
+//a Internal functions
+//fi normalize_description
+/// Normalize a description for comparison, as different exports of
+/// the same transaction may differ in case and whitespace
+fn normalize_description(s: &str) -> String {
+    s.split_whitespace()
+        .map(|w| w.to_lowercase())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
 //a Account
 //tp AccountSummaryOwned
 /// The account for delivery as a summary, without all the transactions
@@ -18,6 +30,7 @@ pub struct AccountSummaryOwned {
     org: String,
     name: String,
     desc: AccountDesc,
+    currency: Currency,
     num_transactions: usize,
 }
 
@@ -29,6 +42,7 @@ impl AccountSummaryOwned {
             org: &self.org,
             name: &self.name,
             desc: &self.desc,
+            currency: self.currency,
             num_transactions: self.num_transactions,
         }
     }
@@ -48,6 +62,7 @@ pub struct AccountSummary<'a> {
     org: &'a str,
     name: &'a str,
     desc: &'a AccountDesc,
+    currency: Currency,
     num_transactions: usize,
 }
 
@@ -56,8 +71,8 @@ impl<'a> std::fmt::Display for AccountSummary<'a> {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
         write!(
             fmt,
-            "Account '{}' with '{}' : {} : with {} transactions",
-            self.name, self.org, self.desc, self.num_transactions
+            "Account '{}' with '{}' : {} : {} : with {} transactions",
+            self.name, self.org, self.desc, self.currency, self.num_transactions
         )
     }
 }
@@ -69,6 +84,7 @@ impl<'a> AccountSummary<'a> {
             org: self.org.to_owned(),
             name: self.name.to_owned(),
             desc: self.desc.clone(),
+            currency: self.currency,
             num_transactions: self.num_transactions,
         }
     }
@@ -87,6 +103,9 @@ pub struct Account {
     org: String,
     name: String,
     desc: AccountDesc,
+    /// Currency of the account; all its bank transactions are in this currency
+    #[serde(default)]
+    currency: Currency,
     /// Bank transactions
     ///
     bank_transactions: OrderedTransactions<DbId>,
@@ -108,16 +127,24 @@ impl Account {
             org,
             name,
             desc,
+            currency: Currency::default(),
             bank_transactions,
         }
     }
 
+    //cp with_currency
+    pub fn with_currency(mut self, currency: Currency) -> Self {
+        self.currency = currency;
+        self
+    }
+
     //ap summary
     pub fn summary<'a>(&'a self) -> AccountSummary<'a> {
         AccountSummary {
             org: &self.org,
             name: &self.name,
             desc: &self.desc,
+            currency: self.currency,
             num_transactions: self.bank_transactions.len(),
         }
     }
@@ -132,6 +159,33 @@ impl Account {
         &self.name
     }
 
+    //ap desc
+    pub fn desc(&self) -> &AccountDesc {
+        &self.desc
+    }
+
+    //ap currency
+    pub fn currency(&self) -> Currency {
+        self.currency
+    }
+
+    //mp set_org
+    pub fn set_org<I: Into<String>>(&mut self, i: I) {
+        self.org = i.into();
+    }
+
+    //mp set_name
+    pub fn set_name<I: Into<String>>(&mut self, i: I) {
+        self.name = i.into();
+    }
+
+    //mp set_desc
+    /// Change the account description; the database must reindex the
+    /// account, so use [Database::update_account]
+    pub fn set_desc(&mut self, desc: AccountDesc) {
+        self.desc = desc;
+    }
+
     //mp get_bank_transaction
     /// Get a bank transaction that matches on the date with the given related party
     ///
@@ -217,16 +271,18 @@ impl Account {
     }
 
     //mp add_bank_transaction
-    /// Add transaction unless it is a duplicate
+    /// Add a transaction to the end of its date; this does not check
+    /// for duplicates, which [Self::add_transactions] does
+    ///
+    /// Return the DbId of the added transaction; a rejected
+    /// transaction is handed back to the caller
+    #[allow(clippy::result_large_err)]
     pub fn add_bank_transaction(
         &mut self,
         db: &Database,
         account_id: DbId,
         mut bt: BankTransaction,
-    ) -> Result<(), BankTransaction> {
-        // if db.bank_transactions().has_transaction(bt.description()) {
-        // return Err(bt);
-        // }
+    ) -> Result<DbId, BankTransaction> {
         if bt.related_party().is_none() {
             bt.set_related_party(db.find_account_related_party(bt.description()));
         }
@@ -245,44 +301,311 @@ impl Account {
             );
         }
         self.bank_transactions.push_to_date(date, db_id);
-        Ok(())
+        Ok(db_id)
+    }
+
+    //mp link_bank_transaction
+    /// Add a bank transaction that is already in the database to the
+    /// end of its date in the account
+    pub fn link_bank_transaction(&mut self, date: Date, db_id: DbId) {
+        self.bank_transactions.push_to_date(date, db_id);
+    }
+
+    //mp remove_bank_transaction
+    /// Remove a bank transaction from the account, returning true if
+    /// it was present
+    pub fn remove_bank_transaction(&mut self, db_id: DbId) -> bool {
+        self.bank_transactions.remove(db_id)
+    }
+
+    //mp move_bank_transaction
+    /// Move a bank transaction of the account to the end of a
+    /// (possibly different) date
+    pub fn move_bank_transaction(&mut self, db_id: DbId, date: Date) -> bool {
+        if !self.bank_transactions.remove(db_id) {
+            return false;
+        }
+        self.bank_transactions.push_to_date(date, db_id);
+        true
+    }
+
+    //mp known_fitids
+    /// Get the set of FITIDs of the bank transactions in the account
+    pub fn known_fitids(&self, db: &Database) -> HashSet<String> {
+        let mut result = HashSet::new();
+        for c in self.bank_transactions.iter() {
+            let Some(db_bt) = db.get_bank_transaction(self.bank_transactions[c]) else {
+                continue;
+            };
+            let bt = db_bt.inner();
+            if let Some(fitid) = bt.fitid() {
+                result.insert(fitid.to_string());
+            }
+        }
+        result
+    }
+
+    //mp find_duplicate
+    /// Find a bank transaction in the account that is the same as
+    /// `bt`, with the same date, amounts, balance and description, and
+    /// that has not already been matched
+    pub fn find_duplicate(
+        &self,
+        db: &Database,
+        bt: &BankTransaction,
+        matched: &HashSet<DbId>,
+    ) -> Option<DbId> {
+        let description = normalize_description(bt.description());
+        for db_id in self.bank_transactions.of_date(bt.date())? {
+            if matched.contains(db_id) {
+                continue;
+            }
+            let Some(db_bt) = db.get_bank_transaction(*db_id) else {
+                continue;
+            };
+            let existing = db_bt.inner();
+            if existing.debit() == bt.debit()
+                && existing.credit() == bt.credit()
+                && existing.balance() == bt.balance()
+                && normalize_description(existing.description()) == description
+            {
+                return Some(*db_id);
+            }
+        }
+        None
+    }
+
+    //mp balance_at_end_of
+    /// Get the date and balance of the account after the last bank
+    /// transaction on or before a date, if there is one
+    pub fn balance_at_end_of(&self, db: &Database, date: Date) -> Option<(Date, Amount)> {
+        let (c, accurate) = self
+            .bank_transactions
+            .cursor_of_date(date, !self.bank_transactions.contains_date(date));
+        if !accurate {
+            return None;
+        }
+        let db_id = self.bank_transactions.cursor_id(&c)?;
+        let c_date = self.bank_transactions.cursor_date(&c)?;
+        if c_date > date {
+            return None;
+        }
+        db.get_bank_transaction(db_id)
+            .map(|db_bt| (c_date, db_bt.inner().balance()))
+    }
+
+    //mp opening_balance_after
+    /// Get the date and opening balance (the balance before it) of
+    /// the first bank transaction after a date, if there is one
+    pub fn opening_balance_after(&self, db: &Database, date: Date) -> Option<(Date, Amount)> {
+        let (mut c, _) = self.bank_transactions.cursor_of_date(date, false);
+        let mut c_date = self.bank_transactions.cursor_date(&c)?;
+        while c_date <= date {
+            if !self.bank_transactions.cursor_next(&mut c) {
+                return None;
+            }
+            c_date = self.bank_transactions.cursor_date(&c)?;
+        }
+        let db_id = self.bank_transactions.cursor_id(&c)?;
+        db.get_bank_transaction(db_id).map(|db_bt| {
+            let bt = db_bt.inner();
+            (c_date, bt.balance() - bt.balance_delta())
+        })
+    }
+
+    //mp plan_transactions
+    /// Decide the outcome of adding each of a Vec of transactions to
+    /// the account, without changing the account or the database
+    ///
+    /// Transactions that are already in the account (with a FITID
+    /// that is already in the account, or with the same date, amount,
+    /// description and balance as one already there) are skipped, as
+    /// they have been imported before; this permits statements that
+    /// overlap to be imported
+    ///
+    /// A transaction whose balance does not follow on from the
+    /// balance of the account at the point it would be added (the end
+    /// of its date), or which the transaction after that point does
+    /// not follow on from, is a conflict; the others are new
+    ///
+    /// The balance of the account after the new transactions were
+    /// added is also returned
+    pub fn plan_transactions(
+        &self,
+        db: &Database,
+        bank_transactions: &[BankTransaction],
+    ) -> (Vec<ImportOutcome>, Option<Amount>) {
+        let mut outcomes = vec![];
+        let mut fitids = self.known_fitids(db);
+        let mut matched = HashSet::new();
+        // Date, balance and opening balance of each transaction that
+        // would be added
+        let mut pending: Vec<(Date, Amount, Amount)> = vec![];
+        let balance_at = |pending: &[(Date, Amount, Amount)], date: Date| {
+            let existing = self.balance_at_end_of(db, date);
+            let added = pending.iter().rev().find(|(d, _, _)| *d <= date);
+            match (existing, added) {
+                (Some(e), Some(a)) if e.0 > a.0 => Some(e.1),
+                (_, Some(a)) => Some(a.1),
+                (e, None) => e.map(|e| e.1),
+            }
+        };
+        // The opening balance of the transaction after the end of a
+        // date; an existing one comes before one added on the same date
+        let opening_after = |pending: &[(Date, Amount, Amount)], date: Date| {
+            let existing = self.opening_balance_after(db, date);
+            let added = pending.iter().find(|(d, _, _)| *d > date);
+            match (existing, added) {
+                (Some(e), Some(a)) if e.0 > a.0 => Some(a.2),
+                (Some(e), _) => Some(e.1),
+                (None, a) => a.map(|a| a.2),
+            }
+        };
+        for t in bank_transactions.iter() {
+            if let Some(fitid) = t.fitid() {
+                if !fitids.insert(fitid.to_string()) {
+                    let db_id = self.find_duplicate(db, t, &matched);
+                    if let Some(db_id) = db_id {
+                        matched.insert(db_id);
+                    }
+                    outcomes.push(ImportOutcome::Skipped(db_id.unwrap_or_else(DbId::none)));
+                    continue;
+                }
+            }
+            if let Some(db_id) = self.find_duplicate(db, t, &matched) {
+                matched.insert(db_id);
+                outcomes.push(ImportOutcome::Skipped(db_id));
+                continue;
+            }
+            if let Some(balance) = balance_at(&pending, t.date()) {
+                let opening = t.balance() - t.balance_delta();
+                if balance != opening {
+                    outcomes.push(ImportOutcome::Conflict(format!(
+                        "{} '{}' follows a balance of {} but the account has {} at that point",
+                        t.date(),
+                        t.description(),
+                        opening.to_string().trim(),
+                        balance.to_string().trim(),
+                    )));
+                    continue;
+                }
+            }
+            if let Some(next_opening) = opening_after(&pending, t.date()) {
+                if next_opening != t.balance() {
+                    outcomes.push(ImportOutcome::Conflict(format!(
+                        "{} '{}' leaves a balance of {} but the next transaction in the account follows a balance of {}",
+                        t.date(),
+                        t.description(),
+                        t.balance().to_string().trim(),
+                        next_opening.to_string().trim(),
+                    )));
+                    continue;
+                }
+            }
+            // Keep pending in date order, after any of the same date
+            let n = pending.partition_point(|(d, _, _)| *d <= t.date());
+            pending.insert(n, (t.date(), t.balance(), t.balance() - t.balance_delta()));
+            outcomes.push(ImportOutcome::New);
+        }
+        let last_date = {
+            let last_existing = self
+                .bank_transactions
+                .iter()
+                .last()
+                .and_then(|c| self.bank_transactions.cursor_date(&c));
+            let last_pending = pending.last().map(|(d, _, _)| *d);
+            last_existing.max(last_pending)
+        };
+        let closing_balance = last_date.and_then(|d| balance_at(&pending, d));
+        (outcomes, closing_balance)
+    }
+
+    //mp preview_transactions
+    /// Preview adding a Vec of transactions to the account, without
+    /// changing the account or the database
+    ///
+    /// This provides the outcome for each transaction (as
+    /// [Self::plan_transactions]), the related party it would be
+    /// matched to, and the closing balance of the account
+    pub fn preview_transactions(
+        &self,
+        db: &Database,
+        bank_transactions: &[BankTransaction],
+    ) -> Result<ImportPreview, Error> {
+        for t in bank_transactions.iter() {
+            if t.account_desc() != &self.desc {
+                return Err(Error::TransactionLog(format!(
+                    "transaction for account {} cannot be added to account {}",
+                    t.account_desc(),
+                    self.desc
+                )));
+            }
+        }
+        let (outcomes, closing_balance) = self.plan_transactions(db, bank_transactions);
+        let mut preview = ImportPreview::new(closing_balance);
+        for (t, outcome) in bank_transactions.iter().zip(outcomes) {
+            let related_party = {
+                if t.related_party().is_none() {
+                    db.match_account_related_party(t.description())
+                } else {
+                    RelatedPartyMatch::Found(t.related_party())
+                }
+            };
+            preview.push(t, outcome, related_party, db);
+        }
+        Ok(preview)
     }
 
     //mp add_transactions
     /// Add a Vec of transactions to the account
     ///
     /// Any transactions for the same date should be in the correct
-    /// order, and should be able to be appended to those from the
-    /// same date already in the account
+    /// order, and are appended to those from the same date already
+    /// in the account
     ///
-    /// Return a Vec for the transactions *not* added (in the same
-    /// order that they arrived)
+    /// Only those transactions that [Self::plan_transactions] finds
+    /// are new are added; duplicates are skipped, and conflicts are
+    /// not added
+    ///
+    /// Return a report of the outcome for each transaction (in the
+    /// same order that they arrived); or if the transactions are not
+    /// for this account then they are handed back
     pub fn add_transactions(
         &mut self,
         db: &Database,
         account_id: DbId,
         bank_transactions: Vec<BankTransaction>,
-    ) -> Result<(), Vec<BankTransaction>> {
+    ) -> Result<ImportReport, Vec<BankTransaction>> {
+        let mut report = ImportReport::default();
         if bank_transactions.is_empty() {
-            return Ok(());
+            return Ok(report);
         }
         for t in bank_transactions.iter() {
             if t.account_desc() != &self.desc {
                 return Err(bank_transactions);
             }
         }
-        let mut errors = vec![];
-        for t in bank_transactions.into_iter() {
-            if let Err(e) = self.add_bank_transaction(db, account_id, t) {
-                errors.push(e);
+        let (outcomes, _) = self.plan_transactions(db, &bank_transactions);
+        for (t, outcome) in bank_transactions.into_iter().zip(outcomes) {
+            if outcome != ImportOutcome::New {
+                report.push(outcome);
+                continue;
+            }
+            match self.add_bank_transaction(db, account_id, t) {
+                Ok(db_id) => {
+                    report.push(ImportOutcome::Added(db_id));
+                }
+                Err(t) => {
+                    report.push(ImportOutcome::Conflict(format!(
+                        "{} '{}' could not be added",
+                        t.date(),
+                        t.description()
+                    )));
+                }
             }
         }
         self.bank_transactions.sort();
-        if errors.is_empty() {
-            Ok(())
-        } else {
-            Err(errors)
-        }
+        Ok(report)
     }
 
     //mp rebuild
@@ -369,7 +692,37 @@ impl DbAccounts {
         state.array.push(db_account.clone());
         state
             .map
-            .insert(db_account.inner().desc, db_account.clone());
+            .insert(db_account.inner().desc.clone(), db_account.clone());
+        true
+    }
+
+    //mp remove_account
+    /// Remove an account, returning true if it was present
+    pub fn remove_account(&self, db_id: DbId) -> bool {
+        let mut state = self.state.borrow_mut();
+        let Some(n) = state.array.iter().position(|a| a.id() == db_id) else {
+            return false;
+        };
+        state.array.remove(n);
+        state.map.retain(|_, a| a.id() != db_id);
+        true
+    }
+
+    //mp reindex_account
+    /// Reindex an account after its description has changed from
+    /// `old_desc`; this fails if another account has the new
+    /// description
+    pub fn reindex_account(&self, db_account: &DbAccount, old_desc: &AccountDesc) -> bool {
+        let desc = db_account.inner().desc.clone();
+        if desc == *old_desc {
+            return true;
+        }
+        if self.has_account(&desc) {
+            return false;
+        }
+        let mut state = self.state.borrow_mut();
+        state.map.remove(old_desc);
+        state.map.insert(desc, db_account.clone());
         true
     }
 
@@ -5,7 +5,25 @@ use serde::{Deserialize, Serialize};
 
 //a AccountDesc
 //tp AccountDesc
-#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, Hash, PartialEq, Eq)]
+/// A description of an account sufficient to identify it uniquely;
+/// this is used as the key of the accounts in the database
+///
+/// This is displayed (and parsed) as one of:
+///
+/// * `<None>`
+///
+/// * `xx-yy-zz:nnnnnnnn` for a UK sort code and account number
+///
+/// * `iban:GB82WEST12345698765432` for an IBAN
+///
+/// * `bic:NWBKGB2L:account` for a BIC with an account identifier
+///
+/// * `card:************1234` for a card with a masked card number
+///
+/// * `wallet:paypal:someone@example.com` for an online wallet
+///
+/// * `cash:name` for an internal cash box
+#[derive(Debug, Default, Clone, Serialize, Deserialize, Hash, PartialEq, Eq)]
 pub enum AccountDesc {
     #[default]
     None,
@@ -13,6 +31,30 @@ pub enum AccountDesc {
         sort_code: u32,
         account: usize,
     },
+    /// An IBAN, held in upper case without spaces
+    Iban {
+        iban: String,
+    },
+    /// A BIC (held in upper case) with the account identifier at that bank
+    Bic {
+        bic: String,
+        account: String,
+    },
+    /// A credit or debit card, with all but (at most) the first six
+    /// and last four digits of the card number masked as '*'
+    Card {
+        masked_pan: String,
+    },
+    /// An online wallet such as PayPal; the provider is lower case,
+    /// and the account is an email address or merchant id
+    Wallet {
+        provider: String,
+        account: String,
+    },
+    /// An internal cash box
+    CashBox {
+        name: String,
+    },
 }
 
 //ip Display for AccountDesc
@@ -25,6 +67,21 @@ impl std::fmt::Display for AccountDesc {
                 let sc2 = sort_code % 100;
                 write!(fmt, "{sc0:02}-{sc1:02}-{sc2:02}:{account:08}")
             }
+            AccountDesc::Iban { iban } => {
+                write!(fmt, "iban:{iban}")
+            }
+            AccountDesc::Bic { bic, account } => {
+                write!(fmt, "bic:{bic}:{account}")
+            }
+            AccountDesc::Card { masked_pan } => {
+                write!(fmt, "card:{masked_pan}")
+            }
+            AccountDesc::Wallet { provider, account } => {
+                write!(fmt, "wallet:{provider}:{account}")
+            }
+            AccountDesc::CashBox { name } => {
+                write!(fmt, "cash:{name}")
+            }
             AccountDesc::None => {
                 write!(fmt, "<None>")
             }
@@ -32,11 +89,51 @@ impl std::fmt::Display for AccountDesc {
     }
 }
 
+//ip FromStr for AccountDesc
+impl std::str::FromStr for AccountDesc {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let s = s.trim();
+        if s.is_empty() || s == "<None>" {
+            return Ok(Self::None);
+        }
+        let Some((kind, rest)) = s.split_once(':') else {
+            return Err(Error::ParseAccount(s.into()));
+        };
+        match kind {
+            "iban" => Self::iban(rest),
+            "bic" => {
+                let Some((bic, account)) = rest.split_once(':') else {
+                    return Err(Error::ParseAccount(s.into()));
+                };
+                Self::bic(bic, account)
+            }
+            "card" => Self::card(rest),
+            "wallet" => {
+                let Some((provider, account)) = rest.split_once(':') else {
+                    return Err(Error::ParseAccount(s.into()));
+                };
+                Self::wallet(provider, account)
+            }
+            "cash" => Self::cash_box(rest),
+            _ => {
+                let account = rest
+                    .parse::<usize>()
+                    .map_err(|_| Error::ParseAccount(s.into()))?;
+                Self::parse_uk(kind, account)
+            }
+        }
+    }
+}
+
 //ip AccountDesc
 impl AccountDesc {
+    //cp uk
     pub fn uk(sort_code: u32, account: usize) -> Self {
         Self::Uk { sort_code, account }
     }
+
+    //cp parse_uk
     pub fn parse_uk(sc_str: &str, account: usize) -> Result<Self, Error> {
         let re = regex::Regex::new(r"^(\d{2})-(\d{2})-(\d{2})$").unwrap();
         let Some(captures) = re.captures(sc_str) else {
@@ -52,4 +149,135 @@ impl AccountDesc {
             Ok(Self::Uk { sort_code, account })
         }
     }
+
+    //cp iban
+    /// Create an IBAN account description, validating the mod-97 checksum
+    ///
+    /// Spaces are removed and the IBAN is converted to upper case
+    pub fn iban(iban: &str) -> Result<Self, Error> {
+        let iban: String = iban
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .map(|c| c.to_ascii_uppercase())
+            .collect();
+        if !iban_is_valid(&iban) {
+            return Err(Error::ParseAccount(format!("iban:{iban} (bad IBAN)")));
+        }
+        Ok(Self::Iban { iban })
+    }
+
+    //cp bic
+    /// Create a BIC account description; the BIC must be 8 or 11
+    /// characters, and the account identifier must not be empty
+    pub fn bic(bic: &str, account: &str) -> Result<Self, Error> {
+        let bic = bic.trim().to_ascii_uppercase();
+        let account = account.trim();
+        let b = bic.as_bytes();
+        let valid = (b.len() == 8 || b.len() == 11)
+            && b[0..6].iter().all(|c| c.is_ascii_uppercase())
+            && b[6..].iter().all(|c| c.is_ascii_alphanumeric());
+        if !valid || account.is_empty() {
+            return Err(Error::ParseAccount(format!("bic:{bic}:{account}")));
+        }
+        Ok(Self::Bic {
+            bic,
+            account: account.into(),
+        })
+    }
+
+    //cp card
+    /// Create a card account description from a masked card number
+    ///
+    /// Masking characters may be '*', 'x' or 'X'; spaces and dashes
+    /// are removed. The last four digits must be present, and no
+    /// more than the first six and last four may be
+    pub fn card(masked_pan: &str) -> Result<Self, Error> {
+        let err = || Error::ParseAccount(format!("card:{masked_pan}"));
+        let masked_pan: String = masked_pan
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != '-')
+            .map(|c| if c == 'x' || c == 'X' { '*' } else { c })
+            .collect();
+        let b = masked_pan.as_bytes();
+        if b.len() < 12 || b.len() > 19 {
+            return Err(err());
+        }
+        if !b.iter().all(|c| c.is_ascii_digit() || *c == b'*') {
+            return Err(err());
+        }
+        let (head, tail) = b.split_at(b.len() - 4);
+        if !tail.iter().all(|c| c.is_ascii_digit()) {
+            return Err(err());
+        }
+        let num_leading = head.iter().take_while(|c| c.is_ascii_digit()).count();
+        if num_leading > 6 || !head[num_leading..].iter().all(|c| *c == b'*') {
+            return Err(err());
+        }
+        Ok(Self::Card { masked_pan })
+    }
+
+    //cp wallet
+    /// Create an online wallet account description, such as a PayPal
+    /// account by email address or merchant id
+    pub fn wallet(provider: &str, account: &str) -> Result<Self, Error> {
+        let provider = provider.trim().to_ascii_lowercase();
+        let account = account.trim();
+        if provider.is_empty()
+            || !provider.chars().all(|c| c.is_ascii_alphanumeric())
+            || account.is_empty()
+        {
+            return Err(Error::ParseAccount(format!("wallet:{provider}:{account}")));
+        }
+        Ok(Self::Wallet {
+            provider,
+            account: account.into(),
+        })
+    }
+
+    //cp cash_box
+    pub fn cash_box(name: &str) -> Result<Self, Error> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(Error::ParseAccount("cash:".into()));
+        }
+        Ok(Self::CashBox { name: name.into() })
+    }
+
+    //ap is_none
+    pub fn is_none(&self) -> bool {
+        matches!(self, Self::None)
+    }
+}
+
+//a Internal functions
+//fi iban_is_valid
+/// Validate an IBAN (upper case, no spaces) using its mod-97 checksum
+///
+/// The first four characters are moved to the end, letters are
+/// replaced by 10 to 35, and the resulting number must be 1 modulo 97
+fn iban_is_valid(iban: &str) -> bool {
+    let b = iban.as_bytes();
+    if b.len() < 15 || b.len() > 34 {
+        return false;
+    }
+    if !b[0..2].iter().all(|c| c.is_ascii_uppercase())
+        || !b[2..4].iter().all(|c| c.is_ascii_digit())
+    {
+        return false;
+    }
+    if !b
+        .iter()
+        .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+    {
+        return false;
+    }
+    let mut remainder = 0_u32;
+    for c in b[4..].iter().chain(b[0..4].iter()) {
+        if c.is_ascii_digit() {
+            remainder = (remainder * 10 + (c - b'0') as u32) % 97;
+        } else {
+            remainder = (remainder * 100 + (c - b'A') as u32 + 10) % 97;
+        }
+    }
+    remainder == 1
 }
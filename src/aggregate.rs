@@ -0,0 +1,421 @@
+//a Documentation
+//! Aggregation of the transactions and bank transactions that match
+//! a query
+//!
+//! An [Aggregation] groups the items by one or more keys (such as
+//! month and fund), and an [AggregateResult] holds the number of
+//! items in each group and the sum, minimum and maximum of their
+//! amounts.
+//!
+//! The amount of a transaction is its amount; the amount of a bank
+//! transaction is its balance delta (so credits are positive and
+//! debits negative). Other items that match the query are not
+//! aggregated.
+//!
+//! The query must select transactions or bank transactions, but not
+//! both (such as with 'type:transaction'), as a bank transaction and
+//! the transactions it is reconciled with are the same money.
+//!
+//! The fund of a transaction is whichever of its debit and credit
+//! is a fund (the fund credited, for a transfer between funds), and
+//! its related party likewise; the fund and transaction type of a
+//! bank transaction are those of the first of its transactions.
+//!
+//! Quarters are of the financial year, which starts in April unless
+//! given otherwise; a financial year starting in January is a
+//! calendar year.
+
+//a Imports
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::indexed_vec::Idx;
+use crate::{Amount, Database, Date, DbId, DbItemType, DbQuery, Error, TransactionType};
+
+//a GroupBy
+//tp GroupBy
+/// A key to group items by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum GroupBy {
+    Month,
+    Quarter,
+    FinancialYear,
+    Fund,
+    RelatedParty,
+    RelatedPartyType,
+    TransactionType,
+}
+
+//ip FromStr for GroupBy
+impl std::str::FromStr for GroupBy {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s.to_ascii_lowercase().as_str() {
+            "month" => Ok(Self::Month),
+            "quarter" => Ok(Self::Quarter),
+            "year" | "financial_year" => Ok(Self::FinancialYear),
+            "fund" => Ok(Self::Fund),
+            "rp" | "related_party" => Ok(Self::RelatedParty),
+            "rp_type" => Ok(Self::RelatedPartyType),
+            "ttype" | "transaction_type" => Ok(Self::TransactionType),
+            _ => Err(format!(
+                "Unknown group {s} (try month, quarter, year, fund, rp, rp_type or ttype)"
+            )
+            .into()),
+        }
+    }
+}
+
+//ip Display for GroupBy
+impl std::fmt::Display for GroupBy {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        match self {
+            GroupBy::Month => write!(fmt, "month"),
+            GroupBy::Quarter => write!(fmt, "quarter"),
+            GroupBy::FinancialYear => write!(fmt, "year"),
+            GroupBy::Fund => write!(fmt, "fund"),
+            GroupBy::RelatedParty => write!(fmt, "rp"),
+            GroupBy::RelatedPartyType => write!(fmt, "rp_type"),
+            GroupBy::TransactionType => write!(fmt, "ttype"),
+        }
+    }
+}
+
+//a AggregateGroup
+//tp AggregateGroup
+/// The count, sum, minimum and maximum of the amounts of a group of
+/// items
+#[derive(Debug, Clone, Serialize)]
+pub struct AggregateGroup {
+    /// The value of each key of the aggregation for the group
+    keys: Vec<String>,
+    count: usize,
+    sum: Amount,
+    min: Amount,
+    max: Amount,
+}
+
+//ip AggregateGroup
+impl AggregateGroup {
+    //cp new
+    fn new(keys: Vec<String>) -> Self {
+        Self {
+            keys,
+            count: 0,
+            sum: Amount::default(),
+            min: Amount::default(),
+            max: Amount::default(),
+        }
+    }
+
+    //mi add
+    fn add(&mut self, amount: Amount) {
+        if self.count == 0 || amount.value() < self.min.value() {
+            self.min = amount;
+        }
+        if self.count == 0 || amount.value() > self.max.value() {
+            self.max = amount;
+        }
+        self.count += 1;
+        self.sum += amount;
+    }
+
+    //ap keys
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    //ap count
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    //ap sum
+    pub fn sum(&self) -> Amount {
+        self.sum
+    }
+
+    //ap min
+    pub fn min(&self) -> Amount {
+        self.min
+    }
+
+    //ap max
+    pub fn max(&self) -> Amount {
+        self.max
+    }
+}
+
+//a Aggregation
+//tp Aggregation
+/// How to aggregate the items that match a query
+#[derive(Debug, Clone)]
+pub struct Aggregation {
+    /// The keys to group by, in order
+    group_by: Vec<GroupBy>,
+    /// The month (1 to 12) in which the financial year starts
+    year_start: u32,
+}
+
+//ip Default for Aggregation
+impl Default for Aggregation {
+    fn default() -> Self {
+        Self {
+            group_by: vec![],
+            year_start: 4,
+        }
+    }
+}
+
+//ip Aggregation
+impl Aggregation {
+    //cp with_group_by
+    pub fn with_group_by<I: IntoIterator<Item = GroupBy>>(mut self, group_by: I) -> Self {
+        self.group_by = group_by.into_iter().collect();
+        self
+    }
+
+    //cp with_year_start
+    /// Set the month (1 to 12) that the financial year starts in
+    pub fn with_year_start(mut self, month: u32) -> Self {
+        self.year_start = month.clamp(1, 12);
+        self
+    }
+
+    //mi year_and_quarter
+    /// The financial year (by the calendar year it starts in) and
+    /// quarter (0 to 3) of a date
+    fn year_and_quarter(&self, date: Date) -> (i32, u32) {
+        let (_, month, year) = date.dmy();
+        let months_in = (month + 12 - self.year_start) % 12;
+        let year = if month < self.year_start {
+            year - 1
+        } else {
+            year
+        };
+        (year, months_in / 3)
+    }
+
+    //mi year_name
+    fn year_name(&self, year: i32) -> String {
+        if self.year_start == 1 {
+            format!("{year}")
+        } else {
+            format!("{year}/{:02}", (year + 1) % 100)
+        }
+    }
+
+    //mi key
+    fn key(&self, db: &Database, facts: &ItemFacts, group_by: GroupBy) -> String {
+        let none = || "-".to_string();
+        match group_by {
+            GroupBy::Month => {
+                let (_, month, year) = facts.date.dmy();
+                format!("{year:04}-{month:02}")
+            }
+            GroupBy::Quarter => {
+                let (year, quarter) = self.year_and_quarter(facts.date);
+                format!("{} Q{}", self.year_name(year), quarter + 1)
+            }
+            GroupBy::FinancialYear => self.year_name(self.year_and_quarter(facts.date).0),
+            GroupBy::Fund => db
+                .get_fund(facts.fund)
+                .map(|f| f.inner().name().to_string())
+                .unwrap_or_else(none),
+            GroupBy::RelatedParty => db
+                .get_related_party(facts.related_party)
+                .map(|rp| rp.inner().name().to_string())
+                .unwrap_or_else(none),
+            GroupBy::RelatedPartyType => db
+                .get_related_party(facts.related_party)
+                .map(|rp| format!("{:?}", rp.inner().rp_type()))
+                .unwrap_or_else(none),
+            GroupBy::TransactionType => facts
+                .ttype
+                .map(|ttype| ttype.to_string())
+                .unwrap_or_else(none),
+        }
+    }
+
+    //mp aggregate
+    /// Aggregate the items that match a query, which must not match
+    /// both transactions and bank transactions
+    pub(crate) fn aggregate(
+        &self,
+        db: &Database,
+        query: DbQuery,
+    ) -> Result<AggregateResult, Error> {
+        if query.may_match_item_type(DbItemType::Transaction)
+            && query.may_match_item_type(DbItemType::BankTransaction)
+        {
+            return Err(
+                "Aggregate either transactions or bank transactions, not both"
+                    .to_string()
+                    .into(),
+            );
+        }
+        let mut groups = BTreeMap::new();
+        let mut total = AggregateGroup::new(vec![]);
+        for db_id in db.query(query) {
+            let Some(facts) = ItemFacts::of_item(db, db_id) else {
+                continue;
+            };
+            let keys: Vec<_> = self
+                .group_by
+                .iter()
+                .map(|g| self.key(db, &facts, *g))
+                .collect();
+            groups
+                .entry(keys.clone())
+                .or_insert_with(|| AggregateGroup::new(keys))
+                .add(facts.amount);
+            total.add(facts.amount);
+        }
+        Ok(AggregateResult {
+            group_by: self.group_by.clone(),
+            groups: groups.into_values().collect(),
+            total,
+        })
+    }
+}
+
+//a ItemFacts
+//tp ItemFacts
+/// The values of an item that it can be aggregated by
+struct ItemFacts {
+    date: Date,
+    amount: Amount,
+    fund: DbId,
+    related_party: DbId,
+    ttype: Option<TransactionType>,
+}
+
+//ip ItemFacts
+impl ItemFacts {
+    //cp of_transaction
+    fn of_transaction(db: &Database, db_id: DbId) -> Option<Self> {
+        let t = db.get_transaction(db_id)?;
+        let t = t.inner();
+        let (debit_id, credit_id) = t.db_ids();
+        let fund = [credit_id, debit_id]
+            .into_iter()
+            .find(|id| db.get_fund(*id).is_some())
+            .unwrap_or(DbId::none());
+        let related_party = [credit_id, debit_id]
+            .into_iter()
+            .find(|id| db.get_related_party(*id).is_some())
+            .unwrap_or(DbId::none());
+        Some(Self {
+            date: t.date(),
+            amount: t.amount(),
+            fund,
+            related_party,
+            ttype: Some(t.ttype()),
+        })
+    }
+
+    //cp of_item
+    /// The values of a transaction or bank transaction
+    fn of_item(db: &Database, db_id: DbId) -> Option<Self> {
+        if let Some(facts) = Self::of_transaction(db, db_id) {
+            return Some(facts);
+        }
+        let bt = db.get_bank_transaction(db_id)?;
+        let bt = bt.inner();
+        let first = bt
+            .transactions()
+            .first()
+            .and_then(|t_id| Self::of_transaction(db, *t_id));
+        Some(Self {
+            date: bt.date(),
+            amount: bt.balance_delta(),
+            fund: first.as_ref().map(|f| f.fund).unwrap_or(DbId::none()),
+            related_party: bt.related_party(),
+            ttype: first.and_then(|f| f.ttype),
+        })
+    }
+}
+
+//a AggregateResult
+//tp AggregateResult
+/// The groups of an aggregation, in order of their keys, and the
+/// total over all of them
+#[derive(Debug, Clone, Serialize)]
+pub struct AggregateResult {
+    group_by: Vec<GroupBy>,
+    groups: Vec<AggregateGroup>,
+    total: AggregateGroup,
+}
+
+//ip AggregateResult
+impl AggregateResult {
+    //ap group_by
+    pub fn group_by(&self) -> &[GroupBy] {
+        &self.group_by
+    }
+
+    //ap groups
+    pub fn groups(&self) -> &[AggregateGroup] {
+        &self.groups
+    }
+
+    //ap total
+    pub fn total(&self) -> &AggregateGroup {
+        &self.total
+    }
+}
+
+//ip Display for AggregateResult
+impl std::fmt::Display for AggregateResult {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        let mut rows = vec![];
+        let mut header: Vec<String> = self.group_by.iter().map(|g| g.to_string()).collect();
+        header.extend(["count", "sum", "min", "max"].map(String::from));
+        rows.push(header);
+        let total = AggregateGroup {
+            keys: self
+                .group_by
+                .iter()
+                .enumerate()
+                .map(|(i, _)| if i == 0 { "total" } else { "" }.to_string())
+                .collect(),
+            ..self.total.clone()
+        };
+        // Without any keys the only group is the total
+        let groups = if self.group_by.is_empty() {
+            &[][..]
+        } else {
+            &self.groups[..]
+        };
+        for g in groups.iter().chain(std::iter::once(&total)) {
+            let mut row = g.keys.clone();
+            row.push(g.count.to_string());
+            for a in [g.sum, g.min, g.max] {
+                row.push(a.to_string().trim().to_string());
+            }
+            rows.push(row);
+        }
+        let n = rows[0].len();
+        let widths: Vec<_> = (0..n)
+            .map(|i| rows.iter().map(|r| r[i].chars().count()).max().unwrap_or(0))
+            .collect();
+        let keys = self.group_by.len();
+        for row in rows {
+            let cells: Vec<_> = row
+                .iter()
+                .zip(widths.iter())
+                .enumerate()
+                .map(|(i, (c, w))| {
+                    if i < keys {
+                        format!("{c:<w$}")
+                    } else {
+                        format!("{c:>w$}")
+                    }
+                })
+                .collect();
+            writeln!(fmt, "{}", cells.join("  ").trim_end())?;
+        }
+        Ok(())
+    }
+}
@@ -1,8 +1,24 @@
 //a Imports
 use serde::{Deserialize, Serialize};
 
-use crate::Error;
-use num_traits::cast::NumCast;
+use crate::{Currency, Error};
+
+//a AmountRounding
+//tp AmountRounding
+/// The policy used when an amount is given to more decimal places
+/// than an [Amount] can hold
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AmountRounding {
+    /// Round half a penny away from zero (so 0.125 becomes 0.13)
+    #[default]
+    HalfAwayFromZero,
+    /// Round half a penny to the nearest even penny
+    HalfEven,
+    /// Drop any digits beyond the pence
+    Truncate,
+    /// Refuse any non-zero digits beyond the pence
+    Exact,
+}
 
 //a Amount
 //tp Amount
@@ -64,43 +80,171 @@ impl std::ops::Neg for Amount {
 
 //ip Amount
 impl Amount {
-    //ci of_f32
-    /// Create an [Amount] from an f32 by rounding to nearest penny
-    fn of_f32(f: f32) -> Result<Self, Error> {
-        let pennies = (f * 100.0).round();
-        if let Some(value) = <isize as NumCast>::from(pennies) {
-            Ok(Self { value })
-        } else {
-            Err(Error::ParseDate(format!(
-                "parsing amount out of range '{f}'"
-            )))
+    //cp parse_with_rounding
+    /// Parse a string as an exact decimal amount, using the given
+    /// rounding policy for any digits beyond the pence
+    ///
+    /// This accepts a leading or trailing sign, a currency symbol
+    /// ('£', '$', '€') or ISO code ('GBP 10.00', '10.00 EUR'),
+    /// thousands separators ('1,234.56') and accountancy-style
+    /// parenthesised negatives ('(12.34)')
+    pub fn parse_with_rounding(s: &str, rounding: AmountRounding) -> Result<Self, Error> {
+        let err = |reason: &str| Error::ParseAmount(format!("'{s}': {reason}"));
+
+        let mut text = s.trim();
+        let mut negative = false;
+        if let Some(inner) = text.strip_prefix('(').and_then(|t| t.strip_suffix(')')) {
+            negative = true;
+            text = inner.trim();
+        }
+
+        let mut signed = false;
+        let mut currency_stripped = false;
+        loop {
+            let before = text;
+            if !signed {
+                if let Some(t) = text.strip_prefix('-') {
+                    negative = !negative;
+                    signed = true;
+                    text = t;
+                } else if let Some(t) = text.strip_suffix('-') {
+                    negative = !negative;
+                    signed = true;
+                    text = t;
+                } else if let Some(t) = text.strip_prefix('+') {
+                    signed = true;
+                    text = t;
+                }
+            }
+            if !currency_stripped {
+                if let Some(t) = strip_currency(text) {
+                    currency_stripped = true;
+                    text = t;
+                }
+            }
+            text = text.trim();
+            if text == before {
+                break;
+            }
+        }
+
+        let (int_part, frac_part) = match text.split_once('.') {
+            Some((i, f)) => (i, f),
+            None => (text, ""),
+        };
+        if int_part.is_empty() && frac_part.is_empty() {
+            return Err(err("no digits"));
+        }
+        if !frac_part.chars().all(|c| c.is_ascii_digit()) {
+            return Err(err("bad decimal places"));
+        }
+
+        // An empty integer part is permitted for (e.g.) '.05'
+        let groups = int_part.split(',').filter(|_| !int_part.is_empty());
+        let separated = int_part.contains(',');
+        let mut pounds: i128 = 0;
+        for (i, group) in groups.enumerate() {
+            if group.is_empty() || !group.chars().all(|c| c.is_ascii_digit()) {
+                return Err(err("bad digits"));
+            }
+            let bad_group = if i == 0 {
+                separated && group.len() > 3
+            } else {
+                group.len() != 3
+            };
+            if bad_group {
+                return Err(err("bad thousands separator"));
+            }
+            for c in group.chars() {
+                pounds = pounds * 10 + (c as i128 - '0' as i128);
+                if pounds > isize::MAX as i128 {
+                    return Err(err("out of range"));
+                }
+            }
+        }
+
+        let frac = frac_part.as_bytes();
+        let digit = |n: usize| frac.get(n).map(|c| (c - b'0') as i128).unwrap_or(0);
+        let mut pence = pounds * 100 + digit(0) * 10 + digit(1);
+        if frac.len() > 2 {
+            let rest = &frac[2..];
+            let first = rest[0] - b'0';
+            let more_than_half = first > 5 || (first == 5 && rest[1..].iter().any(|c| *c != b'0'));
+            let exactly_half = first == 5 && !more_than_half;
+            let is_exact = rest.iter().all(|c| *c == b'0');
+            let round_up = match rounding {
+                AmountRounding::HalfAwayFromZero => first >= 5,
+                AmountRounding::HalfEven => more_than_half || (exactly_half && pence % 2 == 1),
+                AmountRounding::Truncate => false,
+                AmountRounding::Exact => {
+                    if !is_exact {
+                        return Err(err("more than two decimal places"));
+                    }
+                    false
+                }
+            };
+            if round_up {
+                pence += 1;
+            }
         }
+
+        if negative {
+            pence = -pence;
+        }
+        let Ok(value) = isize::try_from(pence) else {
+            return Err(err("out of range"));
+        };
+        Ok(Self { value })
     }
 
+    //ap is_zero
     pub fn is_zero(&self) -> bool {
         self.value == 0
     }
+
+    //ap value
     pub fn value(&self) -> isize {
         self.value
     }
 }
 
+//fi strip_currency
+/// Strip a currency symbol or the ISO code of a known currency from
+/// either end of the string, if there is one
+fn strip_currency(s: &str) -> Option<&str> {
+    for symbol in ["£", "$", "€"] {
+        if let Some(t) = s.strip_prefix(symbol).or_else(|| s.strip_suffix(symbol)) {
+            return Some(t);
+        }
+    }
+    let is_code = |c: &str| Currency::KNOWN.iter().any(|k| k.code() == c);
+    if s.len() > 3 {
+        if s.is_char_boundary(3) && is_code(&s[..3]) {
+            return Some(&s[3..]);
+        }
+        let n = s.len() - 3;
+        if s.is_char_boundary(n) && is_code(&s[n..]) {
+            return Some(&s[..n]);
+        }
+    }
+    None
+}
+
 //ip FromStr for Amount
 impl std::str::FromStr for Amount {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Error> {
-        if let Ok(f) = s.parse::<f32>() {
-            Self::of_f32(f)
-        } else {
-            Err(Error::ParseDate(format!("parsing amount '{s}'")))
-        }
+        Self::parse_with_rounding(s, AmountRounding::default())
     }
 }
 
 //ip Display for Amount
 impl std::fmt::Display for Amount {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
-        write!(fmt, "{:10.2}", (self.value as f64) / 100.0)
+        let sign = if self.value < 0 { "-" } else { "" };
+        let pence = self.value.unsigned_abs();
+        let s = format!("{sign}{}.{:02}", pence / 100, pence % 100);
+        write!(fmt, "{s:>10}")
     }
 }
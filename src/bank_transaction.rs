@@ -1,44 +1,249 @@
 //a Imports
 use std::cell::RefCell;
+use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize, Serializer};
 
+use crate::dated_index::DatedIndex;
 use crate::indexed_vec::Idx;
 use crate::Error;
-use crate::{AccountDesc, Amount, DatabaseRebuild, Date, DbId};
+use crate::{AccountDesc, Amount, DatabaseRebuild, Date, DateRange, DbId};
 
 //a BankTransactionType
 //tp BankTransactionType
 /// A transaction type can be a BACS transfer, deposit at the bank,
 /// direct debit, etc
+///
+/// Most methods of payment have separate inbound (credit) and
+/// outbound (debit) types; cheques carry their cheque number, if
+/// known
 #[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, Hash, PartialEq, Eq)]
 pub enum BankTransactionType {
     #[default]
     Unknown,
+    /// Standing order paid out
     StandingOrder,
+    /// BACS credit (bank giro credit)
     BacsIn,
+    /// Faster payment in
     Fpi,
+    /// Cash (or cheques) paid in at the bank
     Deposit,
+    /// Direct debit paid out
     DirectDebit,
+    /// Standing order paid in
+    StandingOrderIn,
+    /// Refund or reversal of a direct debit
+    DirectDebitRefund,
+    /// BACS payment out
+    BacsOut,
+    /// Faster payment out
+    FasterPaymentOut,
+    /// Bill payment (made through internet banking)
+    BillPayment,
+    /// Transfer in from another account
+    TransferIn,
+    /// Transfer out to another account
+    TransferOut,
+    /// Debit card payment
+    CardPayment,
+    /// Refund to a debit card
+    CardRefund,
+    /// Cash withdrawal at a cash machine or counter
+    CashWithdrawal,
+    /// Cheque paid in, with the cheque number if known
+    ChequeIn(Option<u32>),
+    /// Cheque paid out, with the cheque number if known
+    ChequeOut(Option<u32>),
+    /// Interest paid in
+    Interest,
+    /// Bank charge, fee, commission or overdraft interest
+    Charge,
+    /// Correction by the bank
+    Correction,
 }
 
+//tp BankTypeMap
+/// A mapping table from the codes (or description prefixes) used by
+/// a bank to the transaction types for a credit and for a debit
+pub type BankTypeMap = [(&'static str, BankTransactionType, BankTransactionType)];
+
 //ip BankTransactionType
 impl BankTransactionType {
+    //cc STANDARD_CODES
+    /// The standard (Lloyds-style) transaction codes used by many UK
+    /// banks
+    pub const STANDARD_CODES: &'static BankTypeMap = {
+        use BankTransactionType::*;
+        &[
+            ("SO", StandingOrderIn, StandingOrder),
+            ("DD", DirectDebitRefund, DirectDebit),
+            ("DDR", DirectDebitRefund, DirectDebit),
+            ("BGC", BacsIn, BacsOut),
+            ("BAC", BacsIn, BacsOut),
+            ("BACS", BacsIn, BacsOut),
+            ("FPI", Fpi, FasterPaymentOut),
+            ("FPO", Fpi, FasterPaymentOut),
+            ("MPI", Fpi, FasterPaymentOut),
+            ("MPO", Fpi, FasterPaymentOut),
+            ("BP", BacsIn, BillPayment),
+            ("PAY", BacsIn, BillPayment),
+            ("TFR", TransferIn, TransferOut),
+            ("DEB", CardRefund, CardPayment),
+            ("POS", CardRefund, CardPayment),
+            ("CPT", Deposit, CashWithdrawal),
+            ("ATM", Deposit, CashWithdrawal),
+            ("CSH", Deposit, CashWithdrawal),
+            ("DEP", Deposit, CashWithdrawal),
+            ("CHQ", ChequeIn(None), ChequeOut(None)),
+            ("INT", Interest, Charge),
+            ("FEE", Correction, Charge),
+            ("CHG", Correction, Charge),
+            ("COM", Correction, Charge),
+            ("COR", Correction, Correction),
+        ]
+    };
+
     //cp parse
-    /// Parse a string into a transaction type
-    pub fn parse(s: &str, _is_debit: bool) -> Result<Self, Error> {
-        if s == "SO" {
-            Ok(Self::StandingOrder)
-        } else if s == "BGC" {
-            Ok(Self::BacsIn)
-        } else if s == "FPI" {
-            Ok(Self::Fpi)
-        } else if s == "DD" {
-            Ok(Self::DirectDebit)
-        } else {
-            Ok(Self::Unknown)
+    /// Parse a string into a transaction type, using the standard
+    /// codes
+    pub fn parse(s: &str, is_debit: bool) -> Result<Self, Error> {
+        Ok(Self::parse_with_map(s, is_debit, &[]))
+    }
+
+    //cp parse_with_map
+    /// Parse a string into a transaction type, using the mapping table
+    /// of a bank and then the standard codes
+    ///
+    /// An entry of a table matches if the string (ignoring case) is
+    /// its code, or starts with its code followed by a space, so that
+    /// descriptions that start with the type can also be parsed. A
+    /// string of just digits is a cheque number; a cheque type
+    /// followed by a number takes that as its cheque number.
+    pub fn parse_with_map(s: &str, is_debit: bool, map: &BankTypeMap) -> Self {
+        let s = s.trim().to_ascii_uppercase();
+        if let Some(number) = cheque_number(&s) {
+            if is_debit {
+                return Self::ChequeOut(Some(number));
+            } else {
+                return Self::ChequeIn(Some(number));
+            }
+        }
+        for (code, credit, debit) in map.iter().chain(Self::STANDARD_CODES.iter()) {
+            let Some(rest) = s.strip_prefix(code) else {
+                continue;
+            };
+            if !rest.is_empty() && !rest.starts_with(' ') {
+                continue;
+            }
+            let ttype = if is_debit { *debit } else { *credit };
+            let number = rest.split_whitespace().find_map(cheque_number);
+            return ttype.with_cheque_number(number);
+        }
+        Self::Unknown
+    }
+
+    //cp with_cheque_number
+    /// Set the cheque number of a cheque type; other types are
+    /// unchanged
+    #[must_use]
+    pub fn with_cheque_number(self, number: Option<u32>) -> Self {
+        match self {
+            Self::ChequeIn(_) => Self::ChequeIn(number),
+            Self::ChequeOut(_) => Self::ChequeOut(number),
+            _ => self,
+        }
+    }
+
+    //ap is_inbound
+    /// Return true if the type is only used for money coming in
+    pub fn is_inbound(&self) -> bool {
+        matches!(
+            self,
+            Self::BacsIn
+                | Self::Fpi
+                | Self::Deposit
+                | Self::StandingOrderIn
+                | Self::DirectDebitRefund
+                | Self::TransferIn
+                | Self::CardRefund
+                | Self::ChequeIn(_)
+                | Self::Interest
+        )
+    }
+
+    //ap is_outbound
+    /// Return true if the type is only used for money going out
+    pub fn is_outbound(&self) -> bool {
+        matches!(
+            self,
+            Self::StandingOrder
+                | Self::DirectDebit
+                | Self::BacsOut
+                | Self::FasterPaymentOut
+                | Self::BillPayment
+                | Self::TransferOut
+                | Self::CardPayment
+                | Self::CashWithdrawal
+                | Self::ChequeOut(_)
+                | Self::Charge
+        )
+    }
+
+    //ap is_card
+    pub fn is_card(&self) -> bool {
+        matches!(self, Self::CardPayment | Self::CardRefund)
+    }
+
+    //ap is_cheque
+    pub fn is_cheque(&self) -> bool {
+        matches!(self, Self::ChequeIn(_) | Self::ChequeOut(_))
+    }
+
+    //ap cheque_number
+    pub fn cheque_number(&self) -> Option<u32> {
+        match self {
+            Self::ChequeIn(n) | Self::ChequeOut(n) => *n,
+            _ => None,
         }
     }
+
+    //ap code
+    /// The standard code for the type; a cheque with a number is just
+    /// its number (as in a QIF file)
+    pub fn code(&self) -> String {
+        let code = match self {
+            Self::Unknown => "",
+            Self::StandingOrder | Self::StandingOrderIn => "SO",
+            Self::DirectDebit | Self::DirectDebitRefund => "DD",
+            Self::BacsIn | Self::BacsOut => "BGC",
+            Self::Fpi => "FPI",
+            Self::FasterPaymentOut => "FPO",
+            Self::BillPayment => "BP",
+            Self::TransferIn | Self::TransferOut => "TFR",
+            Self::CardPayment | Self::CardRefund => "DEB",
+            Self::CashWithdrawal => "CPT",
+            Self::Deposit => "DEP",
+            Self::ChequeIn(Some(n)) | Self::ChequeOut(Some(n)) => {
+                return n.to_string();
+            }
+            Self::ChequeIn(None) | Self::ChequeOut(None) => "CHQ",
+            Self::Interest => "INT",
+            Self::Charge => "FEE",
+            Self::Correction => "COR",
+        };
+        code.to_string()
+    }
+}
+
+//fi cheque_number
+/// A cheque number is a string of (up to nine) digits
+fn cheque_number(s: &str) -> Option<u32> {
+    if s.is_empty() || s.len() > 9 || !s.chars().all(|c| c.is_ascii_digit()) {
+        None
+    } else {
+        s.parse().ok()
+    }
 }
 
 //a BankTransaction, DbBankTransaction
@@ -77,6 +282,10 @@ pub struct BankTransaction {
     /// Transactions
     #[serde(default)]
     transactions: Vec<DbId>,
+    /// Financial institution transaction ID (FITID), unique to the
+    /// transaction within the account, if the bank provided one
+    #[serde(default)]
+    fitid: Option<String>,
 }
 
 //ip Display for BankTransaction
@@ -120,9 +329,16 @@ impl BankTransaction {
             account_id: DbId::none(),
             related_party: DbId::none(),
             transactions: vec![],
+            fitid: None,
         }
     }
 
+    //cp with_fitid
+    pub fn with_fitid(mut self, fitid: Option<String>) -> Self {
+        self.fitid = fitid;
+        self
+    }
+
     //ap balance
     pub fn balance(&self) -> Amount {
         self.balance
@@ -158,6 +374,16 @@ impl BankTransaction {
         self.date
     }
 
+    //ap ttype
+    pub fn ttype(&self) -> BankTransactionType {
+        self.ttype
+    }
+
+    //ap fitid
+    pub fn fitid(&self) -> Option<&str> {
+        self.fitid.as_deref()
+    }
+
     //ap related_party
     pub fn related_party(&self) -> DbId {
         self.related_party
@@ -168,16 +394,49 @@ impl BankTransaction {
         &self.description
     }
 
+    //ap transactions
+    /// The transactions (against funds) that make up the bank transaction
+    pub fn transactions(&self) -> &[DbId] {
+        &self.transactions
+    }
+
     //mp set_related_party
     pub fn set_related_party(&mut self, related_party: DbId) {
         self.related_party = related_party;
     }
 
+    //mp set_date
+    /// Change the date of the bank transaction; the account must
+    /// reorder it, so use [crate::Database::update_bank_transaction]
+    pub fn set_date(&mut self, date: Date) {
+        self.date = date;
+    }
+
+    //mp set_description
+    pub fn set_description<I: Into<String>>(&mut self, i: I) {
+        self.description = i.into();
+    }
+
     //mp set_account_id
     pub fn set_account_id(&mut self, account_id: DbId) {
         self.account_id = account_id;
     }
 
+    //mp add_transaction
+    /// Record a transaction (against a fund) as part of the bank transaction
+    pub fn add_transaction(&mut self, t_id: DbId) {
+        self.transactions.push(t_id);
+    }
+
+    //mp remove_transaction
+    /// Remove a transaction from the bank transaction, returning true
+    /// if it was present
+    pub fn remove_transaction(&mut self, t_id: DbId) -> bool {
+        let n = self.transactions.len();
+        self.transactions.retain(|t| *t != t_id);
+        self.transactions.len() != n
+    }
+
     //mp rebuild
     pub fn rebuild(&mut self, database_rebuild: &DatabaseRebuild) -> Result<(), Error> {
         if !self.related_party.is_none() {
@@ -186,6 +445,9 @@ impl BankTransaction {
         }
         self.account_id =
             database_rebuild.get_new_id("BankTransaction account ID", self.account_id)?;
+        for t_id in self.transactions.iter_mut() {
+            *t_id = database_rebuild.get_new_id("BankTransaction transaction", *t_id)?;
+        }
         Ok(())
     }
 
@@ -207,8 +469,23 @@ crate::make_db_item!(DbBankTransaction, BankTransaction, show_name);
 pub struct DbBankTransactionsState {
     /// All the transactions
     array: Vec<DbBankTransaction>,
-    // Indexed by description
-    // index: HashMap<String, DbBankTransaction>,
+    /// The transactions by DbId
+    by_id: HashMap<DbId, DbBankTransaction>,
+    /// Index by date, and by account and related party
+    index: DatedIndex,
+}
+
+//ip DbBankTransactionsState
+impl DbBankTransactionsState {
+    //mi index_transaction
+    fn index_transaction(&mut self, db_transaction: &DbBankTransaction) {
+        let bt = db_transaction.inner();
+        self.index.insert(
+            db_transaction.id(),
+            bt.date(),
+            vec![bt.account_id(), bt.related_party()],
+        );
+    }
 }
 
 //tp DbBankTransactions
@@ -239,6 +516,8 @@ impl DbBankTransactions {
         db_bank_transaction: DbBankTransaction,
         database_rebuild: &DatabaseRebuild,
     ) -> Result<(), Error> {
+        // Rebuild first so that it is indexed by its new DbIds
+        db_bank_transaction.inner_mut().rebuild(database_rebuild)?;
         if !self.add_transaction(db_bank_transaction.clone()) {
             return Err(format!(
                 "Failed to rebuild bank transaction {}:{}, already present?",
@@ -247,16 +526,68 @@ impl DbBankTransactions {
             )
             .into());
         }
-        db_bank_transaction.inner_mut().rebuild(database_rebuild)
+        Ok(())
     }
 
     //mp add_transaction
     pub fn add_transaction(&self, db_transaction: DbBankTransaction) -> bool {
         let mut state = self.state.borrow_mut();
-        state.array.push(db_transaction.clone());
+        if state.by_id.contains_key(&db_transaction.id()) {
+            return false;
+        }
+        state.index_transaction(&db_transaction);
+        state
+            .by_id
+            .insert(db_transaction.id(), db_transaction.clone());
+        state.array.push(db_transaction);
+        true
+    }
+
+    //mp reindex_transaction
+    /// Reindex a bank transaction after its date or related party
+    /// have changed, returning true if it is present
+    pub fn reindex_transaction(&self, db_id: DbId) -> bool {
+        let mut state = self.state.borrow_mut();
+        let Some(db_transaction) = state.by_id.get(&db_id).cloned() else {
+            return false;
+        };
+        state.index_transaction(&db_transaction);
+        true
+    }
+
+    //mp remove_transaction
+    /// Remove a bank transaction, returning true if it was present
+    pub fn remove_transaction(&self, db_id: DbId) -> bool {
+        let mut state = self.state.borrow_mut();
+        if state.by_id.remove(&db_id).is_none() {
+            return false;
+        }
+        state.index.remove(db_id);
+        if let Some(n) = state.array.iter().position(|t| t.id() == db_id) {
+            state.array.remove(n);
+        }
         true
     }
 
+    //mp select
+    /// The bank transactions in a date range (if it is not empty)
+    /// of all of the given accounts and related parties, in DbId
+    /// order; all the bank transactions (in the order they were
+    /// added) if neither restricts them
+    ///
+    /// This borrows the bank transactions just once, and uses binary
+    /// search of the indexes rather than a scan of every one
+    pub fn select(&self, date_range: DateRange, references: &[DbId]) -> Vec<DbBankTransaction> {
+        let state = self.state.borrow();
+        match state.index.select(date_range, references) {
+            Some(db_ids) => db_ids
+                .iter()
+                .filter_map(|db_id| state.by_id.get(db_id).cloned())
+                .collect(),
+            None => state.array.clone(),
+        }
+    }
+
     //zz All done
 }
 
@@ -0,0 +1,82 @@
+// Number,Date,Account,Amount,Subcategory,Memo
+//  ,28/08/2024,20-32-06 13152170,-12.50,PAYMENT,NAME REASON
+//
+// Barclays exports have a single signed amount and no balance
+use super::{BankRow, RowOrder};
+use crate::{AccountDesc, Amount, BankTransaction, BankTransactionType, BankTypeMap, Date, Error};
+
+//fi TYPE_MAP
+/// Barclays subcategories
+const TYPE_MAP: &BankTypeMap = {
+    use BankTransactionType::*;
+    &[
+        ("DIRECTDEBIT", DirectDebitRefund, DirectDebit),
+        ("DIRECT DEBIT", DirectDebitRefund, DirectDebit),
+        ("STANDING ORDER", StandingOrderIn, StandingOrder),
+        ("STO", StandingOrderIn, StandingOrder),
+        ("DIRECTDEP", BacsIn, BacsOut),
+        ("FT", Fpi, FasterPaymentOut),
+        ("PAYMENT", BacsIn, BillPayment),
+        ("DEBIT", CardRefund, CardPayment),
+        ("CASH", Deposit, CashWithdrawal),
+        ("CASH DEPOSIT", Deposit, CashWithdrawal),
+        ("DEPOSIT", Deposit, CashWithdrawal),
+        ("CHEQUE", ChequeIn(None), ChequeOut(None)),
+        ("TRANSFER", TransferIn, TransferOut),
+        ("INTEREST", Interest, Charge),
+        ("CHARGES", Correction, Charge),
+    ]
+};
+
+//fi ttype
+/// Map a Barclays subcategory to a transaction type
+fn ttype(subcategory: &str, is_debit: bool) -> Result<BankTransactionType, Error> {
+    Ok(BankTransactionType::parse_with_map(
+        subcategory,
+        is_debit,
+        TYPE_MAP,
+    ))
+}
+
+//fi account_desc
+/// Parse a Barclays account column, which is "sort code, space, account number"
+fn account_desc(s: &str) -> Result<AccountDesc, Error> {
+    let Some((sort_code, account)) = s.trim().split_once(' ') else {
+        return s.parse();
+    };
+    let account = account
+        .trim()
+        .parse::<usize>()
+        .map_err(|_| Error::ParseAccount(s.into()))?;
+    AccountDesc::parse_uk(sort_code, account)
+}
+
+//fp read_transactions_csv
+/// Read a Barclays CSV transactions file and return a Vec<BankTransaction>
+///
+/// If the account description is None then the account is taken from
+/// the export; as there are no balances in the export, the opening
+/// balance is required
+pub fn read_transactions_csv<R: std::io::Read>(
+    reader: R,
+    account_desc: &AccountDesc,
+    opening_balance: Option<Amount>,
+) -> Result<Vec<BankTransaction>, Error> {
+    let (columns, records) = super::read_csv_rows(reader)?;
+    let c_date = columns.require("Date")?;
+    let c_amount = columns.require("Amount")?;
+    let mut rows = vec![];
+    for r in records.iter() {
+        let date = Date::parse(super::field(r, c_date).unwrap_or_default())?;
+        let amount: Amount = super::field(r, c_amount).unwrap_or("0").parse()?;
+        let subcategory = columns.field(r, "Subcategory").unwrap_or_default();
+        let description = columns.field(r, "Memo").unwrap_or_default();
+        let mut row = BankRow::new(date, ttype(subcategory, amount.value() < 0)?, description)
+            .with_signed_amount(amount);
+        if let Some(account) = columns.field(r, "Account") {
+            row = row.with_account_desc(self::account_desc(account)?);
+        }
+        rows.push(row);
+    }
+    super::rows_to_transactions(rows, account_desc, opening_balance, RowOrder::NewestFirst)
+}
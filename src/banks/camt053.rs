@@ -0,0 +1,270 @@
+//a Documentation
+//! Import of ISO 20022 camt.053 (bank to customer statement) XML
+//!
+//! A document has one or more statements (Stmt), each with the
+//! account (Acct), its balances (Bal) and its entries (Ntry). The
+//! opening (OPBD, or PRCD) and closing (CLBD) booked balances are
+//! checked against the entries.
+//!
+//! The counterparty of an entry is the debtor of a credit, or the
+//! creditor of a debit, from the related parties of the transaction
+//! details (NtryDtls/TxDtls/RltdPties)
+//!
+//! Elements are matched by local name, so that any version of the
+//! camt.053 namespace is accepted
+
+//a Imports
+use roxmltree::Node;
+
+use super::{BankRow, Counterparty, RowOrder, Statement};
+use crate::{AccountDesc, Amount, BankTransactionType, BankTypeMap, Date, Error};
+
+//a Internal functions
+//fi child
+/// Find the first child element with the given local name
+fn child<'a, 'i>(node: Node<'a, 'i>, name: &str) -> Option<Node<'a, 'i>> {
+    node.children()
+        .find(|n| n.is_element() && n.tag_name().name() == name)
+}
+
+//fi children
+/// Iterate over the child elements with the given local name
+fn children<'a, 'i: 'a>(node: Node<'a, 'i>, name: &'a str) -> impl Iterator<Item = Node<'a, 'i>> {
+    node.children()
+        .filter(move |n| n.is_element() && n.tag_name().name() == name)
+}
+
+//fi path
+/// Follow a path of child elements
+fn path<'a, 'i>(node: Node<'a, 'i>, names: &[&str]) -> Option<Node<'a, 'i>> {
+    names.iter().try_fold(node, |n, name| child(n, name))
+}
+
+//fi text
+/// Get the trimmed text of the element at a path, if it is not empty
+fn text<'a>(node: Node<'a, '_>, names: &[&str]) -> Option<&'a str> {
+    path(node, names)
+        .and_then(|n| n.text())
+        .map(|t| t.trim())
+        .filter(|t| !t.is_empty())
+}
+
+//fi parse_date
+/// Parse a date element (Dt, or the date of a DtTm)
+fn parse_date(node: Node) -> Result<Date, Error> {
+    let s = text(node, &["Dt"])
+        .or_else(|| text(node, &["DtTm"]))
+        .unwrap_or_default();
+    let Some(ymd) = s.get(0..10) else {
+        return Err(Error::ParseDate(s.into()));
+    };
+    Date::parse_with_format(ymd, "%Y-%m-%d")
+}
+
+//fi signed_amount
+/// Get the amount of a balance or entry, negative if it is a debit
+fn signed_amount(node: Node) -> Result<Amount, Error> {
+    let amount: Amount = text(node, &["Amt"]).unwrap_or("0").parse()?;
+    match text(node, &["CdtDbtInd"]) {
+        Some("DBIT") => Ok(-amount),
+        Some("CRDT") => Ok(amount),
+        s => Err(Error::TransactionLog(format!(
+            "camt.053 credit/debit indicator must be CRDT or DBIT, not {s:?}"
+        ))),
+    }
+}
+
+//fi TYPE_MAP
+/// ISO 20022 bank transaction codes, as 'family subfamily'
+const TYPE_MAP: &BankTypeMap = {
+    use BankTransactionType::*;
+    &[
+        ("RDDT", DirectDebitRefund, DirectDebit),
+        ("IDDT", DirectDebitRefund, DirectDebit),
+        ("ICDT STDO", StandingOrderIn, StandingOrder),
+        ("RCDT STDO", StandingOrderIn, StandingOrder),
+        ("ICDT", Fpi, FasterPaymentOut),
+        ("RCDT", Fpi, FasterPaymentOut),
+        ("CNTR CDPT", Deposit, CashWithdrawal),
+        ("CNTR CWDL", Deposit, CashWithdrawal),
+        ("CCRD", CardRefund, CardPayment),
+        ("ICHQ", ChequeIn(None), ChequeOut(None)),
+        ("RCHQ", ChequeIn(None), ChequeOut(None)),
+    ]
+};
+
+//fi ttype
+/// Map a bank transaction code (domain/family/subfamily) to a transaction type
+fn ttype(entry: Node, is_debit: bool) -> Result<BankTransactionType, Error> {
+    let family = text(entry, &["BkTxCd", "Domn", "Fmly", "Cd"]).unwrap_or_default();
+    let sub_family = text(entry, &["BkTxCd", "Domn", "Fmly", "SubFmlyCd"]).unwrap_or_default();
+    if family.is_empty() {
+        return Ok(BankTransactionType::Unknown);
+    }
+    Ok(BankTransactionType::parse_with_map(
+        &format!("{family} {sub_family}"),
+        is_debit,
+        TYPE_MAP,
+    ))
+}
+
+//fi counterparty
+/// Get the counterparty of an entry from its transaction details
+fn counterparty(entry: Node, is_debit: bool) -> Counterparty {
+    let Some(tx_dtls) = path(entry, &["NtryDtls", "TxDtls"]) else {
+        return Counterparty::default();
+    };
+    let (party, account) = {
+        if is_debit {
+            ("Cdtr", "CdtrAcct")
+        } else {
+            ("Dbtr", "DbtrAcct")
+        }
+    };
+    let name = text(tx_dtls, &["RltdPties", party, "Nm"])
+        .or_else(|| text(tx_dtls, &["RltdPties", party, "Pty", "Nm"]))
+        .map(|s| s.to_string());
+    let iban = text(tx_dtls, &["RltdPties", account, "Id", "IBAN"]).map(|s| s.to_string());
+    Counterparty { name, iban }
+}
+
+//fi description
+/// Build the description of an entry from the unstructured
+/// remittance information, or the additional entry information
+fn description(entry: Node, counterparty: &Counterparty) -> String {
+    let mut parts = vec![];
+    if let Some(name) = &counterparty.name {
+        parts.push(name.as_str());
+    }
+    if let Some(tx_dtls) = path(entry, &["NtryDtls", "TxDtls"]) {
+        if let Some(rmt_inf) = child(tx_dtls, "RmtInf") {
+            for u in children(rmt_inf, "Ustrd") {
+                if let Some(t) = u.text() {
+                    parts.push(t.trim());
+                }
+            }
+        }
+    }
+    if parts.len() <= 1 {
+        if let Some(t) = text(entry, &["AddtlNtryInf"]) {
+            parts.push(t);
+        }
+    }
+    parts.join(" ")
+}
+
+//fi account_desc
+fn account_desc(stmt: Node) -> Result<AccountDesc, Error> {
+    if let Some(iban) = text(stmt, &["Acct", "Id", "IBAN"]) {
+        return AccountDesc::iban(iban);
+    }
+    let other = text(stmt, &["Acct", "Id", "Othr", "Id"]);
+    let bic = text(stmt, &["Acct", "Svcr", "FinInstnId", "BICFI"])
+        .or_else(|| text(stmt, &["Acct", "Svcr", "FinInstnId", "BIC"]));
+    match (bic, other) {
+        (Some(bic), Some(other)) => AccountDesc::bic(bic, other),
+        _ => Ok(AccountDesc::None),
+    }
+}
+
+//fi balance
+/// Find the balance of a statement with one of the given type codes
+fn balance(stmt: Node, codes: &[&str]) -> Result<Option<Amount>, Error> {
+    for bal in children(stmt, "Bal") {
+        let code = text(bal, &["Tp", "CdOrPrtry", "Cd"]).unwrap_or_default();
+        if codes.contains(&code) {
+            return Ok(Some(signed_amount(bal)?));
+        }
+    }
+    Ok(None)
+}
+
+//fi read_statement
+fn read_statement(stmt: Node, account_desc: &AccountDesc) -> Result<Statement, Error> {
+    let account_desc = {
+        if account_desc.is_none() {
+            self::account_desc(stmt)?
+        } else {
+            account_desc.clone()
+        }
+    };
+    let Some(opening_balance) = balance(stmt, &["OPBD", "PRCD"])? else {
+        return Err(Error::TransactionLog(
+            "camt.053 statement has no opening balance".into(),
+        ));
+    };
+    let closing_balance = balance(stmt, &["CLBD"])?;
+
+    let mut rows = vec![];
+    for entry in children(stmt, "Ntry") {
+        if text(entry, &["Sts"])
+            .or_else(|| text(entry, &["Sts", "Cd"]))
+            .is_some_and(|s| s != "BOOK")
+        {
+            continue;
+        }
+        let amount = signed_amount(entry)?;
+        let is_debit = amount.value() < 0;
+        let date = {
+            if let Some(d) = child(entry, "BookgDt") {
+                parse_date(d)?
+            } else if let Some(d) = child(entry, "ValDt") {
+                parse_date(d)?
+            } else {
+                return Err(Error::TransactionLog(
+                    "camt.053 entry has no booking or value date".into(),
+                ));
+            }
+        };
+        let counterparty = counterparty(entry, is_debit);
+        let description = description(entry, &counterparty);
+        let fitid = text(entry, &["AcctSvcrRef"])
+            .or_else(|| text(entry, &["NtryRef"]))
+            .map(|s| s.to_string());
+        rows.push(
+            BankRow::new(date, ttype(entry, is_debit)?, &description)
+                .with_signed_amount(amount)
+                .with_fitid(fitid)
+                .with_counterparty(counterparty),
+        );
+    }
+    let statement = super::rows_to_statement(
+        rows,
+        &account_desc,
+        Some(opening_balance),
+        RowOrder::OldestFirst,
+    )?;
+    if let Some(closing_balance) = closing_balance {
+        super::check_closing_balance(&statement.transactions, opening_balance, closing_balance)?;
+    }
+    Ok(statement)
+}
+
+//a Public functions
+//fp read_statements_camt053
+/// Read a camt.053 XML document and return its statements
+///
+/// If the account description is not None then it is used instead of
+/// the account of each statement
+pub fn read_statements_camt053<R: std::io::Read>(
+    mut reader: R,
+    account_desc: &AccountDesc,
+) -> Result<Vec<Statement>, Error> {
+    let mut text = String::new();
+    reader.read_to_string(&mut text)?;
+    let doc = roxmltree::Document::parse(&text)
+        .map_err(|e| Error::TransactionLog(format!("camt.053 XML error: {e}")))?;
+    let mut result = vec![];
+    for stmt in doc
+        .descendants()
+        .filter(|n| n.is_element() && n.tag_name().name() == "Stmt")
+    {
+        result.push(read_statement(stmt, account_desc)?);
+    }
+    if result.is_empty() {
+        return Err(Error::TransactionLog(
+            "camt.053 document has no statements".into(),
+        ));
+    }
+    Ok(result)
+}
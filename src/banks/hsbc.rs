@@ -0,0 +1,49 @@
+// 28/08/2024,NAME REASON,-12.50
+// 27/08/2024,DD ELECTRICITY,"-1,234.56"
+//
+// HSBC exports have no header row, and a single signed amount; some
+// have a fourth balance column
+use super::{BankRow, RowOrder};
+use crate::{AccountDesc, Amount, BankTransaction, BankTransactionType, Date, Error};
+
+//fi parse_date
+fn parse_date(s: &str) -> Result<Date, Error> {
+    Date::parse(s).or_else(|_| Date::parse_with_format(s, "%d %b %Y"))
+}
+
+//fp read_transactions_csv
+/// Read an HSBC CSV transactions file and return a Vec<BankTransaction>
+///
+/// HSBC exports do not identify the account, so the account
+/// description must be provided; if there is no balance column then
+/// the opening balance is required
+pub fn read_transactions_csv<R: std::io::Read>(
+    reader: R,
+    account_desc: &AccountDesc,
+    opening_balance: Option<Amount>,
+) -> Result<Vec<BankTransaction>, Error> {
+    let mut csv_reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(reader);
+    let mut rows = vec![];
+    for record in csv_reader.records() {
+        let r = record?;
+        let Some(date) = super::field(&r, 0) else {
+            continue;
+        };
+        let date = parse_date(date)?;
+        let description = super::field(&r, 1).unwrap_or_default();
+        let amount: Amount = super::field(&r, 2).unwrap_or("0").parse()?;
+        let balance = super::parse_amount(super::field(&r, 3))?;
+        // HSBC descriptions often start with the transaction type (e.g. 'DD', 'SO')
+        let code = description.split_whitespace().next().unwrap_or_default();
+        let ttype = BankTransactionType::parse(code, amount.value() < 0)?;
+        rows.push(
+            BankRow::new(date, ttype, description)
+                .with_signed_amount(amount)
+                .with_balance(balance),
+        );
+    }
+    super::rows_to_transactions(rows, account_desc, opening_balance, RowOrder::NewestFirst)
+}
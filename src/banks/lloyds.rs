@@ -15,10 +15,13 @@ pub struct CsvTransaction {
     #[serde(rename = "Transaction Type")]
     ttype: Option<String>,
     /// CSV Sort Code, Account Number
+    ///
+    /// If there is no sort code then the account number may be any
+    /// account description (such as a masked card number)
     #[serde(rename = "Sort Code")]
     sort_code: String,
     #[serde(rename = "Account Number")]
-    account_number: usize,
+    account_number: String,
     /// Transaction Description,
     #[serde(rename = "Transaction Description")]
     description: String,
@@ -65,10 +68,14 @@ impl TryFrom<CsvTransaction> for BankTransaction {
         let ttype = BankTransactionType::parse(ttype.unwrap_or(""), !debit.is_zero())?;
 
         let account_desc = {
+            let account_number = csv.account_number.trim_start_matches("'").trim();
             if !csv.sort_code.is_empty() {
-                AccountDesc::parse_uk(csv.sort_code.trim_start_matches("'"), csv.account_number)?
+                let account = account_number.parse::<usize>().map_err(|_| {
+                    Error::ParseAccount(format!("{}:{account_number}", csv.sort_code))
+                })?;
+                AccountDesc::parse_uk(csv.sort_code.trim_start_matches("'"), account)?
             } else {
-                AccountDesc::default()
+                account_number.parse::<AccountDesc>()?
             }
         };
         Ok(BankTransaction::new(
@@ -100,23 +107,6 @@ pub fn read_transactions_csv<R: std::io::Read>(reader: R) -> Result<Vec<BankTran
         result.push(transaction);
     }
     let result: Vec<BankTransaction> = result.into_iter().rev().collect();
-    if result.len() > 1 {
-        let mut balance = result[0].balance();
-        for i in 1..result.len() {
-            if result[i].account_desc() != result[0].account_desc() {
-                return Err(Error::TransactionLog(format!(
-                    "entry {} has different account description {} to the first entry {}",
-                    i + 1,
-                    result[i].account_desc(),
-                    result[0].account_desc()
-                )));
-            }
-            let new_balance = balance + result[i].balance_delta();
-            if new_balance != result[i].balance() {
-                return Err(Error::TransactionLog(format!("balance before entry {} was {balance} but after it was calculated to be {new_balance}", i+1)));
-            }
-            balance = new_balance;
-        }
-    }
+    super::validate_balance_chain(&result, None)?;
     Ok(result)
 }
@@ -0,0 +1,327 @@
+//a Documentation
+//! A generic CSV importer driven by a column mapping
+//!
+//! The mapping is read from a YAML or JSON file, such as:
+//!
+//! ```yaml
+//! date: "Transaction Date"
+//! date_format: "%Y-%m-%d"
+//! description: ["Payee", "Reference"]
+//! amount: "Amount"
+//! balance: 5
+//! order: newest_first
+//! account_desc: "iban:GB82WEST12345698765432"
+//! ```
+//!
+//! Columns may be given by header name or by index (from 0); if the
+//! export has no header row then they must be given by index
+
+//a Imports
+use serde::{Deserialize, Serialize};
+
+use super::{BankRow, CsvColumns, RowOrder};
+use crate::{AccountDesc, Amount, BankTransaction, BankTransactionType, Date, Error, FileType};
+
+//a CsvColumn
+//tp CsvColumn
+/// A column of a CSV export, by index or by header name
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum CsvColumn {
+    Index(usize),
+    Name(String),
+}
+
+//ip Display for CsvColumn
+impl std::fmt::Display for CsvColumn {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        match self {
+            CsvColumn::Index(n) => write!(fmt, "column {n}"),
+            CsvColumn::Name(s) => write!(fmt, "column '{s}'"),
+        }
+    }
+}
+
+//a CsvMapping
+//tp CsvMapping
+/// The mapping from the columns of a bank CSV export to the fields of
+/// a bank transaction
+///
+/// Either a signed 'amount' column, or 'debit' and/or 'credit'
+/// columns, must be given
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CsvMapping {
+    /// True if the export has a header row
+    has_headers: bool,
+    /// Number of lines to skip before the header row (or first record)
+    skip_lines: usize,
+    /// Column with the transaction date
+    date: CsvColumn,
+    /// Format of the date (as chrono, e.g. "%d/%m/%Y"); if not
+    /// given, then the UK day/month/year forms are accepted
+    date_format: Option<String>,
+    /// Columns that are joined (with a space) to form the description
+    description: Vec<CsvColumn>,
+    /// Column with the bank's transaction type code
+    ttype: Option<CsvColumn>,
+    /// Column with the debit amount
+    debit: Option<CsvColumn>,
+    /// Column with the credit amount
+    credit: Option<CsvColumn>,
+    /// Column with a signed amount, positive for a credit
+    amount: Option<CsvColumn>,
+    /// If true then a positive signed amount is a debit (as in some
+    /// credit card exports)
+    negate_amount: bool,
+    /// Column with the balance after the transaction
+    balance: Option<CsvColumn>,
+    /// Columns with the sort code and account number, if the export has them
+    sort_code: Option<CsvColumn>,
+    account_number: Option<CsvColumn>,
+    /// Order of the rows, if it cannot be detected from the dates or balances
+    order: RowOrder,
+    /// Account description to use for all the transactions, if the
+    /// export does not include one
+    account_desc: Option<String>,
+}
+
+//ip Default for CsvMapping
+impl Default for CsvMapping {
+    fn default() -> Self {
+        Self {
+            has_headers: true,
+            skip_lines: 0,
+            date: CsvColumn::Name("Date".into()),
+            date_format: None,
+            description: vec![CsvColumn::Name("Description".into())],
+            ttype: None,
+            debit: None,
+            credit: None,
+            amount: None,
+            negate_amount: false,
+            balance: None,
+            sort_code: None,
+            account_number: None,
+            order: RowOrder::OldestFirst,
+            account_desc: None,
+        }
+    }
+}
+
+//ip CsvMapping
+impl CsvMapping {
+    //cp from_str
+    /// Parse a mapping from a YAML or JSON string
+    pub fn from_str(s: &str, file_type: FileType) -> Result<Self, Error> {
+        let mapping: Self = match file_type {
+            FileType::Json => serde_json::from_str(s)?,
+            FileType::Yaml => serde_yaml::from_str(s)?,
+            _ => return Err(Error::FileTypeNotSupported(file_type, "CSV mapping")),
+        };
+        mapping.validate()?;
+        Ok(mapping)
+    }
+
+    //cp read_file
+    /// Read a mapping from a YAML or JSON file
+    pub fn read_file(filename: &str) -> Result<Self, Error> {
+        let file_type = FileType::from_filename(filename)?;
+        let s = std::fs::read_to_string(filename)?;
+        Self::from_str(&s, file_type)
+    }
+
+    //mi validate
+    fn validate(&self) -> Result<(), Error> {
+        if self.amount.is_none() && self.debit.is_none() && self.credit.is_none() {
+            return Err(Error::TransactionLog(
+                "CSV mapping must have an 'amount' column or 'debit'/'credit' columns".into(),
+            ));
+        }
+        if self.amount.is_some() && (self.debit.is_some() || self.credit.is_some()) {
+            return Err(Error::TransactionLog(
+                "CSV mapping cannot have both an 'amount' column and 'debit'/'credit' columns"
+                    .into(),
+            ));
+        }
+        if let Some(account_desc) = &self.account_desc {
+            account_desc.parse::<AccountDesc>()?;
+        }
+        Ok(())
+    }
+
+    //mi resolve
+    /// Find the index of a column
+    fn resolve(&self, columns: &CsvColumns, column: &CsvColumn) -> Result<usize, Error> {
+        match column {
+            CsvColumn::Index(n) => Ok(*n),
+            CsvColumn::Name(name) => {
+                if !self.has_headers {
+                    return Err(Error::TransactionLog(format!(
+                        "CSV mapping names {column} but the export has no header row"
+                    )));
+                }
+                columns.require(name)
+            }
+        }
+    }
+
+    //mi resolve_opt
+    fn resolve_opt(
+        &self,
+        columns: &CsvColumns,
+        column: &Option<CsvColumn>,
+    ) -> Result<Option<usize>, Error> {
+        column
+            .as_ref()
+            .map(|c| self.resolve(columns, c))
+            .transpose()
+    }
+
+    //mi parse_date
+    fn parse_date(&self, s: &str) -> Result<Date, Error> {
+        match &self.date_format {
+            Some(format) => Date::parse_with_format(s, format),
+            None => Date::parse(s),
+        }
+    }
+
+    //mi parse_row
+    fn parse_row(&self, r: &csv::StringRecord, c: &MappedColumns) -> Result<BankRow, Error> {
+        let date_str = super::field(r, c.date).unwrap_or_default();
+        let date = self.parse_date(date_str)?;
+        let description: Vec<&str> = c
+            .description
+            .iter()
+            .filter_map(|n| super::field(r, *n))
+            .collect();
+        let description = description.join(" ");
+        let row = {
+            if let Some(n) = c.amount {
+                let amount = super::parse_amount(super::field(r, n))?.unwrap_or_default();
+                let amount = if self.negate_amount { -amount } else { amount };
+                BankRow::new(date, BankTransactionType::Unknown, &description)
+                    .with_signed_amount(amount)
+            } else {
+                let debit = c.debit.and_then(|n| super::field(r, n));
+                let credit = c.credit.and_then(|n| super::field(r, n));
+                let debit = super::parse_amount(debit)?.map(|a| {
+                    // Some exports show debits as negative numbers
+                    if a.value() < 0 {
+                        -a
+                    } else {
+                        a
+                    }
+                });
+                let credit = super::parse_amount(credit)?;
+                BankRow::new(date, BankTransactionType::Unknown, &description)
+                    .with_debit_credit(debit, credit)
+            }
+        };
+        let is_debit = !row.debit.is_zero();
+        let ttype = c.ttype.and_then(|n| super::field(r, n)).unwrap_or_default();
+        let mut row = BankRow {
+            ttype: BankTransactionType::parse(ttype, is_debit)?,
+            ..row
+        };
+        row.balance = super::parse_amount(c.balance.and_then(|n| super::field(r, n)))?;
+        if let (Some(sc), Some(an)) = (c.sort_code, c.account_number) {
+            if let (Some(sc), Some(an)) = (super::field(r, sc), super::field(r, an)) {
+                let sc = sc.trim_start_matches('\'');
+                let an = an.trim_start_matches('\'');
+                let account = an
+                    .parse::<usize>()
+                    .map_err(|_| Error::ParseAccount(format!("{sc}:{an}")))?;
+                row.account_desc = AccountDesc::parse_uk(sc, account)?;
+            }
+        }
+        Ok(row)
+    }
+
+    //mp read_transactions_csv
+    /// Read a CSV transactions file using the mapping, and return a
+    /// Vec<BankTransaction> oldest first
+    ///
+    /// The account description (if not None) overrides that of the
+    /// mapping, which in turn overrides any sort code and account
+    /// number columns; if there are no balances in the export, the
+    /// opening balance is required
+    pub fn read_transactions_csv<R: std::io::Read>(
+        &self,
+        reader: R,
+        account_desc: &AccountDesc,
+        opening_balance: Option<Amount>,
+    ) -> Result<Vec<BankTransaction>, Error> {
+        let mut bytes = vec![];
+        let mut reader = reader;
+        reader.read_to_end(&mut bytes)?;
+        let mut start = 0;
+        for _ in 0..self.skip_lines {
+            match bytes[start..].iter().position(|b| *b == b'\n') {
+                Some(n) => start += n + 1,
+                None => start = bytes.len(),
+            }
+        }
+        let mut csv_reader = csv::ReaderBuilder::new()
+            .has_headers(self.has_headers)
+            .flexible(true)
+            .from_reader(&bytes[start..]);
+        let columns = {
+            if self.has_headers {
+                CsvColumns::new(csv_reader.headers()?)
+            } else {
+                CsvColumns::default()
+            }
+        };
+        let mapped = MappedColumns {
+            date: self.resolve(&columns, &self.date)?,
+            description: self
+                .description
+                .iter()
+                .map(|c| self.resolve(&columns, c))
+                .collect::<Result<_, _>>()?,
+            ttype: self.resolve_opt(&columns, &self.ttype)?,
+            debit: self.resolve_opt(&columns, &self.debit)?,
+            credit: self.resolve_opt(&columns, &self.credit)?,
+            amount: self.resolve_opt(&columns, &self.amount)?,
+            balance: self.resolve_opt(&columns, &self.balance)?,
+            sort_code: self.resolve_opt(&columns, &self.sort_code)?,
+            account_number: self.resolve_opt(&columns, &self.account_number)?,
+        };
+
+        let mut rows = vec![];
+        for record in csv_reader.records() {
+            let r = record?;
+            if r.iter().all(|f| f.trim().is_empty()) {
+                continue;
+            }
+            rows.push(self.parse_row(&r, &mapped)?);
+        }
+
+        let account_desc = {
+            if !account_desc.is_none() {
+                account_desc.clone()
+            } else if let Some(account_desc) = &self.account_desc {
+                account_desc.parse()?
+            } else {
+                AccountDesc::None
+            }
+        };
+        super::rows_to_transactions(rows, &account_desc, opening_balance, self.order)
+    }
+}
+
+//a MappedColumns
+//ti MappedColumns
+/// The column indices of a mapping for a particular export
+struct MappedColumns {
+    date: usize,
+    description: Vec<usize>,
+    ttype: Option<usize>,
+    debit: Option<usize>,
+    credit: Option<usize>,
+    amount: Option<usize>,
+    balance: Option<usize>,
+    sort_code: Option<usize>,
+    account_number: Option<usize>,
+}
@@ -1 +1,475 @@
+//a Documentation
+//! Importers for the transaction exports of various banks
+//!
+//! Each bank module provides a `read_transactions_csv` function that
+//! returns a Vec<BankTransaction> in date order (oldest first), whose
+//! balances have been validated as a chain
+//!
+//! The banks differ in whether they export newest-first or
+//! oldest-first, whether they include a balance column, and whether
+//! they have separate debit/credit columns or a single signed amount;
+//! the [BankRow] intermediate and the functions here handle these
+//! differences once for all the banks
+
+//a Imports
+use serde::{Deserialize, Serialize};
+
+use crate::indexed_vec::Idx;
+use crate::{AccountDesc, Amount, BankTransaction, BankTransactionType, Database, Date, Error};
+
+//a Bank modules
+pub mod barclays;
+pub mod camt053;
+pub mod hsbc;
 pub mod lloyds;
+pub mod mapping;
+pub mod monzo;
+pub mod mt940;
+pub mod nationwide;
+pub mod natwest;
+pub mod ofx;
+pub mod qif;
+pub mod santander;
+pub mod starling;
+
+//a RowOrder
+//tp RowOrder
+/// The order of the rows in a bank export
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RowOrder {
+    /// Oldest transaction first
+    #[default]
+    OldestFirst,
+    /// Newest transaction first
+    NewestFirst,
+}
+
+//a BankRow
+//tp BankRow
+/// A row of a bank export, before it becomes a [BankTransaction]
+///
+/// The balance is optional, as not all exports provide it
+#[derive(Debug, Default, Clone)]
+pub struct BankRow {
+    pub date: Date,
+    pub ttype: BankTransactionType,
+    pub account_desc: AccountDesc,
+    pub description: String,
+    pub debit: Amount,
+    pub credit: Amount,
+    pub balance: Option<Amount>,
+    pub fitid: Option<String>,
+    pub counterparty: Counterparty,
+}
+
+//ip BankRow
+impl BankRow {
+    //cp new
+    pub fn new(date: Date, ttype: BankTransactionType, description: &str) -> Self {
+        Self {
+            date,
+            ttype,
+            description: description.trim().into(),
+            ..Default::default()
+        }
+    }
+
+    //cp with_signed_amount
+    /// Set the debit or credit from a single signed amount, where a
+    /// negative amount is a debit
+    pub fn with_signed_amount(mut self, amount: Amount) -> Self {
+        if amount.value() < 0 {
+            self.debit = -amount;
+        } else {
+            self.credit = amount;
+        }
+        self
+    }
+
+    //cp with_debit_credit
+    /// Set the debit and credit from separate (possibly empty) columns
+    pub fn with_debit_credit(mut self, debit: Option<Amount>, credit: Option<Amount>) -> Self {
+        self.debit = debit.unwrap_or_default();
+        self.credit = credit.unwrap_or_default();
+        self
+    }
+
+    //cp with_balance
+    pub fn with_balance(mut self, balance: Option<Amount>) -> Self {
+        self.balance = balance;
+        self
+    }
+
+    //cp with_account_desc
+    pub fn with_account_desc(mut self, account_desc: AccountDesc) -> Self {
+        self.account_desc = account_desc;
+        self
+    }
+
+    //cp with_fitid
+    pub fn with_fitid(mut self, fitid: Option<String>) -> Self {
+        self.fitid = fitid;
+        self
+    }
+
+    //cp with_counterparty
+    pub fn with_counterparty(mut self, counterparty: Counterparty) -> Self {
+        self.counterparty = counterparty;
+        self
+    }
+
+    //ap balance_delta
+    pub fn balance_delta(&self) -> Amount {
+        self.credit - self.debit
+    }
+}
+
+//a Counterparty
+//tp Counterparty
+/// The other party of a bank transaction, as given by a statement
+///
+/// Statements such as camt.053 and MT940 may give the name and IBAN
+/// of the payer or payee; these identify the related party far better
+/// than the description of the transaction does
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Counterparty {
+    pub name: Option<String>,
+    pub iban: Option<String>,
+}
+
+//ip Counterparty
+impl Counterparty {
+    //ap is_none
+    pub fn is_none(&self) -> bool {
+        self.name.is_none() && self.iban.is_none()
+    }
+}
+
+//a Statement
+//tp Statement
+/// The bank transactions of a statement, oldest first, with the
+/// counterparty (if known) of each
+#[derive(Debug, Default)]
+pub struct Statement {
+    pub transactions: Vec<BankTransaction>,
+    pub counterparties: Vec<Counterparty>,
+}
+
+//ip Statement
+impl Statement {
+    //mp resolve_related_parties
+    /// Set the related party of every transaction whose counterparty
+    /// is a known related party
+    ///
+    /// Transactions without a known counterparty are left for the
+    /// related party to be found from the description when they are
+    /// added to an account
+    pub fn resolve_related_parties(&mut self, db: &Database) {
+        for (t, c) in self.transactions.iter_mut().zip(self.counterparties.iter()) {
+            if !t.related_party().is_none() {
+                continue;
+            }
+            let db_id = db.find_counterparty_related_party(c);
+            if !db_id.is_none() {
+                t.set_related_party(db_id);
+            }
+        }
+    }
+
+    //mp into_transactions
+    pub fn into_transactions(self) -> Vec<BankTransaction> {
+        self.transactions
+    }
+}
+
+//a CsvColumns
+//tp CsvColumns
+/// The columns of a CSV export, found by header name
+///
+/// Header names are matched ignoring case and surrounding whitespace
+#[derive(Debug, Default)]
+pub struct CsvColumns {
+    headers: Vec<String>,
+}
+
+//ip CsvColumns
+impl CsvColumns {
+    //cp new
+    pub fn new(headers: &csv::StringRecord) -> Self {
+        let headers = headers
+            .iter()
+            .map(|h| h.trim().trim_matches('"').to_ascii_lowercase())
+            .collect();
+        Self { headers }
+    }
+
+    //ap index
+    pub fn index(&self, name: &str) -> Option<usize> {
+        let name = name.to_ascii_lowercase();
+        self.headers.iter().position(|h| *h == name)
+    }
+
+    //mp require
+    /// Get the index of a column that must be present
+    pub fn require(&self, name: &str) -> Result<usize, Error> {
+        self.index(name)
+            .ok_or_else(|| Error::TransactionLog(format!("CSV has no '{name}' column")))
+    }
+
+    //mp field
+    /// Get the trimmed contents of a column of a record, if the
+    /// column exists and is not empty
+    pub fn field<'r>(&self, record: &'r csv::StringRecord, name: &str) -> Option<&'r str> {
+        field(record, self.index(name)?)
+    }
+}
+
+//a Public functions
+//fp field
+/// Get the trimmed contents of a field of a record, if it exists and
+/// is not empty
+pub fn field(record: &csv::StringRecord, index: usize) -> Option<&str> {
+    let s = record.get(index)?.trim();
+    if s.is_empty() {
+        None
+    } else {
+        Some(s)
+    }
+}
+
+//fp parse_amount
+/// Parse an optional amount field
+pub fn parse_amount(s: Option<&str>) -> Result<Option<Amount>, Error> {
+    s.map(|s| s.parse::<Amount>()).transpose()
+}
+
+//fp detect_row_order
+/// Detect the order of the rows of an export
+///
+/// If the first and last dates differ then they determine the order;
+/// otherwise, if the balances are present, the order is the one in
+/// which they form a valid chain; otherwise the default is used
+pub fn detect_row_order(rows: &[BankRow], default: RowOrder) -> RowOrder {
+    let (Some(first), Some(last)) = (rows.first(), rows.last()) else {
+        return default;
+    };
+    if first.date < last.date {
+        return RowOrder::OldestFirst;
+    }
+    if first.date > last.date {
+        return RowOrder::NewestFirst;
+    }
+    let chains = |rows: &mut dyn Iterator<Item = &BankRow>| {
+        let mut balance: Option<Amount> = None;
+        for r in rows {
+            let Some(b) = r.balance else {
+                return false;
+            };
+            if let Some(balance) = balance {
+                if balance + r.balance_delta() != b {
+                    return false;
+                }
+            }
+            balance = Some(b);
+        }
+        true
+    };
+    let forwards = chains(&mut rows.iter());
+    let backwards = chains(&mut rows.iter().rev());
+    match (forwards, backwards) {
+        (true, false) => RowOrder::OldestFirst,
+        (false, true) => RowOrder::NewestFirst,
+        _ => default,
+    }
+}
+
+//fp fill_balances
+/// Fill in any missing balances of rows that are oldest first
+///
+/// Rows before the first known balance are filled by working back
+/// from that balance; later rows are filled forward from the previous
+/// balance. If no row has a balance then the opening balance (the
+/// balance before the first row) is required.
+pub fn fill_balances(rows: &mut [BankRow], opening_balance: Option<Amount>) -> Result<(), Error> {
+    if rows.is_empty() {
+        return Ok(());
+    }
+    let mut balance = {
+        if let Some(opening_balance) = opening_balance {
+            opening_balance
+        } else if let Some(n) = rows.iter().position(|r| r.balance.is_some()) {
+            let mut balance = rows[n].balance.unwrap() - rows[n].balance_delta();
+            for r in rows[0..n].iter().rev() {
+                balance -= r.balance_delta();
+            }
+            balance
+        } else {
+            return Err(Error::TransactionLog(
+                "export has no balances, and no opening balance was provided".into(),
+            ));
+        }
+    };
+    for r in rows.iter_mut() {
+        match r.balance {
+            Some(b) => {
+                balance = b;
+            }
+            None => {
+                balance += r.balance_delta();
+                r.balance = Some(balance);
+            }
+        }
+    }
+    Ok(())
+}
+
+//fp validate_balance_chain
+/// Validate that the transactions all belong to the same account, and
+/// that each balance is the previous balance plus the transaction
+///
+/// If an opening balance is given then the first transaction must
+/// follow on from it
+pub fn validate_balance_chain(
+    transactions: &[BankTransaction],
+    opening_balance: Option<Amount>,
+) -> Result<(), Error> {
+    let Some(first) = transactions.first() else {
+        return Ok(());
+    };
+    if let Some(opening_balance) = opening_balance {
+        let new_balance = opening_balance + first.balance_delta();
+        if new_balance != first.balance() {
+            return Err(Error::TransactionLog(format!("opening balance was {opening_balance} but after entry 1 it was calculated to be {new_balance} rather than {}", first.balance())));
+        }
+    }
+    let mut balance = first.balance();
+    for (i, t) in transactions.iter().enumerate().skip(1) {
+        if t.account_desc() != first.account_desc() {
+            return Err(Error::TransactionLog(format!(
+                "entry {} has different account description {} to the first entry {}",
+                i + 1,
+                t.account_desc(),
+                first.account_desc()
+            )));
+        }
+        let new_balance = balance + t.balance_delta();
+        if new_balance != t.balance() {
+            return Err(Error::TransactionLog(format!(
+                "balance before entry {} was {balance} but after it was calculated to be {new_balance}",
+                i + 1
+            )));
+        }
+        balance = new_balance;
+    }
+    Ok(())
+}
+
+//fp rows_to_statement
+/// Convert the rows of an export into a statement of validated bank
+/// transactions, oldest first
+///
+/// The rows are reordered if required (using the default order if
+/// the order cannot be detected), missing balances are filled in, and
+/// the balance chain is validated
+///
+/// If the account description is not None then it is used for every
+/// transaction; otherwise each row must have provided its own
+pub fn rows_to_statement(
+    mut rows: Vec<BankRow>,
+    account_desc: &AccountDesc,
+    opening_balance: Option<Amount>,
+    default_order: RowOrder,
+) -> Result<Statement, Error> {
+    if detect_row_order(&rows, default_order) == RowOrder::NewestFirst {
+        rows.reverse();
+    }
+    fill_balances(&mut rows, opening_balance)?;
+    let mut statement = Statement::default();
+    for r in rows {
+        let row_desc = {
+            if account_desc.is_none() {
+                r.account_desc
+            } else {
+                account_desc.clone()
+            }
+        };
+        if row_desc.is_none() {
+            return Err(Error::TransactionLog(
+                "export does not identify the account, and no account was provided".into(),
+            ));
+        }
+        statement.transactions.push(
+            BankTransaction::new(
+                r.date,
+                r.ttype,
+                row_desc,
+                r.description,
+                r.debit,
+                r.credit,
+                r.balance.unwrap_or_default(),
+            )
+            .with_fitid(r.fitid),
+        );
+        statement.counterparties.push(r.counterparty);
+    }
+    validate_balance_chain(&statement.transactions, opening_balance)?;
+    Ok(statement)
+}
+
+//fp rows_to_transactions
+/// Convert the rows of an export into validated bank transactions,
+/// oldest first, as for [rows_to_statement]
+pub fn rows_to_transactions(
+    rows: Vec<BankRow>,
+    account_desc: &AccountDesc,
+    opening_balance: Option<Amount>,
+    default_order: RowOrder,
+) -> Result<Vec<BankTransaction>, Error> {
+    Ok(rows_to_statement(rows, account_desc, opening_balance, default_order)?.transactions)
+}
+
+//fp check_closing_balance
+/// Check that the last transaction of a statement has the closing
+/// balance given by the statement
+pub fn check_closing_balance(
+    transactions: &[BankTransaction],
+    opening_balance: Amount,
+    closing_balance: Amount,
+) -> Result<(), Error> {
+    let balance = transactions
+        .last()
+        .map(|t| t.balance())
+        .unwrap_or(opening_balance);
+    if balance != closing_balance {
+        return Err(Error::TransactionLog(format!(
+            "statement closing balance is {closing_balance} but the transactions give {balance}"
+        )));
+    }
+    Ok(())
+}
+
+//fp read_csv_rows
+/// Read all the records of a CSV export that has a header row,
+/// returning the columns and the records
+///
+/// Any lines before the header row (as some banks have) must be
+/// skipped by the caller first
+pub fn read_csv_rows<R: std::io::Read>(
+    reader: R,
+) -> Result<(CsvColumns, Vec<csv::StringRecord>), Error> {
+    let mut csv_reader = csv::ReaderBuilder::new()
+        .has_headers(true)
+        .flexible(true)
+        .from_reader(reader);
+    let columns = CsvColumns::new(csv_reader.headers()?);
+    let mut records = vec![];
+    for record in csv_reader.records() {
+        let record = record?;
+        if record.iter().all(|f| f.trim().is_empty()) {
+            continue;
+        }
+        records.push(record);
+    }
+    Ok((columns, records))
+}
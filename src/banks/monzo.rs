@@ -0,0 +1,62 @@
+// Transaction ID,Date,Time,Type,Name,Emoji,Category,Amount,Currency,Local amount,Local currency,Notes and #tags,Address,Receipt,Description,Category split,Money Out,Money In
+// tx_0000A,28/08/2024,10:15:00,Faster payment,NAME,,income,20.00,GBP,20.00,GBP,,,,REASON,,,20.00
+//
+// Monzo exports have a single signed amount and no balance
+use super::{BankRow, RowOrder};
+use crate::{AccountDesc, Amount, BankTransaction, BankTransactionType, BankTypeMap, Date, Error};
+
+//fi TYPE_MAP
+/// Monzo types
+const TYPE_MAP: &BankTypeMap = {
+    use BankTransactionType::*;
+    &[
+        ("DIRECT DEBIT", DirectDebitRefund, DirectDebit),
+        ("STANDING ORDER", StandingOrderIn, StandingOrder),
+        ("FASTER PAYMENT", Fpi, FasterPaymentOut),
+        ("MONZO-TO-MONZO", Fpi, FasterPaymentOut),
+        ("BACS", BacsIn, BacsOut),
+        ("CARD PAYMENT", CardRefund, CardPayment),
+        ("POT TRANSFER", TransferIn, TransferOut),
+        ("ACCOUNT INTEREST", Interest, Charge),
+    ]
+};
+
+//fi ttype
+/// Map a Monzo type to a transaction type
+fn ttype(s: &str, is_debit: bool) -> Result<BankTransactionType, Error> {
+    Ok(BankTransactionType::parse_with_map(s, is_debit, TYPE_MAP))
+}
+
+//fp read_transactions_csv
+/// Read a Monzo CSV transactions file and return a Vec<BankTransaction>
+///
+/// Monzo exports do not identify the account, so the account
+/// description must be provided; as there are no balances in the
+/// export, the opening balance is required
+pub fn read_transactions_csv<R: std::io::Read>(
+    reader: R,
+    account_desc: &AccountDesc,
+    opening_balance: Option<Amount>,
+) -> Result<Vec<BankTransaction>, Error> {
+    let (columns, records) = super::read_csv_rows(reader)?;
+    let c_date = columns.require("Date")?;
+    let c_amount = columns.require("Amount")?;
+    let mut rows = vec![];
+    for r in records.iter() {
+        let date = Date::parse(super::field(r, c_date).unwrap_or_default())?;
+        let amount: Amount = super::field(r, c_amount).unwrap_or("0").parse()?;
+        let name = columns.field(r, "Name").unwrap_or_default();
+        let description = {
+            match columns.field(r, "Description") {
+                Some(d) if d != name => format!("{name} {d}"),
+                _ => name.to_string(),
+            }
+        };
+        let ttype_str = columns.field(r, "Type").unwrap_or_default();
+        rows.push(
+            BankRow::new(date, ttype(ttype_str, amount.value() < 0)?, &description)
+                .with_signed_amount(amount),
+        );
+    }
+    super::rows_to_transactions(rows, account_desc, opening_balance, RowOrder::OldestFirst)
+}
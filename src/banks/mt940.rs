@@ -0,0 +1,303 @@
+//a Documentation
+//! Import of SWIFT MT940 statements
+//!
+//! A file has one or more statements, each starting with a :20: tag
+//! and containing:
+//!
+//! * :25: the account (an IBAN, or bank code/account)
+//!
+//! * :60F: (or :60M:) the opening balance, e.g. C240801EUR1234,56
+//!
+//! * :61: statement lines, e.g. 2408280828C20,00NTRFNONREF//BANKREF
+//!
+//! * :86: information for the preceding statement line
+//!
+//! * :62F: (or :62M:) the closing balance
+//!
+//! The :86: field may be structured, either with German '?nn'
+//! subfields (?32/?33 name, ?31 account) or with '/NAME/.../IBAN/...'
+//! style keywords; the counterparty is taken from these if present
+
+//a Imports
+use regex::Regex;
+
+use super::{BankRow, Counterparty, RowOrder, Statement};
+use crate::{AccountDesc, Amount, BankTransactionType, BankTypeMap, Date, Error};
+
+//a Internal functions
+//fi tags
+/// Split the text of a statement into its tags and their values,
+/// joining continuation lines (with a newline)
+fn tags(text: &str) -> Vec<(String, String)> {
+    let mut result: Vec<(String, String)> = vec![];
+    for line in text.lines() {
+        let line = line.trim_end_matches('\r');
+        // Skip the SWIFT block headers and the end of block markers
+        if line.starts_with('{') || line.trim() == "-" || line.trim() == "-}" {
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix(':') {
+            if let Some((tag, value)) = rest.split_once(':') {
+                result.push((tag.to_string(), value.to_string()));
+                continue;
+            }
+        }
+        if let Some((_, value)) = result.last_mut() {
+            value.push('\n');
+            value.push_str(line);
+        }
+    }
+    result
+}
+
+//fi parse_amount
+/// Parse an MT940 amount, which uses a comma as the decimal separator
+fn parse_amount(s: &str) -> Result<Amount, Error> {
+    s.replace(',', ".").parse()
+}
+
+//fi parse_date
+/// Parse an MT940 YYMMDD date
+fn parse_date(s: &str) -> Result<Date, Error> {
+    let n = |r: std::ops::Range<usize>| {
+        s.get(r)
+            .and_then(|s| s.parse::<u32>().ok())
+            .ok_or_else(|| Error::ParseDate(s.into()))
+    };
+    Date::of_dmy(n(4..6)?, n(2..4)?, n(0..2)? as i32)
+}
+
+//fi parse_balance
+/// Parse a balance tag such as C240801EUR1234,56
+fn parse_balance(s: &str) -> Result<Amount, Error> {
+    let s = s.lines().next().unwrap_or_default().trim();
+    let err = || Error::TransactionLog(format!("bad MT940 balance {s}"));
+    let sign = s.get(0..1).ok_or_else(err)?;
+    let amount = parse_amount(s.get(10..).ok_or_else(err)?)?;
+    match sign {
+        "C" => Ok(amount),
+        "D" => Ok(-amount),
+        _ => Err(err()),
+    }
+}
+
+//fi TYPE_MAP
+/// MT940 transaction type identification codes
+const TYPE_MAP: &BankTypeMap = {
+    use BankTransactionType::*;
+    &[
+        ("NDDT", DirectDebitRefund, DirectDebit),
+        ("NSTO", StandingOrderIn, StandingOrder),
+        ("NTRF", Fpi, FasterPaymentOut),
+        ("NCHK", ChequeIn(None), ChequeOut(None)),
+        ("NCMI", Deposit, CashWithdrawal),
+        ("NCHG", Correction, Charge),
+        ("NINT", Interest, Charge),
+    ]
+};
+
+//fi ttype
+/// Map an MT940 transaction type identification code to a transaction type
+fn ttype(code: &str, is_debit: bool) -> Result<BankTransactionType, Error> {
+    Ok(BankTransactionType::parse_with_map(
+        code, is_debit, TYPE_MAP,
+    ))
+}
+
+//fi parse_information
+/// Parse a :86: information field into a description and a counterparty
+fn parse_information(info: &str) -> Result<(String, Counterparty), Error> {
+    let info = info.replace('\n', "");
+    let mut counterparty = Counterparty::default();
+    let b = info.as_bytes();
+    if b.len() > 4 && b[0..3].iter().all(|c| c.is_ascii_digit()) && b[3] == b'?' {
+        // German structured subfields: ?nn followed by the value
+        let mut remittance = vec![];
+        let mut name = String::new();
+        for field in info[4..].split('?') {
+            let Some(code) = field
+                .get(..2)
+                .filter(|c| c.bytes().all(|b| b.is_ascii_digit()))
+            else {
+                return Err(Error::TransactionLog(format!(
+                    "bad MT940 information subfield ?{field}"
+                )));
+            };
+            let value = &field[2..];
+            match code {
+                "20" | "21" | "22" | "23" | "24" | "25" | "26" | "27" | "28" | "29" => {
+                    remittance.push(value.trim().to_string())
+                }
+                "31" => counterparty.iban = Some(value.trim().to_string()),
+                "32" | "33" => name.push_str(value),
+                _ => (),
+            }
+        }
+        if !name.trim().is_empty() {
+            counterparty.name = Some(name.trim().to_string());
+        }
+        if counterparty
+            .iban
+            .as_ref()
+            .is_some_and(|i| AccountDesc::iban(i).is_err())
+        {
+            counterparty.iban = None;
+        }
+        let mut description = remittance.join(" ");
+        if let Some(name) = &counterparty.name {
+            description = format!("{name} {description}");
+        }
+        return Ok((description.trim().to_string(), counterparty));
+    }
+    if info.starts_with('/') {
+        // Keyword structure: /KEY/value/KEY/value...; '//' within a
+        // value (as in /ORDP//NAME/...) introduces sub-keywords
+        let mut remittance = vec![];
+        let parts: Vec<&str> = info.split('/').collect();
+        let mut i = 1;
+        while i + 1 < parts.len() {
+            let key = parts[i];
+            let value = parts[i + 1].trim();
+            match key {
+                "NAME" => counterparty.name = Some(value.to_string()),
+                "IBAN" => counterparty.iban = Some(value.to_string()),
+                "REMI" | "EREF" => remittance.push(value.to_string()),
+                "ORDP" | "BENM" | "" => {
+                    i += 1;
+                    continue;
+                }
+                _ => (),
+            }
+            i += 2;
+        }
+        let mut description = remittance.join(" ");
+        if let Some(name) = &counterparty.name {
+            description = format!("{name} {description}");
+        }
+        return Ok((description.trim().to_string(), counterparty));
+    }
+    Ok((info.trim().to_string(), counterparty))
+}
+
+//fi read_statement
+fn read_statement(
+    tags: &[(String, String)],
+    account_desc: &AccountDesc,
+    line_re: &Regex,
+) -> Result<Statement, Error> {
+    let mut statement_desc = AccountDesc::None;
+    let mut opening_balance = None;
+    let mut closing_balance = None;
+    let mut rows: Vec<BankRow> = vec![];
+    for (tag, value) in tags {
+        match tag.as_str() {
+            "25" => {
+                let v = value.trim();
+                if let Ok(iban) = AccountDesc::iban(v) {
+                    statement_desc = iban;
+                } else if let Some((bank, account)) = v.split_once('/') {
+                    statement_desc = AccountDesc::bic(bank, account).unwrap_or_default();
+                }
+            }
+            "60F" | "60M" => {
+                opening_balance = Some(parse_balance(value)?);
+            }
+            "62F" | "62M" => {
+                closing_balance = Some(parse_balance(value)?);
+            }
+            "61" => {
+                let Some(c) = line_re.captures(value) else {
+                    return Err(Error::TransactionLog(format!(
+                        "bad MT940 statement line {value}"
+                    )));
+                };
+                let date = parse_date(&c[1])?;
+                let is_debit = c[2].ends_with('D');
+                let amount = parse_amount(&c[3])?;
+                let amount = if is_debit { -amount } else { amount };
+                let customer_ref = c[5].trim();
+                let bank_ref = c
+                    .get(6)
+                    .map(|m| m.as_str().trim())
+                    .filter(|s| !s.is_empty());
+                let mut row = BankRow::new(date, ttype(&c[4], is_debit)?, "")
+                    .with_signed_amount(amount)
+                    .with_fitid(bank_ref.map(|s| s.to_string()));
+                if customer_ref != "NONREF" {
+                    row.description = customer_ref.to_string();
+                }
+                rows.push(row);
+            }
+            "86" => {
+                if let Some(row) = rows.last_mut() {
+                    let (description, counterparty) = parse_information(value)?;
+                    if !description.is_empty() {
+                        row.description = description;
+                    }
+                    row.counterparty = counterparty;
+                }
+            }
+            _ => (),
+        }
+    }
+    let account_desc = {
+        if account_desc.is_none() {
+            statement_desc
+        } else {
+            account_desc.clone()
+        }
+    };
+    let Some(opening_balance) = opening_balance else {
+        return Err(Error::TransactionLog(
+            "MT940 statement has no opening balance".into(),
+        ));
+    };
+    let statement = super::rows_to_statement(
+        rows,
+        &account_desc,
+        Some(opening_balance),
+        RowOrder::OldestFirst,
+    )?;
+    if let Some(closing_balance) = closing_balance {
+        super::check_closing_balance(&statement.transactions, opening_balance, closing_balance)?;
+    }
+    Ok(statement)
+}
+
+//a Public functions
+//fp read_statements_mt940
+/// Read an MT940 file and return its statements
+///
+/// If the account description is not None then it is used instead of
+/// the account (:25:) of each statement
+pub fn read_statements_mt940<R: std::io::Read>(
+    mut reader: R,
+    account_desc: &AccountDesc,
+) -> Result<Vec<Statement>, Error> {
+    let mut bytes = vec![];
+    reader.read_to_end(&mut bytes)?;
+    let text = String::from_utf8_lossy(&bytes);
+    // date, [entry date], [R]C/D, [funds code], amount, type, customer ref, [//bank ref]
+    let line_re = Regex::new(
+        r"^(\d{6})(?:\d{4})?(R?[CD])[A-Z]?(\d+,\d*)([NSF][A-Z0-9]{3})([^/\n]*)(?://([^\n]*))?",
+    )
+    .unwrap();
+    let all_tags = tags(&text);
+    let mut result = vec![];
+    let mut start = None;
+    for (i, (tag, _)) in all_tags.iter().enumerate() {
+        if tag == "20" {
+            if let Some(s) = start {
+                result.push(read_statement(&all_tags[s..i], account_desc, &line_re)?);
+            }
+            start = Some(i);
+        }
+    }
+    if let Some(s) = start {
+        result.push(read_statement(&all_tags[s..], account_desc, &line_re)?);
+    }
+    if result.is_empty() {
+        return Err(Error::TransactionLog("MT940 file has no statements".into()));
+    }
+    Ok(result)
+}
@@ -0,0 +1,86 @@
+// "Account Name:","FlexAccount ****12345"
+// "Account Balance:","£1,234.56"
+// "Available Balance: ","£1,234.56"
+//
+// "Date","Transaction type","Description","Paid out","Paid in","Balance"
+// "28 Aug 2024","Bank credit NAME","NAME REASON","","£20.00","£1,234.56"
+//
+// Nationwide exports have some account lines before the header, and
+// are usually encoded as Windows-1252 rather than UTF-8
+use super::{BankRow, RowOrder};
+use crate::{AccountDesc, Amount, BankTransaction, BankTransactionType, BankTypeMap, Date, Error};
+
+//fi TYPE_MAP
+/// Nationwide transaction types, which start with the type
+const TYPE_MAP: &BankTypeMap = {
+    use BankTransactionType::*;
+    &[
+        ("DIRECT DEBIT", DirectDebitRefund, DirectDebit),
+        ("STANDING ORDER", StandingOrderIn, StandingOrder),
+        ("BANK CREDIT", BacsIn, BacsOut),
+        ("TRANSFER FROM", Fpi, FasterPaymentOut),
+        ("TRANSFER TO", Fpi, FasterPaymentOut),
+        ("PAYMENT TO", Fpi, FasterPaymentOut),
+        ("CASH CREDIT", Deposit, CashWithdrawal),
+        ("CHEQUE CREDIT", ChequeIn(None), ChequeOut(None)),
+        ("CHEQUE", ChequeIn(None), ChequeOut(None)),
+        ("VISA PURCHASE", CardRefund, CardPayment),
+        ("CONTACTLESS PAYMENT", CardRefund, CardPayment),
+        ("ATM WITHDRAWAL", Deposit, CashWithdrawal),
+        ("INTEREST", Interest, Charge),
+    ]
+};
+
+//fi ttype
+/// Map a Nationwide transaction type to a transaction type
+fn ttype(s: &str, is_debit: bool) -> Result<BankTransactionType, Error> {
+    Ok(BankTransactionType::parse_with_map(s, is_debit, TYPE_MAP))
+}
+
+//fi decode
+/// Decode the export as UTF-8 if it is, or else as Windows-1252 (for
+/// the characters that matter, such as '£', this is Latin-1)
+fn decode(bytes: Vec<u8>) -> String {
+    match String::from_utf8(bytes) {
+        Ok(s) => s,
+        Err(e) => e.into_bytes().into_iter().map(|b| b as char).collect(),
+    }
+}
+
+//fp read_transactions_csv
+/// Read a Nationwide CSV transactions file and return a Vec<BankTransaction>
+///
+/// Nationwide exports do not fully identify the account, so the
+/// account description must be provided
+pub fn read_transactions_csv<R: std::io::Read>(
+    mut reader: R,
+    account_desc: &AccountDesc,
+    opening_balance: Option<Amount>,
+) -> Result<Vec<BankTransaction>, Error> {
+    let mut bytes = vec![];
+    reader.read_to_end(&mut bytes)?;
+    let text = decode(bytes);
+    let Some(start) = text.find("\"Date\"") else {
+        return Err(Error::TransactionLog(
+            "Nationwide export has no 'Date' header row".into(),
+        ));
+    };
+    let (columns, records) = super::read_csv_rows(&text.as_bytes()[start..])?;
+    let c_date = columns.require("Date")?;
+    let mut rows = vec![];
+    for r in records.iter() {
+        let date =
+            Date::parse_with_format(super::field(r, c_date).unwrap_or_default(), "%d %b %Y")?;
+        let paid_out = super::parse_amount(columns.field(r, "Paid out"))?;
+        let paid_in = super::parse_amount(columns.field(r, "Paid in"))?;
+        let balance = super::parse_amount(columns.field(r, "Balance"))?;
+        let ttype_str = columns.field(r, "Transaction type").unwrap_or_default();
+        let description = columns.field(r, "Description").unwrap_or_default();
+        rows.push(
+            BankRow::new(date, ttype(ttype_str, paid_out.is_some())?, description)
+                .with_debit_credit(paid_out, paid_in)
+                .with_balance(balance),
+        );
+    }
+    super::rows_to_transactions(rows, account_desc, opening_balance, RowOrder::OldestFirst)
+}
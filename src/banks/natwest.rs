@@ -0,0 +1,87 @@
+// Date, Type, Description, Value, Balance, Account Name, Account Number
+// 28/08/2024,BAC,"'NAME , REASON",20.00,12004.61,"'CURRENT","'601234-12345678",
+//
+// NatWest exports have a single signed value and a balance
+use super::{BankRow, RowOrder};
+use crate::{AccountDesc, Amount, BankTransaction, BankTransactionType, BankTypeMap, Date, Error};
+
+//fi TYPE_MAP
+/// NatWest type codes
+const TYPE_MAP: &BankTypeMap = {
+    use BankTransactionType::*;
+    &[
+        ("D/D", DirectDebitRefund, DirectDebit),
+        ("S/O", StandingOrderIn, StandingOrder),
+        ("BAC", BacsIn, BacsOut),
+        ("DPC", Fpi, FasterPaymentOut),
+        ("OTR", Fpi, FasterPaymentOut),
+        ("CDM", Deposit, CashWithdrawal),
+        ("C/L", Deposit, CashWithdrawal),
+        ("CHQ", ChequeIn(None), ChequeOut(None)),
+        ("POS", CardRefund, CardPayment),
+    ]
+};
+
+//fi ttype
+/// Map a NatWest type code to a transaction type
+fn ttype(code: &str, is_debit: bool) -> Result<BankTransactionType, Error> {
+    Ok(BankTransactionType::parse_with_map(
+        code, is_debit, TYPE_MAP,
+    ))
+}
+
+//fi account_desc
+/// Parse a NatWest account number column, which is "sortcode-account"
+fn account_desc(s: &str) -> Result<AccountDesc, Error> {
+    let s = s.trim_start_matches('\'');
+    let Some((sort_code, account)) = s.split_once('-') else {
+        return s.parse();
+    };
+    let account = account
+        .parse::<usize>()
+        .map_err(|_| Error::ParseAccount(s.into()))?;
+    if sort_code.len() != 6 || !sort_code.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(Error::ParseAccount(s.into()));
+    }
+    let sort_code = format!(
+        "{}-{}-{}",
+        &sort_code[0..2],
+        &sort_code[2..4],
+        &sort_code[4..6]
+    );
+    AccountDesc::parse_uk(&sort_code, account)
+}
+
+//fp read_transactions_csv
+/// Read a NatWest CSV transactions file and return a Vec<BankTransaction>
+///
+/// If the account description is None then the account is taken from
+/// the export
+pub fn read_transactions_csv<R: std::io::Read>(
+    reader: R,
+    account_desc: &AccountDesc,
+    opening_balance: Option<Amount>,
+) -> Result<Vec<BankTransaction>, Error> {
+    let (columns, records) = super::read_csv_rows(reader)?;
+    let c_date = columns.require("Date")?;
+    let c_value = columns.require("Value")?;
+    let mut rows = vec![];
+    for r in records.iter() {
+        let date = Date::parse(super::field(r, c_date).unwrap_or_default())?;
+        let value: Amount = super::field(r, c_value).unwrap_or("0").parse()?;
+        let code = columns.field(r, "Type").unwrap_or_default();
+        let description = columns
+            .field(r, "Description")
+            .unwrap_or_default()
+            .trim_start_matches('\'');
+        let balance = super::parse_amount(columns.field(r, "Balance"))?;
+        let mut row = BankRow::new(date, ttype(code, value.value() < 0)?, description)
+            .with_signed_amount(value)
+            .with_balance(balance);
+        if let Some(account) = columns.field(r, "Account Number") {
+            row = row.with_account_desc(self::account_desc(account)?);
+        }
+        rows.push(row);
+    }
+    super::rows_to_transactions(rows, account_desc, opening_balance, RowOrder::NewestFirst)
+}
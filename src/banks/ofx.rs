@@ -0,0 +1,382 @@
+//a Documentation
+//! Import of OFX (and QFX) statements
+//!
+//! OFX 1.x files are SGML, with a header of 'KEY:VALUE' lines and
+//! with no closing tags for the leaf elements; OFX 2.x files are XML.
+//! Both are handled by the same tokenizer, which treats any element
+//! followed directly by text as a leaf.
+//!
+//! A statement (STMTRS for a bank account, CCSTMTRS for a card) has
+//! the account (BANKACCTFROM or CCACCTFROM), a list of transactions
+//! (STMTTRN within BANKTRANLIST), and the ledger balance (LEDGERBAL)
+//! at the end of the statement.
+//!
+//! OFX transactions do not have a running balance; the balances are
+//! calculated back from the ledger balance, or forward from an opening
+//! balance if one is given (in which case the result must match the
+//! ledger balance)
+
+//a Imports
+use super::{BankRow, RowOrder};
+use crate::{AccountDesc, Amount, BankTransaction, BankTransactionType, BankTypeMap, Date, Error};
+
+//a OfxElement
+//tp OfxElement
+/// An element of an OFX document; this is either a leaf with a value,
+/// or an aggregate with children
+#[derive(Debug, Default, Clone)]
+pub struct OfxElement {
+    name: String,
+    value: String,
+    children: Vec<OfxElement>,
+}
+
+//ip OfxElement
+impl OfxElement {
+    //cp parse
+    /// Parse an OFX document (SGML or XML) into its root element
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let Some(start) = text.find("<OFX>") else {
+            return Err(Error::TransactionLog(
+                "OFX document has no <OFX> element".into(),
+            ));
+        };
+        let mut stack = vec![OfxElement {
+            name: "".into(),
+            ..Default::default()
+        }];
+        let mut rest = &text[start..];
+        while let Some(open) = rest.find('<') {
+            let Some(close) = rest[open..].find('>') else {
+                return Err(Error::TransactionLog(
+                    "OFX document has an unterminated tag".into(),
+                ));
+            };
+            let tag = rest[open + 1..open + close].trim();
+            rest = &rest[open + close + 1..];
+            let text_len = rest.find('<').unwrap_or(rest.len());
+            let text = rest[..text_len].trim();
+            if tag.starts_with('?') || tag.starts_with('!') {
+                continue;
+            }
+            if let Some(name) = tag.strip_prefix('/') {
+                // Close the aggregate; any unclosed elements within it are closed too
+                if !stack.iter().skip(1).any(|e| e.name == name) {
+                    // Closing tag of a leaf that has already been completed
+                    continue;
+                }
+                while let Some(element) = stack.pop() {
+                    let complete = element.name == name;
+                    stack.last_mut().unwrap().children.push(element);
+                    if complete {
+                        break;
+                    }
+                }
+            } else if !text.is_empty() {
+                stack.last_mut().unwrap().children.push(OfxElement {
+                    name: tag.into(),
+                    value: decode_entities(text),
+                    children: vec![],
+                });
+            } else {
+                stack.push(OfxElement {
+                    name: tag.into(),
+                    ..Default::default()
+                });
+            }
+        }
+        while stack.len() > 1 {
+            let element = stack.pop().unwrap();
+            stack.last_mut().unwrap().children.push(element);
+        }
+        let root = stack.pop().unwrap();
+        root.children
+            .into_iter()
+            .find(|e| e.name == "OFX")
+            .ok_or_else(|| Error::TransactionLog("OFX document has no <OFX> element".into()))
+    }
+
+    //ap name
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    //ap value
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    //ap child
+    /// Get the first child element with the given name
+    pub fn child(&self, name: &str) -> Option<&OfxElement> {
+        self.children.iter().find(|e| e.name == name)
+    }
+
+    //ap get
+    /// Get the value of the first child element with the given name
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.child(name)
+            .map(|e| e.value())
+            .filter(|v| !v.is_empty())
+    }
+
+    //mp find_all
+    /// Find all the descendants with the given name
+    pub fn find_all<'a>(&'a self, name: &str, result: &mut Vec<&'a OfxElement>) {
+        for c in self.children.iter() {
+            if c.name == name {
+                result.push(c);
+            } else {
+                c.find_all(name, result);
+            }
+        }
+    }
+}
+
+//a Internal functions
+//fi decode_entities
+fn decode_entities(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+//fi parse_date
+/// Parse an OFX date, which is YYYYMMDD optionally followed by a time
+/// and timezone (which are ignored)
+fn parse_date(s: &str) -> Result<Date, Error> {
+    let Some(ymd) = s.get(0..8) else {
+        return Err(Error::ParseDate(s.into()));
+    };
+    Date::parse_with_format(ymd, "%Y%m%d")
+}
+
+//fi TYPE_MAP
+/// OFX TRNTYPE values
+const TYPE_MAP: &BankTypeMap = {
+    use BankTransactionType::*;
+    &[
+        ("CASH", Deposit, CashWithdrawal),
+        ("DIRECTDEP", BacsIn, BacsOut),
+        ("DIRECTDEBIT", DirectDebitRefund, DirectDebit),
+        ("REPEATPMT", StandingOrderIn, StandingOrder),
+        ("XFER", Fpi, FasterPaymentOut),
+        ("CREDIT", Fpi, FasterPaymentOut),
+        ("PAYMENT", BacsIn, BillPayment),
+        ("CHECK", ChequeIn(None), ChequeOut(None)),
+        ("SRVCHG", Correction, Charge),
+    ]
+};
+
+//fi ttype
+/// Map an OFX TRNTYPE to a transaction type
+fn ttype(trntype: &str, is_debit: bool) -> Result<BankTransactionType, Error> {
+    Ok(BankTransactionType::parse_with_map(
+        trntype, is_debit, TYPE_MAP,
+    ))
+}
+
+//fi mask_pan
+/// Mask all but the last four digits of a card number
+fn mask_pan(pan: &str) -> String {
+    let n = pan.len().saturating_sub(4);
+    pan.chars()
+        .enumerate()
+        .map(|(i, c)| if i < n && c.is_ascii_digit() { '*' } else { c })
+        .collect()
+}
+
+//fi account_desc
+/// Derive the account description from a BANKACCTFROM or CCACCTFROM
+///
+/// UK accounts have a six digit BANKID (the sort code) and an eight
+/// digit ACCTID, or (from some banks) a fourteen digit ACCTID that
+/// combines the two; otherwise an IBAN ACCTID is used, or a BIC
+/// BANKID with the ACCTID
+fn account_desc(acct: &OfxElement) -> Result<AccountDesc, Error> {
+    let acct_id = acct
+        .get("ACCTID")
+        .unwrap_or_default()
+        .replace([' ', '-'], "");
+    if acct.name() == "CCACCTFROM" {
+        return AccountDesc::card(&mask_pan(&acct_id));
+    }
+    let bank_id = acct
+        .get("BANKID")
+        .unwrap_or_default()
+        .replace([' ', '-'], "");
+    let all_digits = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+    let uk = |sort_code: &str, account: &str| {
+        let sort_code = format!(
+            "{}-{}-{}",
+            &sort_code[0..2],
+            &sort_code[2..4],
+            &sort_code[4..6]
+        );
+        AccountDesc::parse_uk(&sort_code, account.parse().unwrap())
+    };
+    if bank_id.len() == 6 && all_digits(&bank_id) && all_digits(&acct_id) {
+        if acct_id.len() == 14 && acct_id.starts_with(&bank_id) {
+            return uk(&bank_id, &acct_id[6..]);
+        }
+        if acct_id.len() <= 8 {
+            return uk(&bank_id, &acct_id);
+        }
+    }
+    if bank_id.is_empty() && acct_id.len() == 14 && all_digits(&acct_id) {
+        return uk(&acct_id[0..6], &acct_id[6..]);
+    }
+    if let Ok(iban) = AccountDesc::iban(&acct_id) {
+        return Ok(iban);
+    }
+    AccountDesc::bic(&bank_id, &acct_id)
+}
+
+//fi statement_account_desc
+/// The account of a statement, unless one is provided
+fn statement_account_desc(
+    stmt: &OfxElement,
+    account_desc: &AccountDesc,
+) -> Result<AccountDesc, Error> {
+    if !account_desc.is_none() {
+        Ok(account_desc.clone())
+    } else if let Some(acct) = stmt
+        .child("BANKACCTFROM")
+        .or_else(|| stmt.child("CCACCTFROM"))
+    {
+        self::account_desc(acct)
+    } else {
+        Err(Error::TransactionLog(
+            "OFX statement has no account, and no account was provided".into(),
+        ))
+    }
+}
+
+//fi statement_transactions
+/// The transactions of a statement, and its closing balance (if
+/// known)
+fn statement_transactions(
+    stmt: &OfxElement,
+    account_desc: AccountDesc,
+    opening_balance: Option<Amount>,
+) -> Result<(Vec<BankTransaction>, Option<Amount>), Error> {
+    let mut stmttrns = vec![];
+    if let Some(tranlist) = stmt.child("BANKTRANLIST") {
+        tranlist.find_all("STMTTRN", &mut stmttrns);
+    }
+    let mut rows = vec![];
+    for t in stmttrns {
+        let date = parse_date(t.get("DTPOSTED").unwrap_or_default())?;
+        let amount: Amount = t.get("TRNAMT").unwrap_or("0").parse()?;
+        let description = {
+            match (t.get("NAME").or_else(|| t.get("PAYEE")), t.get("MEMO")) {
+                (Some(n), Some(m)) => format!("{n} {m}"),
+                (Some(n), None) => n.to_string(),
+                (None, Some(m)) => m.to_string(),
+                (None, None) => "".to_string(),
+            }
+        };
+        let trntype = t.get("TRNTYPE").unwrap_or_default();
+        rows.push(
+            BankRow::new(date, ttype(trntype, amount.value() < 0)?, &description)
+                .with_signed_amount(amount)
+                .with_fitid(t.get("FITID").map(|s| s.to_string())),
+        );
+    }
+    // OFX does not define the order; use date order, keeping the order within a date
+    rows.sort_by_key(|r| r.date);
+
+    let ledger_balance = stmt
+        .child("LEDGERBAL")
+        .and_then(|l| l.get("BALAMT"))
+        .map(|b| b.parse::<Amount>())
+        .transpose()?;
+    let total = rows
+        .iter()
+        .fold(Amount::default(), |acc, r| acc + r.balance_delta());
+    let opening = match (opening_balance, ledger_balance) {
+        (Some(opening), Some(ledger)) => {
+            if opening + total != ledger {
+                return Err(Error::TransactionLog(format!(
+                    "OFX ledger balance is {ledger} but opening balance {opening} plus the transactions gives {}",
+                    opening + total
+                )));
+            }
+            Some(opening)
+        }
+        (Some(opening), None) => Some(opening),
+        (None, Some(ledger)) => {
+            // The ledger balance is the balance after the last
+            // transaction; there is nothing to check it against
+            if let Some(last) = rows.last_mut() {
+                last.balance = Some(ledger);
+            }
+            None
+        }
+        (None, None) => {
+            return Err(Error::TransactionLog(
+                "OFX statement has no ledger balance, and no opening balance was provided".into(),
+            ));
+        }
+    };
+    let transactions =
+        super::rows_to_transactions(rows, &account_desc, opening, RowOrder::OldestFirst)?;
+    let closing = transactions
+        .last()
+        .map(|t| t.balance())
+        .or(opening)
+        .or(ledger_balance);
+    Ok((transactions, closing))
+}
+
+//a Public functions
+//fp read_transactions_ofx
+/// Read an OFX or QFX file and return a Vec<BankTransaction>, oldest
+/// first, for each statement in the file
+///
+/// If the account description is not None then it is used instead of
+/// the account in the statement
+///
+/// The balances are calculated from the ledger balance of each
+/// statement. If the opening balance is given then it is the opening
+/// balance of the first statement, and is checked against its ledger
+/// balance instead; a later statement of the same account opens with
+/// the closing balance of the statement before it
+pub fn read_transactions_ofx<R: std::io::Read>(
+    mut reader: R,
+    account_desc: &AccountDesc,
+    opening_balance: Option<Amount>,
+) -> Result<Vec<Vec<BankTransaction>>, Error> {
+    let mut bytes = vec![];
+    reader.read_to_end(&mut bytes)?;
+    let text = String::from_utf8_lossy(&bytes);
+    let ofx = OfxElement::parse(&text)?;
+    let mut statements = vec![];
+    ofx.find_all("STMTRS", &mut statements);
+    ofx.find_all("CCSTMTRS", &mut statements);
+    if statements.is_empty() {
+        return Err(Error::TransactionLog(
+            "OFX document has no statements".into(),
+        ));
+    }
+    let mut result = vec![];
+    let mut closing_balances: Vec<(AccountDesc, Amount)> = vec![];
+    for (i, stmt) in statements.into_iter().enumerate() {
+        let stmt_desc = statement_account_desc(stmt, account_desc)?;
+        let opening = closing_balances
+            .iter()
+            .rev()
+            .find(|(desc, _)| *desc == stmt_desc)
+            .map(|(_, balance)| *balance)
+            .or(if i == 0 { opening_balance } else { None });
+        let (transactions, closing) = statement_transactions(stmt, stmt_desc.clone(), opening)?;
+        if let Some(closing) = closing {
+            closing_balances.push((stmt_desc, closing));
+        }
+        result.push(transactions);
+    }
+    Ok(result)
+}
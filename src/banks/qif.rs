@@ -0,0 +1,530 @@
+//a Documentation
+//! Import and export of QIF (Quicken Interchange Format) files
+//!
+//! A QIF file has a '!Type:' header line (such as '!Type:Bank') and
+//! then records of one line per field, each starting with a code
+//! letter, and each record ending with a '^' line. The fields used
+//! here are:
+//!
+//! * D - date (day first, as '31/12/2023' or '31/12'23', or '2023-12-31')
+//! * T (or U) - amount, negative for money out
+//! * P - payee
+//! * M - memo
+//! * N - number (a cheque number or a transaction type such as 'DD')
+//! * L - category of the whole transaction
+//! * S, E, $ - the category, memo and amount of each split
+//!
+//! A category in square brackets is a transfer to another account,
+//! rather than a fund; other categories are the names (or aliases) of
+//! funds, and on import each category or split becomes a
+//! [Transaction] between that fund and the related party of the bank
+//! transaction.
+//!
+//! QIF has no running balance; the balances are calculated forward
+//! from the opening balance, which is either provided or taken from an
+//! 'Opening Balance' record at the start of the file.
+
+//a Imports
+use std::io::Write;
+
+use super::{detect_row_order, BankRow, Counterparty, RowOrder, Statement};
+use crate::indexed_vec::Idx;
+use crate::{
+    Account, AccountDesc, Amount, BankTransaction, BankTransactionType, Database, Date, DateRange,
+    DbAccount, DbId, Error, ImportOutcome, ImportPreview, ImportReport, Transaction,
+    TransactionType,
+};
+
+//a QifSplit
+//tp QifSplit
+/// A split line of a QIF transaction (or the category of the whole
+/// transaction, if it is not split)
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct QifSplit {
+    /// Category, without any '/class' suffix
+    pub category: String,
+    /// Memo for the split
+    pub memo: String,
+    /// Amount, positive for money in
+    pub amount: Amount,
+}
+
+//ip QifSplit
+impl QifSplit {
+    //ap is_transfer
+    /// Return true if the category is a transfer to another account
+    pub fn is_transfer(&self) -> bool {
+        self.category.starts_with('[')
+    }
+}
+
+//a QifRecord
+//ti QifRecord
+/// A record of a QIF file as read, before it becomes a [BankRow]
+#[derive(Debug, Default)]
+struct QifRecord {
+    date: Option<Date>,
+    amount: Option<Amount>,
+    payee: String,
+    memo: String,
+    number: String,
+    category: String,
+    splits: Vec<QifSplit>,
+}
+
+//ii QifRecord
+impl QifRecord {
+    //mi is_empty
+    fn is_empty(&self) -> bool {
+        self.date.is_none() && self.amount.is_none()
+    }
+
+    //mi is_opening_balance
+    /// Quicken starts an export with a record for the opening balance,
+    /// with the account itself as the category
+    fn is_opening_balance(&self) -> bool {
+        self.payee.eq_ignore_ascii_case("Opening Balance") && self.category.starts_with('[')
+    }
+
+    //mi add_field
+    fn add_field(&mut self, code: char, value: &str) -> Result<(), Error> {
+        match code {
+            'D' => {
+                self.date = Some(parse_date(value)?);
+            }
+            'T' | 'U' => {
+                self.amount = Some(value.parse()?);
+            }
+            'P' => {
+                self.payee = value.to_string();
+            }
+            'M' => {
+                self.memo = value.to_string();
+            }
+            'N' => {
+                self.number = value.to_string();
+            }
+            'L' => {
+                self.category = strip_class(value);
+            }
+            'S' => {
+                self.splits.push(QifSplit {
+                    category: strip_class(value),
+                    ..Default::default()
+                });
+            }
+            'E' => {
+                if let Some(split) = self.splits.last_mut() {
+                    split.memo = value.to_string();
+                }
+            }
+            '$' => {
+                if let Some(split) = self.splits.last_mut() {
+                    split.amount = value.parse()?;
+                }
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    //mi into_row
+    /// Convert the record into a bank row and its splits
+    fn into_row(mut self) -> Result<(BankRow, Vec<QifSplit>), Error> {
+        let Some(date) = self.date else {
+            return Err(Error::TransactionLog(format!(
+                "QIF transaction '{}' has no date",
+                self.payee
+            )));
+        };
+        let amount = self.amount.unwrap_or_default();
+        let description = {
+            if self.payee.is_empty() {
+                self.memo.clone()
+            } else {
+                self.payee.clone()
+            }
+        };
+        let ttype = BankTransactionType::parse(&self.number, amount.value() < 0)?;
+        let counterparty = Counterparty {
+            name: (!self.payee.is_empty()).then(|| self.payee.clone()),
+            iban: None,
+        };
+        if self.splits.is_empty() && !self.category.is_empty() {
+            self.splits.push(QifSplit {
+                category: self.category,
+                memo: self.memo,
+                amount,
+            });
+        }
+        let row = BankRow::new(date, ttype, &description)
+            .with_signed_amount(amount)
+            .with_counterparty(counterparty);
+        Ok((row, self.splits))
+    }
+}
+
+//a QifStatement
+//tp QifStatement
+/// The bank transactions of a QIF file, oldest first, with the
+/// counterparty and splits of each
+#[derive(Debug, Default)]
+pub struct QifStatement {
+    pub statement: Statement,
+    pub splits: Vec<Vec<QifSplit>>,
+}
+
+//ip QifStatement
+impl QifStatement {
+    //mp split_transactions
+    /// Create the transactions for the splits of a bank transaction,
+    /// against the funds named by the categories
+    ///
+    /// The splits (including transfers) must add up to the amount of
+    /// the bank transaction. Transfers to other accounts are ignored;
+    /// any other category must be the name or alias of a fund, and
+    /// the bank transaction must have a related party
+    pub fn split_transactions(
+        db: &Database,
+        bank_transaction: &BankTransaction,
+        splits: &[QifSplit],
+        account: &Account,
+    ) -> Result<Vec<Transaction>, Error> {
+        if !splits.is_empty() {
+            let total = splits
+                .iter()
+                .fold(Amount::default(), |acc, s| acc + s.amount);
+            if total != bank_transaction.balance_delta() {
+                return Err(Error::TransactionLog(format!(
+                    "QIF splits of '{}' on {} add up to {} rather than {}",
+                    bank_transaction.description(),
+                    bank_transaction.date(),
+                    total.to_string().trim(),
+                    bank_transaction.balance_delta().to_string().trim(),
+                )));
+            }
+        }
+        let mut transactions = vec![];
+        for split in splits {
+            if split.category.is_empty() || split.is_transfer() || split.amount.is_zero() {
+                continue;
+            }
+            let Some(db_fund) = db.funds().get_fund(&split.category) else {
+                return Err(Error::TransactionLog(format!(
+                    "QIF category '{}' of '{}' on {} is not the name of a fund",
+                    split.category,
+                    bank_transaction.description(),
+                    bank_transaction.date()
+                )));
+            };
+            let rp_id = bank_transaction.related_party();
+            if rp_id.is_none() {
+                return Err(Error::TransactionLog(format!(
+                    "QIF transaction '{}' on {} has categories but no known related party",
+                    bank_transaction.description(),
+                    bank_transaction.date()
+                )));
+            }
+            let date = bank_transaction.date();
+            let mut transaction = {
+                if split.amount.value() < 0 {
+                    Transaction::new_payment(date, -split.amount, db_fund.id(), rp_id)
+                } else {
+                    Transaction::new_income(date, split.amount, rp_id, db_fund.id())
+                }
+            }
+            .with_currency(account.currency());
+            if !split.memo.is_empty() {
+                transaction.add_note(&split.memo);
+            }
+            transaction.check_db_ids(db)?;
+            transaction.check_currencies(db)?;
+            transactions.push(transaction);
+        }
+        Ok(transactions)
+    }
+
+    //mp preview
+    /// Preview importing the statement into an account, as
+    /// [Account::preview_transactions], after checking the splits
+    pub fn preview(
+        mut self,
+        db: &Database,
+        db_account: &DbAccount,
+    ) -> Result<ImportPreview, Error> {
+        self.resolve_related_parties(db);
+        for (bt, splits) in self.statement.transactions.iter().zip(self.splits.iter()) {
+            Self::split_transactions(db, bt, splits, &db_account.inner())?;
+        }
+        db_account
+            .inner()
+            .preview_transactions(db, &self.statement.transactions)
+    }
+
+    //mi resolve_related_parties
+    /// Resolve the related parties of the transactions from their
+    /// payees, or failing that their descriptions
+    fn resolve_related_parties(&mut self, db: &Database) {
+        self.statement.resolve_related_parties(db);
+        for bt in self.statement.transactions.iter_mut() {
+            if bt.related_party().is_none() {
+                bt.set_related_party(db.find_account_related_party(bt.description()));
+            }
+        }
+    }
+
+    //mp import
+    /// Import the statement into an account, adding a transaction for
+    /// each split (as [Self::split_transactions]) linked to its bank
+    /// transaction
+    ///
+    /// All of the splits of every bank transaction are checked (as
+    /// [Self::split_transactions]) before anything is added to the
+    /// database, so that a bad split leaves the database unchanged;
+    /// bank transactions that are skipped (as they are already
+    /// present) or that conflict do not have their splits added
+    ///
+    /// The report of the import and the DbIds of the split
+    /// transactions are returned
+    pub fn import(
+        mut self,
+        db: &Database,
+        db_account: &DbAccount,
+    ) -> Result<(ImportReport, Vec<DbId>), Error> {
+        self.resolve_related_parties(db);
+
+        let mut split_transactions = vec![];
+        for (bt, splits) in self.statement.transactions.iter().zip(self.splits.iter()) {
+            split_transactions.push(Self::split_transactions(
+                db,
+                bt,
+                splits,
+                &db_account.inner(),
+            )?);
+        }
+
+        let report = db_account
+            .inner_mut()
+            .add_transactions(db, db_account.id(), self.statement.into_transactions())
+            .map_err(|e| {
+                Error::TransactionLog(format!("failed to add {} QIF transactions", e.len()))
+            })?;
+
+        let mut t_ids = vec![];
+        for (outcome, transactions) in report.outcomes().iter().zip(split_transactions) {
+            let ImportOutcome::Added(bt_id) = outcome else {
+                continue;
+            };
+            let Some(db_bt) = db.get_bank_transaction(*bt_id) else {
+                continue;
+            };
+            for transaction in transactions {
+                let (t_id, okay) = db.add_transaction(transaction)?;
+                if !okay {
+                    return Err(
+                        format!("Added transaction {t_id} but database is not consistent").into(),
+                    );
+                }
+                db_bt.inner_mut().add_transaction(t_id);
+                t_ids.push(t_id);
+            }
+        }
+        Ok((report, t_ids))
+    }
+}
+
+//a Internal functions
+//fi strip_class
+/// Strip any '/class' suffix from a category
+fn strip_class(s: &str) -> String {
+    s.split('/').next().unwrap_or_default().trim().to_string()
+}
+
+//fi parse_date
+/// Parse a QIF date; Quicken uses an apostrophe before the year for
+/// dates from 2000, and pads the day and month with spaces
+fn parse_date(s: &str) -> Result<Date, Error> {
+    let s: String = s
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| if c == '\'' { '/' } else { c })
+        .collect();
+    if s.contains('-') {
+        Date::parse_with_format(&s, "%Y-%m-%d")
+    } else {
+        Date::parse(&s)
+    }
+}
+
+//fi qif_date
+fn qif_date(date: Date) -> String {
+    let (day, month, year) = date.dmy();
+    format!("{day:02}/{month:02}/{year}")
+}
+
+//fi qif_amount
+fn qif_amount(amount: Amount) -> String {
+    amount.to_string().trim().to_string()
+}
+
+//a Reader
+//fp read_statement_qif
+/// Read a QIF file for an account
+///
+/// QIF does not identify the account, so the account description is
+/// required; an opening balance is required unless the file starts
+/// with an 'Opening Balance' record
+pub fn read_statement_qif<R: std::io::Read>(
+    mut reader: R,
+    account_desc: &AccountDesc,
+    opening_balance: Option<Amount>,
+) -> Result<QifStatement, Error> {
+    if account_desc.is_none() {
+        return Err(Error::TransactionLog(
+            "QIF does not identify the account, and no account was provided".into(),
+        ));
+    }
+    let mut text = String::new();
+    reader.read_to_string(&mut text)?;
+
+    let mut records = vec![];
+    let mut record = QifRecord::default();
+    for line in text.lines() {
+        let line = line.trim_end();
+        let mut chars = line.chars();
+        let Some(code) = chars.next() else {
+            continue;
+        };
+        match code {
+            '!' => {
+                if !line.starts_with("!Type:") && !line.starts_with("!Option:") {
+                    return Err(Error::TransactionLog(format!(
+                        "QIF section '{line}' is not supported"
+                    )));
+                }
+            }
+            '^' => {
+                let r = std::mem::take(&mut record);
+                if !r.is_empty() {
+                    records.push(r);
+                }
+            }
+            _ => {
+                record.add_field(code, chars.as_str().trim())?;
+            }
+        }
+    }
+    if !record.is_empty() {
+        records.push(record);
+    }
+
+    let mut opening_balance = opening_balance;
+    if records.first().is_some_and(|r| r.is_opening_balance()) {
+        let r = records.remove(0);
+        if opening_balance.is_none() {
+            opening_balance = r.amount;
+        }
+    }
+    if opening_balance.is_none() {
+        return Err(Error::TransactionLog(
+            "QIF has no opening balance record, and no opening balance was provided".into(),
+        ));
+    }
+
+    let mut rows = vec![];
+    let mut splits = vec![];
+    for r in records {
+        let (row, s) = r.into_row()?;
+        rows.push(row);
+        splits.push(s);
+    }
+    if detect_row_order(&rows, RowOrder::OldestFirst) == RowOrder::NewestFirst {
+        rows.reverse();
+        splits.reverse();
+    }
+    let statement =
+        super::rows_to_statement(rows, account_desc, opening_balance, RowOrder::OldestFirst)?;
+    Ok(QifStatement { statement, splits })
+}
+
+//a Writer
+//fp write_qif
+/// Write the bank transactions of an account within a date range as
+/// QIF, starting with an 'Opening Balance' record
+///
+/// The transactions linked to each bank transaction are written as its
+/// category (if there is one covering the whole amount) or as splits
+pub fn write_qif<W: Write>(
+    writer: &mut W,
+    db: &Database,
+    account: &Account,
+    date_range: DateRange,
+) -> Result<(), Error> {
+    let qif_type = match account.desc() {
+        AccountDesc::Card { .. } => "CCard",
+        AccountDesc::CashBox { .. } => "Cash",
+        _ => "Bank",
+    };
+    writeln!(writer, "!Type:{qif_type}")?;
+    let bt_ids = account.bank_transactions_in_range(date_range);
+    if let Some(db_bt) = bt_ids.first().and_then(|id| db.get_bank_transaction(*id)) {
+        let bt = db_bt.inner();
+        writeln!(writer, "D{}", qif_date(bt.date()))?;
+        writeln!(writer, "T{}", qif_amount(bt.balance() - bt.balance_delta()))?;
+        writeln!(writer, "POpening Balance")?;
+        writeln!(writer, "L[{}]", account.name())?;
+        writeln!(writer, "^")?;
+    }
+    for bt_id in bt_ids {
+        let Some(db_bt) = db.get_bank_transaction(bt_id) else {
+            continue;
+        };
+        let bt = db_bt.inner();
+        writeln!(writer, "D{}", qif_date(bt.date()))?;
+        writeln!(writer, "T{}", qif_amount(bt.balance_delta()))?;
+        writeln!(writer, "P{}", bt.description())?;
+        let code = bt.ttype().code();
+        if !code.is_empty() {
+            writeln!(writer, "N{code}")?;
+        }
+
+        let mut splits = vec![];
+        for t_id in bt.transactions() {
+            let Some(db_t) = db.get_transaction(*t_id) else {
+                continue;
+            };
+            let t = db_t.inner();
+            let (debit_id, credit_id) = t.db_ids();
+            let (fund_id, amount) = match t.ttype() {
+                TransactionType::ToRp => (debit_id, -t.amount()),
+                TransactionType::FromRp => (credit_id, t.amount()),
+                _ => continue,
+            };
+            let Some(db_fund) = db.get_fund(fund_id) else {
+                continue;
+            };
+            let category = db_fund.inner().name().to_string();
+            splits.push(QifSplit {
+                category,
+                memo: t.notes().join("; "),
+                amount,
+            });
+        }
+        if splits.len() == 1 && splits[0].amount == bt.balance_delta() {
+            writeln!(writer, "L{}", splits[0].category)?;
+            if !splits[0].memo.is_empty() {
+                writeln!(writer, "M{}", splits[0].memo)?;
+            }
+        } else {
+            for split in splits {
+                writeln!(writer, "S{}", split.category)?;
+                if !split.memo.is_empty() {
+                    writeln!(writer, "E{}", split.memo)?;
+                }
+                writeln!(writer, "${}", qif_amount(split.amount))?;
+            }
+        }
+        writeln!(writer, "^")?;
+    }
+    Ok(())
+}
@@ -0,0 +1,72 @@
+// Date,Description,Amount,Balance
+// 28/08/2024,FASTER PAYMENTS RECEIPT REF.REASON FROM NAME,20.00,12004.61
+//
+// Some Santander exports have "Money in" and "Money out" columns
+// instead of a single signed amount
+use super::{BankRow, RowOrder};
+use crate::{AccountDesc, Amount, BankTransaction, BankTransactionType, BankTypeMap, Date, Error};
+
+//fi TYPE_MAP
+/// Santander description prefixes
+const TYPE_MAP: &BankTypeMap = {
+    use BankTransactionType::*;
+    &[
+        ("DIRECT DEBIT", DirectDebitRefund, DirectDebit),
+        ("STANDING ORDER", StandingOrderIn, StandingOrder),
+        ("BANK GIRO CREDIT", BacsIn, BacsOut),
+        ("FASTER PAYMENTS RECEIPT", Fpi, FasterPaymentOut),
+        ("BILL PAYMENT", BacsIn, BillPayment),
+        ("CARD PAYMENT", CardRefund, CardPayment),
+        ("CASH DEPOSIT", Deposit, CashWithdrawal),
+        ("CASH WITHDRAWAL", Deposit, CashWithdrawal),
+        ("CHEQUE PAID IN", ChequeIn(None), ChequeOut(None)),
+        ("CHEQUE", ChequeIn(None), ChequeOut(None)),
+        ("TRANSFER", TransferIn, TransferOut),
+        ("INTEREST PAID", Interest, Charge),
+    ]
+};
+
+//fi ttype
+/// Santander has no type column, but the description starts with the type
+fn ttype(description: &str, is_debit: bool) -> Result<BankTransactionType, Error> {
+    Ok(BankTransactionType::parse_with_map(
+        description,
+        is_debit,
+        TYPE_MAP,
+    ))
+}
+
+//fp read_transactions_csv
+/// Read a Santander CSV transactions file and return a Vec<BankTransaction>
+///
+/// Santander exports do not identify the account, so the account
+/// description must be provided
+pub fn read_transactions_csv<R: std::io::Read>(
+    reader: R,
+    account_desc: &AccountDesc,
+    opening_balance: Option<Amount>,
+) -> Result<Vec<BankTransaction>, Error> {
+    let (columns, records) = super::read_csv_rows(reader)?;
+    let c_date = columns.require("Date")?;
+    let mut rows = vec![];
+    for r in records.iter() {
+        let date = Date::parse(super::field(r, c_date).unwrap_or_default())?;
+        let description = columns.field(r, "Description").unwrap_or_default();
+        let balance = super::parse_amount(columns.field(r, "Balance"))?;
+        let row = {
+            if columns.index("Amount").is_some() {
+                let amount = super::parse_amount(columns.field(r, "Amount"))?;
+                let amount = amount.unwrap_or_default();
+                BankRow::new(date, ttype(description, amount.value() < 0)?, description)
+                    .with_signed_amount(amount)
+            } else {
+                let money_out = super::parse_amount(columns.field(r, "Money out"))?;
+                let money_in = super::parse_amount(columns.field(r, "Money in"))?;
+                BankRow::new(date, ttype(description, money_out.is_some())?, description)
+                    .with_debit_credit(money_out, money_in)
+            }
+        };
+        rows.push(row.with_balance(balance));
+    }
+    super::rows_to_transactions(rows, account_desc, opening_balance, RowOrder::NewestFirst)
+}
@@ -0,0 +1,63 @@
+// Date,Counter Party,Reference,Type,Amount (GBP),Balance (GBP),Spending Category,Notes
+// 28/08/2024,NAME,REASON,FASTER PAYMENT,20.00,12004.61,INCOME,
+use super::{BankRow, RowOrder};
+use crate::{AccountDesc, Amount, BankTransaction, BankTransactionType, BankTypeMap, Date, Error};
+
+//fi TYPE_MAP
+/// Starling types
+const TYPE_MAP: &BankTypeMap = {
+    use BankTransactionType::*;
+    &[
+        ("DIRECT DEBIT", DirectDebitRefund, DirectDebit),
+        ("STANDING ORDER", StandingOrderIn, StandingOrder),
+        ("FASTER PAYMENT", Fpi, FasterPaymentOut),
+        ("BACS", BacsIn, BacsOut),
+        ("CONTACTLESS", CardRefund, CardPayment),
+        ("ONLINE PAYMENT", CardRefund, CardPayment),
+        ("CHIP AND PIN", CardRefund, CardPayment),
+        ("MAGNETIC STRIP", CardRefund, CardPayment),
+        ("APPLE PAY", CardRefund, CardPayment),
+        ("GOOGLE PAY", CardRefund, CardPayment),
+        ("MASTER CARD", CardRefund, CardPayment),
+        ("CASH WITHDRAWAL", Deposit, CashWithdrawal),
+        ("DEPOSIT", Deposit, CashWithdrawal),
+        ("CHEQUE", ChequeIn(None), ChequeOut(None)),
+    ]
+};
+
+//fi ttype
+/// Map a Starling type to a transaction type
+fn ttype(s: &str, is_debit: bool) -> Result<BankTransactionType, Error> {
+    Ok(BankTransactionType::parse_with_map(s, is_debit, TYPE_MAP))
+}
+
+//fp read_transactions_csv
+/// Read a Starling CSV transactions file and return a Vec<BankTransaction>
+///
+/// Starling exports do not identify the account, so the account
+/// description must be provided
+pub fn read_transactions_csv<R: std::io::Read>(
+    reader: R,
+    account_desc: &AccountDesc,
+    opening_balance: Option<Amount>,
+) -> Result<Vec<BankTransaction>, Error> {
+    let (columns, records) = super::read_csv_rows(reader)?;
+    let c_date = columns.require("Date")?;
+    let c_amount = columns.require("Amount (GBP)")?;
+    let mut rows = vec![];
+    for r in records.iter() {
+        let date = Date::parse(super::field(r, c_date).unwrap_or_default())?;
+        let amount: Amount = super::field(r, c_amount).unwrap_or("0").parse()?;
+        let balance = super::parse_amount(columns.field(r, "Balance (GBP)"))?;
+        let counter_party = columns.field(r, "Counter Party").unwrap_or_default();
+        let reference = columns.field(r, "Reference").unwrap_or_default();
+        let description = format!("{counter_party} {reference}");
+        let ttype_str = columns.field(r, "Type").unwrap_or_default();
+        rows.push(
+            BankRow::new(date, ttype(ttype_str, amount.value() < 0)?, &description)
+                .with_signed_amount(amount)
+                .with_balance(balance),
+        );
+    }
+    super::rows_to_transactions(rows, account_desc, opening_balance, RowOrder::OldestFirst)
+}
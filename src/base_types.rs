@@ -12,6 +12,7 @@ pub enum FileType {
     Csv,
     Json,
     Yaml,
+    Sqlite,
 }
 
 //ip Display for FileType
@@ -21,6 +22,7 @@ impl std::fmt::Display for FileType {
             FileType::Csv => write!(fmt, "csv"),
             FileType::Json => write!(fmt, "json"),
             FileType::Yaml => write!(fmt, "yaml"),
+            FileType::Sqlite => write!(fmt, "sqlite"),
         }
     }
 }
@@ -34,6 +36,8 @@ impl FileType {
             Ok(Self::Json)
         } else if f.ends_with(".csv") {
             Ok(Self::Csv)
+        } else if f.ends_with(".sqlite") || f.ends_with(".sqlite3") {
+            Ok(Self::Sqlite)
         } else {
             Err(Error::UnknownFileExtension(f.to_string()))
         }
@@ -267,6 +271,15 @@ impl Date {
         }
     }
 
+    //cp parse_with_format
+    /// Parse a date using a chrono format string such as "%d %b %Y"
+    /// or "%Y-%m-%d"
+    pub fn parse_with_format(s: &str, format: &str) -> Result<Self, Error> {
+        let date = NaiveDate::parse_from_str(s.trim(), format)
+            .map_err(|_| Error::ParseDate(format!("{s} (expected format {format})")))?;
+        Ok(Utc.from_utc_datetime(&date.into()).into())
+    }
+
     //mp plus_days
     #[must_use]
     pub fn plus_days(&self, n: usize) -> Self {
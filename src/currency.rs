@@ -0,0 +1,280 @@
+//a Imports
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{Amount, Error};
+
+//a Currency
+//tp Currency
+/// An ISO 4217 currency code, such as GBP or EUR
+///
+/// The default is GBP, as all accounts predating currencies were
+/// sterling accounts
+///
+/// This serializes as the three-letter code
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Currency {
+    code: [u8; 3],
+}
+
+//ip Default for Currency
+impl Default for Currency {
+    fn default() -> Self {
+        Self::GBP
+    }
+}
+
+//ip Currency
+impl Currency {
+    pub const GBP: Currency = Currency { code: *b"GBP" };
+    pub const EUR: Currency = Currency { code: *b"EUR" };
+    pub const USD: Currency = Currency { code: *b"USD" };
+
+    /// The currencies whose codes are recognised around amounts
+    pub const KNOWN: [Currency; 3] = [Self::GBP, Self::EUR, Self::USD];
+
+    //ap is_known
+    pub fn is_known(&self) -> bool {
+        Self::KNOWN.contains(self)
+    }
+
+    //ap code
+    pub fn code(&self) -> &str {
+        // The code is always three ASCII uppercase letters
+        std::str::from_utf8(&self.code).unwrap()
+    }
+}
+
+//ip FromStr for Currency
+impl std::str::FromStr for Currency {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let s = s.trim().to_ascii_uppercase();
+        let bytes = s.as_bytes();
+        if bytes.len() != 3 || !bytes.iter().all(|c| c.is_ascii_uppercase()) {
+            return Err(Error::ParseCurrency(s));
+        }
+        Ok(Self {
+            code: [bytes[0], bytes[1], bytes[2]],
+        })
+    }
+}
+
+//ip Display for Currency
+impl std::fmt::Display for Currency {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        fmt.pad(self.code())
+    }
+}
+
+//ip Serialize for Currency
+impl Serialize for Currency {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.code())
+    }
+}
+
+//ip Deserialize for Currency
+impl<'de> Deserialize<'de> for Currency {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+//a CurrencyAmount
+//tp CurrencyAmount
+/// An [Amount] together with the [Currency] it is in
+///
+/// Arithmetic on these is checked, and fails if the currencies differ
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, Hash, PartialEq, Eq)]
+pub struct CurrencyAmount {
+    currency: Currency,
+    amount: Amount,
+}
+
+//ip CurrencyAmount
+impl CurrencyAmount {
+    //cp new
+    pub fn new(currency: Currency, amount: Amount) -> Self {
+        Self { currency, amount }
+    }
+
+    //ap currency
+    pub fn currency(&self) -> Currency {
+        self.currency
+    }
+
+    //ap amount
+    pub fn amount(&self) -> Amount {
+        self.amount
+    }
+
+    //mp checked_add
+    pub fn checked_add(self, other: Self) -> Result<Self, Error> {
+        self.check_currency(&other)?;
+        Ok(Self::new(self.currency, self.amount + other.amount))
+    }
+
+    //mp checked_sub
+    pub fn checked_sub(self, other: Self) -> Result<Self, Error> {
+        self.check_currency(&other)?;
+        Ok(Self::new(self.currency, self.amount - other.amount))
+    }
+
+    //mi check_currency
+    fn check_currency(&self, other: &Self) -> Result<(), Error> {
+        if self.currency != other.currency {
+            Err(Error::CurrencyMismatch(self.currency, other.currency))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+//ip Add for CurrencyAmount
+impl std::ops::Add<CurrencyAmount> for CurrencyAmount {
+    type Output = Result<CurrencyAmount, Error>;
+    fn add(self, other: CurrencyAmount) -> Result<CurrencyAmount, Error> {
+        self.checked_add(other)
+    }
+}
+
+//ip Sub for CurrencyAmount
+impl std::ops::Sub<CurrencyAmount> for CurrencyAmount {
+    type Output = Result<CurrencyAmount, Error>;
+    fn sub(self, other: CurrencyAmount) -> Result<CurrencyAmount, Error> {
+        self.checked_sub(other)
+    }
+}
+
+//ip Display for CurrencyAmount
+impl std::fmt::Display for CurrencyAmount {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(fmt, "{} {}", self.amount, self.currency)
+    }
+}
+
+//a ExchangeRate
+//tp ExchangeRate
+/// An exchange rate from one currency to another, held exactly to six
+/// decimal places
+///
+/// One unit of 'from' is worth 'rate' units of 'to'
+///
+/// The rate is always positive; a file with any other rate fails to
+/// load
+#[derive(Debug, Clone, Copy, Serialize, Hash, PartialEq, Eq)]
+pub struct ExchangeRate {
+    from: Currency,
+    to: Currency,
+    /// The rate in millionths
+    rate_e6: i64,
+}
+
+//ip ExchangeRate
+impl ExchangeRate {
+    const SCALE: i64 = 1_000_000;
+
+    //cp new
+    /// Create an exchange rate from a decimal string such as '1.1725'
+    pub fn new(from: Currency, to: Currency, rate: &str) -> Result<Self, Error> {
+        let err = || Error::ParseExchangeRate(rate.to_string());
+        let (int_part, frac_part) = rate.trim().split_once('.').unwrap_or((rate.trim(), ""));
+        if int_part.is_empty() && frac_part.is_empty() {
+            return Err(err());
+        }
+        // Digits only, so that a sign (such as '-0.5') is rejected
+        if !int_part.chars().all(|c| c.is_ascii_digit()) {
+            return Err(err());
+        }
+        if frac_part.len() > 6 || !frac_part.chars().all(|c| c.is_ascii_digit()) {
+            return Err(err());
+        }
+        let units = if int_part.is_empty() {
+            0
+        } else {
+            int_part.parse::<i64>().map_err(|_| err())?
+        };
+        let frac = format!("{frac_part:0<6}")
+            .parse::<i64>()
+            .map_err(|_| err())?;
+        let rate_e6 = units
+            .checked_mul(Self::SCALE)
+            .and_then(|r| r.checked_add(frac))
+            .ok_or_else(err)?;
+        if rate_e6 <= 0 {
+            return Err(err());
+        }
+        Ok(Self { from, to, rate_e6 })
+    }
+
+    //ap from
+    pub fn from(&self) -> Currency {
+        self.from
+    }
+
+    //ap to
+    pub fn to(&self) -> Currency {
+        self.to
+    }
+
+    //mp convert
+    /// Convert an amount into the other currency of the exchange
+    /// rate, rounding half a penny away from zero
+    ///
+    /// The amount may be in either currency of the rate
+    pub fn convert(&self, amount: CurrencyAmount) -> Result<CurrencyAmount, Error> {
+        let value = amount.amount().value() as i128;
+        let (currency, numerator, denominator) = {
+            if amount.currency() == self.from {
+                (self.to, value * self.rate_e6 as i128, Self::SCALE as i128)
+            } else if amount.currency() == self.to {
+                (self.from, value * Self::SCALE as i128, self.rate_e6 as i128)
+            } else {
+                return Err(Error::CurrencyMismatch(self.from, amount.currency()));
+            }
+        };
+        let half = denominator / 2;
+        let rounded = if numerator < 0 {
+            (numerator - half) / denominator
+        } else {
+            (numerator + half) / denominator
+        };
+        let value = isize::try_from(rounded)
+            .map_err(|_| Error::ParseAmount(format!("conversion of {amount} out of range")))?;
+        Ok(CurrencyAmount::new(currency, value.into()))
+    }
+}
+
+//ip Deserialize for ExchangeRate
+impl<'de> Deserialize<'de> for ExchangeRate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct Fields {
+            from: Currency,
+            to: Currency,
+            rate_e6: i64,
+        }
+        let Fields { from, to, rate_e6 } = Fields::deserialize(deserializer)?;
+        if rate_e6 <= 0 {
+            return Err(serde::de::Error::custom(format!(
+                "exchange rate from {from} to {to} of {rate_e6} millionths is not positive"
+            )));
+        }
+        Ok(Self { from, to, rate_e6 })
+    }
+}
+
+//ip Display for ExchangeRate
+impl std::fmt::Display for ExchangeRate {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(
+            fmt,
+            "1 {} = {}.{:06} {}",
+            self.from,
+            self.rate_e6 / Self::SCALE,
+            self.rate_e6 % Self::SCALE,
+            self.to
+        )
+    }
+}
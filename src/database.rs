@@ -53,22 +53,26 @@
 //! ordered by date
 
 //a Imports
-use serde::{Deserialize, Deserializer, Serializer};
+use serde::{Deserializer, Serialize, Serializer};
 use std::cell::RefCell;
 use std::collections::HashMap;
 
+use crate::banks::Counterparty;
+use crate::db_dictionary::DbDictionary;
 use crate::indexed_vec::Idx;
+use crate::schema::{SchemaFile, SchemaHeader};
 
 use crate::DbQuery;
 use crate::RelatedParties;
 use crate::{Account, DbAccounts};
-use crate::{BankTransaction, DbBankTransactions};
+use crate::{AggregateResult, Aggregation, QueryResult, QueryView};
+use crate::{BankTransaction, DbBankTransaction, DbBankTransactions};
 use crate::{DbFunds, Fund};
 use crate::{DbId, DbItem, DbItemType};
 use crate::{DbInvoices, Invoice};
-use crate::{DbRelatedParties, RelatedParty};
-use crate::{DbTransactions, Transaction};
-use crate::{Error, FileFormat};
+use crate::{DbRelatedParties, RelatedParty, RelatedPartyMatch};
+use crate::{DbTransaction, DbTransactions, Transaction};
+use crate::{Error, FileFormat, IntegrityProblem, MergeReport};
 
 //a DatabaseRebuild
 //tp DatabaseRebuild
@@ -105,6 +109,10 @@ pub struct DatabaseState {
 
     /// Hash map from DbId to the individual items
     items: HashMap<DbId, DbItem>,
+
+    /// The number of the last journal operation included in the
+    /// items (0 if none)
+    journal_op: usize,
 }
 
 //ip DatabaseState
@@ -151,9 +159,9 @@ impl DatabaseState {
 ///   or for a particular category (such as subscriptions for
 ///   related_parties). This is handled by a set of transaction tags
 ///
-/// A Database can be serialized and deserialized, and will provide
-/// other mechanisms for saving (such as export to MySql datatbase, or
-/// sqlite3)
+/// A Database can be serialized and deserialized, and can be stored
+/// in an sqlite3 database file (see SqliteStore); other mechanisms
+/// for saving (such as export to a MySql database) may follow
 #[derive(Default)]
 pub struct Database {
     /// next_db_id and the items
@@ -182,20 +190,28 @@ pub struct Database {
 }
 
 //tp DatabaseQueryIter<'a>
+/// An iterator over the DbIds of the items that match a query
+///
+/// The transactions and bank transactions that may match are
+/// selected up front, using the indexes of their collections for the
+/// date range, funds, related parties and accounts of the query, so
+/// that a query of a few months of many years of data does not have
+/// to match every transaction
 pub struct DatabaseQueryIter<'a> {
     query: DbQuery,
     accounts: Option<&'a DbAccounts>,
     funds: Option<&'a DbFunds>,
     invoices: Option<&'a DbInvoices>,
     related_parties: Option<&'a DbRelatedParties>,
-    bank_transactions: Option<&'a DbBankTransactions>,
-    transactions: Option<&'a DbTransactions>,
+    bank_transactions: Option<Vec<DbBankTransaction>>,
+    transactions: Option<Vec<DbTransaction>>,
     index: usize,
 }
 impl<'a> DatabaseQueryIter<'a> {
-    pub fn new(db: &'a Database, query: DbQuery) -> Self {
+    pub fn new(db: &'a Database, mut query: DbQuery) -> Self {
+        query.resolve(db);
         let accounts = {
-            if query.item_type_matches(DbItemType::Account) {
+            if query.may_match_item_type(DbItemType::Account) {
                 Some(&db.accounts)
             } else {
                 None
@@ -203,7 +219,7 @@ impl<'a> DatabaseQueryIter<'a> {
         };
 
         let funds = {
-            if query.item_type_matches(DbItemType::Fund) {
+            if query.may_match_item_type(DbItemType::Fund) {
                 Some(&db.funds)
             } else {
                 None
@@ -211,7 +227,7 @@ impl<'a> DatabaseQueryIter<'a> {
         };
 
         let invoices = {
-            if query.item_type_matches(DbItemType::Invoice) {
+            if query.may_match_item_type(DbItemType::Invoice) {
                 Some(&db.invoices)
             } else {
                 None
@@ -219,7 +235,7 @@ impl<'a> DatabaseQueryIter<'a> {
         };
 
         let related_parties = {
-            if query.item_type_matches(DbItemType::RelatedParty) {
+            if query.may_match_item_type(DbItemType::RelatedParty) {
                 Some(&db.related_parties)
             } else {
                 None
@@ -227,16 +243,18 @@ impl<'a> DatabaseQueryIter<'a> {
         };
 
         let bank_transactions = {
-            if query.item_type_matches(DbItemType::BankTransaction) {
-                Some(&db.bank_transactions)
+            if query.may_match_item_type(DbItemType::BankTransaction) {
+                let (date_range, references) = query.index_hint(DbItemType::BankTransaction);
+                Some(db.bank_transactions.select(date_range, &references))
             } else {
                 None
             }
         };
 
         let transactions = {
-            if query.item_type_matches(DbItemType::Transaction) {
-                Some(&db.transactions)
+            if query.may_match_item_type(DbItemType::Transaction) {
+                let (date_range, references) = query.index_hint(DbItemType::Transaction);
+                Some(db.transactions.select(date_range, &references))
             } else {
                 None
             }
@@ -278,16 +296,14 @@ impl<'a> std::iter::Iterator for DatabaseQueryIter<'a> {
                         |d| self.query.matches_related_party(d).then(|| d.id()),
                         self.index,
                     )
-                } else if let Some(transactions) = self.transactions {
-                    transactions.map_nth(
-                        |d| self.query.matches_transaction(d).then(|| d.id()),
-                        self.index,
-                    )
-                } else if let Some(bank_transactions) = self.bank_transactions {
-                    bank_transactions.map_nth(
-                        |d| self.query.matches_bank_transaction(d).then(|| d.id()),
-                        self.index,
-                    )
+                } else if let Some(transactions) = &self.transactions {
+                    transactions
+                        .get(self.index)
+                        .map(|d| self.query.matches_transaction(d).then(|| d.id()))
+                } else if let Some(bank_transactions) = &self.bank_transactions {
+                    bank_transactions
+                        .get(self.index)
+                        .map(|d| self.query.matches_bank_transaction(d).then(|| d.id()))
                 } else {
                     None
                 }
@@ -316,14 +332,14 @@ impl<'a> std::iter::Iterator for DatabaseQueryIter<'a> {
                 self.related_parties = None;
                 continue;
             }
-            if self.bank_transactions.is_some() {
-                self.bank_transactions = None;
-                continue;
-            }
             if self.transactions.is_some() {
                 self.transactions = None;
                 continue;
             }
+            if self.bank_transactions.is_some() {
+                self.bank_transactions = None;
+                continue;
+            }
             return None;
         }
     }
@@ -332,43 +348,119 @@ impl<'a> std::iter::Iterator for DatabaseQueryIter<'a> {
 //ip Database
 impl Database {
     //mp try_rebuild
+    /// Rebuild the database after its items have been loaded, with
+    /// their DbIds renumbered
+    ///
+    /// Every item has its DbIds remapped and is added to its
+    /// collection (in DbId order); then each transaction is added to
+    /// the funds and related parties it debits and credits
     pub fn try_rebuild(&mut self, database_rebuild: &DatabaseRebuild) -> Result<(), Error> {
-        let state = self.state.borrow_mut();
-
-        for db_id in state.items.keys() {
-            let item = &state.items[db_id];
-            match item.itype() {
-                DbItemType::Account => {
-                    self.accounts
-                        .rebuild_add_account(item.account().unwrap(), database_rebuild)?;
-                }
-                DbItemType::Fund => {
-                    self.funds
-                        .rebuild_add_fund(item.fund().unwrap(), database_rebuild)?;
-                }
-                DbItemType::Invoice => {
-                    self.invoices
-                        .rebuild_add_invoice(item.invoice().unwrap(), database_rebuild)?;
-                }
-                DbItemType::RelatedParty => {
-                    self.related_parties.rebuild_add_related_party(
-                        item.related_party().unwrap(),
-                        database_rebuild,
-                    )?;
-                }
-                DbItemType::BankTransaction => {
-                    self.bank_transactions.rebuild_add_bank_transaction(
-                        item.bank_transaction().unwrap(),
-                        database_rebuild,
-                    )?;
-                }
-                _ => (),
+        let mut db_ids: Vec<DbId> = self.state.borrow().items.keys().copied().collect();
+        db_ids.sort();
+
+        for db_id in db_ids.iter() {
+            let item = self.state.borrow().items[db_id].clone();
+            self.rebuild_add_to_collection(item, database_rebuild)?;
+        }
+
+        // Transactions can only be linked once all the funds and
+        // related parties have been rebuilt; a transaction whose side
+        // is missing (such as income with no related party) is linked
+        // to just the other side
+        for t_id in self.transactions.db_ids() {
+            let db_t = self.get_transaction(t_id).unwrap();
+            db_t.inner().update_related_dbs(self, t_id);
+        }
+        Ok(())
+    }
+
+    //mi rebuild_add_to_collection
+    /// Add an item to its collection, remapping its DbIds
+    fn rebuild_add_to_collection(
+        &self,
+        item: DbItem,
+        database_rebuild: &DatabaseRebuild,
+    ) -> Result<(), Error> {
+        match item.itype() {
+            DbItemType::Account => {
+                self.accounts
+                    .rebuild_add_account(item.account().unwrap(), database_rebuild)?;
+            }
+            DbItemType::Fund => {
+                self.funds
+                    .rebuild_add_fund(item.fund().unwrap(), database_rebuild)?;
+            }
+            DbItemType::Invoice => {
+                self.invoices
+                    .rebuild_add_invoice(item.invoice().unwrap(), database_rebuild)?;
+            }
+            DbItemType::RelatedParty => {
+                self.related_parties
+                    .rebuild_add_related_party(item.related_party().unwrap(), database_rebuild)?;
+            }
+            DbItemType::BankTransaction => {
+                self.bank_transactions.rebuild_add_bank_transaction(
+                    item.bank_transaction().unwrap(),
+                    database_rebuild,
+                )?;
+            }
+            DbItemType::Transaction => {
+                self.transactions
+                    .rebuild_add_transaction(item.transaction().unwrap(), database_rebuild)?;
             }
-            // Run through all items - look for transactions, and rebuild the Transactions from the database
         }
         Ok(())
     }
 
+    //mi try_copy
+    /// Create a copy of the database, with copies of its items (so
+    /// that they do not share their contents) keeping their DbIds
+    fn try_copy(&self) -> Result<Database, Error> {
+        let mut items = vec![];
+        for db_id in self.db_ids() {
+            let json = self.get(db_id).unwrap().as_json(false)?;
+            items.push(serde_json::from_str::<DbItem>(&json)?);
+        }
+        let db = Database::try_from_items(items, false)?;
+        {
+            let state = self.state.borrow();
+            let mut copy_state = db.state.borrow_mut();
+            copy_state.next_db_id = state.next_db_id;
+            copy_state.journal_op = state.journal_op;
+        }
+        Ok(db)
+    }
+
+    //mi reserve_db_id
+    /// Reserve a DbId for an item that is to be added with
+    /// [Self::rebuild_add_item]
+    pub(crate) fn reserve_db_id(&self) -> DbId {
+        self.state.borrow_mut().assign_next_free_db_id()
+    }
+
+    //mi rebuild_add_item
+    /// Add an item (from another database) with a reserved DbId,
+    /// remapping the DbIds it refers to
+    ///
+    /// The transactions it refers to are not linked to it
+    pub(crate) fn rebuild_add_item(
+        &self,
+        item: DbItem,
+        database_rebuild: &DatabaseRebuild,
+    ) -> Result<(), Error> {
+        let db_id = item.id();
+        if self.state.borrow().items.contains_key(&db_id) {
+            return Err(Error::DuplicateItemId(db_id));
+        }
+        self.state.borrow_mut().items.insert(db_id, item.clone());
+        self.rebuild_add_to_collection(item, database_rebuild)
+    }
+
+    //ap transactions
+    pub fn transactions(&self) -> &DbTransactions {
+        &self.transactions
+    }
+
     //ap accounts
     pub fn accounts(&self) -> &DbAccounts {
         &self.accounts
@@ -394,6 +486,27 @@ impl Database {
         &self.bank_transactions
     }
 
+    //ap db_ids
+    /// The DbIds of all the items, in order
+    pub fn db_ids(&self) -> Vec<DbId> {
+        let mut db_ids: Vec<DbId> = self.state.borrow().items.keys().copied().collect();
+        db_ids.sort();
+        db_ids
+    }
+
+    //ap journal_op
+    /// The number of the last journal operation whose changes are
+    /// included in the database; a snapshot records this, so that only
+    /// the later operations of its journal are replayed
+    pub fn journal_op(&self) -> usize {
+        self.state.borrow().journal_op
+    }
+
+    //mp set_journal_op
+    pub fn set_journal_op(&self, op: usize) {
+        self.state.borrow_mut().journal_op = op;
+    }
+
     //mp has_db_id
     pub fn has_db_id(&self, id: DbId) -> bool {
         self.state.borrow().items.contains_key(&id)
@@ -468,6 +581,25 @@ impl Database {
         DatabaseQueryIter::new(&self, query)
     }
 
+    //mp query_result
+    /// Query the database, returning the page of (sorted and
+    /// projected) items given by the view, and the total number of
+    /// items that match
+    pub fn query_result(&self, query: DbQuery, view: &QueryView) -> Result<QueryResult, Error> {
+        QueryResult::new(self, query, view)
+    }
+
+    //mp aggregate
+    /// Aggregate the transactions or bank transactions that match a
+    /// query
+    pub fn aggregate(
+        &self,
+        query: DbQuery,
+        aggregation: &Aggregation,
+    ) -> Result<AggregateResult, Error> {
+        aggregation.aggregate(self, query)
+    }
+
     //mi add_item
     fn add_item<I>(&self, item: I) -> (DbId, DbItem)
     where
@@ -498,7 +630,9 @@ impl Database {
     //mp add_fund
     pub fn add_fund(&self, fund: Fund) -> DbId {
         let (db_id, item) = self.add_item(fund);
-        self.funds.add_fund(item.fund().unwrap());
+        let db_fund = item.fund().unwrap();
+        self.funds.add_fund(db_fund.clone());
+        self.funds.add_fund_aliases(&db_fund);
         db_id
     }
 
@@ -513,12 +647,20 @@ impl Database {
     /// The transaction is added to db.transactions, and:
     ///
     ///   If a ToRp transaction, then to the fund it is from and to the RP it is to
-    pub fn add_transaction(&self, transaction: Transaction) -> (DbId, bool) {
+    ///
+    /// A transaction whose currency does not match its funds, and
+    /// which has no exchange rate to convert to them, is not added;
+    /// the currency mismatch is returned as the error
+    ///
+    /// Otherwise return the DbId of the transaction, and true if it
+    /// was added to all the funds and related parties it should be
+    pub fn add_transaction(&self, transaction: Transaction) -> Result<(DbId, bool), Error> {
+        transaction.check_currencies(self)?;
         let (db_id, item) = self.add_item(transaction);
         let db_t = item.transaction().unwrap();
         self.transactions.add_transaction(db_t.clone());
         let okay = db_t.inner().update_related_dbs(self, db_id);
-        (db_id, okay)
+        Ok((db_id, okay))
     }
 
     //mp add_bank_transaction
@@ -528,34 +670,461 @@ impl Database {
         db_id
     }
 
+    //mp references_to
+    /// Find the items that refer to an item: transactions that debit
+    /// or credit it, bank transactions of an account or matched to a
+    /// related party, and bank transactions and invoices that include
+    /// a transaction or are from a supplier
+    ///
+    /// The lists that accounts, funds and related parties keep of
+    /// their transactions are back-references, and are not included
+    pub fn references_to(&self, db_id: DbId) -> Vec<DbId> {
+        let refers_to = |item: &DbItem| {
+            if let Some(db_t) = item.transaction() {
+                let (debit_id, credit_id) = db_t.inner().db_ids();
+                debit_id == db_id || credit_id == db_id
+            } else if let Some(db_bt) = item.bank_transaction() {
+                let bt = db_bt.inner();
+                bt.account_id() == db_id
+                    || bt.related_party() == db_id
+                    || bt.transactions().contains(&db_id)
+            } else if let Some(db_invoice) = item.invoice() {
+                let invoice = db_invoice.inner();
+                invoice.supplier_id() == db_id || invoice.has_transaction(db_id)
+            } else {
+                false
+            }
+        };
+        let mut result: Vec<DbId> = self
+            .state
+            .borrow()
+            .items
+            .values()
+            .filter(|item| refers_to(item))
+            .map(|item| item.id())
+            .collect();
+        result.sort();
+        result
+    }
+
+    //mp remove
+    /// Remove an item from the database, keeping everything that
+    /// refers to it consistent
+    ///
+    /// If other items refer to the item (see [Self::references_to])
+    /// then it is not removed unless `cascade` is true. In that case
+    /// the transactions of a fund or related party, the bank
+    /// transactions of an account, and the invoices of a supplier are
+    /// removed too; bank transactions and invoices that just include
+    /// the item are kept, with the item unlinked from them
+    ///
+    /// Return the DbIds of all the items removed
+    pub fn remove(&self, db_id: DbId, cascade: bool) -> Result<Vec<DbId>, Error> {
+        let Some(item) = self.get(db_id) else {
+            return Err(Error::UnknownItemId(db_id));
+        };
+        let references = self.references_to(db_id);
+        if !references.is_empty() && !cascade {
+            return Err(Error::ItemReferenced(db_id, references.len()));
+        }
+        let mut removed = vec![];
+        for r_id in references {
+            // It may have been removed already by the cascade
+            let Some(r_item) = self.get(r_id) else {
+                continue;
+            };
+            if let Some(db_bt) = r_item.bank_transaction() {
+                let mut bt = db_bt.inner_mut();
+                if bt.account_id() != db_id {
+                    let unlink_related_party = bt.related_party() == db_id;
+                    if unlink_related_party {
+                        bt.set_related_party(DbId::none());
+                    }
+                    bt.remove_transaction(db_id);
+                    drop(bt);
+                    if unlink_related_party {
+                        self.bank_transactions.reindex_transaction(r_id);
+                    }
+                    continue;
+                }
+            }
+            if let Some(db_invoice) = r_item.invoice() {
+                let mut invoice = db_invoice.inner_mut();
+                if invoice.supplier_id() != db_id {
+                    invoice.remove_transaction(db_id);
+                    continue;
+                }
+            }
+            removed.extend(self.remove(r_id, true)?);
+        }
+        self.unlink_item(&item);
+        removed.push(db_id);
+        Ok(removed)
+    }
+
+    //mi unlink_item
+    /// Remove an item that nothing refers to any more from the
+    /// database, and from the back-references to it
+    fn unlink_item(&self, item: &DbItem) {
+        let db_id = item.id();
+        match item.itype() {
+            DbItemType::Account => {
+                self.accounts.remove_account(db_id);
+            }
+            DbItemType::Fund => {
+                self.funds.remove_fund(db_id);
+            }
+            DbItemType::Invoice => {
+                let supplier_id = item.invoice().unwrap().inner().supplier_id();
+                if let Some(db_rp) = self.get_related_party(supplier_id) {
+                    db_rp.inner_mut().remove_invoice(db_id);
+                }
+                self.invoices.remove_invoice(db_id);
+            }
+            DbItemType::RelatedParty => {
+                self.related_parties.remove_related_party(db_id);
+                self.clear_account_related_parties();
+            }
+            DbItemType::BankTransaction => {
+                let account_id = item.bank_transaction().unwrap().inner().account_id();
+                if let Some(db_account) = self.get_account(account_id) {
+                    db_account.inner_mut().remove_bank_transaction(db_id);
+                }
+                self.bank_transactions.remove_transaction(db_id);
+            }
+            DbItemType::Transaction => {
+                let (debit_id, credit_id) = item.transaction().unwrap().inner().db_ids();
+                self.unlink_transaction(db_id, debit_id, credit_id);
+                self.transactions.remove_transaction(db_id);
+            }
+        }
+        self.state.borrow_mut().items.remove(&db_id);
+    }
+
+    //mi unlink_transaction
+    /// Remove a transaction from the funds and related parties on
+    /// its debit and credit sides
+    fn unlink_transaction(&self, t_id: DbId, debit_id: DbId, credit_id: DbId) {
+        for db_id in [debit_id, credit_id] {
+            if let Some(db_f) = self.get_fund(db_id) {
+                db_f.inner_mut().remove_transaction(t_id);
+            }
+            if let Some(db_rp) = self.get_related_party(db_id) {
+                db_rp.inner_mut().remove_transaction(t_id);
+            }
+        }
+    }
+
+    //mp update_account
+    /// Update an account, reindexing it if its description changes
+    ///
+    /// If another account has the new description then the
+    /// description is restored and an error returned
+    pub fn update_account<F: FnOnce(&mut Account)>(&self, db_id: DbId, f: F) -> Result<(), Error> {
+        let Some(db_account) = self.get_account(db_id) else {
+            return Err(Error::UnknownItemId(db_id));
+        };
+        let old_desc = db_account.inner().desc().clone();
+        f(&mut db_account.inner_mut());
+        if !self.accounts.reindex_account(&db_account, &old_desc) {
+            let desc = db_account.inner().desc().clone();
+            db_account.inner_mut().set_desc(old_desc);
+            return Err(format!("Another account already has description {desc}").into());
+        }
+        Ok(())
+    }
+
+    //mp update_fund
+    /// Update a fund, reindexing it if its name or aliases change
+    ///
+    /// If another fund has the new name then the name and aliases
+    /// are restored and an error returned
+    pub fn update_fund<F: FnOnce(&mut Fund)>(&self, db_id: DbId, f: F) -> Result<(), Error> {
+        let Some(db_fund) = self.get_fund(db_id) else {
+            return Err(Error::UnknownItemId(db_id));
+        };
+        let (old_name, old_aliases) = {
+            let fund = db_fund.inner();
+            (fund.name().to_string(), fund.aliases().to_vec())
+        };
+        f(&mut db_fund.inner_mut());
+        if !self.funds.reindex_fund(&db_fund) {
+            let name = db_fund.inner().name().to_string();
+            {
+                let mut fund = db_fund.inner_mut();
+                fund.set_name(old_name);
+                fund.clear_aliases();
+                for a in old_aliases {
+                    fund.add_alias(a);
+                }
+            }
+            self.funds.reindex_fund(&db_fund);
+            return Err(format!("Another fund is already called '{name}'").into());
+        }
+        Ok(())
+    }
+
+    //mp update_related_party
+    /// Update a related party, reindexing it if its name, aliases,
+    /// rp_id or account descriptions change
+    ///
+    /// If another related party has the new name or rp_id then the
+    /// name, aliases and rp_id are restored and an error returned
+    pub fn update_related_party<F: FnOnce(&mut RelatedParty)>(
+        &self,
+        db_id: DbId,
+        f: F,
+    ) -> Result<(), Error> {
+        let Some(db_rp) = self.get_related_party(db_id) else {
+            return Err(Error::UnknownItemId(db_id));
+        };
+        let (old_name, old_aliases, old_rp_id) = {
+            let rp = db_rp.inner();
+            (rp.name().to_string(), rp.aliases().to_vec(), rp.rp_id())
+        };
+        f(&mut db_rp.inner_mut());
+        self.clear_account_related_parties();
+        if !self.related_parties.reindex_related_party(&db_rp) {
+            let (name, rp_id) = (db_rp.inner().name().to_string(), db_rp.inner().rp_id());
+            {
+                let mut rp = db_rp.inner_mut();
+                rp.change_name(old_name);
+                rp.change_rp_id(old_rp_id);
+                rp.clear_aliases();
+                for a in old_aliases {
+                    rp.add_alias(a);
+                }
+            }
+            self.related_parties.reindex_related_party(&db_rp);
+            return Err(format!(
+                "Another related party is already called '{name}' or has rp_id {rp_id}"
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    //mp update_invoice
+    /// Update an invoice, reindexing it if its reason changes
+    ///
+    /// If another invoice has the new reason, or the supplier is not
+    /// a related party, then the reason and supplier are restored and
+    /// an error returned
+    pub fn update_invoice<F: FnOnce(&mut Invoice)>(&self, db_id: DbId, f: F) -> Result<(), Error> {
+        let Some(db_invoice) = self.get(db_id).and_then(|item| item.invoice()) else {
+            return Err(Error::UnknownItemId(db_id));
+        };
+        let (old_reason, old_supplier_id) = {
+            let invoice = db_invoice.inner();
+            (invoice.reason().to_string(), invoice.supplier_id())
+        };
+        f(&mut db_invoice.inner_mut());
+        let supplier_id = db_invoice.inner().supplier_id();
+        let result: Result<(), Error> = {
+            if self.get_related_party(supplier_id).is_none() {
+                Err(format!("Invoice supplier {supplier_id} is not a related party").into())
+            } else if !self.invoices.reindex_invoice(&db_invoice, &old_reason) {
+                Err(format!(
+                    "Another invoice already has reason '{}'",
+                    db_invoice.inner().reason()
+                )
+                .into())
+            } else {
+                Ok(())
+            }
+        };
+        if result.is_err() {
+            let mut invoice = db_invoice.inner_mut();
+            invoice.set_reason(old_reason);
+            invoice.set_supplier_id(old_supplier_id);
+        }
+        result
+    }
+
+    //mp update_transaction
+    /// Update a transaction, moving it between the funds and related
+    /// parties it is on, and to its new date in the invoices that
+    /// include it
+    ///
+    /// If the updated transaction does not have the funds and related
+    /// parties required by its type, or its currency does not match
+    /// them, then it is restored and an error returned
+    pub fn update_transaction<F: FnOnce(&mut Transaction)>(
+        &self,
+        db_id: DbId,
+        f: F,
+    ) -> Result<(), Error> {
+        let Some(db_t) = self.get_transaction(db_id) else {
+            return Err(Error::UnknownItemId(db_id));
+        };
+        let old = db_t.inner().clone();
+        f(&mut db_t.inner_mut());
+        let checked = db_t
+            .inner()
+            .check_db_ids(self)
+            .and_then(|_| db_t.inner().check_currencies(self));
+        if let Err(e) = checked {
+            *db_t.inner_mut() = old;
+            return Err(e);
+        }
+        let (debit_id, credit_id) = old.db_ids();
+        self.unlink_transaction(db_id, debit_id, credit_id);
+        db_t.inner().update_related_dbs(self, db_id);
+        self.transactions.reindex_transaction(db_id);
+        let date = db_t.inner().date();
+        if date != old.date() {
+            for invoice_id in self.invoices.ids() {
+                if let Some(db_invoice) = self.get(invoice_id).and_then(|item| item.invoice()) {
+                    db_invoice.inner_mut().move_transaction(db_id, date);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    //mp update_bank_transaction
+    /// Update a bank transaction, moving it to its new date in its
+    /// account
+    ///
+    /// A bank transaction cannot be moved to another account, and its
+    /// related party and transactions must be in the database;
+    /// otherwise it is restored and an error returned
+    pub fn update_bank_transaction<F: FnOnce(&mut BankTransaction)>(
+        &self,
+        db_id: DbId,
+        f: F,
+    ) -> Result<(), Error> {
+        let Some(db_bt) = self.get_bank_transaction(db_id) else {
+            return Err(Error::UnknownItemId(db_id));
+        };
+        let old = db_bt.inner().clone();
+        f(&mut db_bt.inner_mut());
+        let result: Result<(), Error> = {
+            let bt = db_bt.inner();
+            if bt.account_id() != old.account_id() {
+                Err(format!("Bank transaction {db_id} cannot be moved to another account").into())
+            } else if !bt.related_party().is_none()
+                && self.get_related_party(bt.related_party()).is_none()
+            {
+                Err(format!("{} is not a related party", bt.related_party()).into())
+            } else if let Some(t_id) = bt
+                .transactions()
+                .iter()
+                .find(|t_id| self.get_transaction(**t_id).is_none())
+            {
+                Err(format!("{t_id} is not a transaction").into())
+            } else {
+                Ok(())
+            }
+        };
+        if result.is_err() {
+            *db_bt.inner_mut() = old;
+            return result;
+        }
+        let date = db_bt.inner().date();
+        if date != old.date() {
+            if let Some(db_account) = self.get_account(old.account_id()) {
+                db_account.inner_mut().move_bank_transaction(db_id, date);
+            }
+        }
+        self.bank_transactions.reindex_transaction(db_id);
+        Ok(())
+    }
+
+    //mp check_integrity
+    /// Check the referential integrity of the whole database,
+    /// returning the problems found (each with a suggested fix)
+    pub fn check_integrity(&self) -> Vec<IntegrityProblem> {
+        crate::integrity::check_database(self)
+    }
+
+    //mp merge
+    /// Merge another database into this one, matching items by their
+    /// natural keys and adding those that do not match
+    ///
+    /// Matched items whose contents differ are reported as
+    /// conflicts, for resolving by hand
+    ///
+    /// The other database is merged into a copy of this one, which
+    /// replaces it only if the merge succeeds; so a merge that fails
+    /// (such as for an added item whose name is already in use)
+    /// leaves the database unchanged
+    pub fn merge(&mut self, other: &Database) -> Result<MergeReport, Error> {
+        let merged = self.try_copy()?;
+        let report = crate::merge::merge_database(&merged, other)?;
+        *self = merged;
+        Ok(report)
+    }
+
     //mp clear_account_related_parties
     pub fn clear_account_related_parties(&self) {
         *self.account_related_parties.borrow_mut() = RelatedParties::new(6, 12, 3);
     }
 
     //mp find_account_related_party
+    /// Find the related party whose account descriptions match a bank
+    /// transaction description; none if there is no unique match
     pub fn find_account_related_party(&self, descr: &str) -> DbId {
+        self.match_account_related_party(descr).db_id()
+    }
+
+    //mp match_account_related_party
+    /// Match a bank transaction description against the account
+    /// descriptions of the related parties
+    ///
+    /// Longer prefixes of the description are used while more than
+    /// one related party matches; if the longest permitted still
+    /// matches more than one then the match is ambiguous
+    pub fn match_account_related_party(&self, descr: &str) -> RelatedPartyMatch {
         if self.account_related_parties.borrow().is_none() {
             self.clear_account_related_parties();
         }
-        let Some(db_id) = self
-            .account_related_parties
-            .borrow_mut()
-            .find_item_with_collisions(descr)
-        else {
-            // eprintln!("find_account_related_party: None");
-            return DbId::none();
-        };
-        if !db_id.is_none() {
-            // eprintln!("find_account_related_party: {db_id}");
-            return db_id;
+        loop {
+            let Some(db_id) = self
+                .account_related_parties
+                .borrow()
+                .find_item_with_collisions(descr)
+            else {
+                return RelatedPartyMatch::NotFound;
+            };
+            if !db_id.is_none() {
+                return RelatedPartyMatch::Found(db_id);
+            }
+            if self.try_add_new_account_related_party_cache().is_err() {
+                return RelatedPartyMatch::Ambiguous;
+            }
+        }
+    }
+
+    //mp find_counterparty_related_party
+    /// Find the related party of a counterparty given by a statement;
+    /// the IBAN is matched against the account descriptions of the
+    /// related parties, then the name against their names and aliases
+    pub fn find_counterparty_related_party(&self, counterparty: &Counterparty) -> DbId {
+        if let Some(iban) = &counterparty.iban {
+            if let Some(db_rp) = self.related_parties.find_by_account_descr(iban) {
+                return db_rp.id();
+            }
+        }
+        if let Some(name) = &counterparty.name {
+            if let Some(db_rp) = self.related_parties.find_by_name(name) {
+                return db_rp.id();
+            }
         }
-        self.add_new_account_related_party_cache();
-        self.find_account_related_party(descr)
+        DbId::none()
     }
 
     //mp add_new_account_related_party_cache
     pub fn add_new_account_related_party_cache(&self) {
+        if self.try_add_new_account_related_party_cache().is_err() {
+            panic!("Descriptions are not enough");
+        }
+    }
+
+    //mp try_add_new_account_related_party_cache
+    /// Add a cache of longer description prefixes, failing if the
+    /// longest permitted is already present
+    pub fn try_add_new_account_related_party_cache(&self) -> Result<(), Error> {
         let descr_of_db = |id, f: &mut (dyn for<'a> FnMut(DbId, &'a str))| {
             let state = self.state.borrow();
             let Some(db_item) = state.items.get(&id) else {
@@ -567,14 +1136,9 @@ impl Database {
                 }
             }
         };
-        if self
-            .account_related_parties
+        self.account_related_parties
             .borrow_mut()
             .add_new_cache(self.state.borrow().items.keys().copied(), descr_of_db)
-            .is_err()
-        {
-            panic!("Descriptions are not enough");
-        }
     }
 
     //mp serialize_as_array
@@ -596,46 +1160,140 @@ impl Database {
         seq.end()
     }
 
+    //mp serialize_as_dictionary
+    /// Serialize the database as a dictionary of sections, each keyed
+    /// by the natural key of its items
+    pub fn serialize_as_dictionary<S>(
+        &self,
+        serializer: S,
+    ) -> Result<<S as Serializer>::Ok, <S as Serializer>::Error>
+    where
+        S: Serializer,
+    {
+        use serde::ser::Error;
+        let state = self.state.borrow();
+        let dict = DbDictionary::of_items(self.db_ids().iter().map(|db_id| &state.items[db_id]))
+            .map_err(S::Error::custom)?;
+        dict.serialize(serializer)
+    }
+
+    //mp serialize
+    /// Serialize the database as a file, with a header giving its
+    /// schema version and the items in the given layout
+    pub fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+        ofmt: FileFormat,
+    ) -> Result<<S as Serializer>::Ok, <S as Serializer>::Error> {
+        SchemaHeader::new(self, ofmt).serialize(serializer)
+    }
+
     //mp deserialize_from_array
+    /// Deserialize a database file whose items are an array
     pub fn deserialize_from_array<'de, D>(deserializer: D) -> Result<Self, Error>
     where
         D: Deserializer<'de>,
     {
-        let array = Vec::<DbItem>::deserialize(deserializer)
-            .map_err(|e| Error::Deserialization(e.to_string()))?;
-        Self::try_from(array)
+        Self::deserialize(deserializer, FileFormat::Array)
+    }
+
+    //mp deserialize_from_dictionary
+    /// Deserialize a database file whose items are a dictionary of
+    /// sections
+    pub fn deserialize_from_dictionary<'de, D>(deserializer: D) -> Result<Self, Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Self::deserialize(deserializer, FileFormat::Dictionary)
     }
 
     //cp deserialize
+    /// Deserialize a database file whose items are in the given
+    /// layout; files with an older schema version are upgraded as
+    /// they are loaded
     pub fn deserialize<'de, D: Deserializer<'de>>(
         deserializer: D,
         ifmt: FileFormat,
     ) -> Result<Self, Error> {
-        match ifmt {
-            FileFormat::Array => Self::deserialize_from_array(deserializer),
-            _ => Err(Error::FormatNotSupported(ifmt, "database")),
+        let file = SchemaFile::deserialize(deserializer)?;
+        if file.file_format() != ifmt {
+            return Err(Error::Deserialization(format!(
+                "expected a database file in the {ifmt} format, found {}",
+                file.file_format()
+            )));
         }
+        Self::of_schema_file(file)
     }
 
-    //zz All done
-}
+    //cp deserialize_any_format
+    /// Deserialize a database file, whose items are either an array
+    /// or a dictionary of sections; files with an older schema
+    /// version are upgraded as they are loaded
+    pub fn deserialize_any_format<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, Error> {
+        Self::of_schema_file(SchemaFile::deserialize(deserializer)?)
+    }
 
-//ip TryFrom<Vec<DbItem>> for Database
-impl std::convert::TryFrom<Vec<DbItem>> for Database {
-    type Error = Error;
-    fn try_from(array: Vec<DbItem>) -> Result<Database, Error> {
+    //ci of_schema_file
+    /// Create a database from the contents of a file, upgrading its
+    /// items to the current schema version
+    fn of_schema_file(mut file: SchemaFile) -> Result<Self, Error> {
+        file.upgrade()?;
+        let journal_op = file.journal_op();
+        let db = Self::try_from(file.into_items()?)?;
+        db.set_journal_op(journal_op);
+        Ok(db)
+    }
+
+    //fp file_schema
+    /// Find the schema version and layout of a database file, without
+    /// loading it
+    pub fn file_schema<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<(usize, FileFormat), Error> {
+        let file = SchemaFile::deserialize(deserializer)?;
+        Ok((file.version(), file.file_format()))
+    }
+
+    //cp try_from_items
+    /// Create a database from its items, renumbering them from the
+    /// first DbId if required; otherwise they keep their DbIds, as
+    /// when loading from a store that refers to items by DbId, and
+    /// new items are given DbIds after them
+    pub(crate) fn try_from_items(array: Vec<DbItem>, renumber: bool) -> Result<Self, Error> {
         let mut db = Database::default();
         let mut state = DatabaseState::default();
-        let mut next_db_id = state.assign_next_free_db_id();
         let mut database_rebuild = DatabaseRebuild::default();
         for item in array {
             let old_id = item.id();
-            database_rebuild.add_mapping(old_id, next_db_id)?;
-            state.items.insert(next_db_id, item);
-            next_db_id = state.assign_next_free_db_id();
+            let new_id = {
+                if renumber {
+                    state.assign_next_free_db_id()
+                } else if old_id.is_none() || state.items.contains_key(&old_id) {
+                    return Err(Error::DuplicateItemId(old_id));
+                } else {
+                    old_id
+                }
+            };
+            database_rebuild.add_mapping(old_id, new_id)?;
+            state.items.insert(new_id, item.with_id(new_id));
+            if new_id >= state.next_db_id {
+                state.next_db_id = new_id.increment();
+            }
         }
         db.state = state.into();
         db.try_rebuild(&database_rebuild)?;
         Ok(db)
     }
+
+    //zz All done
+}
+
+//ip TryFrom<Vec<DbItem>> for Database
+impl std::convert::TryFrom<Vec<DbItem>> for Database {
+    type Error = Error;
+    fn try_from(array: Vec<DbItem>) -> Result<Database, Error> {
+        Database::try_from_items(array, true)
+    }
 }
@@ -0,0 +1,131 @@
+//a Imports
+use std::collections::HashMap;
+
+use crate::indexed_vec::Idx;
+use crate::{Date, DateRange, DbId};
+
+//a DatedIndex
+//tp DatedIndex
+/// Secondary indexes of a collection of dated items (such as bank
+/// transactions), by date and by the other items they refer to
+/// (such as their account and related party)
+///
+/// date_order is maintained sorted by (Date, DbId), so that the
+/// items in a date range are found by binary search, in the same way
+/// as the date_order of [crate::OrderedTransactions]
+///
+/// The keys that each item was indexed with are kept, so that it can
+/// be reindexed when it changes
+#[derive(Debug, Default)]
+pub(crate) struct DatedIndex {
+    /// The date and DbId of every item, sorted
+    date_order: Vec<(Date, DbId)>,
+    /// The items that refer to each DbId, in DbId order
+    by_reference: HashMap<DbId, Vec<DbId>>,
+    /// The date and references that each item is indexed by
+    keys: HashMap<DbId, (Date, Vec<DbId>)>,
+}
+
+//ip DatedIndex
+impl DatedIndex {
+    //mp insert
+    /// Index an item with its date and the DbIds it refers to,
+    /// replacing how it was indexed before
+    pub(crate) fn insert(&mut self, db_id: DbId, date: Date, mut references: Vec<DbId>) {
+        self.remove(db_id);
+        references.retain(|r| !r.is_none());
+        references.sort();
+        references.dedup();
+        let pos = self.date_order.partition_point(|d| *d < (date, db_id));
+        self.date_order.insert(pos, (date, db_id));
+        for r in references.iter() {
+            let ids = self.by_reference.entry(*r).or_default();
+            let pos = ids.partition_point(|id| *id < db_id);
+            ids.insert(pos, db_id);
+        }
+        self.keys.insert(db_id, (date, references));
+    }
+
+    //mp remove
+    /// Remove an item from the index, returning true if it was present
+    pub(crate) fn remove(&mut self, db_id: DbId) -> bool {
+        let Some((date, references)) = self.keys.remove(&db_id) else {
+            return false;
+        };
+        if let Ok(pos) = self.date_order.binary_search(&(date, db_id)) {
+            self.date_order.remove(pos);
+        }
+        for r in references {
+            if let Some(ids) = self.by_reference.get_mut(&r) {
+                if let Ok(pos) = ids.binary_search(&db_id) {
+                    ids.remove(pos);
+                }
+                if ids.is_empty() {
+                    self.by_reference.remove(&r);
+                }
+            }
+        }
+        true
+    }
+
+    //ap in_range
+    /// The items in a date range, in date order
+    pub(crate) fn in_range(&self, date_range: DateRange) -> impl Iterator<Item = DbId> + '_ {
+        let start = self
+            .date_order
+            .partition_point(|(d, _)| *d < date_range.start());
+        let end = self
+            .date_order
+            .partition_point(|(d, _)| *d < date_range.end());
+        self.date_order[start..end.max(start)]
+            .iter()
+            .map(|(_, db_id)| *db_id)
+    }
+
+    //ap referring_to
+    /// The items that refer to a DbId, in DbId order
+    pub(crate) fn referring_to(&self, db_id: DbId) -> &[DbId] {
+        self.by_reference
+            .get(&db_id)
+            .map(|ids| ids.as_slice())
+            .unwrap_or_default()
+    }
+
+    //mp select
+    /// Select the items in a date range (if it is not empty) that
+    /// refer to all of the given DbIds, in DbId order
+    ///
+    /// Return None if neither restricts the items, as then all of
+    /// them would be selected
+    pub(crate) fn select(&self, date_range: DateRange, references: &[DbId]) -> Option<Vec<DbId>> {
+        let mut selected: Option<Vec<DbId>> = None;
+        for r in references {
+            let ids = self.referring_to(*r);
+            selected = Some(match selected {
+                None => ids.to_vec(),
+                Some(s) => s
+                    .into_iter()
+                    .filter(|id| ids.binary_search(id).is_ok())
+                    .collect(),
+            });
+        }
+        if !date_range.is_empty() {
+            selected = Some(match selected {
+                None => {
+                    let mut ids: Vec<_> = self.in_range(date_range).collect();
+                    ids.sort();
+                    ids
+                }
+                Some(s) => s
+                    .into_iter()
+                    .filter(|id| {
+                        self.keys
+                            .get(id)
+                            .is_some_and(|(d, _)| date_range.contains(*d))
+                    })
+                    .collect(),
+            });
+        }
+        selected
+    }
+}
@@ -0,0 +1,165 @@
+//a Documentation
+//! The dictionary layout of a Database file
+//!
+//! The items are split into sections (accounts, funds,
+//! related_parties, invoices, bank_transactions and transactions);
+//! accounts, funds and invoices are keyed by their natural key (the
+//! account description, fund name and invoice reason respectively),
+//! related parties by their DbId (as two may have the same related
+//! party id, which [crate::Database::check_integrity] reports), and
+//! the bank transactions and transactions are held in DbId order.
+//!
+//! The keys are sorted, so the file is stable when written out
+//! again; this makes it easier to hand-edit and to compare versions,
+//! than the flat array of DbItems
+//!
+//! Items still refer to each other by DbId; the DbIds are renumbered
+//! when the file is loaded (as for the array layout)
+
+//a Imports
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::db_item::DbItemTypeE;
+use crate::{DbAccount, DbBankTransaction, DbFund, DbInvoice, DbRelatedParty, DbTransaction};
+use crate::{DbId, DbItem, DbItemType, Error};
+
+//a DbDictionary
+//tp DbDictionary
+/// The items of a database by section
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct DbDictionary {
+    #[serde(default)]
+    accounts: BTreeMap<String, DbAccount>,
+    #[serde(default)]
+    funds: BTreeMap<String, DbFund>,
+    #[serde(default)]
+    related_parties: BTreeMap<DbId, DbRelatedParty>,
+    #[serde(default)]
+    invoices: BTreeMap<String, DbInvoice>,
+    #[serde(default)]
+    bank_transactions: Vec<DbBankTransaction>,
+    #[serde(default)]
+    transactions: Vec<DbTransaction>,
+}
+
+//fi insert_unique
+/// Insert an item into a section, failing if its key is already used
+fn insert_unique<K, V>(
+    section: &mut BTreeMap<K, V>,
+    name: &str,
+    key: K,
+    value: V,
+) -> Result<(), Error>
+where
+    K: Ord + std::fmt::Display,
+{
+    if section.contains_key(&key) {
+        return Err(format!("Duplicate {name} '{key}' in database").into());
+    }
+    section.insert(key, value);
+    Ok(())
+}
+
+//fi check_key
+/// Check that the key of an item in a section is its natural key
+fn check_key<K>(name: &str, key: &K, natural_key: &K) -> Result<(), Error>
+where
+    K: PartialEq + std::fmt::Display,
+{
+    if key != natural_key {
+        return Err(format!("{name} keyed by '{key}' should be keyed by '{natural_key}'").into());
+    }
+    Ok(())
+}
+
+//ip DbDictionary
+impl DbDictionary {
+    //fp section_itype
+    /// The type of the items in a section, if it is a section name
+    pub(crate) fn section_itype(name: &str) -> Option<DbItemType> {
+        match name {
+            "accounts" => Some(DbItemType::Account),
+            "funds" => Some(DbItemType::Fund),
+            "related_parties" => Some(DbItemType::RelatedParty),
+            "invoices" => Some(DbItemType::Invoice),
+            "bank_transactions" => Some(DbItemType::BankTransaction),
+            "transactions" => Some(DbItemType::Transaction),
+            _ => None,
+        }
+    }
+
+    //cp of_items
+    /// Split the items (which should be in DbId order) into their
+    /// sections
+    pub(crate) fn of_items<'a, I>(items: I) -> Result<Self, Error>
+    where
+        I: Iterator<Item = &'a DbItem>,
+    {
+        let mut dict = Self::default();
+        for item in items {
+            match item.itype() {
+                DbItemType::Account => {
+                    let db_account = item.account().unwrap();
+                    let key = db_account.inner().desc().to_string();
+                    insert_unique(&mut dict.accounts, "account", key, db_account)?;
+                }
+                DbItemType::Fund => {
+                    let db_fund = item.fund().unwrap();
+                    let key = db_fund.inner().name().to_string();
+                    insert_unique(&mut dict.funds, "fund", key, db_fund)?;
+                }
+                DbItemType::RelatedParty => {
+                    let db_rp = item.related_party().unwrap();
+                    let key = db_rp.id();
+                    insert_unique(&mut dict.related_parties, "related party", key, db_rp)?;
+                }
+                DbItemType::Invoice => {
+                    let db_invoice = item.invoice().unwrap();
+                    let key = db_invoice.inner().reason().to_string();
+                    insert_unique(&mut dict.invoices, "invoice", key, db_invoice)?;
+                }
+                DbItemType::BankTransaction => {
+                    dict.bank_transactions
+                        .push(item.bank_transaction().unwrap());
+                }
+                DbItemType::Transaction => {
+                    dict.transactions.push(item.transaction().unwrap());
+                }
+            }
+        }
+        Ok(dict)
+    }
+
+    //mp into_items
+    /// Convert the sections back into items, in the order of their
+    /// DbIds in the file, checking the key of each
+    pub(crate) fn into_items(self) -> Result<Vec<DbItem>, Error> {
+        let mut items = vec![];
+        for (key, d) in self.accounts {
+            check_key("Account", &key, &d.inner().desc().to_string())?;
+            items.push(DbItemTypeE::Account(d).into());
+        }
+        for (key, d) in self.funds {
+            check_key("Fund", &key, &d.inner().name().to_string())?;
+            items.push(DbItemTypeE::Fund(d).into());
+        }
+        for (key, d) in self.related_parties {
+            check_key("Related party", &key, &d.id())?;
+            items.push(DbItemTypeE::RelatedParty(d).into());
+        }
+        for (key, d) in self.invoices {
+            check_key("Invoice", &key, &d.inner().reason().to_string())?;
+            items.push(DbItemTypeE::Invoice(d).into());
+        }
+        for d in self.bank_transactions {
+            items.push(DbItemTypeE::BankTransaction(d).into());
+        }
+        for d in self.transactions {
+            items.push(DbItemTypeE::Transaction(d).into());
+        }
+        items.sort_by_key(|item: &DbItem| item.id());
+        Ok(items)
+    }
+}
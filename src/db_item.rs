@@ -94,6 +94,9 @@ macro_rules! make_db_item {
             pub fn id(&self) -> $crate :: DbId {
                 self.id
             }
+            pub(crate) fn with_id(self, id: $crate :: DbId) -> Self {
+                Self { id, inner: self.inner }
+            }
         }
 
         impl $crate :: DbItemKindObj for $db_id {
@@ -171,6 +174,19 @@ impl DbItemTypeE {
         }
     }
 
+    //cp with_id
+    /// Renumber the item
+    fn with_id(self, id: DbId) -> Self {
+        match self {
+            DbItemTypeE::Account(d) => DbItemTypeE::Account(d.with_id(id)),
+            DbItemTypeE::Fund(d) => DbItemTypeE::Fund(d.with_id(id)),
+            DbItemTypeE::Invoice(d) => DbItemTypeE::Invoice(d.with_id(id)),
+            DbItemTypeE::RelatedParty(d) => DbItemTypeE::RelatedParty(d.with_id(id)),
+            DbItemTypeE::BankTransaction(d) => DbItemTypeE::BankTransaction(d.with_id(id)),
+            DbItemTypeE::Transaction(d) => DbItemTypeE::Transaction(d.with_id(id)),
+        }
+    }
+
     //ap account
     pub fn account(&self) -> Option<DbAccount> {
         if let DbItemTypeE::Account(account) = &self {
@@ -244,6 +260,16 @@ impl std::fmt::Display for DbItem {
 
 //ip DbItem
 impl DbItem {
+    //cp with_id
+    /// Renumber the item, as when a database is loaded
+    pub(crate) fn with_id(self, id: DbId) -> Self {
+        Self {
+            id,
+            itype: self.itype,
+            value: self.value.with_id(id),
+        }
+    }
+
     //ap id
     pub fn id(&self) -> DbId {
         self.id
@@ -367,6 +393,18 @@ impl From<(DbId, Transaction)> for DbItem {
     }
 }
 
+//ip From<DbItemTypeE> for DbItem
+impl From<DbItemTypeE> for DbItem {
+    fn from(value: DbItemTypeE) -> Self {
+        let d = value.as_dyn_item_kind();
+        Self {
+            id: d.id(),
+            itype: d.itype(),
+            value,
+        }
+    }
+}
+
 //ip PartialEq for DbItem
 impl std::cmp::PartialEq for DbItem {
     fn eq(&self, other: &Self) -> bool {
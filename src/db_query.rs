@@ -1,12 +1,30 @@
 //a Imports
+use std::collections::HashSet;
+
 use regex::Regex;
 
 use crate::indexed_vec::Idx;
 use crate::{
-    Date, DateRange, DbAccount, DbBankTransaction, DbFund, DbId, DbInvoice, DbItemType,
-    DbRelatedParty, DbTransaction, RelatedPartyQuery, RelatedPartyType,
+    Amount, BankTransactionType, Database, Date, DateRange, DbAccount, DbBankTransaction, DbFund,
+    DbId, DbInvoice, DbItemType, DbRelatedParty, DbTransaction, Error, RelatedPartyQuery,
+    RelatedPartyType, TransactionType,
 };
 
+//a AmountSide
+//tp AmountSide
+/// Whether an item must be a credit or a debit
+///
+/// A bank transaction is a credit (or debit) if it has a credit (or
+/// debit) amount. A transaction is a credit (or debit) of the DbId
+/// or fund of the query if it credits (or debits) it; if neither is
+/// given then income (from a related party) is a credit and a
+/// payment (to a related party) is a debit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AmountSide {
+    Credit,
+    Debit,
+}
+
 //a DbQuery
 //tp DbQuery
 /// A query of the database, somewhat abstract. The matching for
@@ -16,6 +34,16 @@ use crate::{
 /// Subsequent checks might be to see if the name matches the name_re;
 /// this does not make sense for (e.g.) a Transaction, so presumably
 /// that is not useful to set in a specific query for transactions.
+///
+/// An item must match every filter of the query that makes sense for
+/// its type; it must also match all of the 'all_of' queries, at least
+/// one of the 'any_of' queries (if there are any), and none of the
+/// 'none_of' queries. These nest, so a query can be a tree of AND, OR
+/// and NOT groups.
+///
+/// Within a 'none_of' query a filter that does not make sense for the
+/// type of an item does not match it, so that (for example) excluding
+/// large amounts does not exclude every related party.
 #[derive(Default, Debug, Clone)]
 pub struct DbQuery {
     /// Database item type that a match must be
@@ -45,42 +73,101 @@ pub struct DbQuery {
 
     /// A data range that the item must be within
     date_range: DateRange,
+
+    /// An amount that the item must be for
+    amount: Option<Amount>,
+
+    /// The minimum amount that the item must be for
+    min_amount: Option<Amount>,
+
+    /// The maximum amount that the item must be for
+    max_amount: Option<Amount>,
+
+    /// Whether the item must be a credit or a debit
+    side: Option<AmountSide>,
+
+    /// The type that a transaction must have
+    transaction_type: Option<TransactionType>,
+
+    /// The type that a bank transaction must have (ignoring any
+    /// cheque number)
+    bank_transaction_type: Option<BankTransactionType>,
+
+    /// A fund that the item must belong to; this can be
+    /// DbId::is_none() for no match
+    fund: DbId,
+
+    /// The transactions of the fund, which bank transactions and
+    /// invoices are matched against; this is set by
+    /// [DbQuery::resolve], as it requires the database
+    fund_transactions: Option<HashSet<DbId>>,
+
+    /// An account that the item must belong to; this can be
+    /// DbId::is_none() for no match
+    account: DbId,
+
+    /// A related party that the item must be for; this can be
+    /// DbId::is_none() for no match
+    related_party: DbId,
+
+    /// Queries that the item must all match
+    all_of: Vec<DbQuery>,
+
+    /// Queries that the item must match at least one of
+    any_of: Vec<DbQuery>,
+
+    /// Queries that the item must match none of
+    none_of: Vec<DbQuery>,
 }
 
 //ip std::fmt::Display for DbQuery
 impl std::fmt::Display for DbQuery {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
         writeln!(fmt, "DbQuery:")?;
-        if let Some(item_type) = &self.item_type {
-            writeln!(fmt, "  item type: {item_type:?}")?;
-        }
-        if let Some(id) = &self.id {
-            writeln!(fmt, "  id: {id}")?;
-        }
-        if let Some(name_match) = &self.name_match {
-            writeln!(fmt, "  name: {name_match}")?;
-        }
-        if let Some(name_re) = &self.name_re {
-            writeln!(fmt, "  name_re: {name_re}")?;
-        }
-        if let Some(desc_match) = &self.desc_match {
-            writeln!(fmt, "  desc start: {desc_match}")?;
-        }
-        if let Some(desc_re) = &self.desc_re {
-            writeln!(fmt, "  desc re:{desc_re}")?;
-        }
-        if !self.rp_query.is_any() {
-            writeln!(fmt, "  rp_query: {:?}", self.rp_query)?;
-        }
-        if !self.date_range.is_empty() {
-            writeln!(fmt, "  dates: {}", self.date_range)?;
-        }
-        Ok(())
+        self.fmt_indented(fmt, "  ")
     }
 }
 
 //ip DbQuery - builders
 impl DbQuery {
+    //cp all_of
+    /// Create a query that matches items that match all of the
+    /// queries
+    pub fn all_of<I: IntoIterator<Item = DbQuery>>(queries: I) -> Self {
+        Self {
+            all_of: queries.into_iter().collect(),
+            ..Default::default()
+        }
+    }
+
+    //cp any_of
+    /// Create a query that matches items that match any of the
+    /// queries
+    pub fn any_of<I: IntoIterator<Item = DbQuery>>(queries: I) -> Self {
+        Self {
+            any_of: queries.into_iter().collect(),
+            ..Default::default()
+        }
+    }
+
+    //cp none_of
+    /// Create a query that matches items that match none of the
+    /// queries
+    pub fn none_of<I: IntoIterator<Item = DbQuery>>(queries: I) -> Self {
+        Self {
+            none_of: queries.into_iter().collect(),
+            ..Default::default()
+        }
+    }
+
+    //cp parse
+    /// Parse the text of a query, such as 'type:transaction
+    /// date:2024-04..2025-03 amount>100 -fund:General'; see the
+    /// query_parser module for the language
+    pub fn parse(text: &str, db: &Database) -> Result<Self, Error> {
+        crate::query_parser::parse_query(text, db)
+    }
+
     //cp with_item_type
     pub fn with_item_type(mut self, opt_item_type: Option<DbItemType>) -> Self {
         self.item_type = opt_item_type;
@@ -123,6 +210,25 @@ impl DbQuery {
         self
     }
 
+    //cp with_name_re
+    /// Require a name to match a regular expression, rather than
+    /// the name (or pattern) given to [DbQuery::with_name]
+    pub fn with_name_re(mut self, name_re: Regex) -> Self {
+        self.name_match = None;
+        self.name_re = Some(name_re);
+        self
+    }
+
+    //cp with_desc_re
+    /// Require a description to match a regular expression, rather
+    /// than the description (or pattern) given to
+    /// [DbQuery::with_desc]
+    pub fn with_desc_re(mut self, desc_re: Regex) -> Self {
+        self.desc_match = None;
+        self.desc_re = Some(desc_re);
+        self
+    }
+
     //cp with_rp_type
     pub fn with_rp_type(mut self, opt_rp_type: Option<RelatedPartyType>) -> Self {
         self.rp_query = opt_rp_type.into();
@@ -134,6 +240,184 @@ impl DbQuery {
         self.date_range = date_range;
         self
     }
+
+    //cp with_amount
+    pub fn with_amount(mut self, amount: Option<Amount>) -> Self {
+        self.amount = amount;
+        self
+    }
+
+    //cp with_min_amount
+    pub fn with_min_amount(mut self, amount: Option<Amount>) -> Self {
+        self.min_amount = amount;
+        self
+    }
+
+    //cp with_max_amount
+    pub fn with_max_amount(mut self, amount: Option<Amount>) -> Self {
+        self.max_amount = amount;
+        self
+    }
+
+    //cp with_side
+    pub fn with_side(mut self, side: Option<AmountSide>) -> Self {
+        self.side = side;
+        self
+    }
+
+    //cp with_transaction_type
+    pub fn with_transaction_type(mut self, ttype: Option<TransactionType>) -> Self {
+        self.transaction_type = ttype;
+        self
+    }
+
+    //cp with_bank_transaction_type
+    pub fn with_bank_transaction_type(mut self, ttype: Option<BankTransactionType>) -> Self {
+        self.bank_transaction_type = ttype;
+        self
+    }
+
+    //cp with_fund
+    pub fn with_fund(mut self, fund: DbId) -> Self {
+        self.fund = fund;
+        self.fund_transactions = None;
+        self
+    }
+
+    //cp with_account
+    pub fn with_account(mut self, account: DbId) -> Self {
+        self.account = account;
+        self
+    }
+
+    //cp with_related_party
+    pub fn with_related_party(mut self, related_party: DbId) -> Self {
+        self.related_party = related_party;
+        self
+    }
+
+    //cp with_all_of
+    /// Add a query that items must also match
+    pub fn with_all_of(mut self, query: DbQuery) -> Self {
+        self.all_of.push(query);
+        self
+    }
+
+    //cp with_any_of
+    /// Add a query to those that items must match at least one of
+    pub fn with_any_of(mut self, query: DbQuery) -> Self {
+        self.any_of.push(query);
+        self
+    }
+
+    //cp with_none_of
+    /// Add a query that items must not match
+    pub fn with_none_of(mut self, query: DbQuery) -> Self {
+        self.none_of.push(query);
+        self
+    }
+
+    //mp resolve
+    /// Resolve the parts of the query that depend on the database,
+    /// such as the transactions of a fund; this is done by
+    /// [Database::query]
+    pub fn resolve(&mut self, db: &Database) {
+        if !self.fund.is_none() {
+            let transactions = db
+                .get_fund(self.fund)
+                .map(|f| f.inner().transactions_in_range(Default::default()))
+                .unwrap_or_default();
+            self.fund_transactions = Some(transactions.into_iter().collect());
+        }
+        for q in self.sub_queries_mut() {
+            q.resolve(db);
+        }
+    }
+
+    //mi sub_queries_mut
+    fn sub_queries_mut(&mut self) -> impl Iterator<Item = &mut DbQuery> {
+        self.all_of
+            .iter_mut()
+            .chain(self.any_of.iter_mut())
+            .chain(self.none_of.iter_mut())
+    }
+}
+
+//ip DbQuery - display
+impl DbQuery {
+    //mi fmt_indented
+    fn fmt_indented(
+        &self,
+        fmt: &mut std::fmt::Formatter,
+        indent: &str,
+    ) -> Result<(), std::fmt::Error> {
+        if let Some(item_type) = &self.item_type {
+            writeln!(fmt, "{indent}item type: {item_type:?}")?;
+        }
+        if let Some(id) = &self.id {
+            writeln!(fmt, "{indent}id: {id}")?;
+        }
+        if !self.db_id.is_none() {
+            writeln!(fmt, "{indent}db id: {}", self.db_id)?;
+        }
+        if let Some(name_match) = &self.name_match {
+            writeln!(fmt, "{indent}name: {name_match}")?;
+        }
+        if let Some(name_re) = &self.name_re {
+            writeln!(fmt, "{indent}name_re: {name_re}")?;
+        }
+        if let Some(desc_match) = &self.desc_match {
+            writeln!(fmt, "{indent}desc start: {desc_match}")?;
+        }
+        if let Some(desc_re) = &self.desc_re {
+            writeln!(fmt, "{indent}desc re:{desc_re}")?;
+        }
+        if !self.rp_query.is_any() {
+            writeln!(fmt, "{indent}rp_query: {:?}", self.rp_query)?;
+        }
+        if !self.date_range.is_empty() {
+            writeln!(fmt, "{indent}dates: {}", self.date_range)?;
+        }
+        if let Some(amount) = &self.amount {
+            writeln!(fmt, "{indent}amount: {amount}")?;
+        }
+        if let Some(amount) = &self.min_amount {
+            writeln!(fmt, "{indent}min amount: {amount}")?;
+        }
+        if let Some(amount) = &self.max_amount {
+            writeln!(fmt, "{indent}max amount: {amount}")?;
+        }
+        if let Some(side) = &self.side {
+            writeln!(fmt, "{indent}side: {side:?}")?;
+        }
+        if let Some(ttype) = &self.transaction_type {
+            writeln!(fmt, "{indent}transaction type: {ttype}")?;
+        }
+        if let Some(ttype) = &self.bank_transaction_type {
+            writeln!(fmt, "{indent}bank transaction type: {ttype:?}")?;
+        }
+        if !self.fund.is_none() {
+            writeln!(fmt, "{indent}fund: {}", self.fund)?;
+        }
+        if !self.account.is_none() {
+            writeln!(fmt, "{indent}account: {}", self.account)?;
+        }
+        if !self.related_party.is_none() {
+            writeln!(fmt, "{indent}related party: {}", self.related_party)?;
+        }
+        let sub_indent = format!("{indent}    ");
+        for (group, queries) in [
+            ("all of", &self.all_of),
+            ("any of", &self.any_of),
+            ("none of", &self.none_of),
+        ] {
+            for (i, q) in queries.iter().enumerate() {
+                writeln!(fmt, "{indent}{group} [{i}]:")?;
+                q.fmt_indented(fmt, &sub_indent)?;
+            }
+        }
+        Ok(())
+    }
 }
 
 //ip DbQuery - matching methods
@@ -143,6 +427,118 @@ impl DbQuery {
         self.item_type.is_none() || (self.item_type == Some(db_it))
     }
 
+    //mp may_match_item_type
+    /// Return true if items of a type may match the query, taking
+    /// into account its nested queries
+    pub fn may_match_item_type(&self, db_it: DbItemType) -> bool {
+        self.item_type_matches(db_it)
+            && self.all_of.iter().all(|q| q.may_match_item_type(db_it))
+            && (self.any_of.is_empty() || self.any_of.iter().any(|q| q.may_match_item_type(db_it)))
+    }
+
+    //mp index_hint
+    /// The date range and the DbIds (such as a fund or related party)
+    /// that every transaction or bank transaction that matches the
+    /// query must have, for selecting them with the indexes of
+    /// [crate::DbTransactions] and [crate::DbBankTransactions]
+    ///
+    /// Only the query and those nested queries that must all match
+    /// restrict the items; an empty date range restricts nothing
+    pub(crate) fn index_hint(&self, db_it: DbItemType) -> (DateRange, Vec<DbId>) {
+        let mut references = match db_it {
+            DbItemType::Transaction => vec![self.db_id, self.fund, self.related_party],
+            DbItemType::BankTransaction => vec![self.account, self.related_party],
+            _ => vec![],
+        };
+        references.retain(|r| !r.is_none());
+        let mut date_range = self.date_range;
+        for q in self.all_of.iter() {
+            let (q_date_range, q_references) = q.index_hint(db_it);
+            if date_range.is_empty() {
+                date_range = q_date_range;
+            }
+            references.extend(q_references);
+        }
+        (date_range, references)
+    }
+
+    //mi filters_apply_to
+    /// Return true if every filter of the query (but not its nested
+    /// queries) is one that items of a type are matched against
+    fn filters_apply_to(&self, db_it: DbItemType) -> bool {
+        use DbItemType::*;
+        let has_name = self.name_match.is_some() || self.name_re.is_some();
+        let has_amount =
+            self.amount.is_some() || self.min_amount.is_some() || self.max_amount.is_some();
+        let filters = [
+            (
+                self.id.is_some(),
+                matches!(db_it, Invoice | RelatedParty | BankTransaction),
+            ),
+            (
+                has_name,
+                matches!(db_it, Account | Fund | Invoice | RelatedParty),
+            ),
+            (self.has_desc(), db_it != RelatedParty),
+            (!self.rp_query.is_any(), db_it == RelatedParty),
+            (
+                has_amount,
+                matches!(db_it, Invoice | Transaction | BankTransaction),
+            ),
+            (
+                self.side.is_some(),
+                matches!(db_it, Transaction | BankTransaction),
+            ),
+            (self.transaction_type.is_some(), db_it == Transaction),
+            (
+                self.bank_transaction_type.is_some(),
+                db_it == BankTransaction,
+            ),
+            (
+                !self.fund.is_none(),
+                !matches!(db_it, Account | RelatedParty),
+            ),
+            (
+                !self.account.is_none(),
+                matches!(db_it, Account | BankTransaction),
+            ),
+            (
+                !self.related_party.is_none(),
+                !matches!(db_it, Account | Fund),
+            ),
+        ];
+        filters.iter().all(|(set, applies)| !set || *applies)
+    }
+
+    //mi matches_combined
+    /// Match an item of a type against the query and its nested
+    /// queries, given a function that matches it against the filters
+    /// of one query
+    ///
+    /// If strict then a filter that does not apply to the type does
+    /// not match; this is the case within a 'none_of' query (and is
+    /// reversed by a 'none_of' within that)
+    fn matches_combined<F>(&self, db_it: DbItemType, strict: bool, f: &F) -> bool
+    where
+        F: Fn(&DbQuery) -> bool,
+    {
+        (!strict || self.filters_apply_to(db_it))
+            && f(self)
+            && self
+                .all_of
+                .iter()
+                .all(|q| q.matches_combined(db_it, strict, f))
+            && (self.any_of.is_empty()
+                || self
+                    .any_of
+                    .iter()
+                    .any(|q| q.matches_combined(db_it, strict, f)))
+            && !self
+                .none_of
+                .iter()
+                .any(|q| q.matches_combined(db_it, !strict, f))
+    }
+
     //mi matches_name
     fn matches_name(&self, s: &str) -> bool {
         if let Some(name) = self.name_match.as_ref() {
@@ -174,6 +570,11 @@ impl DbQuery {
         false
     }
 
+    //mi has_desc
+    fn has_desc(&self) -> bool {
+        self.desc_match.is_some() || self.desc_re.is_some()
+    }
+
     //mi matches_desc
     fn matches_desc(&self, s: &str) -> bool {
         if let Some(desc) = self.desc_match.as_ref() {
@@ -198,6 +599,16 @@ impl DbQuery {
         }
     }
 
+    //mi matches_transactions_in_range
+    /// Match an item that has transactions, which must have some in
+    /// the date range
+    fn matches_transactions_in_range<F>(&self, f: F) -> bool
+    where
+        F: FnOnce(DateRange) -> Vec<DbId>,
+    {
+        self.date_range.is_empty() || !f(self.date_range).is_empty()
+    }
+
     //mi matches_id
     fn matches_id(&self, n: usize) -> bool {
         if let Some(id) = self.id {
@@ -216,67 +627,194 @@ impl DbQuery {
         }
     }
 
-    //mp matches_account - check name
+    //mi matches_amount
+    fn matches_amount(&self, amount: Amount) -> bool {
+        if self.amount.is_some_and(|a| a != amount) {
+            return false;
+        }
+        if self.min_amount.is_some_and(|a| amount.value() < a.value()) {
+            return false;
+        }
+        if self.max_amount.is_some_and(|a| amount.value() > a.value()) {
+            return false;
+        }
+        true
+    }
+
+    //mi matches_fund_transactions
+    /// Match an item that includes transactions, one of which must be
+    /// of the fund
+    fn matches_fund_transactions(&self, transactions: &[DbId]) -> bool {
+        if self.fund.is_none() {
+            return true;
+        }
+        let Some(fund_transactions) = &self.fund_transactions else {
+            return false;
+        };
+        transactions.iter().any(|t| fund_transactions.contains(t))
+    }
+
+    //mi matches_related_party_id
+    fn matches_related_party_id(&self, db_id: DbId) -> bool {
+        self.related_party.is_none() || self.related_party == db_id
+    }
+
+    //mi matches_account_fields
+    fn matches_account_fields(&self, d: &DbAccount) -> bool {
+        let account = d.inner();
+        self.item_type_matches(DbItemType::Account)
+            && self.matches_db_id(d.id())
+            && (self.account.is_none() || self.account == d.id())
+            && self.matches_name(account.name())
+            && self.matches_desc(&account.desc().to_string())
+            && self.matches_transactions_in_range(|r| account.bank_transactions_in_range(r))
+    }
+
+    //mi matches_fund_fields
+    fn matches_fund_fields(&self, d: &DbFund) -> bool {
+        let fund = d.inner();
+        self.item_type_matches(DbItemType::Fund)
+            && self.matches_db_id(d.id())
+            && (self.fund.is_none() || self.fund == d.id())
+            && self.matches_any_name(
+                false,
+                std::iter::once(fund.name()).chain(fund.aliases().iter().map(|s| s.as_str())),
+            )
+            && self.matches_desc(fund.desc())
+            && self.matches_transactions_in_range(|r| fund.transactions_in_range(r))
+    }
+
+    //mi matches_invoice_fields
+    fn matches_invoice_fields(&self, d: &DbInvoice) -> bool {
+        let invoice = d.inner();
+        self.item_type_matches(DbItemType::Invoice)
+            && self.matches_db_id(d.id())
+            && self.matches_name(invoice.reason())
+            && self.matches_id(invoice.supplier_id().index())
+            && self.matches_related_party_id(invoice.supplier_id())
+            && self.matches_desc(invoice.filename())
+            && self.matches_amount(invoice.amount())
+            && self.matches_fund_transactions(&invoice.transactions())
+            && self.matches_transactions_in_range(|r| invoice.transactions_in_range(r))
+    }
+
+    //mi matches_related_party_fields
+    fn matches_related_party_fields(&self, d: &DbRelatedParty) -> bool {
+        let rp = d.inner();
+        self.item_type_matches(DbItemType::RelatedParty)
+            && self.matches_db_id(d.id())
+            && self.matches_id(rp.rp_id())
+            && self.matches_related_party_id(d.id())
+            && self.rp_query.matches_rp_type(rp.rp_type())
+            && self.matches_any_name(
+                false,
+                std::iter::once(rp.name()).chain(rp.aliases().iter().map(|s| s.as_str())),
+            )
+            && self.matches_transactions_in_range(|r| rp.transactions_in_range(r))
+    }
+
+    //mi matches_transaction_fields
+    fn matches_transaction_fields(&self, d: &DbTransaction) -> bool {
+        let t = d.inner();
+        let (debit_id, credit_id) = t.db_ids();
+        let side_matches = match self.side {
+            None => true,
+            Some(side) => {
+                let target = if self.db_id.is_none() {
+                    self.fund
+                } else {
+                    self.db_id
+                };
+                match (side, target.is_none()) {
+                    (AmountSide::Credit, false) => credit_id == target,
+                    (AmountSide::Debit, false) => debit_id == target,
+                    (AmountSide::Credit, true) => t.ttype() == TransactionType::FromRp,
+                    (AmountSide::Debit, true) => t.ttype() == TransactionType::ToRp,
+                }
+            }
+        };
+        self.item_type_matches(DbItemType::Transaction)
+            && self.matches_date_range(t.date())
+            && (self.matches_db_id(debit_id) || self.matches_db_id(credit_id))
+            && (self.fund.is_none() || self.fund == debit_id || self.fund == credit_id)
+            && (self.matches_related_party_id(debit_id) || self.matches_related_party_id(credit_id))
+            && self.transaction_type.is_none_or(|tt| tt == t.ttype())
+            && self.matches_amount(t.amount())
+            && side_matches
+            && (!self.has_desc() || t.notes().iter().any(|n| self.matches_desc(n)))
+    }
+
+    //mi matches_bank_transaction_fields
+    fn matches_bank_transaction_fields(&self, d: &DbBankTransaction) -> bool {
+        let bt = d.inner();
+        let amount = if bt.credit().is_zero() {
+            bt.debit()
+        } else {
+            bt.credit()
+        };
+        let side_matches = match self.side {
+            None => true,
+            Some(AmountSide::Credit) => !bt.credit().is_zero(),
+            Some(AmountSide::Debit) => !bt.debit().is_zero(),
+        };
+        let type_matches = self
+            .bank_transaction_type
+            .is_none_or(|btt| std::mem::discriminant(&btt) == std::mem::discriminant(&bt.ttype()));
+        self.item_type_matches(DbItemType::BankTransaction)
+            && self.matches_date_range(bt.date())
+            && (self.matches_db_id(bt.account_id())
+                || self.matches_db_id(bt.related_party())
+                || bt.transactions().iter().any(|t| self.matches_db_id(*t)))
+            && self.matches_id(bt.related_party().index())
+            && self.matches_related_party_id(bt.related_party())
+            && self.matches_desc(bt.description())
+            && (self.account.is_none() || self.account == bt.account_id())
+            && self.matches_fund_transactions(bt.transactions())
+            && self.matches_amount(amount)
+            && side_matches
+            && type_matches
+    }
+
+    //mp matches_account
     pub fn matches_account(&self, d: &DbAccount) -> bool {
-        self.matches_name(d.inner().name())
+        self.matches_combined(DbItemType::Account, false, &|q: &DbQuery| {
+            q.matches_account_fields(d)
+        })
     }
 
     //mp matches_fund
     pub fn matches_fund(&self, d: &DbFund) -> bool {
-        self.matches_any_name(
-            self.matches_any_name(false, [d.inner().name()].iter().map(|s| *s)),
-            d.inner().aliases().iter().map(|s| s.as_str()),
-        )
+        self.matches_combined(DbItemType::Fund, false, &|q: &DbQuery| {
+            q.matches_fund_fields(d)
+        })
     }
 
     //mp matches_invoice
     pub fn matches_invoice(&self, d: &DbInvoice) -> bool {
-        if !self.matches_name(d.inner().reason()) {
-            return false;
-        }
-        if !self.matches_id(d.inner().supplier_id().index()) {
-            return false;
-        }
-        true
+        self.matches_combined(DbItemType::Invoice, false, &|q: &DbQuery| {
+            q.matches_invoice_fields(d)
+        })
     }
 
     //mp matches_related_party
     pub fn matches_related_party(&self, d: &DbRelatedParty) -> bool {
-        if self.name_match.is_some() {
-            let mut matched_name = self.matches_name(d.inner().name());
-            if d.inner().aliases().iter().any(|s| self.matches_name(s)) {
-                matched_name = true;
-            }
-            if !matched_name {
-                return false;
-            }
-        }
-        true
+        self.matches_combined(DbItemType::RelatedParty, false, &|q: &DbQuery| {
+            q.matches_related_party_fields(d)
+        })
     }
 
     //mp matches_transaction
     pub fn matches_transaction(&self, d: &DbTransaction) -> bool {
-        if !self.matches_date_range(d.inner().date()) {
-            return false;
-        }
-        if !self.matches_db_id(d.inner().db_ids().0) && !self.matches_db_id(d.inner().db_ids().1) {
-            return false;
-        }
-        true
+        self.matches_combined(DbItemType::Transaction, false, &|q: &DbQuery| {
+            q.matches_transaction_fields(d)
+        })
     }
 
     //mp matches_bank_transaction
     pub fn matches_bank_transaction(&self, d: &DbBankTransaction) -> bool {
-        if !self.matches_date_range(d.inner().date()) {
-            return false;
-        }
-        if !self.matches_id(d.inner().related_party().index()) {
-            return false;
-        }
-        if !self.matches_desc(d.inner().description()) {
-            return false;
-        }
-        true
+        self.matches_combined(DbItemType::BankTransaction, false, &|q: &DbQuery| {
+            q.matches_bank_transaction_fields(d)
+        })
     }
 
     //zz All done
@@ -1,6 +1,6 @@
 use thiserror::Error;
 
-use crate::{DbId, FileFormat, FileType};
+use crate::{Currency, DbId, FileFormat, FileType};
 
 #[derive(Debug, Error)]
 pub enum Error {
@@ -12,18 +12,36 @@ pub enum Error {
     Json(#[from] serde_json::Error),
     #[error("{0}")]
     Yaml(#[from] serde_yaml::Error),
+    #[error("sqlite {0}")]
+    Sqlite(#[from] rusqlite::Error),
     #[error("{0}")]
     ParseTransaction(String),
     #[error("failed to parse date {0}")]
     ParseDate(String),
-    #[error("failed to parse account {0}: expected xx-yy-zz:n")]
+    #[error("failed to parse amount {0}")]
+    ParseAmount(String),
+    #[error("failed to parse currency {0}: expected an ISO 4217 code such as GBP")]
+    ParseCurrency(String),
+    #[error("failed to parse exchange rate {0}")]
+    ParseExchangeRate(String),
+    #[error("currency mismatch: {0} and {1}")]
+    CurrencyMismatch(Currency, Currency),
+    #[error("failed to parse query at column {0}: {1}")]
+    ParseQuery(usize, String),
+    #[error("failed to parse account {0}: expected xx-yy-zz:n, iban:..., bic:...:..., card:..., wallet:...:... or cash:...")]
     ParseAccount(String),
     #[error("transaction log {0}")]
     TransactionLog(String),
     #[error("deserialization error {0}")]
     Deserialization(String),
+    #[error("schema version {0} is newer than the version {1} supported")]
+    SchemaVersionTooNew(usize, usize),
     #[error("duplicate item id {0}")]
     DuplicateItemId(DbId),
+    #[error("unknown item id {0}")]
+    UnknownItemId(DbId),
+    #[error("item {0} is referenced by {1} other item(s)")]
+    ItemReferenced(DbId, usize),
     #[error("unknown file extension {0}")]
     UnknownFileExtension(String),
     #[error("unknown file format {0} (try 'array' or 'dict')")]
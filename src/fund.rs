@@ -4,7 +4,10 @@ use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize, Serializer};
 
-use crate::{Amount, Database, DatabaseRebuild, Date, DateRange, DbId, Error, OrderedTransactions};
+use crate::{
+    Amount, Currency, CurrencyAmount, Database, DatabaseRebuild, Date, DateRange, DbId, Error,
+    OrderedTransactions,
+};
 
 //a Fund
 //tp Fund
@@ -22,6 +25,9 @@ pub struct Fund {
     transactions: OrderedTransactions<DbId>,
     start_balance: Amount,
     end_balance: Option<Amount>,
+    /// Currency the fund is held in
+    #[serde(default)]
+    currency: Currency,
 }
 
 //ip Display for Fund
@@ -44,9 +50,16 @@ impl Fund {
             aliases: vec![],
             start_balance: Amount::default(),
             end_balance: None,
+            currency: Currency::default(),
         }
     }
 
+    //cp with_currency
+    pub fn with_currency(mut self, currency: Currency) -> Self {
+        self.currency = currency;
+        self
+    }
+
     //ap name
     pub fn name(&self) -> &str {
         &self.name
@@ -57,11 +70,28 @@ impl Fund {
         &self.description
     }
 
+    //ap currency
+    pub fn currency(&self) -> Currency {
+        self.currency
+    }
+
     //ap aliases
     pub fn aliases(&self) -> &[String] {
         &self.aliases
     }
 
+    //mp set_name
+    /// Change the name of the fund; the database must reindex the
+    /// fund, so use [Database::update_fund]
+    pub fn set_name<I: Into<String>>(&mut self, i: I) {
+        self.name = i.into();
+    }
+
+    //mp set_desc
+    pub fn set_desc<I: Into<String>>(&mut self, i: I) {
+        self.description = i.into();
+    }
+
     //mp add_alias
     pub fn add_alias<I: Into<String>>(&mut self, i: I) {
         self.aliases.push(i.into());
@@ -90,9 +120,62 @@ impl Fund {
         true
     }
 
+    //mp remove_transaction
+    /// Remove a transaction, returning true if it was present
+    pub fn remove_transaction(&mut self, t_id: DbId) -> bool {
+        if !self.transactions.remove(t_id) {
+            return false;
+        }
+        self.end_balance = None;
+        true
+    }
+
+    //ap has_transactions
+    pub fn has_transactions(&self) -> bool {
+        !self.transactions.is_empty()
+    }
+
     //mp rebuild
-    pub fn rebuild(&mut self, database_rebuild: &DatabaseRebuild) -> Result<(), Error> {
-        self.transactions.rebuild(database_rebuild)
+    /// Rebuild the fund; its transactions are repopulated from the
+    /// transactions themselves by [Database::try_rebuild], so they are
+    /// cleared here
+    pub fn rebuild(&mut self, _database_rebuild: &DatabaseRebuild) -> Result<(), Error> {
+        self.transactions.clear();
+        self.end_balance = None;
+        Ok(())
+    }
+
+    //mp balance_in
+    /// Calculate the balance of the fund in a base currency
+    ///
+    /// Transactions in a different currency are converted using the
+    /// exchange rate stored with the transaction; an error is
+    /// returned if there is no suitable rate
+    pub fn balance_in(
+        &self,
+        db: &Database,
+        db_id: DbId,
+        base: Currency,
+    ) -> Result<CurrencyAmount, Error> {
+        let mut balance = CurrencyAmount::new(base, Amount::default());
+        if !self.start_balance.is_zero() {
+            if self.currency != base {
+                return Err(Error::CurrencyMismatch(self.currency, base));
+            }
+            balance = CurrencyAmount::new(base, self.start_balance);
+        }
+        for t in self.transactions.iter() {
+            let t_db_id = self.transactions[t];
+            let Some(db_t) = db.get_transaction(t_db_id) else {
+                continue;
+            };
+            let t = db_t.inner();
+            if let Some(delta) = t.balance_delta_for(db_id) {
+                let delta = t.convert(CurrencyAmount::new(t.currency(), delta), base)?;
+                balance = (balance + delta)?;
+            }
+        }
+        Ok(balance)
     }
 
     //ap show
@@ -216,6 +299,37 @@ impl DbFunds {
         }
     }
 
+    //mp remove_fund
+    /// Remove a fund (with its aliases), returning true if it was present
+    pub fn remove_fund(&self, db_id: DbId) -> bool {
+        let mut state = self.state.borrow_mut();
+        let Some(n) = state.array.iter().position(|f| f.id() == db_id) else {
+            return false;
+        };
+        state.array.remove(n);
+        state.index.retain(|_, f| f.id() != db_id);
+        true
+    }
+
+    //mp reindex_fund
+    /// Reindex a fund after its name or aliases have changed
+    ///
+    /// This fails (leaving the fund unindexed) if another fund has
+    /// the new name; aliases used by other funds are not indexed
+    pub fn reindex_fund(&self, db_fund: &DbFund) -> bool {
+        let db_id = db_fund.id();
+        self.state.borrow_mut().index.retain(|_, f| f.id() != db_id);
+        if self.has_fund(db_fund.inner().name()) {
+            return false;
+        }
+        self.state
+            .borrow_mut()
+            .index
+            .insert(db_fund.inner().name().into(), db_fund.clone());
+        self.add_fund_aliases(db_fund);
+        true
+    }
+
     //ap has_fund
     pub fn has_fund(&self, name: &str) -> bool {
         self.state.borrow().index.contains_key(name)
@@ -0,0 +1,215 @@
+//a Imports
+use serde::Serialize;
+
+use crate::{Amount, BankTransaction, Database, DbId, RelatedPartyMatch};
+
+//a ImportOutcome
+//tp ImportOutcome
+/// What happened to one row of a bank statement when it was imported
+/// into an account
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum ImportOutcome {
+    /// New, and would be added (when previewing an import)
+    New,
+    /// Added as a new bank transaction
+    Added(DbId),
+    /// Already present in the account, as the given bank transaction
+    /// (which is none if the row was skipped on its FITID alone)
+    Skipped(DbId),
+    /// Not added, as it conflicts with the account
+    Conflict(String),
+}
+
+//a ImportReport
+//tp ImportReport
+/// The outcome of importing the rows of a bank statement into an
+/// account, in the order of the rows
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct ImportReport {
+    outcomes: Vec<ImportOutcome>,
+}
+
+//ip ImportReport
+impl ImportReport {
+    //mp push
+    pub fn push(&mut self, outcome: ImportOutcome) {
+        self.outcomes.push(outcome);
+    }
+
+    //ap outcomes
+    pub fn outcomes(&self) -> &[ImportOutcome] {
+        &self.outcomes
+    }
+
+    //ap added
+    /// The DbIds of the bank transactions added
+    pub fn added(&self) -> Vec<DbId> {
+        self.outcomes
+            .iter()
+            .filter_map(|o| match o {
+                ImportOutcome::Added(db_id) => Some(*db_id),
+                _ => None,
+            })
+            .collect()
+    }
+
+    //ap num_skipped
+    pub fn num_skipped(&self) -> usize {
+        self.outcomes
+            .iter()
+            .filter(|o| matches!(o, ImportOutcome::Skipped(_)))
+            .count()
+    }
+
+    //ap conflicts
+    /// The row number and reason for each conflicting row
+    pub fn conflicts(&self) -> Vec<(usize, &str)> {
+        self.outcomes
+            .iter()
+            .enumerate()
+            .filter_map(|(n, o)| match o {
+                ImportOutcome::Conflict(reason) => Some((n, reason.as_str())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    //mp merge
+    /// Add the outcomes of another report to this one
+    pub fn merge(&mut self, other: ImportReport) {
+        self.outcomes.extend(other.outcomes);
+    }
+}
+
+//ip Display for ImportReport
+impl std::fmt::Display for ImportReport {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(
+            fmt,
+            "{} added, {} skipped, {} conflicting",
+            self.added().len(),
+            self.num_skipped(),
+            self.conflicts().len()
+        )
+    }
+}
+
+//a ImportPreview
+//tp ImportPreviewRow
+/// The preview of importing one row of a bank statement
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportPreviewRow {
+    pub date: String,
+    pub description: String,
+    pub balance_delta: Amount,
+    pub balance: Amount,
+    pub outcome: ImportOutcome,
+    /// The related party the row would be matched to
+    pub related_party: RelatedPartyMatch,
+    /// The name of the related party matched, if any
+    pub related_party_name: Option<String>,
+}
+
+//tp ImportPreview
+/// The preview of importing the rows of a bank statement into an
+/// account, in the order of the rows, without changing the database
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct ImportPreview {
+    rows: Vec<ImportPreviewRow>,
+    /// The balance of the account after the import
+    closing_balance: Option<Amount>,
+}
+
+//ip ImportPreview
+impl ImportPreview {
+    //cp new
+    pub fn new(closing_balance: Option<Amount>) -> Self {
+        Self {
+            rows: vec![],
+            closing_balance,
+        }
+    }
+
+    //mp push
+    pub fn push(
+        &mut self,
+        bt: &BankTransaction,
+        outcome: ImportOutcome,
+        related_party: RelatedPartyMatch,
+        db: &Database,
+    ) {
+        let related_party_name = db
+            .get_related_party(related_party.db_id())
+            .map(|db_rp| db_rp.inner().name().to_string());
+        self.rows.push(ImportPreviewRow {
+            date: bt.date().to_string(),
+            description: bt.description().to_string(),
+            balance_delta: bt.balance_delta(),
+            balance: bt.balance(),
+            outcome,
+            related_party,
+            related_party_name,
+        });
+    }
+
+    //ap rows
+    pub fn rows(&self) -> &[ImportPreviewRow] {
+        &self.rows
+    }
+
+    //ap closing_balance
+    pub fn closing_balance(&self) -> Option<Amount> {
+        self.closing_balance
+    }
+
+    //ap num_new
+    pub fn num_new(&self) -> usize {
+        self.count(|o| matches!(o, ImportOutcome::New))
+    }
+
+    //ap num_skipped
+    pub fn num_skipped(&self) -> usize {
+        self.count(|o| matches!(o, ImportOutcome::Skipped(_)))
+    }
+
+    //ap num_conflicts
+    pub fn num_conflicts(&self) -> usize {
+        self.count(|o| matches!(o, ImportOutcome::Conflict(_)))
+    }
+
+    //ap num_ambiguous
+    /// The number of rows whose related party is ambiguous
+    pub fn num_ambiguous(&self) -> usize {
+        self.rows
+            .iter()
+            .filter(|r| r.related_party == RelatedPartyMatch::Ambiguous)
+            .count()
+    }
+
+    //mi count
+    fn count<F: Fn(&ImportOutcome) -> bool>(&self, f: F) -> usize {
+        self.rows.iter().filter(|r| f(&r.outcome)).count()
+    }
+}
+
+//ip Display for ImportPreview
+impl std::fmt::Display for ImportPreview {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(
+            fmt,
+            "{} new, {} skipped, {} conflicting, {} with ambiguous related party",
+            self.num_new(),
+            self.num_skipped(),
+            self.num_conflicts(),
+            self.num_ambiguous(),
+        )?;
+        if let Some(closing_balance) = self.closing_balance {
+            write!(
+                fmt,
+                "; closing balance {}",
+                closing_balance.to_string().trim()
+            )?;
+        }
+        Ok(())
+    }
+}
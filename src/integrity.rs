@@ -0,0 +1,364 @@
+//a Documentation
+//! Checks of the referential integrity of a whole Database
+//!
+//! Every DbId held by an item should refer to an item of the right
+//! type; the lists that accounts, funds and related parties keep of
+//! their (bank) transactions should agree with the transactions
+//! themselves; fund names and aliases should be unique; and every
+//! related party should have a different rp_id.
+//!
+//! Each problem found comes with a suggested fix.
+
+//a Imports
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use serde::Serialize;
+
+use crate::indexed_vec::Idx;
+use crate::{Database, DbId, DbItemType, TransactionType};
+
+//a IntegrityProblem
+//tp IntegrityProblem
+/// A problem with an item in the database, and how it might be fixed
+#[derive(Debug, Clone, Serialize)]
+pub struct IntegrityProblem {
+    /// The item with the problem
+    pub db_id: DbId,
+    pub problem: String,
+    pub fix: String,
+}
+
+//ip Display for IntegrityProblem
+impl std::fmt::Display for IntegrityProblem {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(fmt, "{}: {} (fix: {})", self.db_id, self.problem, self.fix)
+    }
+}
+
+//a IntegrityChecker
+//tp IntegrityChecker
+/// Checks the items of a database, collecting the problems found
+struct IntegrityChecker<'a> {
+    db: &'a Database,
+    /// The bank transactions listed by each account
+    account_lists: HashMap<DbId, HashSet<DbId>>,
+    /// The transactions listed by each fund and related party
+    transaction_lists: HashMap<DbId, HashSet<DbId>>,
+    problems: Vec<IntegrityProblem>,
+}
+
+//ip IntegrityChecker
+impl<'a> IntegrityChecker<'a> {
+    //cp new
+    fn new(db: &'a Database) -> Self {
+        let mut account_lists = HashMap::new();
+        let mut transaction_lists = HashMap::new();
+        for db_id in db.db_ids() {
+            if let Some(db_account) = db.get_account(db_id) {
+                let list = db_account
+                    .inner()
+                    .bank_transactions_in_range(Default::default());
+                account_lists.insert(db_id, list.into_iter().collect());
+            } else if let Some(db_fund) = db.get_fund(db_id) {
+                let list = db_fund.inner().transactions_in_range(Default::default());
+                transaction_lists.insert(db_id, list.into_iter().collect());
+            } else if let Some(db_rp) = db.get_related_party(db_id) {
+                let list = db_rp.inner().transactions_in_range(Default::default());
+                transaction_lists.insert(db_id, list.into_iter().collect());
+            }
+        }
+        Self {
+            db,
+            account_lists,
+            transaction_lists,
+            problems: vec![],
+        }
+    }
+
+    //mi add
+    fn add<P: Into<String>, F: Into<String>>(&mut self, db_id: DbId, problem: P, fix: F) {
+        self.problems.push(IntegrityProblem {
+            db_id,
+            problem: problem.into(),
+            fix: fix.into(),
+        });
+    }
+
+    //mi expect_type
+    /// Check that a DbId held by an item (as 'what') refers to an item
+    /// of one of the expected types, returning true if it does
+    fn expect_type(
+        &mut self,
+        owner: DbId,
+        what: &str,
+        db_id: DbId,
+        expected: &[DbItemType],
+    ) -> bool {
+        let names: Vec<String> = expected.iter().map(|t| format!("{t:?}")).collect();
+        let names = names.join(" or ");
+        match self.db.get(db_id) {
+            None => {
+                self.add(
+                    owner,
+                    format!("{what} {db_id} is not in the database"),
+                    format!("change the {what} to an existing {names}, or remove it"),
+                );
+                false
+            }
+            Some(item) if !expected.contains(&item.itype()) => {
+                self.add(
+                    owner,
+                    format!("{what} {db_id} is a {:?}, not a {names}", item.itype()),
+                    format!("change the {what} to a {names}"),
+                );
+                false
+            }
+            _ => true,
+        }
+    }
+
+    //mi check_account
+    fn check_account(&mut self, a_id: DbId) {
+        let mut listed: Vec<DbId> = self.account_lists[&a_id].iter().copied().collect();
+        listed.sort();
+        for bt_id in listed {
+            if !self.expect_type(
+                a_id,
+                "bank transaction",
+                bt_id,
+                &[DbItemType::BankTransaction],
+            ) {
+                continue;
+            }
+            let bt_account_id = self
+                .db
+                .get_bank_transaction(bt_id)
+                .unwrap()
+                .inner()
+                .account_id();
+            if bt_account_id != a_id {
+                self.add(
+                    a_id,
+                    format!("lists bank transaction {bt_id} which is in account {bt_account_id}"),
+                    format!("remove bank transaction {bt_id} from the account"),
+                );
+            }
+        }
+    }
+
+    //mi check_bank_transaction
+    fn check_bank_transaction(&mut self, bt_id: DbId) {
+        let db_bt = self.db.get_bank_transaction(bt_id).unwrap();
+        let bt = db_bt.inner();
+        let account_id = bt.account_id();
+        if account_id.is_none() {
+            self.add(
+                bt_id,
+                "has no account",
+                format!(
+                    "set its account to that with description {}",
+                    bt.account_desc()
+                ),
+            );
+        } else if self.expect_type(bt_id, "account", account_id, &[DbItemType::Account]) {
+            let db_account = self.db.get_account(account_id).unwrap();
+            let desc = db_account.inner().desc().clone();
+            if desc != *bt.account_desc() {
+                let fix = match self.db.accounts().get_account(bt.account_desc()) {
+                    Some(other) => format!("change its account to {}", other.id()),
+                    None => format!("change its account description to {desc}"),
+                };
+                self.add(
+                    bt_id,
+                    format!(
+                        "is for account {} but its account {account_id} is {desc}",
+                        bt.account_desc()
+                    ),
+                    fix,
+                );
+            }
+            if !self.account_lists[&account_id].contains(&bt_id) {
+                self.add(
+                    bt_id,
+                    format!("is not listed by its account {account_id}"),
+                    format!("add it to the bank transactions of account {account_id}"),
+                );
+            }
+        }
+        let rp_id = bt.related_party();
+        if !rp_id.is_none() {
+            self.expect_type(bt_id, "related party", rp_id, &[DbItemType::RelatedParty]);
+        }
+        for t_id in bt.transactions() {
+            self.expect_type(bt_id, "transaction", *t_id, &[DbItemType::Transaction]);
+        }
+    }
+
+    //mi check_transaction
+    fn check_transaction(&mut self, t_id: DbId) {
+        let db_t = self.db.get_transaction(t_id).unwrap();
+        let (debit_id, credit_id) = db_t.inner().db_ids();
+        let (debit_type, credit_type) = match db_t.inner().ttype() {
+            TransactionType::FromRp => (DbItemType::RelatedParty, DbItemType::Fund),
+            TransactionType::ToRp => (DbItemType::Fund, DbItemType::RelatedParty),
+            _ => (DbItemType::Fund, DbItemType::Fund),
+        };
+        for (what, db_id, itype) in [
+            ("debit", debit_id, debit_type),
+            ("credit", credit_id, credit_type),
+        ] {
+            // A side that is not known (such as the payer of anonymous
+            // income) is permitted
+            if db_id.is_none() || !self.expect_type(t_id, what, db_id, &[itype]) {
+                continue;
+            }
+            if !self.transaction_lists[&db_id].contains(&t_id) {
+                self.add(
+                    t_id,
+                    format!("is not listed by its {what} {db_id}"),
+                    "reload the database, which rebuilds the transactions of funds and related parties",
+                );
+            }
+        }
+    }
+
+    //mi check_transaction_list
+    /// Check the transactions listed by a fund or related party
+    fn check_transaction_list(&mut self, owner: DbId) {
+        let mut listed: Vec<DbId> = self.transaction_lists[&owner].iter().copied().collect();
+        listed.sort();
+        for t_id in listed {
+            if !self.expect_type(owner, "transaction", t_id, &[DbItemType::Transaction]) {
+                continue;
+            }
+            let (debit_id, credit_id) = self.db.get_transaction(t_id).unwrap().inner().db_ids();
+            if debit_id != owner && credit_id != owner {
+                self.add(
+                    owner,
+                    format!("lists transaction {t_id} which neither debits nor credits it"),
+                    "reload the database, which rebuilds the transactions of funds and related parties",
+                );
+            }
+        }
+    }
+
+    //mi check_related_party
+    fn check_related_party(&mut self, rp_id: DbId) {
+        self.check_transaction_list(rp_id);
+        let invoices = self
+            .db
+            .get_related_party(rp_id)
+            .unwrap()
+            .inner()
+            .invoices_in_range(Default::default());
+        for i_id in invoices {
+            self.expect_type(rp_id, "invoice", i_id, &[DbItemType::Invoice]);
+        }
+    }
+
+    //mi check_invoice
+    fn check_invoice(&mut self, i_id: DbId) {
+        let (supplier_id, transactions) = {
+            let item = self.db.get(i_id).unwrap();
+            let db_invoice = item.invoice().unwrap();
+            let invoice = db_invoice.inner();
+            (invoice.supplier_id(), invoice.transactions())
+        };
+        if !supplier_id.is_none() {
+            self.expect_type(i_id, "supplier", supplier_id, &[DbItemType::RelatedParty]);
+        }
+        for t_id in transactions {
+            self.expect_type(i_id, "transaction", t_id, &[DbItemType::Transaction]);
+        }
+    }
+
+    //mi check_fund_names
+    /// Check that no name or alias is used by more than one fund
+    fn check_fund_names(&mut self) {
+        let mut users: BTreeMap<String, Vec<(DbId, bool)>> = BTreeMap::new();
+        for f_id in self.db.db_ids() {
+            let Some(db_fund) = self.db.get_fund(f_id) else {
+                continue;
+            };
+            let fund = db_fund.inner();
+            users
+                .entry(fund.name().to_string())
+                .or_default()
+                .push((f_id, false));
+            for alias in fund.aliases() {
+                users.entry(alias.clone()).or_default().push((f_id, true));
+            }
+        }
+        for (name, users) in users {
+            let Some((first_id, _)) = users.first().copied() else {
+                continue;
+            };
+            for (f_id, is_alias) in users.into_iter().skip(1) {
+                if f_id == first_id {
+                    continue;
+                }
+                let (problem, fix) = {
+                    if is_alias {
+                        (
+                            format!("has alias '{name}' which is also used by fund {first_id}"),
+                            format!("remove the alias '{name}'"),
+                        )
+                    } else {
+                        (
+                            format!("has name '{name}' which is also used by fund {first_id}"),
+                            "rename the fund".to_string(),
+                        )
+                    }
+                };
+                self.add(f_id, problem, fix);
+            }
+        }
+    }
+
+    //mi check_rp_ids
+    /// Check that every related party has a different rp_id
+    fn check_rp_ids(&mut self) {
+        let mut users: BTreeMap<usize, Vec<DbId>> = BTreeMap::new();
+        for db_id in self.db.db_ids() {
+            if let Some(db_rp) = self.db.get_related_party(db_id) {
+                users.entry(db_rp.inner().rp_id()).or_default().push(db_id);
+            }
+        }
+        let mut next_rp_id = users.keys().last().map(|n| n + 1).unwrap_or(1);
+        for (rp_id, users) in users {
+            for db_id in users.iter().skip(1) {
+                self.add(
+                    *db_id,
+                    format!(
+                        "has rp_id {rp_id} which is also used by related party {}",
+                        users[0]
+                    ),
+                    format!("change its rp_id to {next_rp_id}"),
+                );
+                next_rp_id += 1;
+            }
+        }
+    }
+
+    //mp check
+    fn check(mut self) -> Vec<IntegrityProblem> {
+        for db_id in self.db.db_ids() {
+            match self.db.get(db_id).unwrap().itype() {
+                DbItemType::Account => self.check_account(db_id),
+                DbItemType::BankTransaction => self.check_bank_transaction(db_id),
+                DbItemType::Transaction => self.check_transaction(db_id),
+                DbItemType::Fund => self.check_transaction_list(db_id),
+                DbItemType::RelatedParty => self.check_related_party(db_id),
+                DbItemType::Invoice => self.check_invoice(db_id),
+            }
+        }
+        self.check_fund_names();
+        self.check_rp_ids();
+        self.problems
+    }
+}
+
+//fp check_database
+pub(crate) fn check_database(db: &Database) -> Vec<IntegrityProblem> {
+    IntegrityChecker::new(db).check()
+}
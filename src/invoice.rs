@@ -4,7 +4,7 @@ use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize, Serializer};
 
-use crate::{Amount, Database, DatabaseRebuild, Date, DbId, Error, OrderedTransactions};
+use crate::{Amount, Database, DatabaseRebuild, Date, DateRange, DbId, Error, OrderedTransactions};
 
 //a Invoice
 //tp Invoice
@@ -77,6 +77,62 @@ impl Invoice {
         self.amount
     }
 
+    //ap transactions
+    /// The transactions that cover the payment, in date order
+    pub fn transactions(&self) -> Vec<DbId> {
+        self.transactions.transactions_in_range(Default::default())
+    }
+
+    //ap transactions_in_range
+    /// The transactions that cover the payment within a date range, in
+    /// date order
+    pub fn transactions_in_range(&self, date_range: DateRange) -> Vec<DbId> {
+        self.transactions.transactions_in_range(date_range)
+    }
+
+    //mp set_reason
+    /// Change the reason for the invoice; the database must reindex
+    /// the invoice, so use [Database::update_invoice]
+    pub fn set_reason<I: Into<String>>(&mut self, i: I) {
+        self.reason = i.into();
+    }
+
+    //mp set_filename
+    pub fn set_filename<I: Into<String>>(&mut self, i: I) {
+        self.filename = i.into();
+    }
+
+    //mp set_supplier_id
+    pub fn set_supplier_id(&mut self, supplier_id: DbId) {
+        self.supplier_id = supplier_id;
+    }
+
+    //mp set_amount
+    pub fn set_amount(&mut self, amount: Amount) {
+        self.amount = amount;
+    }
+
+    //ap has_transaction
+    pub fn has_transaction(&self, t_id: DbId) -> bool {
+        self.transactions.contains(t_id)
+    }
+
+    //mp remove_transaction
+    /// Remove a transaction, returning true if it was present
+    pub fn remove_transaction(&mut self, t_id: DbId) -> bool {
+        self.transactions.remove(t_id)
+    }
+
+    //mp move_transaction
+    /// Move a transaction of the invoice to a (possibly different) date
+    pub fn move_transaction(&mut self, t_id: DbId, date: Date) -> bool {
+        if !self.transactions.remove(t_id) {
+            return false;
+        }
+        self.transactions.push_to_date(date, t_id);
+        true
+    }
+
     //mp validate_transaction
     fn validate_transaction(
         &self,
@@ -240,6 +296,35 @@ impl DbInvoices {
         true
     }
 
+    //mp remove_invoice
+    /// Remove an invoice, returning true if it was present
+    pub fn remove_invoice(&self, db_id: DbId) -> bool {
+        let mut state = self.state.borrow_mut();
+        let Some(n) = state.array.iter().position(|i| i.id() == db_id) else {
+            return false;
+        };
+        state.array.remove(n);
+        state.map.retain(|_, i| i.id() != db_id);
+        true
+    }
+
+    //mp reindex_invoice
+    /// Reindex an invoice after its reason has changed from
+    /// `old_reason`; this fails if another invoice has the new reason
+    pub fn reindex_invoice(&self, db_invoice: &DbInvoice, old_reason: &str) -> bool {
+        let reason = db_invoice.inner().reason.clone();
+        if reason == old_reason {
+            return true;
+        }
+        if self.has_invoice(&reason) {
+            return false;
+        }
+        let mut state = self.state.borrow_mut();
+        state.map.remove(old_reason);
+        state.map.insert(reason, db_invoice.clone());
+        true
+    }
+
     //ap has_invoice
     pub fn has_invoice(&self, reason: &str) -> bool {
         self.state.borrow().map.contains_key(reason)
@@ -0,0 +1,620 @@
+//a Documentation
+//! An append-only journal of the changes made to a Database
+//!
+//! A database is loaded from a snapshot (a JSON, YAML or sqlite3
+//! file); the changes made to it are then recorded in a journal file
+//! next to the snapshot, so that they are not lost if the snapshot is
+//! not written out again.
+//!
+//! The changes are found by comparing each item with the item as it
+//! was when the changes were last recorded; each changed item is an
+//! entry in the journal (an add, edit or delete) with the item before
+//! and after the change. The entries recorded together are one
+//! operation, and share an operation number.
+//!
+//! The journal file holds one line per operation: a JSON object with
+//! the schema version of its items and its entries. Items written by
+//! an older version of the crate are upgraded (as they would be in a
+//! snapshot) when the journal is read. A last line that is not
+//! complete, as the program stopped while writing it, is an operation
+//! that was never recorded; it is discarded as a whole, and removed
+//! from the file before any more operations are appended.
+//!
+//! A snapshot records
+//! the number of the last operation it includes (the journal_op of
+//! its header); when it is loaded, the entries in its journal of later
+//! operations are replayed. The DbIds of the items are those the
+//! snapshot is loaded with, so the database keeps the DbIds of the
+//! replayed items.
+//!
+//! Writing a new snapshot folds the journal into it, and the journal
+//! can then be emptied.
+//!
+//! The operations recorded since the snapshot was written can be
+//! undone (most recent first) and then redone; undoing an operation
+//! applies the inverse of its entries, and the database is rebuilt
+//! from the resulting items (so the funds, related parties and
+//! accounts are reindexed). An undo or redo is itself recorded in the
+//! journal, marked as such, so that the undo and redo stacks are
+//! recreated when the journal is replayed.
+
+//a Imports
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::indexed_vec::Idx;
+use crate::schema::upgrade_db_item;
+use crate::{Database, DbId, DbItem, Error, SCHEMA_VERSION};
+
+//a JournalOp
+//tp JournalOp
+/// The kind of change to an item recorded in a journal entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JournalOp {
+    Add,
+    Edit,
+    Delete,
+    /// No change to any item; this marks an undo or redo that left
+    /// the items unchanged, so that it is still replayed
+    Mark,
+}
+
+//ip Display for JournalOp
+impl std::fmt::Display for JournalOp {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        match self {
+            JournalOp::Add => write!(fmt, "add"),
+            JournalOp::Edit => write!(fmt, "edit"),
+            JournalOp::Delete => write!(fmt, "delete"),
+            JournalOp::Mark => write!(fmt, "mark"),
+        }
+    }
+}
+
+//a JournalAction
+//tp JournalAction
+/// Why an operation was recorded in a journal
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JournalAction {
+    /// A change made to the database
+    #[default]
+    Change,
+    /// The undo of the most recent operation not yet undone
+    Undo,
+    /// The redo of the most recently undone operation
+    Redo,
+}
+
+//ip JournalAction
+impl JournalAction {
+    //ap is_change
+    pub fn is_change(&self) -> bool {
+        *self == JournalAction::Change
+    }
+}
+
+//a JournalEntry
+//tp JournalEntry
+/// A change to one item of a database
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// Time the change was recorded, in milliseconds since the Unix
+    /// epoch
+    timestamp: i64,
+    /// The operation the change is part of
+    op: usize,
+    /// Why the operation was recorded
+    #[serde(default, skip_serializing_if = "JournalAction::is_change")]
+    action: JournalAction,
+    kind: JournalOp,
+    db_id: DbId,
+    /// The item before the change, for an edit or delete
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    before: Option<DbItem>,
+    /// The item after the change, for an add or edit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    after: Option<DbItem>,
+}
+
+//ip JournalEntry
+impl JournalEntry {
+    //ap timestamp
+    pub fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+
+    //ap op
+    pub fn op(&self) -> usize {
+        self.op
+    }
+
+    //ap action
+    pub fn action(&self) -> JournalAction {
+        self.action
+    }
+
+    //ap kind
+    pub fn kind(&self) -> JournalOp {
+        self.kind
+    }
+
+    //ap db_id
+    pub fn db_id(&self) -> DbId {
+        self.db_id
+    }
+
+    //ap before
+    pub fn before(&self) -> Option<&DbItem> {
+        self.before.as_ref()
+    }
+
+    //ap after
+    pub fn after(&self) -> Option<&DbItem> {
+        self.after.as_ref()
+    }
+
+    //mp inverse
+    /// The entry that reverts this change
+    fn inverse(&self) -> JournalEntry {
+        let kind = match self.kind {
+            JournalOp::Add => JournalOp::Delete,
+            JournalOp::Edit => JournalOp::Edit,
+            JournalOp::Delete => JournalOp::Add,
+            JournalOp::Mark => JournalOp::Mark,
+        };
+        JournalEntry {
+            kind,
+            before: self.after.clone(),
+            after: self.before.clone(),
+            ..self.clone()
+        }
+    }
+}
+
+//ip Display for JournalEntry
+impl std::fmt::Display for JournalEntry {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(fmt, "{}: ", self.op)?;
+        match self.action {
+            JournalAction::Change => (),
+            JournalAction::Undo => write!(fmt, "undo ")?,
+            JournalAction::Redo => write!(fmt, "redo ")?,
+        }
+        if self.kind == JournalOp::Mark {
+            return write!(fmt, "{}", self.kind);
+        }
+        write!(fmt, "{} {}", self.kind, self.db_id)?;
+        if let Some(item) = self.after.as_ref().or(self.before.as_ref()) {
+            write!(fmt, " {}", item.show_name())?;
+        }
+        Ok(())
+    }
+}
+
+//a JournalLine
+//tp JournalLine
+/// An operation as written to a journal file, on one line
+#[derive(Debug, Serialize)]
+struct JournalLine<'a> {
+    /// Schema version of the items in the entries
+    schema_version: usize,
+    entries: &'a [JournalEntry],
+}
+
+//ip JournalLine
+impl JournalLine<'_> {
+    //fp parse
+    /// Parse the entries of a line of a journal file, upgrading their
+    /// items to the current schema version
+    fn parse(line: &str) -> Result<Vec<JournalEntry>, Error> {
+        let mut value: Value = serde_json::from_str(line)?;
+        let version = value
+            .get("schema_version")
+            .and_then(|v| v.as_u64())
+            .ok_or_else(|| Error::Deserialization("operation has no schema_version".into()))?
+            as usize;
+        let Some(Value::Array(entries)) = value.get_mut("entries").map(|e| e.take()) else {
+            return Err(Error::Deserialization("operation has no entries".into()));
+        };
+        let mut result = vec![];
+        for mut entry in entries {
+            for item in ["before", "after"] {
+                if let Some(item) = entry.get_mut(item) {
+                    upgrade_db_item(version, item)?;
+                }
+            }
+            result.push(serde_json::from_value(entry)?);
+        }
+        Ok(result)
+    }
+}
+
+//fi item_of_json
+/// Create a DbItem from its JSON, so that it does not share its
+/// contents with the item in the database
+fn item_of_json(json: &str) -> Result<DbItem, Error> {
+    Ok(serde_json::from_str(json)?)
+}
+
+//a Journal
+//tp Journal
+/// The journal of the changes made to a database since it was loaded
+/// from a snapshot
+#[derive(Debug, Default)]
+pub struct Journal {
+    /// The journal file, if the entries are to be written out
+    path: Option<PathBuf>,
+    /// The JSON of each item when the changes were last recorded
+    baseline: HashMap<DbId, String>,
+    /// Number of the next operation to be recorded
+    next_op: usize,
+    /// The entries of the operations that can be undone, most recent
+    /// last
+    undo_stack: Vec<Vec<JournalEntry>>,
+    /// The entries of the operations that have been undone and can
+    /// be redone, most recently undone last
+    redo_stack: Vec<Vec<JournalEntry>>,
+}
+
+//ip Journal
+impl Journal {
+    //cp new
+    /// Create a journal for a database, with the database's items as
+    /// the baseline for changes; its operations follow the last one
+    /// that the database includes
+    pub fn new(db: &Database) -> Result<Self, Error> {
+        let mut baseline = HashMap::new();
+        for db_id in db.db_ids() {
+            baseline.insert(db_id, db.get(db_id).unwrap().as_json(false)?);
+        }
+        Ok(Self {
+            path: None,
+            baseline,
+            next_op: db.journal_op() + 1,
+            undo_stack: vec![],
+            redo_stack: vec![],
+        })
+    }
+
+    //cp with_path
+    /// Append the entries that are recorded to a journal file
+    pub fn with_path<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    //fp path_of
+    /// The journal file for a snapshot file
+    pub fn path_of<P: AsRef<Path>>(snapshot: P) -> PathBuf {
+        let mut path = snapshot.as_ref().as_os_str().to_owned();
+        path.push(".journal");
+        path.into()
+    }
+
+    //ap path
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    //ap can_undo
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    //ap can_redo
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    //mp record
+    /// Record the changes to the database since they were last
+    /// recorded as one operation, appending them to the journal file
+    /// (if there is one)
+    ///
+    /// The operation can then be undone; operations that were undone
+    /// can no longer be redone
+    pub fn record(&mut self, db: &Database) -> Result<Vec<JournalEntry>, Error> {
+        let entries = self.record_changes(db, JournalAction::Change)?;
+        if !entries.is_empty() {
+            self.push_operation(entries.clone(), JournalAction::Change);
+        }
+        Ok(entries)
+    }
+
+    //mp undo
+    /// Undo the most recent operation (after recording any changes
+    /// not yet recorded), returning the database as it was before the
+    /// operation; this is None if there is nothing to undo
+    pub fn undo(&mut self, db: &Database) -> Result<Option<Database>, Error> {
+        self.record(db)?;
+        let Some(entries) = self.undo_stack.last() else {
+            return Ok(None);
+        };
+        let inverse: Vec<JournalEntry> = entries.iter().rev().map(|e| e.inverse()).collect();
+        let db = Self::replay(db, &inverse)?;
+        self.record_changes(&db, JournalAction::Undo)?;
+        self.push_operation(vec![], JournalAction::Undo);
+        Ok(Some(db))
+    }
+
+    //mp redo
+    /// Redo the most recently undone operation, returning the
+    /// database as it was after the operation; this is None if there
+    /// is nothing to redo (including if changes have been made since
+    /// the undo)
+    pub fn redo(&mut self, db: &Database) -> Result<Option<Database>, Error> {
+        self.record(db)?;
+        let Some(entries) = self.redo_stack.last() else {
+            return Ok(None);
+        };
+        let db = Self::replay(db, entries)?;
+        self.record_changes(&db, JournalAction::Redo)?;
+        self.push_operation(vec![], JournalAction::Redo);
+        Ok(Some(db))
+    }
+
+    //mi push_operation
+    /// Update the undo and redo stacks for an operation that has been
+    /// recorded
+    fn push_operation(&mut self, entries: Vec<JournalEntry>, action: JournalAction) {
+        match action {
+            JournalAction::Change => {
+                self.undo_stack.push(entries);
+                self.redo_stack.clear();
+            }
+            JournalAction::Undo => {
+                if let Some(entries) = self.undo_stack.pop() {
+                    self.redo_stack.push(entries);
+                }
+            }
+            JournalAction::Redo => {
+                if let Some(entries) = self.redo_stack.pop() {
+                    self.undo_stack.push(entries);
+                }
+            }
+        }
+    }
+
+    //mi record_changes
+    /// Record the changes to the database since they were last
+    /// recorded, for an action
+    fn record_changes(
+        &mut self,
+        db: &Database,
+        action: JournalAction,
+    ) -> Result<Vec<JournalEntry>, Error> {
+        let timestamp = chrono::Utc::now().timestamp_millis();
+        let op = self.next_op;
+        let entry = |kind, db_id, before, after| JournalEntry {
+            timestamp,
+            op,
+            action,
+            kind,
+            db_id,
+            before,
+            after,
+        };
+
+        let mut entries = vec![];
+        let db_ids = db.db_ids();
+        for db_id in db_ids.iter().copied() {
+            let json = db.get(db_id).unwrap().as_json(false)?;
+            match self.baseline.get(&db_id) {
+                Some(old) if *old == json => {
+                    continue;
+                }
+                Some(old) => {
+                    let before = Some(item_of_json(old)?);
+                    let after = Some(item_of_json(&json)?);
+                    entries.push(entry(JournalOp::Edit, db_id, before, after));
+                }
+                None => {
+                    let after = Some(item_of_json(&json)?);
+                    entries.push(entry(JournalOp::Add, db_id, None, after));
+                }
+            }
+            self.baseline.insert(db_id, json);
+        }
+
+        let present: HashSet<DbId> = db_ids.into_iter().collect();
+        let mut removed: Vec<DbId> = self
+            .baseline
+            .keys()
+            .filter(|db_id| !present.contains(db_id))
+            .copied()
+            .collect();
+        removed.sort();
+        for db_id in removed {
+            let old = self.baseline.remove(&db_id).unwrap();
+            let before = Some(item_of_json(&old)?);
+            entries.push(entry(JournalOp::Delete, db_id, before, None));
+        }
+
+        // An undo or redo is always written, as replaying it moves an
+        // operation between the undo and redo stacks
+        if entries.is_empty() && !action.is_change() {
+            entries.push(entry(JournalOp::Mark, DbId::none(), None, None));
+        }
+        if !entries.is_empty() {
+            self.next_op += 1;
+            self.append(&entries)?;
+            db.set_journal_op(op);
+        }
+        Ok(entries)
+    }
+
+    //mi append
+    /// Append the entries of an operation to the journal file as a
+    /// single line
+    fn append(&self, entries: &[JournalEntry]) -> Result<(), Error> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let mut f = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        let line = JournalLine {
+            schema_version: SCHEMA_VERSION,
+            entries,
+        };
+        let mut s = serde_json::to_string(&line)?;
+        s.push('\n');
+        f.write_all(s.as_bytes())?;
+        f.sync_data()?;
+        Ok(())
+    }
+
+    //mp truncate
+    /// Empty the journal file, once its entries have been folded into
+    /// a new snapshot
+    pub fn truncate(&self) -> Result<(), Error> {
+        if let Some(path) = &self.path {
+            if path.exists() {
+                std::fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    //fp read_entries
+    /// Read the entries of a journal file (which need not exist)
+    ///
+    /// A last operation that is incomplete (as the program stopped
+    /// while it was being written) is ignored
+    pub fn read_entries<P: AsRef<Path>>(path: P) -> Result<Vec<JournalEntry>, Error> {
+        Ok(Self::read_file(path.as_ref())?.0)
+    }
+
+    //fi read_file
+    /// Read the entries of a journal file (which need not exist), and
+    /// the length of the file up to the end of its last complete
+    /// operation
+    fn read_file(path: &Path) -> Result<(Vec<JournalEntry>, u64), Error> {
+        if !path.exists() {
+            return Ok((vec![], 0));
+        }
+        let mut f = std::io::BufReader::new(std::fs::File::open(path)?);
+        let mut entries = vec![];
+        let mut complete = 0;
+        let mut line = String::new();
+        for n in 1.. {
+            line.clear();
+            if f.read_line(&mut line)? == 0 {
+                break;
+            }
+            if !line.ends_with('\n') {
+                break;
+            }
+            complete += line.len() as u64;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let operation = JournalLine::parse(&line).map_err(|e| {
+                format!(
+                    "Bad operation on line {n} of journal {}: {e}",
+                    path.display()
+                )
+            })?;
+            entries.extend(operation);
+        }
+        Ok((entries, complete))
+    }
+
+    //fp replay
+    /// Apply journal entries to a database, creating a new database
+    /// with the changed items
+    ///
+    /// Each entry sets (or removes) the item with its DbId, so the
+    /// items keep their DbIds
+    pub fn replay(db: &Database, entries: &[JournalEntry]) -> Result<Database, Error> {
+        let mut items: BTreeMap<DbId, DbItem> = BTreeMap::new();
+        for db_id in db.db_ids() {
+            let json = db.get(db_id).unwrap().as_json(false)?;
+            items.insert(db_id, item_of_json(&json)?);
+        }
+        for e in entries {
+            match e.kind {
+                JournalOp::Add | JournalOp::Edit => {
+                    let Some(item) = &e.after else {
+                        return Err(format!("Journal {} of {} has no item", e.kind, e.db_id).into());
+                    };
+                    items.insert(e.db_id, item_of_json(&item.as_json(false)?)?);
+                }
+                JournalOp::Delete => {
+                    items.remove(&e.db_id);
+                }
+                JournalOp::Mark => (),
+            }
+        }
+        let replayed = Database::try_from_items(items.into_values().collect(), false)?;
+        replayed.set_journal_op(db.journal_op());
+        Ok(replayed)
+    }
+
+    //fp replay_file
+    /// Replay the entries of the journal file of a snapshot of the
+    /// operations after the last one the snapshot includes, and create
+    /// the journal to record further changes to the database in the
+    /// same file
+    ///
+    /// The operations replayed can be undone (and redone) as if they
+    /// had just been made
+    pub fn replay_file<P: AsRef<Path>>(
+        db: Database,
+        path: P,
+    ) -> Result<(Database, Journal), Error> {
+        let path = path.as_ref();
+        let included = db.journal_op();
+        let (entries, complete) = Self::read_file(path)?;
+        if path.exists() && std::fs::metadata(path)?.len() > complete {
+            eprintln!(
+                "Discarding the incomplete last operation of journal {}",
+                path.display()
+            );
+            std::fs::OpenOptions::new()
+                .write(true)
+                .open(path)?
+                .set_len(complete)?;
+        }
+        let entries: Vec<JournalEntry> = entries.into_iter().filter(|e| e.op > included).collect();
+        if let Some(e) = entries.first() {
+            if e.op != included + 1 {
+                return Err(format!(
+                    "Journal {} continues from operation {} but the snapshot includes operations up to {included}",
+                    path.display(),
+                    e.op
+                )
+                .into());
+            }
+        }
+        let db = {
+            if entries.is_empty() {
+                db
+            } else {
+                let db = Self::replay(&db, &entries)?;
+                db.set_journal_op(entries.iter().map(|e| e.op).max().unwrap());
+                db
+            }
+        };
+        let mut journal = Journal::new(&db)?.with_path(path);
+        let mut operation: Vec<JournalEntry> = vec![];
+        for e in entries {
+            if operation.first().is_some_and(|o| o.op != e.op) {
+                let action = operation[0].action;
+                journal.push_operation(std::mem::take(&mut operation), action);
+            }
+            operation.push(e);
+        }
+        if let Some(action) = operation.first().map(|o| o.action) {
+            journal.push_operation(operation, action);
+        }
+        Ok((db, journal))
+    }
+
+    //zz All done
+}
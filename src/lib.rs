@@ -45,8 +45,13 @@ pub use base_types::{Date, DateRange, Entity, FileFormat, FileType, Ordering};
 mod ordered;
 pub use ordered::{OTCursor, OTIndex, OrderedTransactions};
 
+mod dated_index;
+
 mod amount;
-pub use amount::Amount;
+pub use amount::{Amount, AmountRounding};
+
+mod currency;
+pub use currency::{Currency, CurrencyAmount, ExchangeRate};
 
 mod account_desc;
 pub use account_desc::AccountDesc;
@@ -61,12 +66,26 @@ pub use db_id::DbId;
 mod db_item;
 pub use db_item::{DbItem, DbItemKind, DbItemKindObj, DbItemType};
 
+mod db_dictionary;
+
+mod schema;
+pub use schema::{Migration, SCHEMA_VERSION};
+
 mod db_query;
-pub use db_query::DbQuery;
+pub use db_query::{AmountSide, DbQuery};
+
+mod query_parser;
+
+mod query_result;
+pub use query_result::{QueryResult, QueryView, SortKey};
+
+mod aggregate;
+pub use aggregate::{AggregateGroup, AggregateResult, Aggregation, GroupBy};
 
 mod related_party;
 pub use related_party::{
-    DbRelatedParties, DbRelatedParty, RelatedParty, RelatedPartyQuery, RelatedPartyType,
+    DbRelatedParties, DbRelatedParty, RelatedParty, RelatedPartyMatch, RelatedPartyQuery,
+    RelatedPartyType,
 };
 
 mod account;
@@ -74,12 +93,15 @@ pub use account::{Account, DbAccount, DbAccounts};
 
 mod bank_transaction;
 pub use bank_transaction::{
-    BankTransaction, BankTransactionType, DbBankTransaction, DbBankTransactions,
+    BankTransaction, BankTransactionType, BankTypeMap, DbBankTransaction, DbBankTransactions,
 };
 
 mod fund;
 pub use fund::{DbFund, DbFunds, Fund};
 
+mod import_report;
+pub use import_report::{ImportOutcome, ImportPreview, ImportPreviewRow, ImportReport};
+
 mod invoice;
 pub use invoice::{DbInvoice, DbInvoices, Invoice};
 
@@ -95,3 +117,15 @@ pub mod banks;
 
 mod database;
 pub use database::{Database, DatabaseRebuild};
+
+mod integrity;
+pub use integrity::IntegrityProblem;
+
+mod merge;
+pub use merge::{MergeConflict, MergeReport};
+
+mod journal;
+pub use journal::{Journal, JournalAction, JournalEntry, JournalOp};
+
+mod sqlite;
+pub use sqlite::{SqliteStore, SqliteWriteReport};
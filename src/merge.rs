@@ -0,0 +1,534 @@
+//a Documentation
+//! Merging one Database into another
+//!
+//! Items of the other database are matched to items of the database
+//! by their natural keys:
+//!
+//! * accounts by their account description
+//!
+//! * funds by their name (or an alias)
+//!
+//! * related parties by their rp_id
+//!
+//! * invoices by their reason
+//!
+//! * bank transactions by their account description, date,
+//!   description and balance
+//!
+//! * transactions by their date, type, amount and the (matched)
+//!   items they debit and credit
+//!
+//! Items that do not match are added to the database, with their
+//! DbIds remapped (using a DatabaseRebuild, as when a database is
+//! loaded) to refer to the matched or added items.
+//!
+//! A matched item keeps its contents; where the other item differs
+//! this is reported as a conflict, to be resolved by hand. The
+//! exception is a bank transaction that has no related party or
+//! transactions where the other has, as when one copy of the
+//! database has had its bank transactions reconciled; these are
+//! taken from the other item.
+
+//a Imports
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::indexed_vec::Idx;
+use crate::related_party::RelatedPartyQuery;
+use crate::{AccountDesc, Amount, Database, DatabaseRebuild, Date, DbId, DbItem, DbItemType};
+use crate::{Error, TransactionType};
+
+//a MergeConflict
+//tp MergeConflict
+/// A difference between an item of the database and the item of
+/// the other database that it was matched to
+#[derive(Debug, Clone, Serialize)]
+pub struct MergeConflict {
+    /// The item in the database
+    pub db_id: DbId,
+    /// The item in the other database
+    pub other_id: DbId,
+    /// The field that differs
+    pub field: String,
+    /// The value in the database, which is kept
+    pub ours: String,
+    /// The value in the other database
+    pub theirs: String,
+}
+
+//ip Display for MergeConflict
+impl std::fmt::Display for MergeConflict {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(
+            fmt,
+            "{}: {} is '{}' but '{}' in the other database (as {})",
+            self.db_id, self.field, self.ours, self.theirs, self.other_id
+        )
+    }
+}
+
+//a MergeReport
+//tp MergeReport
+/// The outcome of merging another database into a database
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct MergeReport {
+    /// The items added, with their DbIds in the database
+    added: Vec<DbId>,
+    /// The items matched, as (other DbId, DbId in the database)
+    matched: Vec<(DbId, DbId)>,
+    /// The matched items updated from the other database
+    updated: Vec<DbId>,
+    /// The conflicts between matched items
+    conflicts: Vec<MergeConflict>,
+}
+
+//ip Display for MergeReport
+impl std::fmt::Display for MergeReport {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(
+            fmt,
+            "{} added, {} matched, {} updated, {} conflicts",
+            self.added.len(),
+            self.matched.len(),
+            self.updated.len(),
+            self.conflicts.len()
+        )
+    }
+}
+
+//ip MergeReport
+impl MergeReport {
+    //ap added
+    pub fn added(&self) -> &[DbId] {
+        &self.added
+    }
+
+    //ap matched
+    pub fn matched(&self) -> &[(DbId, DbId)] {
+        &self.matched
+    }
+
+    //ap updated
+    pub fn updated(&self) -> &[DbId] {
+        &self.updated
+    }
+
+    //ap conflicts
+    pub fn conflicts(&self) -> &[MergeConflict] {
+        &self.conflicts
+    }
+}
+
+//a Merger
+//ti BtKey
+/// The natural key of a bank transaction
+type BtKey = (AccountDesc, Date, String, Amount);
+
+//ti TransactionKey
+/// The natural key of a transaction, once its debit and credit have
+/// been mapped
+type TransactionKey = (Date, TransactionType, Amount, DbId, DbId);
+
+//ti Merger
+/// The state of a merge
+struct Merger<'a> {
+    db: &'a Database,
+    other: &'a Database,
+    /// Maps the DbIds of the other database to the database
+    database_rebuild: DatabaseRebuild,
+    /// The unmatched bank transactions of the database, by key
+    bank_transactions: HashMap<BtKey, Vec<DbId>>,
+    /// The unmatched transactions of the database, by key
+    transactions: HashMap<TransactionKey, Vec<DbId>>,
+    /// The items of the other database to add, with their new DbIds
+    to_add: Vec<(DbId, DbId)>,
+    report: MergeReport,
+}
+
+//ii Merger
+impl<'a> Merger<'a> {
+    //ci new
+    fn new(db: &'a Database, other: &'a Database) -> Result<Self, Error> {
+        let mut database_rebuild = DatabaseRebuild::default();
+        database_rebuild.add_mapping(DbId::none(), DbId::none())?;
+        let mut bank_transactions: HashMap<BtKey, Vec<DbId>> = HashMap::new();
+        for bt_id in db.bank_transactions().db_ids() {
+            let db_bt = db.get_bank_transaction(bt_id).unwrap();
+            let key = Self::bt_key(&db_bt.inner());
+            bank_transactions.entry(key).or_default().push(bt_id);
+        }
+        let mut transactions: HashMap<TransactionKey, Vec<DbId>> = HashMap::new();
+        for t_id in db.transactions().db_ids() {
+            let db_t = db.get_transaction(t_id).unwrap();
+            let (debit_id, credit_id) = db_t.inner().db_ids();
+            let key = Self::transaction_key(&db_t.inner(), debit_id, credit_id);
+            transactions.entry(key).or_default().push(t_id);
+        }
+        Ok(Self {
+            db,
+            other,
+            database_rebuild,
+            bank_transactions,
+            transactions,
+            to_add: vec![],
+            report: MergeReport::default(),
+        })
+    }
+
+    //fi bt_key
+    fn bt_key(bt: &crate::BankTransaction) -> BtKey {
+        (
+            bt.account_desc().clone(),
+            bt.date(),
+            bt.description().to_string(),
+            bt.balance(),
+        )
+    }
+
+    //fi transaction_key
+    fn transaction_key(t: &crate::Transaction, debit_id: DbId, credit_id: DbId) -> TransactionKey {
+        (t.date(), t.ttype(), t.amount(), debit_id, credit_id)
+    }
+
+    //mi take_first
+    /// Take the first DbId of those with a key, so that each item of
+    /// the database is matched at most once
+    fn take_first<K: std::hash::Hash + Eq>(map: &mut HashMap<K, Vec<DbId>>, key: &K) -> DbId {
+        match map.get_mut(key) {
+            Some(db_ids) if !db_ids.is_empty() => db_ids.remove(0),
+            _ => DbId::none(),
+        }
+    }
+
+    //mi conflict
+    fn conflict<F: Into<String>>(
+        &mut self,
+        db_id: DbId,
+        other_id: DbId,
+        field: F,
+        ours: String,
+        theirs: String,
+    ) {
+        self.report.conflicts.push(MergeConflict {
+            db_id,
+            other_id,
+            field: field.into(),
+            ours,
+            theirs,
+        });
+    }
+
+    //mi find_match
+    /// Find the item of the database that an item of the other
+    /// database matches, if any; the items it refers to that are
+    /// needed for its key must already have been mapped
+    fn find_match(&mut self, other_id: DbId) -> Result<DbId, Error> {
+        let item = self.other.get(other_id).unwrap();
+        let db_id = match item.itype() {
+            DbItemType::Account => {
+                let desc = item.account().unwrap().inner().desc().clone();
+                self.db.accounts().get_account(&desc).map(|a| a.id())
+            }
+            DbItemType::Fund => {
+                let name = item.fund().unwrap().inner().name().to_string();
+                self.db.funds().get_fund(&name).map(|f| f.id())
+            }
+            DbItemType::RelatedParty => {
+                let db_rp = item.related_party().unwrap();
+                let rp = db_rp.inner();
+                if let Some(ours) = self.db.related_parties().get_rp_id(rp.rp_id()) {
+                    Some(ours.id())
+                } else {
+                    // A related party with a name (or alias) already
+                    // in use cannot be added, so it is matched to the
+                    // related party that uses it
+                    let names =
+                        std::iter::once(rp.name()).chain(rp.aliases().iter().map(|a| a.as_str()));
+                    let mut found = None;
+                    for name in names {
+                        if let Some(ours) = self
+                            .db
+                            .related_parties()
+                            .get_party(name, RelatedPartyQuery::Any)
+                        {
+                            found = Some((name.to_string(), ours));
+                            break;
+                        }
+                    }
+                    found.map(|(name, ours)| {
+                        let ours_rp_id = ours.inner().rp_id().to_string();
+                        self.conflict(
+                            ours.id(),
+                            other_id,
+                            format!("rp_id of '{name}'"),
+                            ours_rp_id,
+                            rp.rp_id().to_string(),
+                        );
+                        ours.id()
+                    })
+                }
+            }
+            DbItemType::Invoice => {
+                let reason = item.invoice().unwrap().inner().reason().to_string();
+                self.db.invoices().get_invoice(&reason).map(|i| i.id())
+            }
+            DbItemType::BankTransaction => {
+                let key = Self::bt_key(&item.bank_transaction().unwrap().inner());
+                Some(Self::take_first(&mut self.bank_transactions, &key))
+            }
+            DbItemType::Transaction => {
+                let db_t = item.transaction().unwrap();
+                let t = db_t.inner();
+                let (debit_id, credit_id) = t.db_ids();
+                let debit_id = self
+                    .database_rebuild
+                    .get_new_id("Transaction debit", debit_id)?;
+                let credit_id = self
+                    .database_rebuild
+                    .get_new_id("Transaction credit", credit_id)?;
+                let key = Self::transaction_key(&t, debit_id, credit_id);
+                Some(Self::take_first(&mut self.transactions, &key))
+            }
+        };
+        Ok(db_id.unwrap_or(DbId::none()))
+    }
+
+    //mi map_items
+    /// Match the items of the other database, or give them new DbIds
+    /// in the database
+    ///
+    /// Transactions are keyed by the funds and related parties they
+    /// debit and credit, so these are mapped first
+    fn map_items(&mut self) -> Result<(), Error> {
+        let order = [
+            DbItemType::Account,
+            DbItemType::Fund,
+            DbItemType::RelatedParty,
+            DbItemType::Transaction,
+            DbItemType::Invoice,
+            DbItemType::BankTransaction,
+        ];
+        let other_ids = self.other.db_ids();
+        for itype in order {
+            for other_id in other_ids.iter().copied() {
+                if self.other.get(other_id).unwrap().itype() != itype {
+                    continue;
+                }
+                let db_id = self.find_match(other_id)?;
+                if db_id.is_none() {
+                    let new_id = self.db.reserve_db_id();
+                    self.database_rebuild.add_mapping(other_id, new_id)?;
+                    self.to_add.push((other_id, new_id));
+                } else {
+                    self.database_rebuild.add_mapping(other_id, db_id)?;
+                    self.report.matched.push((other_id, db_id));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    //mi add_items
+    /// Add copies of the unmatched items to the database, remapping
+    /// their DbIds, then link them to the matched items
+    fn add_items(&mut self) -> Result<(), Error> {
+        for (other_id, new_id) in self.to_add.iter().copied() {
+            let json = self.other.get(other_id).unwrap().as_json(false)?;
+            let item: DbItem = serde_json::from_str(&json)?;
+            self.db
+                .rebuild_add_item(item.with_id(new_id), &self.database_rebuild)?;
+            self.report.added.push(new_id);
+        }
+        for new_id in self.report.added.iter().copied() {
+            let item = self.db.get(new_id).unwrap();
+            if let Some(db_t) = item.transaction() {
+                db_t.inner().update_related_dbs(self.db, new_id);
+            } else if let Some(db_bt) = item.bank_transaction() {
+                let (account_id, date) = {
+                    let bt = db_bt.inner();
+                    (bt.account_id(), bt.date())
+                };
+                let db_account = self.db.get_account(account_id).unwrap();
+                let mut account = db_account.inner_mut();
+                if !account
+                    .bank_transactions_in_range(Default::default())
+                    .contains(&new_id)
+                {
+                    account.link_bank_transaction(date, new_id);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    //mi compare
+    /// Compare a field of a matched item with that of the other item
+    fn compare<T: PartialEq + std::fmt::Display>(
+        &mut self,
+        db_id: DbId,
+        other_id: DbId,
+        field: &str,
+        ours: T,
+        theirs: T,
+    ) {
+        if ours != theirs {
+            self.conflict(db_id, other_id, field, ours.to_string(), theirs.to_string());
+        }
+    }
+
+    //mi compare_matched
+    /// Compare a matched item with the item of the other database,
+    /// updating the related party and transactions of a bank
+    /// transaction if it has none
+    fn compare_matched(&mut self, other_id: DbId, db_id: DbId) -> Result<(), Error> {
+        let ours = self.db.get(db_id).unwrap();
+        let theirs = self.other.get(other_id).unwrap();
+        match ours.itype() {
+            DbItemType::Account => {
+                let (ours, theirs) = (ours.account().unwrap(), theirs.account().unwrap());
+                let (ours, theirs) = (ours.inner(), theirs.inner());
+                self.compare(db_id, other_id, "org", ours.org(), theirs.org());
+                self.compare(db_id, other_id, "name", ours.name(), theirs.name());
+            }
+            DbItemType::Fund => {
+                let (ours, theirs) = (ours.fund().unwrap(), theirs.fund().unwrap());
+                let (ours, theirs) = (ours.inner(), theirs.inner());
+                self.compare(db_id, other_id, "name", ours.name(), theirs.name());
+                self.compare(db_id, other_id, "description", ours.desc(), theirs.desc());
+                self.compare(
+                    db_id,
+                    other_id,
+                    "currency",
+                    ours.currency(),
+                    theirs.currency(),
+                );
+            }
+            DbItemType::RelatedParty => {
+                let (ours, theirs) = (
+                    ours.related_party().unwrap(),
+                    theirs.related_party().unwrap(),
+                );
+                let (ours, theirs) = (ours.inner(), theirs.inner());
+                self.compare(db_id, other_id, "name", ours.name(), theirs.name());
+                self.compare(
+                    db_id,
+                    other_id,
+                    "type",
+                    format!("{:?}", ours.rp_type()),
+                    format!("{:?}", theirs.rp_type()),
+                );
+            }
+            DbItemType::Invoice => {
+                let (ours, theirs) = (ours.invoice().unwrap(), theirs.invoice().unwrap());
+                let (ours, theirs) = (ours.inner(), theirs.inner());
+                let supplier_id = self
+                    .database_rebuild
+                    .get_new_id("Invoice supplier", theirs.supplier_id())?;
+                self.compare(
+                    db_id,
+                    other_id,
+                    "supplier",
+                    self.db.show_name(ours.supplier_id()),
+                    self.db.show_name(supplier_id),
+                );
+                self.compare(db_id, other_id, "amount", ours.amount(), theirs.amount());
+                self.compare(
+                    db_id,
+                    other_id,
+                    "filename",
+                    ours.filename(),
+                    theirs.filename(),
+                );
+            }
+            DbItemType::Transaction => {
+                // Matched on all but the notes
+                let (ours, theirs) = (ours.transaction().unwrap(), theirs.transaction().unwrap());
+                let (ours, theirs) = (ours.inner(), theirs.inner());
+                self.compare(
+                    db_id,
+                    other_id,
+                    "notes",
+                    ours.notes().join("; "),
+                    theirs.notes().join("; "),
+                );
+            }
+            DbItemType::BankTransaction => {
+                let (db_ours, db_theirs) = (
+                    ours.bank_transaction().unwrap(),
+                    theirs.bank_transaction().unwrap(),
+                );
+                let (rp_id, t_ids) = {
+                    let theirs = db_theirs.inner();
+                    let rp_id = self
+                        .database_rebuild
+                        .get_new_id("BankTransaction related party", theirs.related_party())?;
+                    let mut t_ids = vec![];
+                    for t_id in theirs.transactions() {
+                        t_ids.push(
+                            self.database_rebuild
+                                .get_new_id("BankTransaction transaction", *t_id)?,
+                        );
+                    }
+                    (rp_id, t_ids)
+                };
+                let (our_rp_id, our_t_ids) = {
+                    let ours = db_ours.inner();
+                    (ours.related_party(), ours.transactions().to_vec())
+                };
+                let mut updated = false;
+                if our_rp_id.is_none() && !rp_id.is_none() {
+                    db_ours.inner_mut().set_related_party(rp_id);
+                    self.db.bank_transactions().reindex_transaction(db_id);
+                    updated = true;
+                } else if !rp_id.is_none() {
+                    self.compare(
+                        db_id,
+                        other_id,
+                        "related party",
+                        self.db.show_name(our_rp_id),
+                        self.db.show_name(rp_id),
+                    );
+                }
+                if our_t_ids.is_empty() && !t_ids.is_empty() {
+                    for t_id in t_ids {
+                        db_ours.inner_mut().add_transaction(t_id);
+                    }
+                    updated = true;
+                } else if !t_ids.is_empty() {
+                    let show = |t_ids: &[DbId]| {
+                        let t_ids: Vec<String> = t_ids.iter().map(|t| t.to_string()).collect();
+                        t_ids.join(", ")
+                    };
+                    self.compare(
+                        db_id,
+                        other_id,
+                        "transactions",
+                        show(&our_t_ids),
+                        show(&t_ids),
+                    );
+                }
+                if updated {
+                    self.report.updated.push(db_id);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    //mi merge
+    fn merge(mut self) -> Result<MergeReport, Error> {
+        self.map_items()?;
+        self.add_items()?;
+        let matched = self.report.matched.clone();
+        for (other_id, db_id) in matched {
+            self.compare_matched(other_id, db_id)?;
+        }
+        Ok(self.report)
+    }
+}
+
+//fp merge_database
+pub(crate) fn merge_database(db: &Database, other: &Database) -> Result<MergeReport, Error> {
+    Merger::new(db, other)?.merge()
+}
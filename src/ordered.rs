@@ -216,12 +216,15 @@ where
             .transactions_by_date
             .find_or_add(date, |_| (date, vec![]));
         if !found {
-            self.date_order.push((date, ot_d));
+            let pos = self.date_order.partition_point(|d_idx| d_idx.0 < date);
+            self.date_order.insert(pos, (date, ot_d));
         }
         self.transactions_by_date[ot_d].1.push(item);
     }
 
     //mp sort
+    /// Sort the date order; this is maintained by [Self::push_to_date],
+    /// so this is only required if the order has been disturbed
     pub fn sort(&mut self) {
         self.date_order.sort_by_key(|d_idx| d_idx.0);
     }
@@ -239,14 +242,21 @@ where
         }
     }
 
+    //mi date_order_position
+    /// Find the position in the date order of a date index
+    fn date_order_position(&self, idx: OTIndex) -> usize {
+        let date = self.transactions_by_date[idx].0;
+        self.date_order.partition_point(|d_idx| d_idx.0 < date)
+    }
+
     //mp cursor_prev
     pub fn cursor_prev(&self, cursor: &mut OTCursor) -> bool {
         if cursor.valid {
             if cursor.ofs > 0 {
                 cursor.ofs -= 1;
                 true
-            } else if cursor.idx.index() > 0 {
-                cursor.idx = cursor.idx.decrement();
+            } else if self.date_order_position(cursor.idx) > 0 {
+                cursor.idx = self.date_order[self.date_order_position(cursor.idx) - 1].1;
                 cursor.ofs = self.transactions_by_date[cursor.idx].1.len() - 1;
                 true
             } else {
@@ -263,8 +273,8 @@ where
             if cursor.ofs + 1 < self.transactions_by_date[cursor.idx].1.len() {
                 cursor.ofs += 1;
                 true
-            } else if cursor.idx.index() + 1 < self.transactions_by_date.len() {
-                cursor.idx = cursor.idx.increment();
+            } else if self.date_order_position(cursor.idx) + 1 < self.date_order.len() {
+                cursor.idx = self.date_order[self.date_order_position(cursor.idx) + 1].1;
                 cursor.ofs = 0;
                 true
             } else {
@@ -371,19 +381,14 @@ where
                             (false, true)
                         }
                     };
-                    eprintln!(
-                        "{index}, {}, {}, {index_is_end}, {use_end_of_previous}, {is_accurate}",
-                        self.date_order.len(),
-                        self.transactions_by_date.len()
-                    );
                     if use_end_of_previous {
-                        let index = OTIndex::from_usize(index - 1);
+                        let index = self.date_order[index - 1].1;
                         (
                             OTCursor::new(index, self.transactions_by_date[index].1.len() - 1),
                             is_accurate,
                         )
                     } else {
-                        let index = OTIndex::from_usize(index);
+                        let index = self.date_order[index].1;
                         (OTCursor::new(index, 0), is_accurate)
                     }
                 }
@@ -392,6 +397,50 @@ where
     }
 }
 
+//ip OrderedTransactions with PartialEq
+impl<T> OrderedTransactions<T>
+where
+    T: OrderedTransactionId + PartialEq,
+{
+    //ap contains
+    /// Return true if the item is present (on any date)
+    pub fn contains(&self, item: T) -> bool {
+        self.transactions_by_date
+            .iter()
+            .any(|d| d.1.contains(&item))
+    }
+
+    //mp remove
+    /// Remove an item (from whichever date it is on), returning true
+    /// if it was present
+    ///
+    /// Dates cannot be removed from the index, so if this leaves a
+    /// date with no items the whole ordering is rebuilt
+    pub fn remove(&mut self, item: T) -> bool {
+        let Some(d) = self
+            .transactions_by_date
+            .iter_mut()
+            .find(|d| d.1.contains(&item))
+        else {
+            return false;
+        };
+        if d.1.len() > 1 {
+            d.1.retain(|t| *t != item);
+            return true;
+        }
+        let remaining: Vec<(Date, T)> = self
+            .iter()
+            .filter(|c| self[*c] != item)
+            .map(|c| (self.cursor_date(&c).unwrap(), self[c]))
+            .collect();
+        self.clear();
+        for (date, t) in remaining {
+            self.push_to_date(date, t);
+        }
+        true
+    }
+}
+
 //ip OrderedTransactions<DbId>
 impl OrderedTransactions<DbId> {
     //mp rebuild
@@ -0,0 +1,596 @@
+//a Documentation
+//! A textual query language for the database, which parses into a
+//! [DbQuery]
+//!
+//! A query is a sequence of terms, all of which an item must match;
+//! for example:
+//!
+//! ```text
+//! type:bank_transaction date:2024-04..2025-03 amount>100 desc~"SUB" rp:"Smith" -fund:General
+//! ```
+//!
+//! A term is a field, an operator and a value; the value may be
+//! quoted (with '\"' for a quote within it). The fields are:
+//!
+//! * type - the item type (account, fund, transaction, etc)
+//!
+//! * name, desc - ':' for a name or description that starts with the
+//!   value (or matches it, if it is a pattern), '~' for one that
+//!   contains a match of a regular expression
+//!
+//! * date - a year (2024), month (2024-04), day (2024-04-06 or
+//!   6/4/2024), or a range of these (2024-04..2025-03), which
+//!   includes all of the last
+//!
+//! * amount - ':' for an exact amount, or '>', '>=', '<', '<='
+//!
+//! * rp - a related party, any of whose names contains the value
+//!   (ignoring case)
+//!
+//! * rp_type - the type of a related party (member, supplier, etc)
+//!
+//! * fund, account - a fund (by name or alias) or account (by name or
+//!   account description)
+//!
+//! * side - credit or debit
+//!
+//! * ttype - a transaction type (fund_transfer, from_rp, to_rp or
+//!   revaluation)
+//!
+//! * bank_type - a bank transaction type code (such as DD or FPI);
+//!   this matches both the credit and debit types of the code (such
+//!   as direct debits and their refunds), so 'side' can select one
+//!
+//! * id, db_id - a numeric ID, or database ID
+//!
+//! A term may be negated with a '-' or 'not' before it; terms
+//! separated by 'or' (or '|') are alternatives, and parentheses
+//! group terms. 'and' may be used between terms, but is not
+//! required.
+//!
+//! Errors report the column (counting characters from 1) at which
+//! they occur.
+
+//a Imports
+use regex::Regex;
+
+use crate::{
+    AccountDesc, Amount, AmountSide, BankTransactionType, Database, Date, DateRange, DbId,
+    DbItemType, DbQuery, Error, RelatedPartyType, TransactionType,
+};
+
+//a Tokens
+//tp Op
+/// The operator of a term
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Is,
+    Matches,
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+}
+
+//ip Display for Op
+impl std::fmt::Display for Op {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        let s = match self {
+            Op::Is => ":",
+            Op::Matches => "~",
+            Op::Greater => ">",
+            Op::GreaterOrEqual => ">=",
+            Op::Less => "<",
+            Op::LessOrEqual => "<=",
+        };
+        fmt.write_str(s)
+    }
+}
+
+//tp Term
+/// A term of a query, with the columns of its parts
+#[derive(Debug, Clone)]
+struct Term {
+    field: String,
+    field_column: usize,
+    op: Op,
+    op_column: usize,
+    value: String,
+    value_column: usize,
+}
+
+//tp Token
+#[derive(Debug, Clone)]
+enum Token {
+    Open,
+    Close,
+    Not,
+    And,
+    Or,
+    Term(Term),
+}
+
+//a Lexer
+//tp Lexer
+/// Split the text of a query into tokens
+struct Lexer {
+    chars: Vec<char>,
+    index: usize,
+}
+
+//ip Lexer
+impl Lexer {
+    //cp new
+    fn new(text: &str) -> Self {
+        Self {
+            chars: text.chars().collect(),
+            index: 0,
+        }
+    }
+
+    //mi peek
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.index).copied()
+    }
+
+    //mi column
+    fn column(&self) -> usize {
+        self.index + 1
+    }
+
+    //mi error
+    fn error<S: Into<String>>(&self, column: usize, s: S) -> Error {
+        Error::ParseQuery(column, s.into())
+    }
+
+    //mi take_while
+    fn take_while<F: Fn(char) -> bool>(&mut self, f: F) -> String {
+        let mut s = String::new();
+        while let Some(c) = self.peek().filter(|c| f(*c)) {
+            s.push(c);
+            self.index += 1;
+        }
+        s
+    }
+
+    //mi op
+    fn op(&mut self) -> Option<Op> {
+        let op = match self.peek()? {
+            ':' | '=' => Op::Is,
+            '~' => Op::Matches,
+            '>' => Op::Greater,
+            '<' => Op::Less,
+            _ => return None,
+        };
+        self.index += 1;
+        if self.peek() == Some('=') {
+            match op {
+                Op::Greater => {
+                    self.index += 1;
+                    return Some(Op::GreaterOrEqual);
+                }
+                Op::Less => {
+                    self.index += 1;
+                    return Some(Op::LessOrEqual);
+                }
+                _ => (),
+            }
+        }
+        Some(op)
+    }
+
+    //mi value
+    fn value(&mut self) -> Result<String, Error> {
+        if self.peek() != Some('"') {
+            return Ok(self.take_while(|c| !c.is_whitespace() && c != '(' && c != ')'));
+        }
+        let column = self.column();
+        self.index += 1;
+        let mut s = String::new();
+        loop {
+            match self.peek() {
+                None => return Err(self.error(column, "unterminated quoted value")),
+                Some('"') => {
+                    self.index += 1;
+                    return Ok(s);
+                }
+                Some('\\') if self.chars.get(self.index + 1) == Some(&'"') => {
+                    s.push('"');
+                    self.index += 2;
+                }
+                Some(c) => {
+                    s.push(c);
+                    self.index += 1;
+                }
+            }
+        }
+    }
+
+    //mi next_token
+    /// Get the next token and its column, if there is one
+    fn next_token(&mut self) -> Result<Option<(usize, Token)>, Error> {
+        self.take_while(char::is_whitespace);
+        let column = self.column();
+        let Some(c) = self.peek() else {
+            return Ok(None);
+        };
+        let token = match c {
+            '(' => {
+                self.index += 1;
+                Token::Open
+            }
+            ')' => {
+                self.index += 1;
+                Token::Close
+            }
+            '-' => {
+                self.index += 1;
+                Token::Not
+            }
+            '|' => {
+                self.index += 1;
+                Token::Or
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let field = self.take_while(|c| c.is_alphanumeric() || c == '_');
+                let op_column = self.column();
+                match (self.op(), field.to_ascii_lowercase().as_str()) {
+                    (None, "and") => Token::And,
+                    (None, "or") => Token::Or,
+                    (None, "not") => Token::Not,
+                    (None, _) => {
+                        return Err(self.error(
+                            column,
+                            format!("expected a field, operator and value, such as name:{field}"),
+                        ))
+                    }
+                    (Some(op), _) => {
+                        let value_column = self.column();
+                        let value = self.value()?;
+                        if value.is_empty() {
+                            return Err(self.error(value_column, "expected a value"));
+                        }
+                        Token::Term(Term {
+                            field,
+                            field_column: column,
+                            op,
+                            op_column,
+                            value,
+                            value_column,
+                        })
+                    }
+                }
+            }
+            c => {
+                return Err(self.error(column, format!("unexpected '{c}'")));
+            }
+        };
+        Ok(Some((column, token)))
+    }
+
+    //mi tokens
+    fn tokens(mut self) -> Result<(Vec<(usize, Token)>, usize), Error> {
+        let mut tokens = vec![];
+        while let Some(token) = self.next_token()? {
+            tokens.push(token);
+        }
+        Ok((tokens, self.column()))
+    }
+}
+
+//a Parser
+//tp Parser
+/// A recursive descent parser of the tokens of a query
+struct Parser<'a> {
+    db: &'a Database,
+    tokens: Vec<(usize, Token)>,
+    index: usize,
+    end_column: usize,
+}
+
+//ip Parser
+impl<'a> Parser<'a> {
+    //mi peek
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.index).map(|(_, t)| t)
+    }
+
+    //mi column
+    /// The column of the next token, or of the end of the text
+    fn column(&self) -> usize {
+        self.tokens
+            .get(self.index)
+            .map(|(c, _)| *c)
+            .unwrap_or(self.end_column)
+    }
+
+    //mi error
+    fn error<S: Into<String>>(&self, column: usize, s: S) -> Error {
+        Error::ParseQuery(column, s.into())
+    }
+
+    //mi parse_or
+    /// Parse alternatives separated by 'or'
+    fn parse_or(&mut self) -> Result<DbQuery, Error> {
+        let mut queries = vec![self.parse_and()?];
+        while matches!(self.peek(), Some(Token::Or)) {
+            self.index += 1;
+            queries.push(self.parse_and()?);
+        }
+        if queries.len() == 1 {
+            Ok(queries.pop().unwrap())
+        } else {
+            Ok(DbQuery::any_of(queries))
+        }
+    }
+
+    //mi parse_and
+    /// Parse a sequence of terms, optionally separated by 'and'
+    fn parse_and(&mut self) -> Result<DbQuery, Error> {
+        let mut queries = vec![];
+        loop {
+            match self.peek() {
+                None | Some(Token::Close) | Some(Token::Or) => break,
+                Some(Token::And) if !queries.is_empty() => {
+                    self.index += 1;
+                }
+                _ => (),
+            }
+            queries.push(self.parse_not()?);
+        }
+        match queries.len() {
+            0 => Err(self.error(self.column(), "expected a search term")),
+            1 => Ok(queries.pop().unwrap()),
+            _ => Ok(DbQuery::all_of(queries)),
+        }
+    }
+
+    //mi parse_not
+    /// Parse a term, a negated term, or a group in parentheses
+    fn parse_not(&mut self) -> Result<DbQuery, Error> {
+        let column = self.column();
+        let Some(token) = self.peek().cloned() else {
+            return Err(self.error(column, "expected a search term"));
+        };
+        self.index += 1;
+        match token {
+            Token::Not => Ok(DbQuery::none_of([self.parse_not()?])),
+            Token::Open => {
+                let query = self.parse_or()?;
+                if !matches!(self.peek(), Some(Token::Close)) {
+                    return Err(self.error(
+                        self.column(),
+                        format!("expected ')' to match the '(' at column {column}"),
+                    ));
+                }
+                self.index += 1;
+                Ok(query)
+            }
+            Token::Term(term) => self.parse_term(&term),
+            _ => Err(self.error(column, "expected a search term")),
+        }
+    }
+
+    //mi parse_term
+    /// Parse a single field, operator and value
+    fn parse_term(&self, term: &Term) -> Result<DbQuery, Error> {
+        let q = DbQuery::default();
+        let value = term.value.as_str();
+        let value_error = |e: Error| self.error(term.value_column, e.to_string());
+        let op_error = || {
+            self.error(
+                term.op_column,
+                format!("'{}' cannot be used with '{}'", term.op, term.field),
+            )
+        };
+        let field = term.field.to_ascii_lowercase();
+        match (field.as_str(), term.op) {
+            ("type", Op::Is) => {
+                let item_type = value.parse::<DbItemType>().map_err(value_error)?;
+                Ok(q.with_item_type(Some(item_type)))
+            }
+            ("name", Op::Is) => Ok(q.with_name(value)),
+            ("name", Op::Matches) => Ok(q.with_name_re(self.regex(term)?)),
+            ("desc", Op::Is) => Ok(q.with_desc(value)),
+            ("desc", Op::Matches) => Ok(q.with_desc_re(self.regex(term)?)),
+            ("date", Op::Is) => {
+                let date_range = parse_date_range(value).map_err(value_error)?;
+                Ok(q.with_date_range(date_range))
+            }
+            ("amount", op) => {
+                let amount = value.parse::<Amount>().map_err(value_error)?;
+                let penny = Amount::from(1);
+                Ok(match op {
+                    Op::Is => q.with_amount(Some(amount)),
+                    Op::Greater => q.with_min_amount(Some(amount + penny)),
+                    Op::GreaterOrEqual => q.with_min_amount(Some(amount)),
+                    Op::Less => q.with_max_amount(Some(amount - penny)),
+                    Op::LessOrEqual => q.with_max_amount(Some(amount)),
+                    Op::Matches => return Err(op_error()),
+                })
+            }
+            ("rp", Op::Is) => self.related_parties(term),
+            ("rp_type", Op::Is) => {
+                let rp_type = value.parse::<RelatedPartyType>().map_err(value_error)?;
+                Ok(q.with_rp_type(Some(rp_type)))
+            }
+            ("fund", Op::Is) => {
+                let Some(fund) = self.db.funds().get_fund(value) else {
+                    return Err(self.error(term.value_column, format!("no fund '{value}'")));
+                };
+                Ok(q.with_fund(fund.id()))
+            }
+            ("account", Op::Is) => {
+                let account = self.db.accounts().get_account_by_name(value).or_else(|| {
+                    value
+                        .parse::<AccountDesc>()
+                        .ok()
+                        .and_then(|desc| self.db.accounts().get_account(&desc))
+                });
+                let Some(account) = account else {
+                    return Err(self.error(term.value_column, format!("no account '{value}'")));
+                };
+                Ok(q.with_account(account.id()))
+            }
+            ("side", Op::Is) => {
+                let side = match value.to_ascii_lowercase().as_str() {
+                    "credit" => AmountSide::Credit,
+                    "debit" => AmountSide::Debit,
+                    _ => {
+                        return Err(self.error(
+                            term.value_column,
+                            format!("expected credit or debit, not '{value}'"),
+                        ))
+                    }
+                };
+                Ok(q.with_side(Some(side)))
+            }
+            ("ttype", Op::Is) => {
+                let ttype = match value.to_ascii_lowercase().as_str() {
+                    "fund_transfer" | "fundtransfer" => TransactionType::FundTransfer,
+                    "from_rp" | "fromrp" => TransactionType::FromRp,
+                    "to_rp" | "torp" => TransactionType::ToRp,
+                    "revaluation" | "capitalrevaluation" => TransactionType::CaptialRevaluation,
+                    _ => {
+                        return Err(self.error(
+                            term.value_column,
+                            format!(
+                            "expected fund_transfer, from_rp, to_rp or revaluation, not '{value}'"
+                        ),
+                        ))
+                    }
+                };
+                Ok(q.with_transaction_type(Some(ttype)))
+            }
+            ("bank_type", Op::Is) => {
+                let credit = BankTransactionType::parse(value, false).map_err(value_error)?;
+                let debit = BankTransactionType::parse(value, true).map_err(value_error)?;
+                if credit == debit {
+                    Ok(q.with_bank_transaction_type(Some(credit)))
+                } else {
+                    Ok(DbQuery::any_of([credit, debit].map(|ttype| {
+                        DbQuery::default().with_bank_transaction_type(Some(ttype))
+                    })))
+                }
+            }
+            ("id", Op::Is) => {
+                let id = self.number(term)?;
+                Ok(q.with_id(Some(id)))
+            }
+            ("db_id", Op::Is) => {
+                let db_id = DbId::of_usize(self.number(term)?);
+                Ok(q.with_db_id(db_id))
+            }
+            (
+                "type" | "name" | "desc" | "date" | "rp" | "rp_type" | "fund" | "account" | "side"
+                | "ttype" | "bank_type" | "id" | "db_id",
+                _,
+            ) => Err(op_error()),
+            _ => Err(self.error(term.field_column, format!("unknown field '{}'", term.field))),
+        }
+    }
+
+    //mi regex
+    fn regex(&self, term: &Term) -> Result<Regex, Error> {
+        Regex::new(&term.value).map_err(|e| self.error(term.value_column, e.to_string()))
+    }
+
+    //mi number
+    fn number(&self, term: &Term) -> Result<usize, Error> {
+        term.value.parse::<usize>().map_err(|_| {
+            self.error(
+                term.value_column,
+                format!("expected a number, not '{}'", term.value),
+            )
+        })
+    }
+
+    //mi related_parties
+    /// Find the related parties any of whose names contains the value
+    /// of a term, and create a query for items of any of them
+    fn related_parties(&self, term: &Term) -> Result<DbQuery, Error> {
+        let name_re = Regex::new(&format!("(?i){}", regex::escape(&term.value)))
+            .map_err(|e| self.error(term.value_column, e.to_string()))?;
+        let rp_query = DbQuery::default()
+            .with_item_type(Some(DbItemType::RelatedParty))
+            .with_name_re(name_re);
+        let mut queries: Vec<_> = self
+            .db
+            .query(rp_query)
+            .map(|db_id| DbQuery::default().with_related_party(db_id))
+            .collect();
+        match queries.len() {
+            0 => Err(self.error(
+                term.value_column,
+                format!("no related party matches '{}'", term.value),
+            )),
+            1 => Ok(queries.pop().unwrap()),
+            _ => Ok(DbQuery::any_of(queries)),
+        }
+    }
+}
+
+//a Dates
+//fi parse_date_period
+/// Parse a year, month or day, returning its first day and the day
+/// after its last
+fn parse_date_period(s: &str) -> Result<(Date, Date), Error> {
+    let iso = Regex::new(r"^([0-9]{4})(?:-([0-9]{1,2})(?:-([0-9]{1,2}))?)?$").unwrap();
+    let Some(c) = iso.captures(s) else {
+        let date = Date::parse(s)?;
+        return Ok((date, date.plus_days(1)));
+    };
+    let number = |i: usize| c.get(i).map(|m| m.as_str().parse::<u32>().unwrap());
+    let year = number(1).unwrap() as i32;
+    match (number(2), number(3)) {
+        (None, _) => Ok((Date::of_dmy(1, 1, year)?, Date::of_dmy(1, 1, year + 1)?)),
+        (Some(month), None) => {
+            let end = if month == 12 {
+                Date::of_dmy(1, 1, year + 1)?
+            } else {
+                Date::of_dmy(1, month + 1, year)?
+            };
+            Ok((Date::of_dmy(1, month, year)?, end))
+        }
+        (Some(month), Some(day)) => {
+            let date = Date::of_dmy(day, month, year)?;
+            Ok((date, date.plus_days(1)))
+        }
+    }
+}
+
+//fi parse_date_range
+/// Parse a period, or a range of periods separated by '..'
+fn parse_date_range(s: &str) -> Result<DateRange, Error> {
+    let (start, end) = match s.split_once("..") {
+        Some((first, last)) => (parse_date_period(first)?.0, parse_date_period(last)?.1),
+        None => parse_date_period(s)?,
+    };
+    let date_range: DateRange = (start, end).into();
+    if date_range.is_empty() {
+        return Err(Error::ParseDate(format!("{s} is an empty range")));
+    }
+    Ok(date_range)
+}
+
+//a Functions
+//fp parse_query
+/// Parse the text of a query, using the database to find the funds,
+/// accounts and related parties that it names
+pub(crate) fn parse_query(text: &str, db: &Database) -> Result<DbQuery, Error> {
+    let (tokens, end_column) = Lexer::new(text).tokens()?;
+    if tokens.is_empty() {
+        return Ok(DbQuery::default());
+    }
+    let mut parser = Parser {
+        db,
+        tokens,
+        index: 0,
+        end_column,
+    };
+    let query = parser.parse_or()?;
+    if parser.index < parser.tokens.len() {
+        return Err(parser.error(parser.column(), "unexpected ')'"));
+    }
+    Ok(query)
+}
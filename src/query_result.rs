@@ -0,0 +1,351 @@
+//a Documentation
+//! The results of a query of a Database, sorted, paged and projected
+//!
+//! A [QueryView] describes how the items that match a [DbQuery]
+//! should be returned: the key they are sorted by, the page of them
+//! (an offset and limit), and the fields of each item to include. A
+//! [QueryResult] holds the page of items, and the total number of
+//! items that matched, so that a client can page through them.
+//!
+//! Items that have no value for the sort key (such as a fund when
+//! sorting by date) come after those that do, in either direction;
+//! items with the same value are in DbId order.
+//!
+//! The fields asked for are checked against the types of all the
+//! matching items before the page is taken; a field that none of
+//! them has is an error, and a field that only some types have is
+//! null for the items of the other types.
+
+//a Imports
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::{Database, Date, DbId, DbItem, DbItemType, DbQuery, Error};
+
+//a SortKey
+//tp SortKey
+/// The key to sort the items of a query result by
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum SortKey {
+    /// The DbId of the item, which is the order they were added in
+    #[default]
+    DbId,
+    /// The date of a transaction or bank transaction
+    Date,
+    /// The amount of a transaction, bank transaction (credits
+    /// positive, debits negative) or invoice
+    Amount,
+    /// The name of an account, fund or related party, the reason for
+    /// an invoice, or the description of a bank transaction
+    Name,
+}
+
+//ip FromStr for SortKey
+impl std::str::FromStr for SortKey {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s.to_ascii_lowercase().as_str() {
+            "db_id" | "id" => Ok(Self::DbId),
+            "date" => Ok(Self::Date),
+            "amount" => Ok(Self::Amount),
+            "name" => Ok(Self::Name),
+            _ => Err(format!("Unknown sort key {s} (try db_id, date, amount or name)").into()),
+        }
+    }
+}
+
+//ip Display for SortKey
+impl std::fmt::Display for SortKey {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        match self {
+            SortKey::DbId => write!(fmt, "db_id"),
+            SortKey::Date => write!(fmt, "date"),
+            SortKey::Amount => write!(fmt, "amount"),
+            SortKey::Name => write!(fmt, "name"),
+        }
+    }
+}
+
+//tp SortValue
+/// The value of a sort key for an item
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum SortValue {
+    Date(Date),
+    Amount(isize),
+    Name(String),
+}
+
+//fi sort_value
+/// The value of the sort key for an item, if it has one
+fn sort_value(item: &DbItem, sort_key: SortKey) -> Option<SortValue> {
+    match sort_key {
+        SortKey::DbId => None,
+        SortKey::Date => {
+            if let Some(t) = item.transaction() {
+                Some(SortValue::Date(t.inner().date()))
+            } else {
+                item.bank_transaction()
+                    .map(|bt| SortValue::Date(bt.inner().date()))
+            }
+        }
+        SortKey::Amount => {
+            if let Some(t) = item.transaction() {
+                Some(SortValue::Amount(t.inner().amount().value()))
+            } else if let Some(bt) = item.bank_transaction() {
+                Some(SortValue::Amount(bt.inner().balance_delta().value()))
+            } else {
+                item.invoice()
+                    .map(|i| SortValue::Amount(i.inner().amount().value()))
+            }
+        }
+        SortKey::Name => {
+            let name = if let Some(a) = item.account() {
+                a.inner().name().to_string()
+            } else if let Some(f) = item.fund() {
+                f.inner().name().to_string()
+            } else if let Some(rp) = item.related_party() {
+                rp.inner().name().to_string()
+            } else if let Some(i) = item.invoice() {
+                i.inner().reason().to_string()
+            } else if let Some(bt) = item.bank_transaction() {
+                bt.inner().description().to_string()
+            } else {
+                return None;
+            };
+            Some(SortValue::Name(name.to_lowercase()))
+        }
+    }
+}
+
+//a QueryView
+//tp QueryView
+/// How the items matching a query should be returned
+#[derive(Debug, Default, Clone)]
+pub struct QueryView {
+    /// The key to sort by
+    sort_key: SortKey,
+    /// True if the items should be in descending order of the key
+    descending: bool,
+    /// The number of (sorted) items to skip
+    offset: usize,
+    /// The maximum number of items to return, if limited
+    limit: Option<usize>,
+    /// The fields of each item to return; all of them if empty
+    fields: Vec<String>,
+}
+
+//ip QueryView
+impl QueryView {
+    //cp with_sort_key
+    pub fn with_sort_key(mut self, sort_key: SortKey) -> Self {
+        self.sort_key = sort_key;
+        self
+    }
+
+    //cp with_descending
+    pub fn with_descending(mut self, descending: bool) -> Self {
+        self.descending = descending;
+        self
+    }
+
+    //cp with_offset
+    pub fn with_offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    //cp with_limit
+    pub fn with_limit(mut self, limit: Option<usize>) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    //cp with_fields
+    /// Return only the given fields of each item (as well as its id
+    /// and itype); the query result is an error if none of the
+    /// matching items has one of the fields
+    pub fn with_fields<I, S>(mut self, fields: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.fields = fields.into_iter().map(|s| s.into()).collect();
+        self
+    }
+
+    //mi sort
+    /// Sort the DbIds of items
+    fn sort(&self, db: &Database, db_ids: &mut [DbId]) {
+        if self.sort_key == SortKey::DbId {
+            db_ids.sort();
+            if self.descending {
+                db_ids.reverse();
+            }
+            return;
+        }
+        let mut keyed: Vec<_> = db_ids
+            .iter()
+            .map(|db_id| {
+                let value = db.get(*db_id).and_then(|i| sort_value(&i, self.sort_key));
+                (value, *db_id)
+            })
+            .collect();
+        keyed.sort_by(|(a, a_id), (b, b_id)| {
+            let order = match (a, b) {
+                (Some(a), Some(b)) if self.descending => b.cmp(a),
+                (Some(a), Some(b)) => a.cmp(b),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            };
+            order.then(a_id.cmp(b_id))
+        });
+        for (d, (_, db_id)) in db_ids.iter_mut().zip(keyed) {
+            *d = db_id;
+        }
+    }
+
+    //mi check_fields
+    /// Check that each field asked for is a field of at least one of
+    /// the items
+    fn check_fields(&self, db: &Database, db_ids: &[DbId]) -> Result<(), Error> {
+        if self.fields.is_empty() {
+            return Ok(());
+        }
+        let mut itypes: HashSet<DbItemType> = HashSet::new();
+        let mut known: HashSet<String> = ["id", "itype"].map(String::from).into();
+        for db_id in db_ids {
+            let Some(item) = db.get(*db_id) else {
+                return Err(Error::UnknownItemId(*db_id));
+            };
+            if itypes.insert(item.itype()) {
+                known.extend(item_fields(&item)?.into_iter().map(|(k, _)| k));
+            }
+        }
+        if itypes.is_empty() {
+            return Ok(());
+        }
+        for f in &self.fields {
+            if !known.contains(f) {
+                let mut itypes: Vec<_> = itypes.into_iter().map(|t| format!("{t:?}")).collect();
+                itypes.sort();
+                return Err(format!("Unknown field '{f}' for {}", itypes.join(", ")).into());
+            }
+        }
+        Ok(())
+    }
+
+    //mi project
+    /// The fields of an item to return; a field that the item does
+    /// not have is null
+    fn project(&self, item: &DbItem) -> Result<Value, Error> {
+        let mut all_fields = item_fields(item)?;
+        let mut fields = Map::new();
+        fields.insert("id".into(), serde_json::to_value(item.id())?);
+        fields.insert("itype".into(), serde_json::to_value(item.itype())?);
+        if self.fields.is_empty() {
+            fields.append(&mut all_fields);
+        } else {
+            for f in &self.fields {
+                if let Some(v) = all_fields.remove(f) {
+                    fields.insert(f.clone(), v);
+                } else if !fields.contains_key(f) {
+                    fields.insert(f.clone(), Value::Null);
+                }
+            }
+        }
+        Ok(Value::Object(fields))
+    }
+}
+
+//fi item_fields
+/// All the fields of an item
+fn item_fields(item: &DbItem) -> Result<Map<String, Value>, Error> {
+    let value = if let Some(d) = item.account() {
+        serde_json::to_value(&*d.inner())?
+    } else if let Some(d) = item.fund() {
+        serde_json::to_value(&*d.inner())?
+    } else if let Some(d) = item.invoice() {
+        serde_json::to_value(&*d.inner())?
+    } else if let Some(d) = item.related_party() {
+        serde_json::to_value(&*d.inner())?
+    } else if let Some(d) = item.bank_transaction() {
+        serde_json::to_value(&*d.inner())?
+    } else if let Some(d) = item.transaction() {
+        serde_json::to_value(&*d.inner())?
+    } else {
+        Value::Null
+    };
+    match value {
+        Value::Object(fields) => Ok(fields),
+        _ => Ok(Map::new()),
+    }
+}
+
+//a QueryResult
+//tp QueryResult
+/// A page of the items that match a query
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct QueryResult {
+    /// The total number of items that matched the query
+    total: usize,
+    /// The index (in the sorted items) of the first item of the page
+    offset: usize,
+    /// The DbIds of the items of the page
+    db_ids: Vec<DbId>,
+    /// The (projected) items of the page
+    items: Vec<Value>,
+}
+
+//ip QueryResult
+impl QueryResult {
+    //cp new
+    /// Query the database, and return the page of items for the view
+    pub(crate) fn new(db: &Database, query: DbQuery, view: &QueryView) -> Result<Self, Error> {
+        let mut db_ids: Vec<_> = db.query(query).collect();
+        let total = db_ids.len();
+        view.check_fields(db, &db_ids)?;
+        view.sort(db, &mut db_ids);
+        let db_ids: Vec<_> = db_ids
+            .into_iter()
+            .skip(view.offset)
+            .take(view.limit.unwrap_or(usize::MAX))
+            .collect();
+        let mut items = vec![];
+        for db_id in &db_ids {
+            let Some(item) = db.get(*db_id) else {
+                return Err(Error::UnknownItemId(*db_id));
+            };
+            items.push(view.project(&item)?);
+        }
+        Ok(Self {
+            total,
+            offset: view.offset,
+            db_ids,
+            items,
+        })
+    }
+
+    //ap total
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    //ap offset
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    //ap db_ids
+    pub fn db_ids(&self) -> &[DbId] {
+        &self.db_ids
+    }
+
+    //ap items
+    pub fn items(&self) -> &[Value] {
+        &self.items
+    }
+}
@@ -4,7 +4,8 @@ use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize, Serializer};
 
-use crate::{DatabaseRebuild, Date, DbId, Error, OrderedTransactions};
+use crate::indexed_vec::Idx;
+use crate::{DatabaseRebuild, Date, DateRange, DbId, Error, OrderedTransactions};
 
 //a RelatedPartyType, RelatedPartyQuery
 //tp RelatedPartyType
@@ -68,6 +69,34 @@ impl From<Option<RelatedPartyType>> for RelatedPartyQuery {
     }
 }
 
+//a RelatedPartyMatch
+//tp RelatedPartyMatch
+/// The result of matching a bank transaction description against the
+/// account descriptions of the related parties
+#[derive(Default, Clone, Copy, Debug, Serialize, PartialEq, Eq)]
+pub enum RelatedPartyMatch {
+    /// No related party matches
+    #[default]
+    NotFound,
+    /// Exactly one related party matches
+    Found(DbId),
+    /// More than one related party matches, however much of the
+    /// description is used
+    Ambiguous,
+}
+
+//ip RelatedPartyMatch
+impl RelatedPartyMatch {
+    //ap db_id
+    /// The DbId of the related party matched, or none
+    pub fn db_id(&self) -> DbId {
+        match self {
+            Self::Found(db_id) => *db_id,
+            _ => DbId::none(),
+        }
+    }
+}
+
 //a RelatedPartyPartySummary
 //tp RelatedPartySummaryOwned
 /// The related for delivery as a summary, without all the transactions
@@ -278,6 +307,11 @@ impl RelatedParty {
         self.name = i.into();
     }
 
+    //mp change_rp_id
+    pub fn change_rp_id(&mut self, rp_id: usize) {
+        self.rp_id = rp_id;
+    }
+
     //mp add_alias
     pub fn add_alias<I: Into<String>>(&mut self, i: I) {
         self.aliases.push(i.into());
@@ -348,6 +382,16 @@ impl RelatedParty {
         self.last_gift_aid
     }
 
+    //mp transactions_in_range
+    pub fn transactions_in_range(&self, date_range: DateRange) -> Vec<DbId> {
+        self.transactions.transactions_in_range(date_range)
+    }
+
+    //mp invoices_in_range
+    pub fn invoices_in_range(&self, date_range: DateRange) -> Vec<DbId> {
+        self.invoices.transactions_in_range(date_range)
+    }
+
     //mp add_transaction
     /// Add transaction
     pub fn add_transaction(&mut self, date: Date, t_id: DbId) -> bool {
@@ -360,14 +404,29 @@ impl RelatedParty {
         true
     }
 
+    //mp remove_transaction
+    /// Remove a transaction, returning true if it was present
+    pub fn remove_transaction(&mut self, t_id: DbId) -> bool {
+        self.transactions.remove(t_id)
+    }
+
+    //mp remove_invoice
+    /// Remove an invoice, returning true if it was present
+    pub fn remove_invoice(&mut self, invoice_id: DbId) -> bool {
+        self.invoices.remove(invoice_id)
+    }
+
     //ap matches_query
     pub fn matches_query(&self, query: &RelatedPartyQuery) -> bool {
         query.matches_rp_type(self.rp_type)
     }
 
     //mp rebuild
+    /// Rebuild the related party; its transactions are repopulated
+    /// from the transactions themselves by
+    /// [crate::Database::try_rebuild], so they are cleared here
     pub fn rebuild(&mut self, database_rebuild: &DatabaseRebuild) -> Result<(), Error> {
-        self.transactions.rebuild(database_rebuild)?;
+        self.transactions.clear();
         self.invoices.rebuild(database_rebuild)
     }
 
@@ -499,6 +558,51 @@ impl DbRelatedParties {
         }
     }
 
+    //mp remove_related_party
+    /// Remove a related party (with its aliases), returning true if
+    /// it was present
+    pub fn remove_related_party(&self, db_id: DbId) -> bool {
+        let mut state = self.state.borrow_mut();
+        let Some(n) = state.array.iter().position(|rp| rp.id() == db_id) else {
+            return false;
+        };
+        state.array.remove(n);
+        state.map.retain(|_, rp| rp.id() != db_id);
+        true
+    }
+
+    //mp reindex_related_party
+    /// Reindex a related party after its name, aliases or rp_id have
+    /// changed
+    ///
+    /// This fails (leaving the related party unindexed) if another
+    /// related party has the new name or rp_id; aliases used by
+    /// other related parties are not indexed
+    pub fn reindex_related_party(&self, db_related_party: &DbRelatedParty) -> bool {
+        let db_id = db_related_party.id();
+        self.state.borrow_mut().map.retain(|_, rp| rp.id() != db_id);
+        let rp_id = db_related_party.inner().rp_id;
+        if self
+            .state
+            .borrow()
+            .array
+            .iter()
+            .any(|rp| rp.id() != db_id && rp.inner().rp_id == rp_id)
+        {
+            return false;
+        }
+        let name = db_related_party.inner().name().to_string();
+        if self.state.borrow().map.contains_key(&name) {
+            return false;
+        }
+        self.state
+            .borrow_mut()
+            .map
+            .insert(name, db_related_party.clone());
+        self.add_related_party_aliases(db_related_party);
+        true
+    }
+
     //mp get_party_of_str
     pub fn get_party_of_str(&self, name: &str) -> Option<DbRelatedParty> {
         if name.chars().all(|c| c.is_ascii_digit()) {
@@ -509,6 +613,63 @@ impl DbRelatedParties {
         self.state.borrow().map.get(name).cloned()
     }
 
+    //mp find_by_name
+    /// Find the related party whose name or alias matches, ignoring
+    /// case and repeated whitespace; this is None if more than one
+    /// related party matches, as they differ only in case or spacing
+    pub fn find_by_name(&self, name: &str) -> Option<DbRelatedParty> {
+        let normalize = |s: &str| {
+            s.split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+                .to_lowercase()
+        };
+        let name = normalize(name);
+        if name.is_empty() {
+            return None;
+        }
+        let state = self.state.borrow();
+        let mut found = state.array.iter().filter(|db_rp| {
+            let rp = db_rp.inner();
+            normalize(rp.name()) == name || rp.aliases().iter().any(|a| normalize(a) == name)
+        });
+        let db_rp = found.next()?;
+        if found.next().is_some() {
+            return None;
+        }
+        Some(db_rp.clone())
+    }
+
+    //mp find_by_account_descr
+    /// Find a related party that has an account description matching
+    /// the given IBAN (or other account identifier), ignoring case,
+    /// spaces and any 'iban:' prefix
+    pub fn find_by_account_descr(&self, descr: &str) -> Option<DbRelatedParty> {
+        let normalize = |s: &str| {
+            let s: String = s
+                .chars()
+                .filter(|c| !c.is_whitespace())
+                .collect::<String>()
+                .to_uppercase();
+            s.strip_prefix("IBAN:").map(|s| s.to_string()).unwrap_or(s)
+        };
+        let descr = normalize(descr);
+        if descr.is_empty() {
+            return None;
+        }
+        let state = self.state.borrow();
+        state
+            .array
+            .iter()
+            .find(|db_rp| {
+                db_rp
+                    .inner()
+                    .account_descrs()
+                    .any(|a| normalize(a) == descr)
+            })
+            .cloned()
+    }
+
     //ap get_party
     pub fn get_party(&self, name: &str, query: RelatedPartyQuery) -> Option<DbRelatedParty> {
         if let Some(db_rp) = self.get_party_of_str(name) {
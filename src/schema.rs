@@ -0,0 +1,404 @@
+//a Documentation
+//! Schema versions of a serialized Database, and the migrations
+//! between them
+//!
+//! A database file is written with a header giving its schema
+//! version, with the items in the array or dictionary layout:
+//!
+//! ```text
+//! { "schema_version": 1, "journal_op": 12, "items": [ ... ] }
+//! ```
+//!
+//! The journal_op is the last operation of the database's journal
+//! that the file includes (see [crate::Journal]); it is 0 if the
+//! header omits it.
+//!
+//! Files written before the header was added (just the array of
+//! DbItems, or the dictionary of sections) are schema version 0.
+//!
+//! When a file with an older schema version is loaded its items are
+//! upgraded one version at a time by the registered migrations,
+//! before they are deserialized. Each migration upgrades the
+//! serialized form of a single item (such as a BankTransaction) in
+//! place, so it applies to either layout.
+//!
+//! A change to the serialized fields of an item should bump
+//! SCHEMA_VERSION and add a migration from the previous version.
+
+//a Imports
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+
+use crate::db_dictionary::DbDictionary;
+use crate::indexed_vec::Idx;
+use crate::{Currency, Database, DbId, DbItem, DbItemType, Error, FileFormat};
+
+//a Constants
+//cp SCHEMA_VERSION
+/// The schema version of database files written by this crate
+pub const SCHEMA_VERSION: usize = 1;
+
+//cp MIGRATIONS
+/// The migrations, in order, one from each schema version before
+/// SCHEMA_VERSION
+static MIGRATIONS: &[Migration] = &[Migration {
+    from_version: 0,
+    description: "items always have the fields added before schema versions",
+    upgrade_item: upgrade_0_item_fields,
+}];
+
+//a Migrations
+//fi upgrade_0_item_fields
+/// Version 0 files may omit the fields that were added to items
+/// after their other fields: the related party, transactions and
+/// FITID of a bank transaction, the currency of an account, fund or
+/// transaction, and the exchange rate of a transaction
+fn upgrade_0_item_fields(itype: DbItemType, value: &mut Value) -> Result<(), Error> {
+    let Some(fields) = value.as_object_mut() else {
+        return Err(Error::Deserialization(format!(
+            "{itype:?} is not a dictionary"
+        )));
+    };
+    let currency = serde_json::to_value(Currency::default())?;
+    match itype {
+        DbItemType::BankTransaction => {
+            let none = serde_json::to_value(DbId::none())?;
+            fields.entry("related_party").or_insert(none);
+            fields
+                .entry("transactions")
+                .or_insert_with(|| Value::Array(vec![]));
+            fields.entry("fitid").or_insert(Value::Null);
+        }
+        DbItemType::Account | DbItemType::Fund => {
+            fields.entry("currency").or_insert(currency);
+        }
+        DbItemType::Transaction => {
+            fields.entry("currency").or_insert(currency);
+            fields.entry("exchange_rate").or_insert(Value::Null);
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+//a Migration
+//tp Migration
+/// A migration of the items of a database file from one schema
+/// version to the next
+#[derive(Debug)]
+pub struct Migration {
+    /// The schema version that the migration upgrades from
+    from_version: usize,
+    /// What the migration changes
+    description: &'static str,
+    /// Upgrade the serialized form of an item (such as a
+    /// BankTransaction) of the given type
+    upgrade_item: fn(DbItemType, &mut Value) -> Result<(), Error>,
+}
+
+//ip Display for Migration
+impl std::fmt::Display for Migration {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(
+            fmt,
+            "{} -> {} : {}",
+            self.from_version,
+            self.to_version(),
+            self.description
+        )
+    }
+}
+
+//ip Migration
+impl Migration {
+    //cp all
+    /// All of the migrations, in order
+    pub fn all() -> &'static [Migration] {
+        MIGRATIONS
+    }
+
+    //ap from_version
+    pub fn from_version(&self) -> usize {
+        self.from_version
+    }
+
+    //ap to_version
+    pub fn to_version(&self) -> usize {
+        self.from_version + 1
+    }
+
+    //ap description
+    pub fn description(&self) -> &'static str {
+        self.description
+    }
+
+    //mi upgrade_db_item_kind
+    /// Upgrade a serialized DbAccount, DbFund, etc, which holds the
+    /// item itself as 'inner'
+    fn upgrade_db_item_kind(&self, itype: DbItemType, value: &mut Value) -> Result<(), Error> {
+        let Some(inner) = value.get_mut("inner") else {
+            return Err(Error::Deserialization(format!(
+                "{itype:?} has no 'inner' to upgrade"
+            )));
+        };
+        (self.upgrade_item)(itype, inner)
+    }
+
+    //mi upgrade_db_item
+    /// Upgrade a serialized DbItem, as held in the array layout (and
+    /// by an SqliteStore)
+    fn upgrade_db_item(&self, value: &mut Value) -> Result<(), Error> {
+        let itype = value
+            .get("itype")
+            .cloned()
+            .ok_or_else(|| Error::Deserialization("database item has no 'itype'".into()))?;
+        let itype: DbItemType =
+            serde_json::from_value(itype).map_err(|e| Error::Deserialization(e.to_string()))?;
+        let Some(Value::Object(variant)) = value.get_mut("value") else {
+            return Err(Error::Deserialization(format!(
+                "{itype:?} item has no 'value' to upgrade"
+            )));
+        };
+        for v in variant.values_mut() {
+            self.upgrade_db_item_kind(itype, v)?;
+        }
+        Ok(())
+    }
+
+    //mi upgrade_items
+    /// Upgrade all the items of a file in either layout
+    fn upgrade_items(&self, items: &mut Value) -> Result<(), Error> {
+        match items {
+            Value::Array(array) => {
+                for v in array.iter_mut() {
+                    self.upgrade_db_item(v)?;
+                }
+            }
+            Value::Object(sections) => {
+                for (name, section) in sections.iter_mut() {
+                    let Some(itype) = DbDictionary::section_itype(name) else {
+                        continue;
+                    };
+                    let values: Vec<&mut Value> = match section {
+                        Value::Array(a) => a.iter_mut().collect(),
+                        Value::Object(m) => m.values_mut().collect(),
+                        _ => vec![],
+                    };
+                    for v in values {
+                        self.upgrade_db_item_kind(itype, v)?;
+                    }
+                }
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+}
+
+//a SchemaFile
+//tp SchemaFile
+/// The contents of a database file; its schema version, and its
+/// items (not yet deserialized) in the array or dictionary layout
+#[derive(Debug)]
+pub(crate) struct SchemaFile {
+    version: usize,
+    journal_op: usize,
+    items: Value,
+}
+
+//ip SchemaFile
+impl SchemaFile {
+    //cp deserialize
+    /// Deserialize a database file, with or without a header
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, Error> {
+        // A serde_yaml Value keeps the tags that YAML uses for enum
+        // variants, which a serde_json Value cannot
+        let value = serde_yaml::Value::deserialize(deserializer)
+            .map_err(|e| Error::Deserialization(e.to_string()))?;
+        let value = json_of_yaml(value)?;
+        match value {
+            Value::Object(mut header) if header.contains_key("schema_version") => {
+                let version = header["schema_version"].as_u64().ok_or_else(|| {
+                    Error::Deserialization("schema_version is not a number".into())
+                })? as usize;
+                let journal_op = match header.get("journal_op") {
+                    None => 0,
+                    Some(op) => op.as_u64().ok_or_else(|| {
+                        Error::Deserialization("journal_op is not a number".into())
+                    })? as usize,
+                };
+                let items = header.remove("items").ok_or_else(|| {
+                    Error::Deserialization("database file header has no 'items'".into())
+                })?;
+                Ok(Self {
+                    version,
+                    journal_op,
+                    items,
+                })
+            }
+            Value::Object(_) | Value::Array(_) => Ok(Self {
+                version: 0,
+                journal_op: 0,
+                items: value,
+            }),
+            _ => Err(Error::Deserialization(
+                "expected an array of database items or a dictionary of sections".into(),
+            )),
+        }
+    }
+
+    //ap version
+    pub(crate) fn version(&self) -> usize {
+        self.version
+    }
+
+    //ap journal_op
+    pub(crate) fn journal_op(&self) -> usize {
+        self.journal_op
+    }
+
+    //ap file_format
+    pub(crate) fn file_format(&self) -> FileFormat {
+        if self.items.is_array() {
+            FileFormat::Array
+        } else {
+            FileFormat::Dictionary
+        }
+    }
+
+    //mp upgrade
+    /// Upgrade the items to the current schema version
+    pub(crate) fn upgrade(&mut self) -> Result<(), Error> {
+        self.version = upgrade(self.version, |m| m.upgrade_items(&mut self.items))?;
+        Ok(())
+    }
+
+    //mp into_items
+    /// Deserialize the (upgraded) items
+    pub(crate) fn into_items(self) -> Result<Vec<DbItem>, Error> {
+        if self.items.is_array() {
+            serde_json::from_value(self.items).map_err(|e| Error::Deserialization(e.to_string()))
+        } else {
+            let dict: DbDictionary = serde_json::from_value(self.items)
+                .map_err(|e| Error::Deserialization(e.to_string()))?;
+            dict.into_items()
+        }
+    }
+}
+
+//a Header
+//tp SchemaHeader
+/// A database to be serialized with its header, with the items in
+/// the array or dictionary layout
+pub(crate) struct SchemaHeader<'a> {
+    db: &'a Database,
+    format: FileFormat,
+}
+
+//tp SchemaItems
+/// The items of a database to be serialized in a layout
+struct SchemaItems<'a> {
+    db: &'a Database,
+    format: FileFormat,
+}
+
+//ip SchemaHeader
+impl<'a> SchemaHeader<'a> {
+    //cp new
+    pub(crate) fn new(db: &'a Database, format: FileFormat) -> Self {
+        Self { db, format }
+    }
+}
+
+//ip Serialize for SchemaHeader
+impl Serialize for SchemaHeader<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+        let items = SchemaItems {
+            db: self.db,
+            format: self.format,
+        };
+        let mut map = serializer.serialize_map(Some(3))?;
+        map.serialize_entry("schema_version", &SCHEMA_VERSION)?;
+        map.serialize_entry("journal_op", &self.db.journal_op())?;
+        map.serialize_entry("items", &items)?;
+        map.end()
+    }
+}
+
+//ip Serialize for SchemaItems
+impl Serialize for SchemaItems<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.format {
+            FileFormat::Array => self.db.serialize_as_array(serializer),
+            FileFormat::Dictionary => self.db.serialize_as_dictionary(serializer),
+        }
+    }
+}
+
+//a Functions
+//fi upgrade
+/// Apply the migrations from a schema version to the current
+/// version, returning the current version
+fn upgrade<F>(version: usize, mut f: F) -> Result<usize, Error>
+where
+    F: FnMut(&Migration) -> Result<(), Error>,
+{
+    if version > SCHEMA_VERSION {
+        return Err(Error::SchemaVersionTooNew(version, SCHEMA_VERSION));
+    }
+    for m in MIGRATIONS.iter().filter(|m| m.from_version >= version) {
+        f(m)?;
+    }
+    Ok(SCHEMA_VERSION)
+}
+
+//fp upgrade_db_item
+/// Upgrade a serialized DbItem from a schema version to the current
+/// version
+pub(crate) fn upgrade_db_item(version: usize, value: &mut Value) -> Result<(), Error> {
+    upgrade(version, |m| m.upgrade_db_item(value))?;
+    Ok(())
+}
+
+//fi json_of_yaml
+/// Convert a YAML value to JSON; a YAML tag (as used for an enum
+/// variant) becomes a dictionary with the tag as its single key, as
+/// JSON has it
+fn json_of_yaml(value: serde_yaml::Value) -> Result<Value, Error> {
+    use serde_yaml::Value as Yaml;
+    match value {
+        Yaml::Tagged(tagged) => {
+            let tag = tagged.tag.to_string();
+            let mut map = serde_json::Map::new();
+            map.insert(
+                tag.trim_start_matches('!').to_string(),
+                json_of_yaml(tagged.value)?,
+            );
+            Ok(Value::Object(map))
+        }
+        Yaml::Sequence(seq) => Ok(Value::Array(
+            seq.into_iter()
+                .map(json_of_yaml)
+                .collect::<Result<_, _>>()?,
+        )),
+        Yaml::Mapping(mapping) => {
+            let mut map = serde_json::Map::new();
+            for (k, v) in mapping {
+                let k = match k {
+                    Yaml::String(s) => s,
+                    Yaml::Number(n) => n.to_string(),
+                    Yaml::Bool(b) => b.to_string(),
+                    _ => {
+                        return Err(Error::Deserialization(format!(
+                            "unsupported dictionary key {k:?}"
+                        )))
+                    }
+                };
+                map.insert(k, json_of_yaml(v)?);
+            }
+            Ok(Value::Object(map))
+        }
+        value => Ok(serde_json::to_value(value)?),
+    }
+}
@@ -0,0 +1,475 @@
+//a Documentation
+//! An sqlite3 store for a Database
+//!
+//! The store has an 'items' table that holds every DbItem (by DbId)
+//! as its JSON serialization; this is what a Database is loaded from.
+//!
+//! Each DbItemType then has its own table, keyed by the DbId of the
+//! item, that holds the principal fields of the item as columns; the
+//! DbIds that an item refers to are foreign keys, so that the store
+//! can be queried (and checked) with other sqlite3 tools. The
+//! transactions that a bank transaction or invoice includes are
+//! held in the 'item_transactions' table.
+//!
+//! The lists of transactions that accounts, funds and related parties
+//! keep are back-references, and are rebuilt when the database is
+//! loaded; they are not held as foreign keys.
+//!
+//! Writing a Database to a store only writes the items that have
+//! changed since the store was last written, and removes those that
+//! are no longer in the database.
+//!
+//! The schema version of the items is held as the sqlite3
+//! 'user_version' of the store; items from an older version are
+//! upgraded when the store is loaded, and rewritten at the next
+//! write. The 'header' table holds the other fields of a database
+//! file's header, such as the last journal operation included in the
+//! store.
+
+//a Imports
+use std::collections::HashMap;
+use std::path::Path;
+
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, ToSql, Transaction};
+
+use crate::indexed_vec::Idx;
+use crate::schema::upgrade_db_item;
+use crate::{Database, Date, DbId, DbItem, DbItemType, Error, SCHEMA_VERSION};
+
+//a Schema
+//ci SCHEMA
+/// The tables of the store; foreign keys are deferred to the end of
+/// a write, as items are written in DbId order
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS header (
+    name TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS items (
+    db_id INTEGER PRIMARY KEY,
+    itype TEXT NOT NULL,
+    value TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS accounts (
+    db_id INTEGER PRIMARY KEY REFERENCES items(db_id) ON DELETE CASCADE,
+    org TEXT NOT NULL,
+    name TEXT NOT NULL,
+    account_desc TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS funds (
+    db_id INTEGER PRIMARY KEY REFERENCES items(db_id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    description TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS related_parties (
+    db_id INTEGER PRIMARY KEY REFERENCES items(db_id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    rp_id INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS invoices (
+    db_id INTEGER PRIMARY KEY REFERENCES items(db_id) ON DELETE CASCADE,
+    reason TEXT NOT NULL,
+    supplier_id INTEGER
+        REFERENCES related_parties(db_id) DEFERRABLE INITIALLY DEFERRED,
+    amount INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS transactions (
+    db_id INTEGER PRIMARY KEY REFERENCES items(db_id) ON DELETE CASCADE,
+    date TEXT,
+    debit_id INTEGER REFERENCES items(db_id) DEFERRABLE INITIALLY DEFERRED,
+    credit_id INTEGER REFERENCES items(db_id) DEFERRABLE INITIALLY DEFERRED,
+    amount INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS bank_transactions (
+    db_id INTEGER PRIMARY KEY REFERENCES items(db_id) ON DELETE CASCADE,
+    date TEXT,
+    account_id INTEGER
+        REFERENCES accounts(db_id) DEFERRABLE INITIALLY DEFERRED,
+    related_party INTEGER
+        REFERENCES related_parties(db_id) DEFERRABLE INITIALLY DEFERRED,
+    description TEXT NOT NULL,
+    balance_delta INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS item_transactions (
+    item_id INTEGER NOT NULL REFERENCES items(db_id) ON DELETE CASCADE,
+    transaction_id INTEGER NOT NULL
+        REFERENCES transactions(db_id) DEFERRABLE INITIALLY DEFERRED
+);
+CREATE INDEX IF NOT EXISTS item_transactions_item ON item_transactions(item_id);
+";
+
+//fi itype_name
+/// The name of a DbItemType as held in the 'items' table, which
+/// parses back as the DbItemType
+fn itype_name(itype: DbItemType) -> &'static str {
+    match itype {
+        DbItemType::Account => "account",
+        DbItemType::BankTransaction => "bank_transaction",
+        DbItemType::Fund => "fund",
+        DbItemType::Invoice => "invoice",
+        DbItemType::RelatedParty => "related_party",
+        DbItemType::Transaction => "transaction",
+    }
+}
+
+//fi sql_id
+/// A DbId as an sqlite3 integer, or NULL if it is none
+fn sql_id(db_id: DbId) -> Option<i64> {
+    (!db_id.is_none()).then(|| db_id.index() as i64)
+}
+
+//fi sql_date
+/// A Date as an sqlite3 ISO 8601 date, or NULL if it is none
+fn sql_date(date: Date) -> Option<String> {
+    (!date.is_none()).then(|| {
+        let (d, m, y) = date.dmy();
+        format!("{y:04}-{m:02}-{d:02}")
+    })
+}
+
+//fi upsert
+/// Insert a row into a table keyed by its first column (such as
+/// 'db_id'), or update the row if it is already present
+///
+/// The row is updated in place so that foreign keys that refer to it
+/// are not disturbed
+fn upsert(
+    tx: &Transaction,
+    table: &str,
+    columns: &[&str],
+    values: &[&dyn ToSql],
+) -> Result<(), Error> {
+    let placeholders: Vec<String> = (1..=columns.len()).map(|n| format!("?{n}")).collect();
+    let updates: Vec<String> = columns[1..]
+        .iter()
+        .map(|c| format!("{c} = excluded.{c}"))
+        .collect();
+    let sql = format!(
+        "INSERT INTO {table} ({}) VALUES ({}) ON CONFLICT({}) DO UPDATE SET {}",
+        columns.join(", "),
+        placeholders.join(", "),
+        columns[0],
+        updates.join(", ")
+    );
+    tx.prepare_cached(&sql)?.execute(values)?;
+    Ok(())
+}
+
+//a SqliteWriteReport
+//tp SqliteWriteReport
+/// The number of items written to a store by a write of a database
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SqliteWriteReport {
+    added: usize,
+    updated: usize,
+    removed: usize,
+}
+
+//ip SqliteWriteReport
+impl SqliteWriteReport {
+    //ap num_added
+    pub fn num_added(&self) -> usize {
+        self.added
+    }
+
+    //ap num_updated
+    pub fn num_updated(&self) -> usize {
+        self.updated
+    }
+
+    //ap num_removed
+    pub fn num_removed(&self) -> usize {
+        self.removed
+    }
+}
+
+//ip Display for SqliteWriteReport
+impl std::fmt::Display for SqliteWriteReport {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(
+            fmt,
+            "{} added, {} updated, {} removed",
+            self.added, self.updated, self.removed
+        )
+    }
+}
+
+//a SqliteStore
+//tp SqliteStore
+/// An sqlite3 database file that a Database is loaded from and
+/// written to
+pub struct SqliteStore {
+    connection: Connection,
+}
+
+//ip SqliteStore
+impl SqliteStore {
+    //cp open
+    /// Open an existing sqlite3 database file; it is an error if the
+    /// file does not exist, so that a mistyped filename is not taken
+    /// to be an empty database
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("sqlite3 database {} not found", path.display()),
+            )
+            .into());
+        }
+        let flags = OpenFlags::SQLITE_OPEN_READ_WRITE
+            | OpenFlags::SQLITE_OPEN_URI
+            | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+        Self::of_connection(Connection::open_with_flags(path, flags)?)
+    }
+
+    //cp create
+    /// Open an sqlite3 database file to write to, creating it (and its
+    /// tables) if required
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::of_connection(Connection::open(path)?)
+    }
+
+    //cp open_in_memory
+    /// Open an sqlite3 database held only in memory
+    pub fn open_in_memory() -> Result<Self, Error> {
+        Self::of_connection(Connection::open_in_memory()?)
+    }
+
+    //ci of_connection
+    fn of_connection(connection: Connection) -> Result<Self, Error> {
+        connection.pragma_update(None, "foreign_keys", true)?;
+        connection.execute_batch(SCHEMA)?;
+        Ok(Self { connection })
+    }
+
+    //ap schema_version
+    /// The schema version of the items in the store
+    pub fn schema_version(&self) -> Result<usize, Error> {
+        let version: i64 = self
+            .connection
+            .pragma_query_value(None, "user_version", |row| row.get(0))?;
+        Ok(version as usize)
+    }
+
+    //ap journal_op
+    /// The last journal operation included in the store
+    pub fn journal_op(&self) -> Result<usize, Error> {
+        let op: Option<String> = self
+            .connection
+            .query_row(
+                "SELECT value FROM header WHERE name = 'journal_op'",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let Some(op) = op else {
+            return Ok(0);
+        };
+        op.parse()
+            .map_err(|_| Error::Deserialization(format!("bad journal_op '{op}' in store header")))
+    }
+
+    //mp load
+    /// Load a Database from the store; the items keep the DbIds they
+    /// have in the store, so that later writes update them
+    pub fn load(&self) -> Result<Database, Error> {
+        let version = self.schema_version()?;
+        let mut stmt = self
+            .connection
+            .prepare("SELECT value FROM items ORDER BY db_id")?;
+        let mut items = vec![];
+        for value in stmt.query_map([], |row| row.get::<_, String>(0))? {
+            let mut value: serde_json::Value = serde_json::from_str(&value?)?;
+            upgrade_db_item(version, &mut value)?;
+            let item: DbItem =
+                serde_json::from_value(value).map_err(|e| Error::Deserialization(e.to_string()))?;
+            items.push(item);
+        }
+        let db = Database::try_from_items(items, false)?;
+        db.set_journal_op(self.journal_op()?);
+        Ok(db)
+    }
+
+    //mp write
+    /// Write a Database to the store, adding and updating only those
+    /// items that have changed, and removing those that are no longer
+    /// in the database
+    ///
+    /// The write is a single sqlite3 transaction, so the store is
+    /// unchanged if it fails (for example, if an item refers to one
+    /// that does not exist)
+    pub fn write(&mut self, db: &Database) -> Result<SqliteWriteReport, Error> {
+        let tx = self.connection.transaction()?;
+        let mut stored: HashMap<i64, (String, String)> = HashMap::new();
+        {
+            let mut stmt = tx.prepare("SELECT db_id, itype, value FROM items")?;
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, (row.get(1)?, row.get(2)?))))?;
+            for row in rows {
+                let (db_id, itype_value) = row?;
+                stored.insert(db_id, itype_value);
+            }
+        }
+
+        let mut report = SqliteWriteReport::default();
+        for db_id in db.db_ids() {
+            let item = db.get(db_id).unwrap();
+            let key = db_id.index() as i64;
+            let itype = itype_name(item.itype());
+            let value = item.as_json(false)?;
+            match stored.remove(&key) {
+                Some((s_itype, s_value)) if s_itype == itype && s_value == value => {
+                    continue;
+                }
+                Some((s_itype, _)) => {
+                    // An item whose DbId has been reused for a
+                    // different type of item has to be removed from the
+                    // table for its old type
+                    if s_itype != itype {
+                        tx.execute("DELETE FROM items WHERE db_id = ?1", params![key])?;
+                    }
+                    report.updated += 1;
+                }
+                None => {
+                    report.added += 1;
+                }
+            }
+            upsert(
+                &tx,
+                "items",
+                &["db_id", "itype", "value"],
+                params![key, itype, value],
+            )?;
+            Self::write_item_columns(&tx, key, &item)?;
+        }
+
+        for key in stored.keys() {
+            tx.execute("DELETE FROM items WHERE db_id = ?1", params![key])?;
+            report.removed += 1;
+        }
+        upsert(
+            &tx,
+            "header",
+            &["name", "value"],
+            params!["journal_op", db.journal_op().to_string()],
+        )?;
+        tx.pragma_update(None, "user_version", SCHEMA_VERSION as i64)?;
+        tx.commit()?;
+        Ok(report)
+    }
+
+    //mi write_item_columns
+    /// Write the row of the table for the type of an item, and the
+    /// transactions it includes
+    fn write_item_columns(tx: &Transaction, key: i64, item: &DbItem) -> Result<(), Error> {
+        let mut transactions = vec![];
+        match item.itype() {
+            DbItemType::Account => {
+                let db_account = item.account().unwrap();
+                let account = db_account.inner();
+                upsert(
+                    tx,
+                    "accounts",
+                    &["db_id", "org", "name", "account_desc"],
+                    params![
+                        key,
+                        account.org(),
+                        account.name(),
+                        account.desc().to_string()
+                    ],
+                )?;
+            }
+            DbItemType::Fund => {
+                let db_fund = item.fund().unwrap();
+                let fund = db_fund.inner();
+                upsert(
+                    tx,
+                    "funds",
+                    &["db_id", "name", "description"],
+                    params![key, fund.name(), fund.desc()],
+                )?;
+            }
+            DbItemType::RelatedParty => {
+                let db_rp = item.related_party().unwrap();
+                let rp = db_rp.inner();
+                upsert(
+                    tx,
+                    "related_parties",
+                    &["db_id", "name", "rp_id"],
+                    params![key, rp.name(), rp.rp_id() as i64],
+                )?;
+            }
+            DbItemType::Invoice => {
+                let db_invoice = item.invoice().unwrap();
+                let invoice = db_invoice.inner();
+                upsert(
+                    tx,
+                    "invoices",
+                    &["db_id", "reason", "supplier_id", "amount"],
+                    params![
+                        key,
+                        invoice.reason(),
+                        sql_id(invoice.supplier_id()),
+                        invoice.amount().value() as i64
+                    ],
+                )?;
+                transactions = invoice.transactions();
+            }
+            DbItemType::Transaction => {
+                let db_t = item.transaction().unwrap();
+                let t = db_t.inner();
+                let (debit_id, credit_id) = t.db_ids();
+                upsert(
+                    tx,
+                    "transactions",
+                    &["db_id", "date", "debit_id", "credit_id", "amount"],
+                    params![
+                        key,
+                        sql_date(t.date()),
+                        sql_id(debit_id),
+                        sql_id(credit_id),
+                        t.amount().value() as i64
+                    ],
+                )?;
+            }
+            DbItemType::BankTransaction => {
+                let db_bt = item.bank_transaction().unwrap();
+                let bt = db_bt.inner();
+                upsert(
+                    tx,
+                    "bank_transactions",
+                    &[
+                        "db_id",
+                        "date",
+                        "account_id",
+                        "related_party",
+                        "description",
+                        "balance_delta",
+                    ],
+                    params![
+                        key,
+                        sql_date(bt.date()),
+                        sql_id(bt.account_id()),
+                        sql_id(bt.related_party()),
+                        bt.description(),
+                        bt.balance_delta().value() as i64
+                    ],
+                )?;
+                transactions = bt.transactions().to_vec();
+            }
+        }
+        tx.execute(
+            "DELETE FROM item_transactions WHERE item_id = ?1",
+            params![key],
+        )?;
+        let mut stmt = tx.prepare_cached(
+            "INSERT INTO item_transactions (item_id, transaction_id) VALUES (?1, ?2)",
+        )?;
+        for t_id in transactions {
+            stmt.execute(params![key, t_id.index() as i64])?;
+        }
+        Ok(())
+    }
+
+    //zz All done
+}
@@ -1,9 +1,15 @@
 //a Imports
 use std::cell::RefCell;
+use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize, Serializer};
 
-use crate::{Amount, Database, Date, DbId};
+use crate::dated_index::DatedIndex;
+use crate::indexed_vec::Idx;
+use crate::{
+    Amount, Currency, CurrencyAmount, Database, DatabaseRebuild, Date, DateRange, DbId, Error,
+    ExchangeRate,
+};
 
 //a TransactionType
 //tp TransactionType
@@ -73,6 +79,13 @@ pub struct Transaction {
     amount: Amount,
     /// Notes
     notes: Vec<String>,
+    /// Currency of the amount
+    #[serde(default)]
+    currency: Currency,
+    /// Exchange rate used if the debit or credit side is held in a
+    /// different currency to the amount
+    #[serde(default)]
+    exchange_rate: Option<ExchangeRate>,
 }
 
 //ip Display for Transaction
@@ -103,9 +116,23 @@ impl Transaction {
             debit_id,
             credit_id,
             notes: vec![],
+            currency: Currency::default(),
+            exchange_rate: None,
         }
     }
 
+    //cp with_currency
+    pub fn with_currency(mut self, currency: Currency) -> Self {
+        self.currency = currency;
+        self
+    }
+
+    //cp with_exchange_rate
+    pub fn with_exchange_rate(mut self, exchange_rate: ExchangeRate) -> Self {
+        self.exchange_rate = Some(exchange_rate);
+        self
+    }
+
     //cp new_payment
     pub fn new_payment(date: Date, amount: Amount, from_fund_id: DbId, to_id: DbId) -> Self {
         Self::new(date, TransactionType::ToRp, amount, from_fund_id, to_id)
@@ -165,6 +192,23 @@ impl Transaction {
         self.date
     }
 
+    //mp set_date
+    pub fn set_date(&mut self, date: Date) {
+        self.date = date;
+    }
+
+    //mp set_amount
+    pub fn set_amount(&mut self, amount: Amount) {
+        self.amount = amount;
+    }
+
+    //mp set_db_ids
+    /// Set the debit and credit sides of the transaction
+    pub fn set_db_ids(&mut self, debit_id: DbId, credit_id: DbId) {
+        self.debit_id = debit_id;
+        self.credit_id = credit_id;
+    }
+
     //ap amount
     pub fn amount(&self) -> Amount {
         self.amount
@@ -175,6 +219,70 @@ impl Transaction {
         self.ttype
     }
 
+    //ap currency
+    pub fn currency(&self) -> Currency {
+        self.currency
+    }
+
+    //ap exchange_rate
+    pub fn exchange_rate(&self) -> Option<ExchangeRate> {
+        self.exchange_rate
+    }
+
+    //mp convert
+    /// Convert an amount into a currency, using the exchange rate of
+    /// the transaction if the currencies differ
+    pub fn convert(&self, amount: CurrencyAmount, to: Currency) -> Result<CurrencyAmount, Error> {
+        if amount.currency() == to {
+            return Ok(amount);
+        }
+        match self.exchange_rate {
+            Some(rate)
+                if (rate.from() == amount.currency() && rate.to() == to)
+                    || (rate.to() == amount.currency() && rate.from() == to) =>
+            {
+                rate.convert(amount)
+            }
+            _ => Err(Error::CurrencyMismatch(amount.currency(), to)),
+        }
+    }
+
+    //mp check_currencies
+    /// Check that the funds on either side of the transaction are in
+    /// the currency of the transaction, or that the transaction has an
+    /// exchange rate to convert to them
+    pub fn check_currencies(&self, database: &Database) -> Result<(), Error> {
+        for db_id in [self.debit_id, self.credit_id] {
+            if let Some(db_f) = database.get_fund(db_id) {
+                let amount = CurrencyAmount::new(self.currency, self.amount);
+                self.convert(amount, db_f.inner().currency())?;
+            }
+        }
+        Ok(())
+    }
+
+    //mp check_db_ids
+    /// Check that the debit and credit sides of the transaction are
+    /// the funds and related parties required by its type
+    pub fn check_db_ids(&self, database: &Database) -> Result<(), Error> {
+        let is_fund = |db_id| database.get_fund(db_id).is_some();
+        let is_rp = |db_id| database.get_related_party(db_id).is_some();
+        let okay = match self.ttype {
+            TransactionType::FromRp => is_rp(self.debit_id) && is_fund(self.credit_id),
+            TransactionType::ToRp => is_fund(self.debit_id) && is_rp(self.credit_id),
+            _ => is_fund(self.debit_id) && is_fund(self.credit_id),
+        };
+        if okay {
+            Ok(())
+        } else {
+            Err(format!(
+                "{} transaction must have debit {} and credit {} that are a fund and related party as required",
+                self.ttype, self.debit_id, self.credit_id
+            )
+            .into())
+        }
+    }
+
     //ap db_ids
     pub fn db_ids(&self) -> (DbId, DbId) {
         (self.debit_id, self.credit_id)
@@ -195,6 +303,17 @@ impl Transaction {
         self.notes.push(s.into());
     }
 
+    //mp rebuild
+    pub fn rebuild(&mut self, database_rebuild: &DatabaseRebuild) -> Result<(), Error> {
+        if !self.debit_id.is_none() {
+            self.debit_id = database_rebuild.get_new_id("Transaction debit", self.debit_id)?;
+        }
+        if !self.credit_id.is_none() {
+            self.credit_id = database_rebuild.get_new_id("Transaction credit", self.credit_id)?;
+        }
+        Ok(())
+    }
+
     //mp show_one_line
     pub fn show_one_line(&self, db: &Database) -> String {
         format!(
@@ -241,6 +360,22 @@ crate::make_db_item!(DbTransaction, Transaction, show_name);
 pub struct DbTransactionsState {
     /// All the transactions
     array: Vec<DbTransaction>,
+    /// The transactions by DbId
+    by_id: HashMap<DbId, DbTransaction>,
+    /// Index by date, and by the funds and related parties debited
+    /// and credited
+    index: DatedIndex,
+}
+
+//ip DbTransactionsState
+impl DbTransactionsState {
+    //mi index_transaction
+    fn index_transaction(&mut self, db_transaction: &DbTransaction) {
+        let (debit_id, credit_id) = db_transaction.inner().db_ids();
+        let date = db_transaction.inner().date();
+        self.index
+            .insert(db_transaction.id(), date, vec![debit_id, credit_id]);
+    }
 }
 
 //tp DbTransactions
@@ -265,13 +400,83 @@ impl DbTransactions {
         self.state.borrow().array.iter().map(|db| db.id()).collect()
     }
 
+    //mp rebuild_add_transaction
+    pub fn rebuild_add_transaction(
+        &self,
+        db_transaction: DbTransaction,
+        database_rebuild: &DatabaseRebuild,
+    ) -> Result<(), Error> {
+        // Rebuild first so that it is indexed by its new DbIds
+        db_transaction.inner_mut().rebuild(database_rebuild)?;
+        if !self.add_transaction(db_transaction.clone()) {
+            return Err(format!(
+                "Failed to rebuild transaction {}, already present?",
+                db_transaction.inner().show_name(),
+            )
+            .into());
+        }
+        Ok(())
+    }
+
     //mp add_transaction
     pub fn add_transaction(&self, db_transaction: DbTransaction) -> bool {
         let mut state = self.state.borrow_mut();
-        state.array.push(db_transaction.clone());
+        if state.by_id.contains_key(&db_transaction.id()) {
+            return false;
+        }
+        state.index_transaction(&db_transaction);
+        state
+            .by_id
+            .insert(db_transaction.id(), db_transaction.clone());
+        state.array.push(db_transaction);
+        true
+    }
+
+    //mp reindex_transaction
+    /// Reindex a transaction after its date, debit or credit have
+    /// changed, returning true if it is present
+    pub fn reindex_transaction(&self, db_id: DbId) -> bool {
+        let mut state = self.state.borrow_mut();
+        let Some(db_transaction) = state.by_id.get(&db_id).cloned() else {
+            return false;
+        };
+        state.index_transaction(&db_transaction);
         true
     }
 
+    //mp remove_transaction
+    /// Remove a transaction, returning true if it was present
+    pub fn remove_transaction(&self, db_id: DbId) -> bool {
+        let mut state = self.state.borrow_mut();
+        if state.by_id.remove(&db_id).is_none() {
+            return false;
+        }
+        state.index.remove(db_id);
+        if let Some(n) = state.array.iter().position(|t| t.id() == db_id) {
+            state.array.remove(n);
+        }
+        true
+    }
+
+    //mp select
+    /// The transactions in a date range (if it is not empty) that
+    /// debit or credit all of the given funds and related parties,
+    /// in DbId order; all the transactions (in the order they were
+    /// added) if neither restricts them
+    ///
+    /// This borrows the transactions just once, and uses binary
+    /// search of the indexes rather than a scan of every transaction
+    pub fn select(&self, date_range: DateRange, references: &[DbId]) -> Vec<DbTransaction> {
+        let state = self.state.borrow();
+        match state.index.select(date_range, references) {
+            Some(db_ids) => db_ids
+                .iter()
+                .filter_map(|db_id| state.by_id.get(db_id).cloned())
+                .collect(),
+            None => state.array.clone(),
+        }
+    }
+
     //zz All done
 }
 
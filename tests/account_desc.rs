@@ -0,0 +1,56 @@
+use rust_accounts::AccountDesc;
+
+//a Tests
+#[test]
+fn test_round_trip() -> Result<(), Box<dyn std::error::Error>> {
+    for s in [
+        "<None>",
+        "30-91-74:02344812",
+        "iban:GB82WEST12345698765432",
+        "bic:NWBKGB2L:12345678",
+        "card:************1234",
+        "card:492181******1234",
+        "wallet:paypal:someone@example.com",
+        "cash:Petty cash",
+    ] {
+        let desc = s.parse::<AccountDesc>()?;
+        assert_eq!(desc.to_string(), s);
+        assert_eq!(desc.to_string().parse::<AccountDesc>()?, desc);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_normalize() -> Result<(), Box<dyn std::error::Error>> {
+    assert_eq!(
+        AccountDesc::iban("gb82 west 1234 5698 7654 32")?.to_string(),
+        "iban:GB82WEST12345698765432"
+    );
+    assert_eq!(
+        AccountDesc::card("XXXX XXXX XXXX 1234")?.to_string(),
+        "card:************1234"
+    );
+    assert_eq!(
+        "wallet:PayPal:M123".parse::<AccountDesc>()?,
+        AccountDesc::wallet("paypal", "M123")?
+    );
+    Ok(())
+}
+
+#[test]
+fn test_invalid() {
+    for s in [
+        "iban:GB83WEST12345698765432",
+        "iban:GB82",
+        "bic:NWBK:1234",
+        "bic:NWBKGB2L:",
+        "card:4921811234561234",
+        "card:****1234",
+        "wallet::someone",
+        "cash:",
+        "30-91-7:02344812",
+        "bank:1234",
+    ] {
+        assert!(s.parse::<AccountDesc>().is_err(), "'{s}' should not parse");
+    }
+}
@@ -0,0 +1,56 @@
+use rust_accounts::{Amount, AmountRounding};
+
+//a Tests
+#[test]
+fn test_parse() -> Result<(), Box<dyn std::error::Error>> {
+    assert_eq!("20.00".parse::<Amount>()?.value(), 2000);
+    assert_eq!("12004.61".parse::<Amount>()?.value(), 1200461);
+    assert_eq!("-3.5".parse::<Amount>()?.value(), -350);
+    assert_eq!("+7".parse::<Amount>()?.value(), 700);
+    assert_eq!(".05".parse::<Amount>()?.value(), 5);
+    assert_eq!("£1,234,567.89".parse::<Amount>()?.value(), 123456789);
+    assert_eq!("-£12.34".parse::<Amount>()?.value(), -1234);
+    assert_eq!("£-12.34".parse::<Amount>()?.value(), -1234);
+    assert_eq!("(12.34)".parse::<Amount>()?.value(), -1234);
+    assert_eq!("12.34-".parse::<Amount>()?.value(), -1234);
+    assert_eq!("GBP 10.00".parse::<Amount>()?.value(), 1000);
+    assert_eq!("10.00 EUR".parse::<Amount>()?.value(), 1000);
+    // Beyond the precision of an f32
+    assert_eq!("83886.09".parse::<Amount>()?.value(), 8388609);
+    assert_eq!("123456789.01".parse::<Amount>()?.value(), 12345678901);
+    Ok(())
+}
+
+#[test]
+fn test_parse_errors() {
+    for s in [
+        "", "abc", "1.2.3", "1,23.00", "12,,000", "1.2a", "--1", "£", "ABC10", "10 XYZ", "1234,567",
+    ] {
+        assert!(s.parse::<Amount>().is_err(), "'{s}' should not parse");
+    }
+}
+
+#[test]
+fn test_rounding() -> Result<(), Box<dyn std::error::Error>> {
+    let p = |s, r| Amount::parse_with_rounding(s, r).map(|a| a.value());
+    assert_eq!(p("0.125", AmountRounding::HalfAwayFromZero)?, 13);
+    assert_eq!(p("-0.125", AmountRounding::HalfAwayFromZero)?, -13);
+    assert_eq!(p("0.125", AmountRounding::HalfEven)?, 12);
+    assert_eq!(p("0.135", AmountRounding::HalfEven)?, 14);
+    assert_eq!(p("0.1251", AmountRounding::HalfEven)?, 13);
+    assert_eq!(p("0.129", AmountRounding::Truncate)?, 12);
+    assert_eq!(p("0.1200", AmountRounding::Exact)?, 12);
+    assert!(p("0.121", AmountRounding::Exact).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_display() -> Result<(), Box<dyn std::error::Error>> {
+    assert_eq!(
+        "123456789.01".parse::<Amount>()?.to_string().trim(),
+        "123456789.01"
+    );
+    assert_eq!("-0.05".parse::<Amount>()?.to_string().trim(), "-0.05");
+    assert_eq!(format!("{}", "1.5".parse::<Amount>()?), "      1.50");
+    Ok(())
+}
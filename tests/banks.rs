@@ -0,0 +1,139 @@
+use rust_accounts::banks;
+use rust_accounts::{AccountDesc, Amount, BankTransactionType};
+
+//a Tests
+#[test]
+fn test_natwest_newest_first() -> Result<(), Box<dyn std::error::Error>> {
+    let csv = "Date, Type, Description, Value, Balance, Account Name, Account Number
+29/08/2024,D/D,'ELECTRICITY,-30.00,90.00,'CURRENT,'601234-12345678
+28/08/2024,BAC,'NAME REASON,20.00,120.00,'CURRENT,'601234-12345678
+";
+    let t = banks::natwest::read_transactions_csv(csv.as_bytes(), &AccountDesc::None, None)?;
+    assert_eq!(t.len(), 2);
+    assert_eq!(t[0].description(), "NAME REASON");
+    assert_eq!(t[1].balance().value(), 9000);
+    assert_eq!(t[0].account_desc(), &AccountDesc::uk(601234, 12345678));
+
+    // A sort code that is not six digits is an error
+    let bad = csv.replace("'601234-", "'1€34-");
+    assert!(
+        banks::natwest::read_transactions_csv(bad.as_bytes(), &AccountDesc::None, None).is_err()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_monzo_opening_balance() -> Result<(), Box<dyn std::error::Error>> {
+    let csv = "Transaction ID,Date,Time,Type,Name,Emoji,Category,Amount,Currency,Local amount,Local currency,Notes and #tags,Address,Receipt,Description,Category split,Money Out,Money In
+tx_1,28/08/2024,10:15:00,Faster payment,NAME,,income,20.00,GBP,20.00,GBP,,,,REASON,,,20.00
+tx_2,29/08/2024,11:00:00,Card payment,SHOP,,shopping,-5.50,GBP,-5.50,GBP,,,,SHOP,,5.50,
+";
+    let desc = AccountDesc::wallet("monzo", "main")?;
+    let opening: Amount = "100.00".parse()?;
+    let t = banks::monzo::read_transactions_csv(csv.as_bytes(), &desc, Some(opening))?;
+    assert_eq!(t[0].balance().value(), 12000);
+    assert_eq!(t[1].balance().value(), 11450);
+    assert_eq!(t[0].description(), "NAME REASON");
+    assert!(banks::monzo::read_transactions_csv(csv.as_bytes(), &desc, None).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_hsbc_no_header() -> Result<(), Box<dyn std::error::Error>> {
+    let csv = "29/08/2024,DD ELECTRICITY,\"-1,000.00\",500.00
+28/08/2024,CR NAME,20.00,1500.00
+";
+    let desc = AccountDesc::uk(401234, 11112222);
+    let t = banks::hsbc::read_transactions_csv(csv.as_bytes(), &desc, None)?;
+    assert_eq!(t[0].credit().value(), 2000);
+    assert_eq!(t[1].debit().value(), 100000);
+    Ok(())
+}
+
+#[test]
+fn test_nationwide_bad_chain() {
+    let csv = "\"Account Name:\",\"FlexAccount ****12345\"
+\"Account Balance:\",\"£100.00\"
+
+\"Date\",\"Transaction type\",\"Description\",\"Paid out\",\"Paid in\",\"Balance\"
+\"28 Aug 2024\",\"Bank credit NAME\",\"NAME REASON\",\"\",\"£20.00\",\"£120.00\"
+\"29 Aug 2024\",\"Direct debit\",\"ELECTRICITY\",\"£30.00\",\"\",\"£100.00\"
+";
+    let desc = AccountDesc::uk(70000, 12345);
+    assert!(banks::nationwide::read_transactions_csv(csv.as_bytes(), &desc, None).is_err());
+    let csv = csv.replace("£100.00\"\n", "£90.00\"\n");
+    let t = banks::nationwide::read_transactions_csv(csv.as_bytes(), &desc, None).unwrap();
+    assert_eq!(t.len(), 2);
+}
+
+#[test]
+fn test_mapping() -> Result<(), Box<dyn std::error::Error>> {
+    use rust_accounts::banks::mapping::CsvMapping;
+    use rust_accounts::FileType;
+    let mapping = r#"
+has_headers: false
+skip_lines: 1
+date: 0
+date_format: "%Y-%m-%d"
+description: [1, 2]
+debit: 3
+credit: 4
+balance: 5
+account_desc: "cash:Petty cash"
+"#;
+    let mapping = CsvMapping::from_str(mapping, FileType::Yaml)?;
+    let csv = "Exported by some bank
+2024-08-28,NAME,REASON,,20.00,120.00
+2024-08-29,SHOP,,-30.00,,90.00
+";
+    let t = mapping.read_transactions_csv(csv.as_bytes(), &AccountDesc::None, None)?;
+    assert_eq!(t.len(), 2);
+    assert_eq!(t[0].description(), "NAME REASON");
+    assert_eq!(t[1].debit().value(), 3000);
+    assert_eq!(t[1].account_desc(), &AccountDesc::cash_box("Petty cash")?);
+
+    let mapping = r#"{"date": "When", "amount": "Amount", "description": ["What"]}"#;
+    let mapping = CsvMapping::from_str(mapping, FileType::Json)?;
+    let csv = "When,What,Amount\n29/08/2024,B,-1.00\n28/08/2024,A,2.00\n";
+    let desc = AccountDesc::uk(112233, 1);
+    let t = mapping.read_transactions_csv(csv.as_bytes(), &desc, Some(0.into()))?;
+    assert_eq!(t[0].description(), "A");
+    assert_eq!(t[1].balance().value(), 100);
+
+    assert!(CsvMapping::from_str(r#"{"date": 0}"#, FileType::Json).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_transaction_types() -> Result<(), Box<dyn std::error::Error>> {
+    assert_eq!(
+        BankTransactionType::parse("DD", true)?,
+        BankTransactionType::DirectDebit
+    );
+    assert_eq!(
+        BankTransactionType::parse("dd", false)?,
+        BankTransactionType::DirectDebitRefund
+    );
+    assert_eq!(
+        BankTransactionType::parse("CHQ 000123", true)?,
+        BankTransactionType::ChequeOut(Some(123))
+    );
+    assert_eq!(
+        BankTransactionType::parse("100456", false)?,
+        BankTransactionType::ChequeIn(Some(100456))
+    );
+    assert_eq!(
+        BankTransactionType::parse("DDX", true)?,
+        BankTransactionType::Unknown
+    );
+
+    let csv = "Date, Type, Description, Value, Balance, Account Name, Account Number
+29/08/2024,CHQ,'000123,-30.00,90.00,'CURRENT,'601234-12345678
+28/08/2024,POS,'SHOP,-5.00,120.00,'CURRENT,'601234-12345678
+";
+    let t = banks::natwest::read_transactions_csv(csv.as_bytes(), &AccountDesc::None, None)?;
+    assert_eq!(t[0].ttype(), BankTransactionType::CardPayment);
+    assert!(t[1].ttype().is_cheque());
+    assert!(t[1].ttype().is_outbound());
+    Ok(())
+}
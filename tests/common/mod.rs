@@ -0,0 +1,149 @@
+//a Documentation
+//! The database fixture and helpers shared by the integration tests
+//!
+//! Each test file uses only some of these
+#![allow(dead_code)]
+
+//a Imports
+use rust_accounts::{
+    Account, AccountDesc, Amount, BankTransaction, BankTransactionType, Database, Date, DbId,
+    Error, FileFormat, Fund, RelatedParty, RelatedPartyType, Transaction,
+};
+
+//a Useful functions
+//fp date
+/// The date of the fixture's transactions, 1st August 2024
+pub fn date() -> Date {
+    Date::of_dmy(1, 8, 2024).unwrap()
+}
+
+//fp desc
+/// The description of the fixture's current account
+pub fn desc() -> AccountDesc {
+    AccountDesc::uk(0x301234, 1234)
+}
+
+//fp add_account
+/// Add the current account to a database
+pub fn add_account(db: &Database) -> DbId {
+    db.add_account(Account::new("Bank".into(), "Current".into(), desc()))
+}
+
+//fp bank_transaction
+/// A payment of 20.00 into the current account on the fixture's date
+pub fn bank_transaction(description: &str, balance: isize) -> BankTransaction {
+    BankTransaction::new(
+        date(),
+        BankTransactionType::Fpi,
+        desc(),
+        description.into(),
+        Amount::default(),
+        Amount::from(2000),
+        Amount::from(balance),
+    )
+}
+
+//fp add_bank_transactions
+/// Add bank transactions to an account, returning their DbIds
+pub fn add_bank_transactions(
+    db: &Database,
+    account_id: DbId,
+    bank_transactions: Vec<BankTransaction>,
+) -> Vec<DbId> {
+    let db_account = db.get_account(account_id).unwrap();
+    let report = db_account
+        .inner_mut()
+        .add_transactions(db, account_id, bank_transactions)
+        .unwrap();
+    report.added().to_vec()
+}
+
+//fp add_income
+/// Add income from a related party to a fund
+pub fn add_income(
+    db: &Database,
+    date: Date,
+    amount: isize,
+    rp_id: DbId,
+    fund_id: DbId,
+) -> Result<DbId, Error> {
+    let (t_id, _) = db.add_transaction(Transaction::new_income(
+        date,
+        Amount::from(amount),
+        rp_id,
+        fund_id,
+    ))?;
+    Ok(t_id)
+}
+
+//fp to_json
+/// Write a database as JSON in the array layout, without a header
+pub fn to_json(db: &Database) -> Result<String, Box<dyn std::error::Error>> {
+    let mut s = serde_json::Serializer::pretty(vec![]);
+    db.serialize_as_array(&mut s)?;
+    Ok(String::from_utf8(s.into_inner())?)
+}
+
+//fp from_json
+/// Load a database from JSON in the array layout
+pub fn from_json(json: &str) -> Result<Database, Box<dyn std::error::Error>> {
+    let mut deserializer = serde_json::Deserializer::from_str(json);
+    Ok(Database::deserialize(&mut deserializer, FileFormat::Array)?)
+}
+
+//fp to_snapshot
+/// Write a database as JSON with its header, as a snapshot is saved
+pub fn to_snapshot(db: &Database) -> Result<String, Box<dyn std::error::Error>> {
+    let mut s = serde_json::Serializer::pretty(vec![]);
+    db.serialize(&mut s, FileFormat::Array)?;
+    Ok(String::from_utf8(s.into_inner())?)
+}
+
+//fp from_snapshot
+/// Load a database from JSON in either layout
+pub fn from_snapshot(json: &str) -> Result<Database, Box<dyn std::error::Error>> {
+    let mut deserializer = serde_json::Deserializer::from_str(json);
+    Ok(Database::deserialize_any_format(&mut deserializer)?)
+}
+
+//a Fixture
+//tp Fixture
+/// A database with the General fund and a member, Jane Smith
+pub struct Fixture {
+    pub db: Database,
+    pub fund_id: DbId,
+    pub rp_id: DbId,
+}
+
+//ip Default for Fixture
+impl Default for Fixture {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+//ip Fixture
+impl Fixture {
+    //cp new
+    /// Create the fixture, with the related party id of Jane Smith
+    pub fn new(rp_id: usize) -> Self {
+        let db = Database::default();
+        let fund_id = db.add_fund(Fund::new("General", "General fund"));
+        let rp_id = db.add_related_party(RelatedParty::new(
+            "Jane Smith".into(),
+            rp_id,
+            RelatedPartyType::Member,
+        ));
+        Self { db, fund_id, rp_id }
+    }
+
+    //mp add_account
+    /// Add the current account, and 20.00 of subs paid into it;
+    /// return their DbIds
+    pub fn add_account(&self) -> (DbId, DbId) {
+        let account_id = add_account(&self.db);
+        let bt_ids =
+            add_bank_transactions(&self.db, account_id, vec![bank_transaction("Subs", 2000)]);
+        (account_id, bt_ids[0])
+    }
+}
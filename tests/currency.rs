@@ -0,0 +1,44 @@
+use rust_accounts::{Amount, Currency, CurrencyAmount, ExchangeRate};
+
+//a Tests
+#[test]
+fn test_currency() -> Result<(), Box<dyn std::error::Error>> {
+    assert_eq!("eur".parse::<Currency>()?, Currency::EUR);
+    assert_eq!(Currency::default(), Currency::GBP);
+    assert_eq!(format!("{}", Currency::USD), "USD");
+    assert!("EURO".parse::<Currency>().is_err());
+    assert!("E1R".parse::<Currency>().is_err());
+    Ok(())
+}
+
+#[test]
+fn test_mixed_arithmetic() -> Result<(), Box<dyn std::error::Error>> {
+    let gbp = CurrencyAmount::new(Currency::GBP, "10.00".parse::<Amount>()?);
+    let eur = CurrencyAmount::new(Currency::EUR, "10.00".parse::<Amount>()?);
+    assert_eq!((gbp + gbp)?.amount().value(), 2000);
+    assert!((gbp + eur).is_err());
+    assert!((gbp - eur).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_exchange_rate() -> Result<(), Box<dyn std::error::Error>> {
+    let rate = ExchangeRate::new(Currency::EUR, Currency::GBP, "0.85")?;
+    let eur = CurrencyAmount::new(Currency::EUR, "10.01".parse::<Amount>()?);
+    let gbp = rate.convert(eur)?;
+    assert_eq!(gbp.currency(), Currency::GBP);
+    assert_eq!(gbp.amount().value(), 851);
+    let back = rate.convert(CurrencyAmount::new(Currency::GBP, 850.into()))?;
+    assert_eq!(back.currency(), Currency::EUR);
+    assert_eq!(back.amount().value(), 1000);
+    let usd = CurrencyAmount::new(Currency::USD, 100.into());
+    assert!(rate.convert(usd).is_err());
+    assert!(ExchangeRate::new(Currency::EUR, Currency::GBP, "0").is_err());
+    assert!(ExchangeRate::new(Currency::EUR, Currency::GBP, "1.0000001").is_err());
+    assert!(ExchangeRate::new(Currency::EUR, Currency::GBP, "-0.5").is_err());
+    let json = r#"{"from":"EUR","to":"GBP","rate_e6":0}"#;
+    assert!(serde_json::from_str::<ExchangeRate>(json).is_err());
+    let json = r#"{"from":"EUR","to":"GBP","rate_e6":850000}"#;
+    assert_eq!(serde_json::from_str::<ExchangeRate>(json)?, rate);
+    Ok(())
+}
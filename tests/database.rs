@@ -0,0 +1,842 @@
+use std::collections::HashMap;
+
+use rust_accounts::{
+    Aggregation, Amount, AmountSide, BankTransaction, BankTransactionType, Currency, Database,
+    Date, DateRange, DbId, DbItemType, DbQuery, Error, FileFormat, FileType, Fund, GroupBy, Idx,
+    QueryView, RelatedParty, RelatedPartyType, SortKey, SqliteStore, Transaction, TransactionType,
+    SCHEMA_VERSION,
+};
+
+mod common;
+use common::*;
+
+//a Tests
+#[test]
+fn test_remove_and_update() -> Result<(), Box<dyn std::error::Error>> {
+    let fixture = Fixture::default();
+    let (account_id, bt_id) = fixture.add_account();
+    let Fixture { db, fund_id, rp_id } = fixture;
+    let date = date();
+
+    let (t_id, okay) = db.add_transaction(Transaction::new_income(
+        date,
+        Amount::from(2000),
+        rp_id,
+        fund_id,
+    ))?;
+    assert!(okay);
+    // A transaction in another currency needs an exchange rate
+    let euro = Transaction::new_income(date, Amount::from(2000), rp_id, fund_id)
+        .with_currency(Currency::EUR);
+    assert!(matches!(
+        db.add_transaction(euro),
+        Err(Error::CurrencyMismatch(..))
+    ));
+    db.update_bank_transaction(bt_id, |bt| {
+        bt.set_related_party(rp_id);
+        bt.add_transaction(t_id);
+    })?;
+
+    // The fund has a transaction, so it is only removed with cascade
+    assert!(matches!(
+        db.remove(fund_id, false),
+        Err(Error::ItemReferenced(_, 1))
+    ));
+    let t_date = Date::of_dmy(2, 8, 2024)?;
+    db.update_transaction(t_id, |t| t.set_date(t_date))?;
+    let db_fund = db.get_fund(fund_id).unwrap();
+    assert_eq!(
+        db_fund.inner().transactions_in_range(Default::default()),
+        vec![t_id]
+    );
+
+    // Renaming the fund reindexes it; a clashing name is refused
+    let other_id = db.add_fund(Fund::new("Hall", "Hall upkeep"));
+    db.update_fund(fund_id, |f| f.set_name("Main"))?;
+    assert!(db.funds().get_fund("Main").is_some());
+    assert!(db.funds().get_fund("General").is_none());
+    assert!(db.update_fund(other_id, |f| f.set_name("Main")).is_err());
+    assert_eq!(db.get_fund(other_id).unwrap().inner().name(), "Hall");
+
+    let removed = db.remove(fund_id, true)?;
+    assert_eq!(removed, vec![t_id, fund_id]);
+    assert!(db.get_transaction(t_id).is_none());
+    assert!(db.funds().get_fund("Main").is_none());
+    let db_bt = db.get_bank_transaction(bt_id).unwrap();
+    assert!(db_bt.inner().transactions().is_empty());
+
+    // Removing the related party unlinks it from the bank transaction
+    db.remove(rp_id, true)?;
+    assert_eq!(db_bt.inner().related_party(), DbId::none());
+
+    let removed = db.remove(account_id, true)?;
+    assert_eq!(removed, vec![bt_id, account_id]);
+    assert!(db.accounts().get_account(&desc()).is_none());
+    assert!(db.bank_transactions().db_ids().is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_round_trip() -> Result<(), Box<dyn std::error::Error>> {
+    let Fixture {
+        db,
+        fund_id: general_id,
+        rp_id,
+    } = Fixture::default();
+    // Removed below, leaving a gap in the DbIds so that they are renumbered on load
+    let gap_id = db.add_fund(Fund::new("Spare", "Removed"));
+    let hall_id = db.add_fund(Fund::new("Hall", "Hall upkeep"));
+    add_income(&db, date(), 2000, rp_id, general_id)?;
+    db.add_transaction(Transaction::new(
+        date(),
+        TransactionType::FundTransfer,
+        Amount::from(500),
+        general_id,
+        hall_id,
+    ))?;
+    db.remove(gap_id, false)?;
+
+    let json = to_json(&db)?;
+    let loaded = from_json(&json)?;
+    assert_eq!(loaded.transactions().db_ids().len(), 2);
+
+    // Every item is loaded as it was, but renumbered to close the gap
+    let db_ids = db.db_ids();
+    assert_eq!(loaded.db_ids().len(), db_ids.len());
+    let renumbered: HashMap<DbId, DbId> = db_ids.iter().copied().zip(loaded.db_ids()).collect();
+    let renumber =
+        |db_ids: Vec<DbId>| -> Vec<DbId> { db_ids.iter().map(|d| renumbered[d]).collect() };
+    for (old_id, new_id) in &renumbered {
+        let (old, new) = (db.get(*old_id).unwrap(), loaded.get(*new_id).unwrap());
+        assert_eq!(old.itype(), new.itype());
+        assert_eq!(old.show_name(), new.show_name());
+        if let Some(old_t) = old.transaction() {
+            let (old_t, new_t) = (old_t.inner(), new.transaction().unwrap());
+            let new_t = new_t.inner();
+            assert_eq!(
+                (old_t.date(), old_t.amount(), old_t.ttype()),
+                (new_t.date(), new_t.amount(), new_t.ttype())
+            );
+            let (debit_id, credit_id) = old_t.db_ids();
+            assert_eq!(
+                new_t.db_ids(),
+                (renumbered[&debit_id], renumbered[&credit_id])
+            );
+        }
+        if let Some(old_f) = old.fund() {
+            let new_f = new.fund().unwrap();
+            assert_eq!(
+                renumber(old_f.inner().transactions_in_range(Default::default())),
+                new_f.inner().transactions_in_range(Default::default())
+            );
+        }
+        if let Some(old_rp) = old.related_party() {
+            let new_rp = new.related_party().unwrap();
+            assert_eq!(
+                renumber(old_rp.inner().transactions_in_range(Default::default())),
+                new_rp.inner().transactions_in_range(Default::default())
+            );
+        }
+    }
+    let db_f = loaded.funds().get_fund("Hall").unwrap();
+    let t_ids = db_f.inner().transactions_in_range(Default::default());
+    assert_eq!(t_ids.len(), 1);
+    let (debit_id, credit_id) = loaded.get_transaction(t_ids[0]).unwrap().inner().db_ids();
+    assert_eq!(loaded.show_name(debit_id), "General");
+    assert_eq!(credit_id, db_f.id());
+    let db_f = loaded.funds().get_fund("General").unwrap();
+    let balance = db_f
+        .inner()
+        .balance_in(&loaded, db_f.id(), Currency::default())?;
+    assert_eq!(balance.amount().value(), 1500);
+
+    // Once renumbered, the database survives write then load unchanged
+    let json = to_json(&loaded)?;
+    assert_eq!(to_json(&from_json(&json)?)?, json);
+    Ok(())
+}
+
+#[test]
+fn test_sqlite_store() -> Result<(), Box<dyn std::error::Error>> {
+    let Fixture {
+        db,
+        fund_id: general_id,
+        rp_id,
+    } = Fixture::default();
+    let gap_id = db.add_fund(Fund::new("Spare", "Removed"));
+    let hall_id = db.add_fund(Fund::new("Hall", "Hall upkeep"));
+    add_income(&db, date(), 2000, rp_id, general_id)?;
+    let (transfer_id, _) = db.add_transaction(Transaction::new(
+        date(),
+        TransactionType::FundTransfer,
+        Amount::from(500),
+        general_id,
+        hall_id,
+    ))?;
+    db.remove(gap_id, false)?;
+
+    db.set_journal_op(3);
+
+    let mut store = SqliteStore::open_in_memory()?;
+    let report = store.write(&db)?;
+    assert_eq!(report.num_added(), 5);
+
+    // Loaded items keep their DbIds, so an unchanged database writes nothing
+    let loaded = store.load()?;
+    assert_eq!(loaded.db_ids(), db.db_ids());
+    assert_eq!(loaded.journal_op(), 3);
+    assert_eq!(to_json(&loaded)?, to_json(&db)?);
+    let report = store.write(&loaded)?;
+    assert_eq!(report.to_string(), "0 added, 0 updated, 0 removed");
+
+    // Removing the transfer changes both funds
+    loaded.remove(transfer_id, false)?;
+    loaded.update_fund(hall_id, |f| f.set_name("Hall fund"))?;
+    let report = store.write(&loaded)?;
+    assert_eq!(report.num_updated(), 2);
+    assert_eq!(report.num_removed(), 1);
+
+    let reloaded = store.load()?;
+    assert_eq!(reloaded.show_name(hall_id), "Hall fund");
+    assert_eq!(reloaded.transactions().db_ids().len(), 1);
+    assert_eq!(to_json(&reloaded)?, to_json(&loaded)?);
+
+    // Only these extensions are sqlite3 stores
+    assert_eq!(FileType::from_filename("a.sqlite3")?, FileType::Sqlite);
+    assert!(FileType::from_filename("a.db").is_err());
+
+    // A store file is only created to be written to
+    let path = std::env::temp_dir().join(format!("store_{}.sqlite", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    assert!(SqliteStore::open(&path).is_err());
+    assert!(!path.exists());
+    SqliteStore::create(&path)?.write(&reloaded)?;
+    let loaded = SqliteStore::open(&path)?.load()?;
+    assert_eq!(to_json(&loaded)?, to_json(&reloaded)?);
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+#[test]
+fn test_dictionary_format() -> Result<(), Box<dyn std::error::Error>> {
+    // The related party id differs from its DbId
+    let Fixture { db, fund_id, rp_id } = Fixture::new(7);
+    add_income(&db, date(), 2000, rp_id, fund_id)?;
+
+    let mut s = serde_json::Serializer::pretty(vec![]);
+    db.serialize(&mut s, FileFormat::Dictionary)?;
+    let dict = String::from_utf8(s.into_inner())?;
+    let value: serde_json::Value = serde_json::from_str(&dict)?;
+    let items = &value["items"];
+    assert!(items["funds"]["General"].is_object());
+    assert!(items["related_parties"][rp_id.to_string()].is_object());
+    assert_eq!(items["transactions"].as_array().unwrap().len(), 1);
+
+    let mut deserializer = serde_json::Deserializer::from_str(&dict);
+    let loaded = Database::deserialize(&mut deserializer, FileFormat::Dictionary)?;
+    assert_eq!(to_json(&loaded)?, to_json(&db)?);
+
+    // Either layout is accepted when the format is not known
+    for json in [dict.clone(), to_json(&db)?] {
+        let mut deserializer = serde_json::Deserializer::from_str(&json);
+        let loaded = Database::deserialize_any_format(&mut deserializer)?;
+        assert_eq!(to_json(&loaded)?, to_json(&db)?);
+    }
+
+    // A hand-edited key must match the item
+    let bad = dict.replace("\"General\": {", "\"Hall\": {");
+    let mut deserializer = serde_json::Deserializer::from_str(&bad);
+    assert!(Database::deserialize_any_format(&mut deserializer).is_err());
+
+    // Related parties with the same rp_id can still be saved
+    db.add_related_party(RelatedParty::new(
+        "John Smith".into(),
+        7,
+        RelatedPartyType::Member,
+    ));
+    assert_eq!(db.check_integrity().len(), 1);
+    let mut s = serde_json::Serializer::pretty(vec![]);
+    db.serialize(&mut s, FileFormat::Dictionary)?;
+    let value: serde_json::Value = serde_json::from_slice(&s.into_inner())?;
+    assert_eq!(
+        value["items"]["related_parties"].as_object().unwrap().len(),
+        2
+    );
+    Ok(())
+}
+
+#[test]
+fn test_integrity() -> Result<(), Box<dyn std::error::Error>> {
+    let Fixture {
+        db,
+        fund_id: general_id,
+        rp_id,
+    } = Fixture::default();
+    let hall_id = db.add_fund(Fund::new("Hall", "Hall upkeep"));
+    let t_id = add_income(&db, date(), 2000, rp_id, general_id)?;
+    assert!(db.check_integrity().is_empty());
+
+    // Break the database in three ways
+    let other_id = db.add_related_party(RelatedParty::new(
+        "John Smith".into(),
+        1,
+        RelatedPartyType::Member,
+    ));
+    db.get_fund(hall_id)
+        .unwrap()
+        .inner_mut()
+        .add_alias("General");
+    db.get_fund(general_id)
+        .unwrap()
+        .inner_mut()
+        .remove_transaction(t_id);
+
+    let problems: Vec<_> = db
+        .check_integrity()
+        .into_iter()
+        .map(|p| (p.db_id, p.fix))
+        .collect();
+    assert_eq!(
+        problems,
+        vec![
+            (
+                t_id,
+                "reload the database, which rebuilds the transactions of funds and related parties"
+                    .to_string()
+            ),
+            (hall_id, "remove the alias 'General'".to_string()),
+            (other_id, "change its rp_id to 2".to_string()),
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_schema_versions() -> Result<(), Box<dyn std::error::Error>> {
+    let fixture = Fixture::new(7);
+    fixture.add_account();
+    let db = fixture.db;
+
+    // A version 0 file is just the items, and its bank transactions
+    // may not have a related party, transactions or FITID, nor its
+    // account a currency
+    let mut v0: serde_json::Value = serde_json::from_str(&to_json(&db)?)?;
+    let bt_value = v0[3]["value"]["BankTransaction"]["inner"]
+        .as_object_mut()
+        .unwrap();
+    assert!(bt_value.remove("related_party").is_some());
+    assert!(bt_value.remove("transactions").is_some());
+    assert!(bt_value.remove("fitid").is_some());
+    let account_value = v0[2]["value"]["Account"]["inner"].as_object_mut().unwrap();
+    assert!(account_value.remove("currency").is_some());
+    let v0 = v0.to_string();
+    let mut deserializer = serde_json::Deserializer::from_str(&v0);
+    assert_eq!(
+        Database::file_schema(&mut deserializer)?,
+        (0, FileFormat::Array)
+    );
+    let mut deserializer = serde_json::Deserializer::from_str(&v0);
+    let loaded = Database::deserialize_any_format(&mut deserializer)?;
+    assert_eq!(to_json(&loaded)?, to_json(&db)?);
+
+    // Files are written with a header, in either layout, and as YAML
+    for format in [FileFormat::Array, FileFormat::Dictionary] {
+        let mut w = vec![];
+        db.serialize(&mut serde_yaml::Serializer::new(&mut w), format)?;
+        let yaml = String::from_utf8(w)?;
+        let deserializer = serde_yaml::Deserializer::from_str(&yaml);
+        assert_eq!(
+            Database::file_schema(deserializer)?,
+            (SCHEMA_VERSION, format)
+        );
+        let deserializer = serde_yaml::Deserializer::from_str(&yaml);
+        let loaded = Database::deserialize_any_format(deserializer)?;
+        assert_eq!(to_json(&loaded)?, to_json(&db)?);
+    }
+
+    // A file from a later version cannot be loaded
+    let v99 = format!("{{\"schema_version\": 99, \"items\": {v0}}}");
+    let mut deserializer = serde_json::Deserializer::from_str(&v99);
+    assert!(matches!(
+        Database::deserialize_any_format(&mut deserializer),
+        Err(Error::SchemaVersionTooNew(99, SCHEMA_VERSION))
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_pre_schema_file() -> Result<(), Box<dyn std::error::Error>> {
+    // An array file written before schema versions, currencies and
+    // FITIDs were added
+    let json = include_str!("data/pre_schema_array.json");
+    let mut deserializer = serde_json::Deserializer::from_str(json);
+    assert_eq!(
+        Database::file_schema(&mut deserializer)?,
+        (0, FileFormat::Array)
+    );
+
+    let mut loaded = vec![];
+    let mut deserializer = serde_json::Deserializer::from_str(json);
+    loaded.push(Database::deserialize(&mut deserializer, FileFormat::Array)?);
+    let mut deserializer = serde_json::Deserializer::from_str(json);
+    loaded.push(Database::deserialize_from_array(&mut deserializer)?);
+    let mut deserializer = serde_json::Deserializer::from_str(json);
+    loaded.push(Database::deserialize_any_format(&mut deserializer)?);
+    for db in &loaded {
+        let account = db.get_account(DbId::of_usize(1)).unwrap();
+        assert_eq!(account.inner().currency(), Currency::GBP);
+        let bt = db.get_bank_transaction(DbId::of_usize(4)).unwrap();
+        assert_eq!(bt.inner().fitid(), None);
+        assert_eq!(bt.inner().related_party(), DbId::none());
+        let t = db.get_transaction(DbId::of_usize(5)).unwrap();
+        assert_eq!(t.inner().currency(), Currency::GBP);
+        let fund = db.funds().get_fund("General").unwrap();
+        assert_eq!(
+            fund.inner().transactions_in_range(Default::default()),
+            vec![DbId::of_usize(5)]
+        );
+        assert_eq!(to_json(db)?, to_json(&loaded[0])?);
+    }
+
+    // It is not in the dictionary format
+    let mut deserializer = serde_json::Deserializer::from_str(json);
+    assert!(Database::deserialize(&mut deserializer, FileFormat::Dictionary).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_merge() -> Result<(), Box<dyn std::error::Error>> {
+    let fixture = Fixture::default();
+    let (account_id, bt_id) = fixture.add_account();
+    let Fixture {
+        db,
+        fund_id: general_id,
+        rp_id,
+    } = fixture;
+    let snapshot = to_json(&db)?;
+
+    // One copy imports a bank statement and reconciles it...
+    let theirs = from_json(&snapshot)?;
+    add_bank_transactions(
+        &theirs,
+        account_id,
+        vec![bank_transaction("Hall hire", 4000)],
+    );
+    let bob_id = theirs.add_related_party(RelatedParty::new(
+        "Bob Jones".into(),
+        2,
+        RelatedPartyType::Member,
+    ));
+    add_income(&theirs, date(), 2000, bob_id, general_id)?;
+    theirs.update_bank_transaction(bt_id, |bt| bt.set_related_party(rp_id))?;
+    theirs.update_fund(general_id, |f| f.set_desc("Day to day"))?;
+
+    // ...while the other adds a fund
+    let mut ours = from_json(&snapshot)?;
+    let roof_id = ours.add_fund(Fund::new("Roof", "Roof repairs"));
+
+    let report = ours.merge(&theirs)?;
+    assert_eq!(report.added().len(), 3);
+    assert_eq!(report.updated(), &[bt_id]);
+    let conflicts: Vec<_> = report
+        .conflicts()
+        .iter()
+        .map(|c| (c.db_id, c.field.as_str(), c.theirs.as_str()))
+        .collect();
+    assert_eq!(conflicts, vec![(general_id, "description", "Day to day")]);
+
+    // The added items refer to the items of the database
+    assert!(ours.related_parties().get_rp_id(2).unwrap().id() > roof_id);
+    let db_account = ours.get_account(account_id).unwrap();
+    let bt_ids = db_account
+        .inner()
+        .bank_transactions_in_range(Default::default());
+    assert_eq!(bt_ids.len(), 2);
+    assert_eq!(
+        ours.get_bank_transaction(bt_id)
+            .unwrap()
+            .inner()
+            .related_party(),
+        rp_id
+    );
+    let db_f = ours.funds().get_fund("General").unwrap();
+    let balance = db_f
+        .inner()
+        .balance_in(&ours, general_id, Currency::default())?;
+    assert_eq!(balance.amount().value(), 2000);
+    assert!(ours.check_integrity().is_empty());
+
+    // Merging again adds nothing
+    let report = ours.merge(&theirs)?;
+    assert!(report.added().is_empty() && report.updated().is_empty());
+
+    // A merge that fails (after adding a fund) leaves the database
+    // unchanged
+    let broken = from_json(&snapshot)?;
+    broken.add_fund(Fund::new("Building", "Building works"));
+    broken
+        .get_bank_transaction(bt_id)
+        .unwrap()
+        .inner_mut()
+        .set_related_party(DbId::of_usize(999));
+    let before = to_json(&ours)?;
+    assert!(ours.merge(&broken).is_err());
+    assert_eq!(to_json(&ours)?, before);
+    assert!(ours.funds().get_fund("Building").is_none());
+    Ok(())
+}
+
+#[test]
+fn test_query_combinators() -> Result<(), Box<dyn std::error::Error>> {
+    let Fixture {
+        db,
+        fund_id: general_id,
+        rp_id,
+    } = Fixture::default();
+    let roof_id = db.add_fund(Fund::new("Roof", "Roof repairs"));
+    let subs_id = add_income(&db, date(), 2000, rp_id, general_id)?;
+    let gift_id = add_income(&db, date(), 50000, rp_id, roof_id)?;
+    let (repair_id, _) = db.add_transaction(Transaction::new_payment(
+        date(),
+        Amount::from(30000),
+        roof_id,
+        rp_id,
+    ))?;
+    let transactions = || DbQuery::default().with_item_type(Some(DbItemType::Transaction));
+    let found = |q: DbQuery| db.query(q).collect::<Vec<_>>();
+
+    let q = transactions().with_min_amount(Some(Amount::from(2500)));
+    assert_eq!(found(q), vec![gift_id, repair_id]);
+    let q = transactions().with_side(Some(AmountSide::Credit));
+    assert_eq!(found(q), vec![subs_id, gift_id]);
+    let q = transactions()
+        .with_fund(roof_id)
+        .with_side(Some(AmountSide::Debit));
+    assert_eq!(found(q), vec![repair_id]);
+    let q = transactions().with_transaction_type(Some(TransactionType::ToRp));
+    assert_eq!(found(q), vec![repair_id]);
+
+    // Small amounts or the roof fund, but not payments
+    let q = transactions()
+        .with_any_of(DbQuery::default().with_max_amount(Some(Amount::from(2000))))
+        .with_any_of(DbQuery::default().with_fund(roof_id))
+        .with_none_of(DbQuery::default().with_transaction_type(Some(TransactionType::ToRp)));
+    assert_eq!(found(q), vec![subs_id, gift_id]);
+
+    // Excluding large amounts does not exclude a related party
+    let q = DbQuery::default()
+        .with_item_type(Some(DbItemType::RelatedParty))
+        .with_none_of(DbQuery::default().with_min_amount(Some(Amount::from(10000))));
+    assert_eq!(found(q), vec![rp_id]);
+
+    // Groups of different item types
+    let q = DbQuery::any_of([
+        DbQuery::default()
+            .with_item_type(Some(DbItemType::Fund))
+            .with_name("Roof"),
+        transactions().with_amount(Some(Amount::from(2000))),
+    ]);
+    assert_eq!(found(q), vec![roof_id, subs_id]);
+    Ok(())
+}
+
+#[test]
+fn test_query_language() -> Result<(), Box<dyn std::error::Error>> {
+    let Fixture {
+        db,
+        fund_id: general_id,
+        rp_id: smith_id,
+    } = Fixture::default();
+    let roof_id = db.add_fund(Fund::new("Roof", "Roof repairs"));
+    let jones_id = db.add_related_party(RelatedParty::new(
+        "Bob Jones".into(),
+        2,
+        RelatedPartyType::Supplier,
+    ));
+    let subs_id = add_income(&db, Date::of_dmy(1, 5, 2024)?, 2000, smith_id, general_id)?;
+    let gift_id = add_income(&db, Date::of_dmy(1, 6, 2024)?, 50000, smith_id, roof_id)?;
+    let late_id = add_income(&db, Date::of_dmy(1, 4, 2025)?, 50000, smith_id, general_id)?;
+    let (repair_id, _) = db.add_transaction(Transaction::new_payment(
+        Date::of_dmy(1, 3, 2025)?,
+        Amount::from(30000),
+        roof_id,
+        jones_id,
+    ))?;
+    let found = |text: &str| -> Result<Vec<DbId>, Error> {
+        Ok(db.query(DbQuery::parse(text, &db)?).collect())
+    };
+
+    assert_eq!(
+        found("type:transaction date:2024-04..2025-03 amount>100 rp:\"smith\"")?,
+        vec![gift_id]
+    );
+    assert_eq!(
+        found("type:transaction date:2024-04..2025-03 -fund:General")?,
+        vec![gift_id, repair_id]
+    );
+    assert_eq!(
+        found("type:transaction (amount<=20 or ttype:to_rp or amount:500) and not date:2025-04")?,
+        vec![subs_id, gift_id, repair_id]
+    );
+    assert_eq!(
+        found("type:transaction (side:credit amount:500 | rp:jones)")?,
+        vec![gift_id, late_id, repair_id]
+    );
+    assert_eq!(found("type:fund name~^R")?, vec![roof_id]);
+
+    // Excluding an amount does not exclude items that have no amount
+    assert_eq!(
+        found("type:related_party -amount>100")?,
+        vec![smith_id, jones_id]
+    );
+    assert_eq!(
+        found("type:related_party -(amount>100 or name:Bob)")?,
+        vec![smith_id]
+    );
+
+    for (text, column) in [
+        ("type:transaction amount>ten", 25),
+        ("type:transaction (fund:General", 31),
+        ("type:fund colour:red", 11),
+        ("rp:Nobody", 4),
+        ("desc:\"SUB", 6),
+        ("type:fund amount~1", 17),
+    ] {
+        match DbQuery::parse(text, &db) {
+            Err(Error::ParseQuery(c, _)) => assert_eq!(c, column, "{text}"),
+            x => panic!("expected a parse error for {text}, got {x:?}"),
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_query_bank_type() -> Result<(), Box<dyn std::error::Error>> {
+    let db = Database::default();
+    let account_id = add_account(&db);
+    let bt = |day, ttype, debit: isize, credit: isize, balance: isize| -> Result<_, Error> {
+        Ok(BankTransaction::new(
+            Date::of_dmy(day, 8, 2024)?,
+            ttype,
+            desc(),
+            "Payment".into(),
+            Amount::from(debit),
+            Amount::from(credit),
+            Amount::from(balance),
+        ))
+    };
+    let bt_ids = add_bank_transactions(
+        &db,
+        account_id,
+        vec![
+            bt(1, BankTransactionType::Fpi, 0, 5000, 5000)?,
+            bt(2, BankTransactionType::DirectDebit, 2000, 0, 3000)?,
+            bt(3, BankTransactionType::DirectDebitRefund, 0, 2000, 5000)?,
+        ],
+    );
+    let (fpi_id, dd_id, refund_id) = (bt_ids[0], bt_ids[1], bt_ids[2]);
+    let found = |text: &str| -> Result<Vec<DbId>, Error> {
+        Ok(db.query(DbQuery::parse(text, &db)?).collect())
+    };
+
+    // A code is the type of both its credits and its debits
+    assert_eq!(
+        found("type:bank_transaction bank_type:DD")?,
+        vec![dd_id, refund_id]
+    );
+    assert_eq!(
+        found("type:bank_transaction bank_type:dd side:debit")?,
+        vec![dd_id]
+    );
+    assert_eq!(found("type:bank_transaction -bank_type:DD")?, vec![fpi_id]);
+    assert_eq!(
+        found("type:bank_transaction bank_type:SO")?,
+        Vec::<DbId>::new()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_query_result() -> Result<(), Box<dyn std::error::Error>> {
+    let Fixture { db, fund_id, rp_id } = Fixture::default();
+    let mut t_ids = vec![];
+    for (day, amount) in [(3, 500), (1, 2000), (2, 100), (4, 2000)] {
+        let date = Date::of_dmy(day, 8, 2024)?;
+        t_ids.push(add_income(&db, date, amount, rp_id, fund_id)?);
+    }
+    let transactions = || DbQuery::default().with_item_type(Some(DbItemType::Transaction));
+
+    let view = QueryView::default().with_sort_key(SortKey::Date);
+    let result = db.query_result(transactions(), &view)?;
+    assert_eq!(result.total(), 4);
+    assert_eq!(result.db_ids(), &[t_ids[1], t_ids[2], t_ids[0], t_ids[3]]);
+
+    // Equal amounts are in DbId order, whichever the direction
+    let view = QueryView::default()
+        .with_sort_key(SortKey::Amount)
+        .with_descending(true)
+        .with_offset(1)
+        .with_limit(Some(2))
+        .with_fields(["amount"]);
+    let result = db.query_result(transactions(), &view)?;
+    assert_eq!(result.total(), 4);
+    assert_eq!(result.offset(), 1);
+    assert_eq!(result.db_ids(), &[t_ids[3], t_ids[0]]);
+    let item = result.items()[1].as_object().unwrap();
+    let mut keys: Vec<_> = item.keys().map(|k| k.as_str()).collect();
+    keys.sort();
+    assert_eq!(keys, vec!["amount", "id", "itype"]);
+    assert_eq!(item["amount"], 500);
+
+    // A field that none of the items has is an error, whatever the page
+    let view = QueryView::default()
+        .with_fields(["amount", "colour"])
+        .with_limit(Some(0));
+    let err = db.query_result(transactions(), &view).unwrap_err();
+    assert!(err.to_string().contains("'colour'"), "{err}");
+
+    // A field that only some of the items have is null for the others
+    let view = QueryView::default().with_fields(["date", "name"]);
+    let result = db.query_result(DbQuery::default(), &view)?;
+    assert_eq!(result.items().len(), 6);
+    assert_eq!(result.items()[0]["name"], "General");
+    assert!(result.items()[0]["date"].is_null());
+    assert!(result.items()[2]["name"].is_null());
+    assert!(result.items()[2]["date"].is_object());
+
+    // Items without a date sort after those with one
+    let view = QueryView::default()
+        .with_sort_key(SortKey::Date)
+        .with_descending(true)
+        .with_limit(Some(1));
+    let result = db.query_result(DbQuery::default(), &view)?;
+    assert_eq!(result.total(), 6);
+    assert_eq!(result.db_ids(), &[t_ids[3]]);
+    let view = view.with_offset(4);
+    let result = db.query_result(DbQuery::default(), &view)?;
+    assert_eq!(result.db_ids(), &[fund_id]);
+    Ok(())
+}
+
+#[test]
+fn test_aggregate() -> Result<(), Box<dyn std::error::Error>> {
+    let Fixture {
+        db,
+        fund_id: general_id,
+        rp_id,
+    } = Fixture::default();
+    let roof_id = db.add_fund(Fund::new("Roof", "Roof repairs"));
+    for (d, m, y, amount, fund_id) in [
+        (1, 3, 2024, 2000, general_id),
+        (1, 4, 2024, 2000, general_id),
+        (5, 4, 2024, 500, roof_id),
+        (1, 7, 2024, 3000, general_id),
+    ] {
+        add_income(&db, Date::of_dmy(d, m, y)?, amount, rp_id, fund_id)?;
+    }
+    let transactions = DbQuery::default().with_item_type(Some(DbItemType::Transaction));
+
+    let aggregation = Aggregation::default().with_group_by([GroupBy::Quarter, GroupBy::Fund]);
+    let result = db.aggregate(transactions.clone(), &aggregation)?;
+    let groups: Vec<_> = result
+        .groups()
+        .iter()
+        .map(|g| (g.keys().join(" "), g.count(), g.sum().value()))
+        .collect();
+    assert_eq!(
+        groups,
+        vec![
+            ("2023/24 Q4 General".to_string(), 1, 2000),
+            ("2024/25 Q1 General".to_string(), 1, 2000),
+            ("2024/25 Q1 Roof".to_string(), 1, 500),
+            ("2024/25 Q2 General".to_string(), 1, 3000),
+        ]
+    );
+    assert_eq!(result.total().count(), 4);
+    assert_eq!(result.total().sum(), Amount::from(7500));
+    assert_eq!(result.total().min(), Amount::from(500));
+    assert_eq!(result.total().max(), Amount::from(3000));
+
+    let aggregation = Aggregation::default()
+        .with_group_by([GroupBy::FinancialYear, GroupBy::RelatedPartyType])
+        .with_year_start(1);
+    let result = db.aggregate(transactions, &aggregation)?;
+    assert_eq!(result.groups().len(), 1);
+    assert_eq!(result.groups()[0].keys(), &["2024", "Member"]);
+    assert_eq!(result.groups()[0].max(), Amount::from(3000));
+    let table = result.to_string();
+    let header: Vec<_> = table.lines().next().unwrap().split_whitespace().collect();
+    assert_eq!(
+        header,
+        vec!["year", "rp_type", "count", "sum", "min", "max"]
+    );
+    assert!(table.contains("total"));
+
+    // Transactions and bank transactions are not aggregated together
+    assert!(db.aggregate(DbQuery::default(), &aggregation).is_err());
+    let either = DbQuery::any_of([
+        DbQuery::default().with_item_type(Some(DbItemType::Transaction)),
+        DbQuery::default().with_item_type(Some(DbItemType::BankTransaction)),
+    ]);
+    assert!(db.aggregate(either, &aggregation).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_query_indexes() -> Result<(), Box<dyn std::error::Error>> {
+    let Fixture {
+        db,
+        fund_id: general_id,
+        rp_id: smith_id,
+    } = Fixture::default();
+    let jones_id = db.add_related_party(RelatedParty::new(
+        "Bob Jones".into(),
+        2,
+        RelatedPartyType::Member,
+    ));
+    // Ten years of monthly subs from Smith, and one year from Jones
+    let mut smith_ids = vec![];
+    let mut jones_ids = vec![];
+    for year in 2015..2025 {
+        for month in 1..=12 {
+            let date = Date::of_dmy(1, month, year)?;
+            smith_ids.push(add_income(&db, date, 2000, smith_id, general_id)?);
+            if year == 2020 {
+                jones_ids.push(add_income(&db, date, 1000, jones_id, general_id)?);
+            }
+        }
+    }
+    let year_2020: DateRange = (Date::of_dmy(1, 1, 2020)?, Date::of_dmy(1, 1, 2021)?).into();
+    let in_2020 = || {
+        DbQuery::default()
+            .with_item_type(Some(DbItemType::Transaction))
+            .with_date_range(year_2020)
+    };
+    let found = |q: DbQuery| db.query(q).collect::<Vec<_>>();
+    assert_eq!(found(in_2020()).len(), 24);
+    assert_eq!(found(in_2020().with_related_party(jones_id)), jones_ids);
+    let q = DbQuery::default()
+        .with_item_type(Some(DbItemType::Transaction))
+        .with_related_party(jones_id);
+    assert_eq!(found(q).len(), 12);
+
+    // Moving a transaction to another date and related party reindexes it
+    db.update_transaction(smith_ids[0], |t| {
+        t.set_date(Date::of_dmy(15, 6, 2020).unwrap());
+        t.set_db_ids(jones_id, general_id);
+    })?;
+    assert_eq!(found(in_2020()).len(), 25);
+    let jones_2020 = found(in_2020().with_related_party(jones_id));
+    assert_eq!(jones_2020.len(), 13);
+    assert_eq!(jones_2020[0], smith_ids[0]);
+
+    db.remove(jones_ids[0], false)?;
+    assert_eq!(found(in_2020().with_related_party(jones_id)).len(), 12);
+
+    // The indexes are rebuilt with the renumbered DbIds on load
+    let loaded = from_json(&to_json(&db)?)?;
+    let jones_id = loaded.related_parties().get_rp_id(2).unwrap().id();
+    let q = in_2020().with_related_party(jones_id);
+    assert_eq!(loaded.query(q).count(), 12);
+    Ok(())
+}
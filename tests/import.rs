@@ -0,0 +1,136 @@
+use rust_accounts::{
+    AccountDesc, Amount, BankTransaction, BankTransactionType, Database, Date, ImportOutcome,
+    RelatedParty, RelatedPartyMatch, RelatedPartyType,
+};
+
+mod common;
+use common::{add_account, desc};
+
+//a Useful functions
+fn bt(
+    desc: &AccountDesc,
+    day: u32,
+    description: &str,
+    delta: isize,
+    balance: isize,
+) -> BankTransaction {
+    let (debit, credit) = if delta < 0 { (-delta, 0) } else { (0, delta) };
+    BankTransaction::new(
+        Date::of_dmy(day, 8, 2024).unwrap(),
+        BankTransactionType::Unknown,
+        desc.clone(),
+        description.into(),
+        Amount::from(debit * 100),
+        Amount::from(credit * 100),
+        Amount::from(balance * 100),
+    )
+}
+
+//a Tests
+#[test]
+fn test_import_overlap() -> Result<(), Box<dyn std::error::Error>> {
+    let db = Database::default();
+    let account_id = add_account(&db);
+    let desc = desc();
+    let db_account = db.get_account(account_id).unwrap();
+
+    let first = vec![
+        bt(&desc, 1, "Subs", 20, 120),
+        bt(&desc, 3, "Power", -30, 90),
+    ];
+    let report = db_account
+        .inner_mut()
+        .add_transactions(&db, account_id, first)
+        .unwrap();
+    assert_eq!(report.added().len(), 2);
+
+    // Overlaps the first statement, with a description that differs
+    // in case and whitespace
+    let second = vec![
+        bt(&desc, 3, "POWER ", -30, 90),
+        bt(&desc, 5, "Hall hire", 50, 140),
+    ];
+    let report = db_account
+        .inner_mut()
+        .add_transactions(&db, account_id, second)
+        .unwrap();
+    assert_eq!(report.added().len(), 1);
+    assert_eq!(report.num_skipped(), 1);
+
+    // Would fill in a gap, but the existing Hall hire does not follow
+    // on from its balance; and the second row has the wrong balance
+    let third = vec![
+        bt(&desc, 4, "Donation", 10, 100),
+        bt(&desc, 5, "Raffle", 5, 110),
+    ];
+    let report = db_account
+        .inner_mut()
+        .add_transactions(&db, account_id, third)
+        .unwrap();
+    assert_eq!(report.added().len(), 0);
+    assert_eq!(report.conflicts().len(), 2);
+    assert!(matches!(report.outcomes()[0], ImportOutcome::Conflict(_)));
+    assert!(matches!(report.outcomes()[1], ImportOutcome::Conflict(_)));
+
+    let dates: Vec<_> = db_account
+        .inner()
+        .bank_transactions_in_range(Default::default())
+        .into_iter()
+        .map(|id| db.get_bank_transaction(id).unwrap().inner().date().dmy().0)
+        .collect();
+    assert_eq!(dates, vec![1, 3, 5]);
+
+    // A dry run previews the same conflict
+    let preview = db_account
+        .inner()
+        .preview_transactions(&db, &[bt(&desc, 4, "Donation", 10, 100)])?;
+    assert_eq!(preview.num_new(), 0);
+    assert!(matches!(
+        preview.rows()[0].outcome,
+        ImportOutcome::Conflict(_)
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_import_preview() -> Result<(), Box<dyn std::error::Error>> {
+    let db = Database::default();
+    let account_id = add_account(&db);
+    let desc = desc();
+    let db_account = db.get_account(account_id).unwrap();
+    let mut jane = RelatedParty::new("Jane Smith".into(), 1, RelatedPartyType::Member);
+    jane.add_account_descr("JANE SMITH SUBS");
+    let jane_id = db.add_related_party(jane);
+    for (n, name) in ["Hall Trust", "Hall Trustees"].iter().enumerate() {
+        let mut rp = RelatedParty::new(name.to_string(), 2 + n, RelatedPartyType::Supplier);
+        rp.add_account_descr("HALL TRUST HIRE CHARGES");
+        db.add_related_party(rp);
+    }
+
+    db_account
+        .inner_mut()
+        .add_transactions(&db, account_id, vec![bt(&desc, 1, "Opening", 100, 100)])
+        .unwrap();
+    let rows = vec![
+        bt(&desc, 1, "Opening", 100, 100),
+        bt(&desc, 2, "JANE SMITH SUBS", 20, 120),
+        bt(&desc, 3, "HALL TRUST HIRE CHARGES", -50, 70),
+    ];
+    let preview = db_account.inner().preview_transactions(&db, &rows)?;
+    assert_eq!(preview.num_new(), 2);
+    assert_eq!(preview.num_skipped(), 1);
+    assert_eq!(preview.num_ambiguous(), 1);
+    assert_eq!(
+        preview.rows()[1].related_party,
+        RelatedPartyMatch::Found(jane_id)
+    );
+    assert_eq!(preview.closing_balance(), Some(Amount::from(7000)));
+    assert_eq!(
+        db_account
+            .inner()
+            .bank_transactions_in_range(Default::default())
+            .len(),
+        1
+    );
+    Ok(())
+}
@@ -0,0 +1,240 @@
+use rust_accounts::{Error, Fund, Journal, JournalOp, SCHEMA_VERSION};
+
+mod common;
+use common::*;
+
+//a Tests
+#[test]
+fn test_journal_replay() -> Result<(), Box<dyn std::error::Error>> {
+    let Fixture {
+        db,
+        fund_id: general_id,
+        rp_id,
+    } = Fixture::default();
+    let snapshot = to_json(&db)?;
+
+    let path = std::env::temp_dir().join(format!("journal_{}.journal", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let db = from_json(&snapshot)?;
+    let mut journal = Journal::new(&db)?.with_path(&path);
+
+    // Adding income changes the fund and related party too
+    let t_id = add_income(&db, date(), 2000, rp_id, general_id)?;
+    let entries = journal.record(&db)?;
+    let kinds: Vec<_> = entries.iter().map(|e| (e.kind(), e.db_id())).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            (JournalOp::Edit, general_id),
+            (JournalOp::Edit, rp_id),
+            (JournalOp::Add, t_id)
+        ]
+    );
+    assert!(journal.record(&db)?.is_empty());
+    assert_eq!(db.journal_op(), 1);
+    let first_snapshot = to_snapshot(&db)?;
+
+    let hall_id = db.add_fund(Fund::new("Hall", "Hall upkeep"));
+    db.update_fund(general_id, |f| f.set_name("Main"))?;
+    db.remove(t_id, false)?;
+    let entries = journal.record(&db)?;
+    assert_eq!(entries.len(), 4);
+    assert!(entries.iter().all(|e| e.op() == 2));
+    assert_eq!(entries[3].kind(), JournalOp::Delete);
+    assert_eq!(entries[0].before().unwrap().show_name(), "General");
+
+    // Replaying the journal over the snapshot recreates the database
+    let (replayed, _) = Journal::replay_file(from_json(&snapshot)?, &path)?;
+    assert_eq!(to_json(&replayed)?, to_json(&db)?);
+    assert_eq!(replayed.show_name(hall_id), "Hall");
+    assert_eq!(replayed.journal_op(), 2);
+
+    // Operations that a snapshot includes are not replayed again
+    let (replayed, _) = Journal::replay_file(from_snapshot(&first_snapshot)?, &path)?;
+    assert_eq!(to_json(&replayed)?, to_json(&db)?);
+    let (replayed, _) = Journal::replay_file(from_snapshot(&to_snapshot(&db)?)?, &path)?;
+    assert_eq!(replayed.journal_op(), 2);
+    assert_eq!(replayed.db_ids().len(), db.db_ids().len());
+
+    // Operations continue from the last one replayed
+    let (replayed, mut journal2) = Journal::replay_file(from_snapshot(&first_snapshot)?, &path)?;
+    replayed.add_fund(Fund::new("Roof", "Roof repairs"));
+    assert!(journal2.record(&replayed)?.iter().all(|e| e.op() == 3));
+
+    // A journal that does not follow on from the snapshot is an error
+    journal.truncate()?;
+    assert!(Journal::read_entries(&path)?.is_empty());
+    db.add_fund(Fund::new("Roof", "Roof repairs"));
+    journal.record(&db)?;
+    assert!(Journal::replay_file(from_json(&snapshot)?, &path).is_err());
+
+    journal.truncate()?;
+    Ok(())
+}
+
+#[test]
+fn test_undo_redo() -> Result<(), Box<dyn std::error::Error>> {
+    let Fixture {
+        db,
+        fund_id: general_id,
+        rp_id,
+    } = Fixture::default();
+    let snapshot = to_json(&db)?;
+
+    let path = std::env::temp_dir().join(format!("undo_{}.journal", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let mut db = from_json(&snapshot)?;
+    let mut journal = Journal::new(&db)?.with_path(&path);
+
+    let hall_id = db.add_fund(Fund::new("Hall", "Hall upkeep"));
+    journal.record(&db)?;
+    let after_hall = to_json(&db)?;
+    let t_id = add_income(&db, date(), 2000, rp_id, hall_id)?;
+    let after_income = to_json(&db)?;
+
+    // The income is recorded by the undo, then undone along with the
+    // transaction lists of the fund and related party
+    db = journal.undo(&db)?.unwrap();
+    assert!(db.get_transaction(t_id).is_none());
+    let db_f = db.funds().get_fund("Hall").unwrap();
+    assert!(db_f
+        .inner()
+        .transactions_in_range(Default::default())
+        .is_empty());
+    assert_eq!(to_json(&db)?, after_hall);
+
+    db = journal.undo(&db)?.unwrap();
+    assert!(db.funds().get_fund("Hall").is_none());
+    assert!(journal.undo(&db)?.is_none());
+    assert_eq!(to_json(&db)?, snapshot);
+
+    db = journal.redo(&db)?.unwrap();
+    assert!(db.funds().get_fund("Hall").is_some());
+
+    // The undo and redo stacks are recreated from the journal file
+    let (mut db, mut journal) = Journal::replay_file(from_json(&snapshot)?, &path)?;
+    assert!(journal.can_undo() && journal.can_redo());
+    db = journal.redo(&db)?.unwrap();
+    assert_eq!(to_json(&db)?, after_income);
+    assert_eq!(db.funds().get_fund("General").unwrap().id(), general_id);
+
+    // A change after an undo means there is nothing to redo
+    db = journal.undo(&db)?.unwrap();
+    db.add_fund(Fund::new("Roof", "Roof repairs"));
+    assert!(journal.redo(&db)?.is_none());
+
+    journal.truncate()?;
+    Ok(())
+}
+
+#[test]
+fn test_undo_without_changes() -> Result<(), Box<dyn std::error::Error>> {
+    let Fixture {
+        db,
+        fund_id: general_id,
+        ..
+    } = Fixture::default();
+    let snapshot = to_json(&db)?;
+
+    // An operation whose undo leaves the items as they are
+    let path = std::env::temp_dir().join(format!("mark_{}.journal", std::process::id()));
+    let item = serde_json::to_value(db.get(general_id).unwrap())?;
+    let entry = serde_json::json!({
+        "timestamp": 0,
+        "op": 1,
+        "kind": "Edit",
+        "db_id": general_id,
+        "before": item,
+        "after": item,
+    });
+    let line = serde_json::json!({"schema_version": SCHEMA_VERSION, "entries": [entry]});
+    std::fs::write(&path, format!("{line}\n"))?;
+
+    let (db, mut journal) = Journal::replay_file(from_json(&snapshot)?, &path)?;
+    assert!(journal.can_undo());
+    journal.undo(&db)?.unwrap();
+    let entries = Journal::read_entries(&path)?;
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[1].kind(), JournalOp::Mark);
+
+    // The undo is replayed, so the operation can only be redone
+    let (_, journal) = Journal::replay_file(from_json(&snapshot)?, &path)?;
+    assert!(!journal.can_undo() && journal.can_redo());
+
+    journal.truncate()?;
+    Ok(())
+}
+
+#[test]
+fn test_journal_file() -> Result<(), Box<dyn std::error::Error>> {
+    let Fixture {
+        db,
+        fund_id: general_id,
+        ..
+    } = Fixture::default();
+    let snapshot = to_json(&db)?;
+
+    let path = std::env::temp_dir().join(format!("file_{}.journal", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let mut journal = Journal::new(&db)?.with_path(&path);
+    db.add_fund(Fund::new("Hall", "Hall upkeep"));
+    journal.record(&db)?;
+    let first = std::fs::read_to_string(&path)?;
+    db.add_fund(Fund::new("Roof", "Roof repairs"));
+    db.update_fund(general_id, |f| f.set_name("Main"))?;
+    journal.record(&db)?;
+
+    // Each operation is one line
+    let lines = std::fs::read_to_string(&path)?;
+    assert_eq!(lines.lines().count(), 2);
+    assert_eq!(Journal::read_entries(&path)?.len(), 3);
+
+    // An operation only partly written is discarded as a whole, and
+    // removed from the file before the next is appended
+    std::fs::write(&path, &lines[..lines.len() - 20])?;
+    assert_eq!(Journal::read_entries(&path)?.len(), 1);
+    let (db, mut journal) = Journal::replay_file(from_json(&snapshot)?, &path)?;
+    assert_eq!(db.journal_op(), 1);
+    assert!(db.funds().get_fund("Roof").is_none());
+    assert!(db.funds().get_fund("General").is_some());
+    assert_eq!(std::fs::read_to_string(&path)?, first);
+    db.add_fund(Fund::new("Spire", "Spire repairs"));
+    journal.record(&db)?;
+    let (db, _) = Journal::replay_file(from_json(&snapshot)?, &path)?;
+    assert_eq!(db.journal_op(), 2);
+    assert!(db.funds().get_fund("Spire").is_some());
+
+    // Any other bad line is an error
+    let lines = std::fs::read_to_string(&path)?;
+    std::fs::write(&path, format!("{lines}{{\"schema_version\": 1}}\n"))?;
+    assert!(Journal::read_entries(&path).is_err());
+
+    // The items of each operation are upgraded from its schema version
+    let mut item = serde_json::to_value(db.get(general_id).unwrap())?;
+    item["value"]["Fund"]["inner"]
+        .as_object_mut()
+        .unwrap()
+        .remove("currency");
+    let entry = serde_json::json!({
+        "timestamp": 0,
+        "op": 1,
+        "kind": "Edit",
+        "db_id": general_id,
+        "before": item,
+        "after": item,
+    });
+    let line = serde_json::json!({"schema_version": 0, "entries": [entry]});
+    std::fs::write(&path, format!("{line}\n"))?;
+    let (db, _) = Journal::replay_file(from_json(&snapshot)?, &path)?;
+    assert_eq!(db.journal_op(), 1);
+    let line = serde_json::json!({"schema_version": 99, "entries": [entry]});
+    std::fs::write(&path, format!("{line}\n"))?;
+    assert!(Journal::read_entries(&path).is_err());
+    assert!(matches!(
+        Journal::replay_file(from_json(&snapshot)?, &path),
+        Err(Error::Msg(_))
+    ));
+
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
@@ -0,0 +1,108 @@
+use rust_accounts::banks::ofx;
+use rust_accounts::{AccountDesc, BankTransactionType};
+
+const SGML: &str = "OFXHEADER:100
+DATA:OFXSGML
+VERSION:102
+
+<OFX>
+<SIGNONMSGSRSV1><SONRS><STATUS><CODE>0<SEVERITY>INFO</STATUS></SONRS></SIGNONMSGSRSV1>
+<BANKMSGSRSV1><STMTTRNRS><TRNUID>1
+<STMTRS><CURDEF>GBP
+<BANKACCTFROM><BANKID>309174<ACCTID>02344812<ACCTTYPE>CHECKING</BANKACCTFROM>
+<BANKTRANLIST><DTSTART>20240801<DTEND>20240831
+<STMTTRN><TRNTYPE>DIRECTDEBIT<DTPOSTED>20240829120000[0:GMT]<TRNAMT>-30.00<FITID>2024082902<NAME>ELECTRICITY</STMTTRN>
+<STMTTRN><TRNTYPE>CREDIT<DTPOSTED>20240828<TRNAMT>20.00<FITID>2024082801<NAME>NAME<MEMO>REASON &amp; MORE</STMTTRN>
+</BANKTRANLIST>
+<LEDGERBAL><BALAMT>90.00<DTASOF>20240831</LEDGERBAL>
+</STMTRS></STMTTRNRS></BANKMSGSRSV1>
+</OFX>
+";
+
+const XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<?OFX OFXHEADER="200" VERSION="220"?>
+<OFX><CREDITCARDMSGSRSV1><CCSTMTTRNRS><CCSTMTRS>
+<CCACCTFROM><ACCTID>4921811234561234</ACCTID></CCACCTFROM>
+<BANKTRANLIST>
+<STMTTRN><TRNTYPE>DEBIT</TRNTYPE><DTPOSTED>20240828</DTPOSTED><TRNAMT>-5.50</TRNAMT><FITID>A1</FITID><NAME>SHOP</NAME></STMTTRN>
+</BANKTRANLIST>
+<LEDGERBAL><BALAMT>-5.50</BALAMT><DTASOF>20240831</DTASOF></LEDGERBAL>
+</CCSTMTRS></CCSTMTTRNRS></CREDITCARDMSGSRSV1></OFX>
+"#;
+
+const TWO_STATEMENTS: &str = "OFXHEADER:100
+DATA:OFXSGML
+VERSION:102
+
+<OFX>
+<BANKMSGSRSV1><STMTTRNRS><TRNUID>1
+<STMTRS><CURDEF>GBP
+<BANKACCTFROM><BANKID>309174<ACCTID>02344812<ACCTTYPE>CHECKING</BANKACCTFROM>
+<BANKTRANLIST>
+<STMTTRN><TRNTYPE>DEBIT<DTPOSTED>20240805<TRNAMT>-10.00<FITID>1<NAME>SHOP</STMTTRN>
+</BANKTRANLIST>
+<LEDGERBAL><BALAMT>90.00<DTASOF>20240831</LEDGERBAL>
+</STMTRS></STMTTRNRS>
+<STMTTRNRS><TRNUID>2
+<STMTRS><CURDEF>GBP
+<BANKACCTFROM><BANKID>309174<ACCTID>02344812<ACCTTYPE>CHECKING</BANKACCTFROM>
+<BANKTRANLIST>
+<STMTTRN><TRNTYPE>CREDIT<DTPOSTED>20240905<TRNAMT>25.00<FITID>2<NAME>SUBS</STMTTRN>
+</BANKTRANLIST>
+<LEDGERBAL><BALAMT>115.00<DTASOF>20240930</LEDGERBAL>
+</STMTRS></STMTTRNRS></BANKMSGSRSV1>
+</OFX>
+";
+
+//a Tests
+#[test]
+fn test_sgml() -> Result<(), Box<dyn std::error::Error>> {
+    let s = ofx::read_transactions_ofx(SGML.as_bytes(), &AccountDesc::None, None)?;
+    assert_eq!(s.len(), 1);
+    let t = &s[0];
+    assert_eq!(t.len(), 2);
+    assert_eq!(t[0].account_desc(), &AccountDesc::uk(309174, 2344812));
+    assert_eq!(t[0].description(), "NAME REASON & MORE");
+    assert_eq!(t[0].fitid(), Some("2024082801"));
+    assert_eq!(t[0].balance().value(), 12000);
+    assert_eq!(t[1].balance().value(), 9000);
+    assert_eq!(t[1].ttype(), BankTransactionType::DirectDebit);
+    Ok(())
+}
+
+#[test]
+fn test_ledger_balance_check() {
+    let opening = "100.00".parse().unwrap();
+    assert!(ofx::read_transactions_ofx(SGML.as_bytes(), &AccountDesc::None, Some(opening)).is_ok());
+    let opening = "99.00".parse().unwrap();
+    assert!(
+        ofx::read_transactions_ofx(SGML.as_bytes(), &AccountDesc::None, Some(opening)).is_err()
+    );
+}
+
+#[test]
+fn test_multiple_statements() -> Result<(), Box<dyn std::error::Error>> {
+    // The opening balance is of the first statement; the second
+    // continues from its closing balance
+    let read = |opening: &str| {
+        let opening = opening.parse().unwrap();
+        ofx::read_transactions_ofx(TWO_STATEMENTS.as_bytes(), &AccountDesc::None, Some(opening))
+    };
+    let s = read("100.00")?;
+    assert_eq!(s[0][0].balance().value(), 9000);
+    assert_eq!(s[1][0].balance().value(), 11500);
+    assert!(read("99.00").is_err());
+    let s = ofx::read_transactions_ofx(TWO_STATEMENTS.as_bytes(), &AccountDesc::None, None)?;
+    assert_eq!(s[1][0].balance().value(), 11500);
+    Ok(())
+}
+
+#[test]
+fn test_xml_card() -> Result<(), Box<dyn std::error::Error>> {
+    let s = ofx::read_transactions_ofx(XML.as_bytes(), &AccountDesc::None, None)?;
+    let t = &s[0];
+    assert_eq!(t[0].account_desc(), &AccountDesc::card("************1234")?);
+    assert_eq!(t[0].balance().value(), -550);
+    assert_eq!(t[0].fitid(), Some("A1"));
+    Ok(())
+}
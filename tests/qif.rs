@@ -0,0 +1,77 @@
+use rust_accounts::banks::qif;
+use rust_accounts::{DateRange, Fund, RelatedParty, RelatedPartyType};
+
+const QIF: &str = "!Type:Bank
+D01/08'24
+T100.00
+POpening Balance
+L[Current]
+^
+D28/08'24
+T20.00
+PJane Smith
+LSubs
+^
+D29/08'24
+T-30.00
+PPower Co
+NDD
+SHall
+EElectricity
+$-25.00
+SGeneral/2024
+$-5.00
+^
+";
+
+mod common;
+use common::{add_account, desc, Fixture};
+
+//a Tests
+#[test]
+fn test_qif_round_trip() -> Result<(), Box<dyn std::error::Error>> {
+    let Fixture { db, .. } = Fixture::default();
+    let account_id = add_account(&db);
+    let desc = desc();
+    db.add_fund(Fund::new("Hall", "Hall upkeep"));
+    let mut subs = Fund::new("Subscriptions", "Member subscriptions");
+    subs.add_alias("Subs");
+    db.add_fund(subs);
+    db.add_related_party(RelatedParty::new(
+        "Power Co".into(),
+        2,
+        RelatedPartyType::Supplier,
+    ));
+
+    let s = qif::read_statement_qif(QIF.as_bytes(), &desc, None)?;
+    assert_eq!(s.statement.transactions.len(), 2);
+    assert_eq!(s.statement.transactions[1].balance().value(), 9000);
+    assert_eq!(s.splits[1].len(), 2);
+    assert_eq!(s.splits[1][1].category, "General");
+
+    // Splits that do not add up to the amount are rejected, and
+    // nothing is imported
+    let db_account = db.get_account(account_id).unwrap();
+    let bad = QIF.replace("$-5.00", "$-6.00");
+    let bad = qif::read_statement_qif(bad.as_bytes(), &desc, None)?;
+    assert!(bad.import(&db, &db_account).is_err());
+    assert!(db.bank_transactions().db_ids().is_empty());
+    assert!(db.transactions().db_ids().is_empty());
+
+    let (report, t_ids) = s.import(&db, &db_account)?;
+    assert_eq!(report.added().len(), 2);
+    assert_eq!(t_ids.len(), 3);
+
+    let mut out = vec![];
+    qif::write_qif(&mut out, &db, &db_account.inner(), DateRange::default())?;
+    let s = qif::read_statement_qif(out.as_slice(), &desc, None)?;
+    assert_eq!(s.statement.transactions[0].description(), "Jane Smith");
+    assert_eq!(s.splits[0][0].category, "Subscriptions");
+    assert_eq!(s.splits[1][0].amount.value(), -2500);
+    assert_eq!(s.statement.transactions[1].balance().value(), 9000);
+
+    let bad = QIF.replace("SHall", "SRoof");
+    let s = qif::read_statement_qif(bad.as_bytes(), &desc, None)?;
+    assert!(s.import(&db, &db_account).is_err());
+    Ok(())
+}
@@ -0,0 +1,110 @@
+use rust_accounts::banks::{camt053, mt940};
+use rust_accounts::{AccountDesc, Database, DbId, Idx, RelatedParty, RelatedPartyType};
+
+const CAMT053: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Document xmlns="urn:iso:std:iso:20022:tech:xsd:camt.053.001.02">
+<BkToCstmrStmt><Stmt><Id>1</Id>
+<Acct><Id><IBAN>GB82WEST12345698765432</IBAN></Id><Ccy>EUR</Ccy></Acct>
+<Bal><Tp><CdOrPrtry><Cd>OPBD</Cd></CdOrPrtry></Tp><Amt Ccy="EUR">100.00</Amt><CdtDbtInd>CRDT</CdtDbtInd><Dt><Dt>2024-08-01</Dt></Dt></Bal>
+<Bal><Tp><CdOrPrtry><Cd>CLBD</Cd></CdOrPrtry></Tp><Amt Ccy="EUR">90.00</Amt><CdtDbtInd>CRDT</CdtDbtInd><Dt><Dt>2024-08-31</Dt></Dt></Bal>
+<Ntry><Amt Ccy="EUR">20.00</Amt><CdtDbtInd>CRDT</CdtDbtInd><Sts>BOOK</Sts>
+<BookgDt><Dt>2024-08-28</Dt></BookgDt><AcctSvcrRef>REF1</AcctSvcrRef>
+<NtryDtls><TxDtls><RltdPties><Dbtr><Nm>Jane Smith</Nm></Dbtr><DbtrAcct><Id><IBAN>DE89370400440532013000</IBAN></Id></DbtrAcct></RltdPties>
+<RmtInf><Ustrd>Subscription</Ustrd></RmtInf></TxDtls></NtryDtls></Ntry>
+<Ntry><Amt Ccy="EUR">30.00</Amt><CdtDbtInd>DBIT</CdtDbtInd><Sts>BOOK</Sts>
+<BookgDt><Dt>2024-08-29</Dt></BookgDt><AcctSvcrRef>REF2</AcctSvcrRef>
+<BkTxCd><Domn><Cd>PMNT</Cd><Fmly><Cd>IDDT</Cd><SubFmlyCd>ESDD</SubFmlyCd></Fmly></Domn></BkTxCd>
+<NtryDtls><TxDtls><RltdPties><Cdtr><Nm>POWER CO</Nm></Cdtr></RltdPties></TxDtls></NtryDtls></Ntry>
+</Stmt></BkToCstmrStmt></Document>
+"#;
+
+const MT940: &str = ":20:STARTUMSE
+:25:GB82WEST12345698765432
+:28C:00001/001
+:60F:C240801EUR100,00
+:61:2408280828C20,00NTRFNONREF//B1
+:86:166?00GUTSCHRIFT?20Subscription?31DE89370400440532013000?32JANE SMITH
+:61:240829D30,00NDDTNONREF//B2
+:86:/NAME/POWER CO/REMI/Electricity
+:62F:C240831EUR90,00
+-
+";
+
+//a Tests
+#[test]
+fn test_camt053() -> Result<(), Box<dyn std::error::Error>> {
+    let s = camt053::read_statements_camt053(CAMT053.as_bytes(), &AccountDesc::None)?;
+    assert_eq!(s.len(), 1);
+    let t = &s[0].transactions;
+    assert_eq!(
+        t[0].account_desc(),
+        &AccountDesc::iban("GB82WEST12345698765432")?
+    );
+    assert_eq!(t[0].description(), "Jane Smith Subscription");
+    assert_eq!(t[0].fitid(), Some("REF1"));
+    assert_eq!(t[1].balance().value(), 9000);
+    assert_eq!(s[0].counterparties[1].name.as_deref(), Some("POWER CO"));
+
+    let bad = CAMT053.replace(">90.00<", ">91.00<");
+    assert!(camt053::read_statements_camt053(bad.as_bytes(), &AccountDesc::None).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_mt940() -> Result<(), Box<dyn std::error::Error>> {
+    let s = mt940::read_statements_mt940(MT940.as_bytes(), &AccountDesc::None)?;
+    assert_eq!(s.len(), 1);
+    let t = &s[0].transactions;
+    assert_eq!(t.len(), 2);
+    assert_eq!(t[0].description(), "JANE SMITH Subscription");
+    assert_eq!(t[0].fitid(), Some("B1"));
+    assert_eq!(t[1].description(), "POWER CO Electricity");
+    assert_eq!(t[1].balance().value(), 9000);
+    assert_eq!(
+        s[0].counterparties[0].iban.as_deref(),
+        Some("DE89370400440532013000")
+    );
+
+    let bad = MT940.replace(":62F:C240831EUR90,00", ":62F:C240831EUR91,00");
+    assert!(mt940::read_statements_mt940(bad.as_bytes(), &AccountDesc::None).is_err());
+
+    // Subfield values may be any text, but their codes are two digits
+    let umlaut = MT940.replace("?20Subscription", "?20Beitrag für 2024");
+    let s = mt940::read_statements_mt940(umlaut.as_bytes(), &AccountDesc::None)?;
+    assert_eq!(
+        s[0].transactions[0].description(),
+        "JANE SMITH Beitrag für 2024"
+    );
+    let bad = MT940.replace("?20Subscription", "?€20");
+    assert!(mt940::read_statements_mt940(bad.as_bytes(), &AccountDesc::None).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_counterparty_related_party() -> Result<(), Box<dyn std::error::Error>> {
+    let db = Database::default();
+    let mut jane = RelatedParty::new("Jane Smith".into(), 1, RelatedPartyType::Member);
+    jane.add_account_descr("iban:DE89 3704 0044 0532 0130 00");
+    let jane_id = db.add_related_party(jane);
+    let power_id = db.add_related_party(RelatedParty::new(
+        "Power Co".into(),
+        2,
+        RelatedPartyType::Supplier,
+    ));
+
+    let mut s = mt940::read_statements_mt940(MT940.as_bytes(), &AccountDesc::None)?;
+    s[0].resolve_related_parties(&db);
+    assert_eq!(s[0].transactions[0].related_party(), jane_id);
+    assert_eq!(s[0].transactions[1].related_party(), power_id);
+
+    // A name that matches two related parties matches neither
+    db.add_related_party(RelatedParty::new(
+        "POWER  CO".into(),
+        3,
+        RelatedPartyType::Supplier,
+    ));
+    let mut s = mt940::read_statements_mt940(MT940.as_bytes(), &AccountDesc::None)?;
+    s[0].resolve_related_parties(&db);
+    assert_eq!(s[0].transactions[1].related_party(), DbId::none());
+    Ok(())
+}